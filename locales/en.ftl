@@ -0,0 +1,468 @@
+# English messages, the baseline every translation falls back to.
+#
+# Each message is an id, an equals sign and its text. duosplit fills in each { $name }, so
+# keep those as they are when translating, though they can move within the text. Indented
+# lines continue the message above them on a new line. The spaces around a message are
+# trimmed, so leading spaces are written as a string, as in {"  "}, and so is a brace right
+# before a placeholder, as in {"{"}.
+
+## Errors and warnings
+
+error = Error: { $message }
+error-strict = Error: { $message } (--strict)
+channel-red = red
+channel-green = green
+channel-blue = blue
+warning = Warning: { $message }
+write-failed = Failed to write { $path }: { $error }
+read-failed = Failed to read { $path }: { $error }
+open-failed = Failed to open { $path }: { $error }
+list-failed = Failed to list { $path }: { $error }
+create-failed = Failed to create { $path }: { $error }
+encode-failed = Failed to encode { $path }: { $error }
+download-failed = Failed to download { $url }: { $error }
+no-config-directory = No config directory to keep { $file } in
+parse-failed = Failed to parse { $path }: { $error }
+
+## Splitting
+
+stdout-fits-only = only FITS can be streamed to stdout, without previews, composites or post-processing commands
+reading-input = Reading input: { $path }
+error-reading-input = Error reading input: { $message }
+correcting-extinction = Correcting for atmospheric extinction at airmass { $airmass }
+flushed-denormals = Flushed { $count } denormal samples to zero
+bad-samples = { $non-finite } infinite or NaN samples and { $out-of-range } beyond ±{ $limit }
+replaced-samples = Replaced { $found } with zero
+sanitized = replaced { $found } with zero
+poisoned-samples = Error: the image has { $found }, which would poison the fit of every genome; fix the stack, or pass --sanitize to replace them with zero
+stretched = The image looks stretched; duosplit needs linear data to separate the lines accurately. If you only have a stretched export, --linearize can approximately undo the stretch.
+not-stretched = The image doesn't look stretched, so --linearize may make it less linear rather than more.
+inverting-stretch = Inverting a { $stretch } stretch only approximates linear data: clipping, curves and noise reduction in the export can't be undone, so the split will be less accurate than one from a linear stack.
+undoing-white-balance = Undoing white balance multipliers: r = { $r }, g = { $g }, b = { $b }
+background-too-faint = the sky background is too faint to estimate the white balance from; give the multipliers with --wb r,g,b
+background-unbalanceable = the sky background doesn't fit the quantum efficiencies under any white balance; give the multipliers with --wb r,g,b
+adding-noise = Adding Gaussian noise with standard deviation { $sigma }
+solving-panel = Solving panel { $panel }: { $width }x{ $height } at ({ $x }, { $y })
+panel-coefficients = Panel { $panel }: H-alpha r = { $h-alpha-r }, g = { $h-alpha-g }, b = { $h-alpha-b }; OIII r = { $oiii-r }, g = { $oiii-g }, b = { $oiii-b }
+gpu-apply-failed = applying the coefficients on the GPU failed, using the CPU: { $message }
+gpu-composite-failed = building the composite on the GPU failed, using the CPU: { $message }
+mosaic-results = Mean of the panel results, weighted by area:
+best-genome-results = Best genome results:
+h-alpha-coefficients = H-alpha coefficients: r = { $r }, g = { $g }, b = { $b }
+oiii-coefficients = OIII coefficients: r = { $r }, g = { $g }, b = { $b }
+line-diagnostics = { $line } diagnostics: noise { $noise }, { $negative }% negative pixels
+channel-fits = Channel fits under the quantum efficiencies: R² red { $red }, green { $green }, blue { $blue }
+poor-channel-fit = A channel fits poorly; check that the quantum efficiencies match the camera and filter
+reference-size-differs = { $path } is { $width }x{ $height }, but the image is { $image-width }x{ $image-height }; the reference frames must be registered to it
+reference-score = { $line } against the reference: correlation { $correlation }, RMS { $rms } after scaling the reference by { $scale }
+post-processing = Post-processing the outputs: { $stages }
+done = Done!
+
+## Writing the outputs
+
+wrote = Wrote { $path }
+error-writing = Error writing { $path }: { $message }
+error-creating = Error creating { $path }: { $message }
+wrote-siril-script = Wrote Siril script to { $path }
+error-writing-siril-script = Error writing Siril script: { $message }
+wrote-report = Wrote report to { $path }
+error-writing-report = Error writing report: { $message }
+error-writing-fits-stream = Error writing FITS stream: { $message }
+stdout-flush-failed = Failed to flush stdout: { $error }
+stdout-dup-failed = Failed to duplicate stdout: { $error }
+stdout-redirect-failed = Failed to redirect stdout to stderr: { $error }
+stdout-unix-only = writing to stdout with --output - is only supported on Unix
+error-writing-h-alpha = Error writing H-alpha file: { $message }
+error-writing-oiii = Error writing OIII file: { $message }
+error-writing-composite = Error writing composite file: { $message }
+wrote-preview = Wrote preview to { $path }
+error-writing-preview = Error writing preview: { $message }
+wrote-manifest = Wrote manifest to { $path }
+template-without-line = the naming template "{ $template }" must contain {line}, or both outputs get the same name
+template-unclosed = unclosed placeholder in naming template "{ $template }"
+template-unknown-placeholder = unknown placeholder {"{"}{ $placeholder }} in naming template
+error-writing-manifest = Error writing manifest: { $message }
+running-command = Running { $command }
+empty-command = post-processing command "{ $command }" is empty
+unclosed-single-quote = unclosed ' in "{ $command }"
+unclosed-double-quote = unclosed " in "{ $command }"
+command-not-run = failed to run { $program }: { $error }
+command-failed = { $command } failed with { $status }
+
+## duosplit init
+
+init-intro = Setting up a duosplit profile. Press Enter to take the answer in brackets.
+init-no-answer = No answer given; `duosplit init` asks its questions interactively
+init-io-failed = Failed to talk to the terminal: { $error }
+init-choose-number = Enter a number from 1 to { $count }
+init-profile-name = Profile name
+init-which-camera = Which camera took the images?
+init-camera = Camera
+init-camera-not-listed = A camera not listed here
+init-no-cameras = There are no cameras in the database yet, so describe yours.
+init-camera-name = Camera name
+init-quantum-efficiency-chart = The quantum efficiencies are on the sensor's response chart, as fractions of 1.
+init-quantum-efficiency = Quantum efficiency of the { $channel } channel at { $line }
+init-enter-fraction = Enter a number from 0 to 1, such as 0.8 for 80%
+init-red = red
+init-green = green
+init-blue = blue
+init-h-alpha = H-alpha (656.3 nm)
+init-oiii = OIII (500.7 nm)
+init-which-filter = Which filter was in front of it?
+init-filter = Filter
+init-no-filter = No dual-band filter
+init-optics = JSON transmission curve of a telescope or corrector (Enter for none or no more)
+init-cannot-use = Can't use { $path }: { $message }
+init-added-profile = Added profile { $name } in { $path }
+init-updated-profile = Updated profile { $name } in { $path }
+init-usage = Split an image with it using: duosplit image.fit --profile { $name }
+
+## GPU
+
+gpu-no-backends-left = No GPU backends left to try
+gpu-no-software-adapter = Failed to find a GPU or software adapter: { $message }
+gpu-no-adapter = Failed to find a GPU adapter: { $message }
+gpu-using = Using GPU: { $name } ({ $backend })
+gpu-request-device-failed = Failed to request GPU device: { $message }
+gpu-device-lost = GPU device lost ({ $reason }): { $message }
+gpu-error = GPU error: { $message }
+gpu-kernels-failed = Failed to build the GPU kernels: { $message }
+gpu-composite-kernel-failed = Failed to build the composite kernel: { $message }
+gpu-moments-kernel-failed = Failed to build the moments kernel: { $message }
+gpu-moments-chunks = {"  "}Moments chunks: { $chunks }
+gpu-chunk-too-large = Image chunk size exceeds maximum buffer size for the GPU adapter. You must increase the chunk amount in order to process the image
+gpu-pipeline-cache-not-saved = Failed to save pipeline cache: { $message }
+gpu-adapter = Adapter: { $name } ({ $backend }, driver { $driver } { $driver-info })
+gpu-no-other-backend = No other backend has an adapter; try updating the GPU driver, or use --cpu
+gpu-try-backend = Try another backend: { $options }
+gpu-map-retry = { $message }; retrying in { $pieces } pieces
+gpu-poll-failed = Failed to poll device: { $message }
+gpu-map-failed = Failed to map buffer: { $message }
+
+# Printed by --gpu-info
+gpu-info-adapter = GPU adapter: { $name } ({ $backend }, { $device-type })
+gpu-info-driver = {"  "}Driver: { $driver } { $driver-info }
+gpu-info-limits = {"  "}Limits:
+gpu-info-max-buffer-size = {"    "}Max buffer size: { $size }
+gpu-info-max-storage-binding = {"    "}Max storage buffer binding size: { $size }
+gpu-info-max-uniform-binding = {"    "}Max uniform buffer binding size: { $size }
+gpu-info-max-workgroup-size = {"    "}Max workgroup size: { $x } x { $y } x { $z }
+gpu-info-max-invocations = {"    "}Max invocations per workgroup: { $count }
+gpu-info-max-workgroups = {"    "}Max workgroups per dimension: { $count }
+gpu-info-features = {"  "}Features:
+gpu-info-f16 = {"    "}f16 shaders: { $available }
+gpu-info-timestamp-queries = {"    "}Timestamp queries: { $available }
+gpu-info-subgroups = {"    "}Subgroups: { $available }
+gpu-info-pipeline-cache = {"    "}Pipeline cache: { $available }
+gpu-info-mappable-buffers = {"    "}Mappable storage buffers: { $available }
+gpu-info-used = {"  "}duosplit will use:
+gpu-info-subgroup-moments = {"    "}Subgroup moments reduction: { $enabled }
+gpu-info-disk-cache = {"    "}On-disk pipeline cache: { $enabled }
+gpu-info-direct-readback = {"    "}Readback without staging copies: { $enabled }
+gpu-info-yes = yes
+gpu-info-no = no
+gpu-info-on = on
+gpu-info-off = off
+
+## Reading FITS
+
+fits-open-failed = Failed to open FITS file { $path }: { $error }
+fits-read-failed = Failed to read FITS file { $path }: { $error }
+fits-invalid = { $path }: { $message }
+fits-no-hdu = No HDU found in FITS file
+fits-no-hdu-in = No HDU found in FITS file { $path }
+fits-missing-key = Missing { $key } in FITS header
+fits-bad-value = Unexpected { $key } value { $value }
+fits-bad-bitpix = Unexpected BITPIX value { $bitpix }
+fits-too-many-axes = Expected an image of up to three axes, found { $axes }
+fits-truncated = FITS data is shorter than its header says
+fits-precision-loss = Converting FITS data from 64 bit to 32 bit; this may lose precision.
+fits-too-few-planes = Expected three colour planes, found { $planes }
+fits-row-not-interleaved = Expected interleaved RGB rows, but NAXIS1 { $row } isn't a multiple of 3
+fits-guessed-interleaved = The image has no NAXIS3 but its rows look like interleaved RGB pixels, so it is read as such; pass --interleaved if that's right.
+fits-interleaved-ignored = --interleaved only applies to single-plane images, so it is ignored for this cube of colour planes.
+fits-single-plane = Expected a three-dimensional RGB image, found a single plane; pass --interleaved if its rows hold interleaved RGB pixels
+fits-wrong-axes = Expected a three-dimensional RGB image, found { $axes } axes
+fits-not-mono = Expected a single-channel image, found axes { $axes }
+fits-write-hdu-failed = Failed to write the { $name } HDU: { $error }
+fits-flush-failed = Failed to flush the FITS stream: { $error }
+
+## Solving
+
+invalid-solver-settings = Invalid solver settings: { $message }
+error-loading-recording = Error loading recording: { $message }
+error-saving-recording = Error saving recording: { $message }
+setting-up-gpu = Setting up GPU context...
+setting-up-cpu = Setting up CPU context...
+setting-up-cuda = Setting up CUDA context...
+error-setting-up-gpu = Error setting up GPU context: { $message }
+error-setting-up-gpu-strict = Error setting up GPU context: { $message } (--strict forbids falling back to the CPU)
+falling-back-to-cpu = Falling back to the CPU; this will be slower.
+gpu-fallback = set up on the CPU after the GPU failed: { $message }
+cuda-fallback = { $message }; falling back to the other GPU backends
+cuda-using = Using CUDA device: { $name }
+cuda-open-failed = Failed to open CUDA device 0: { $error }
+cuda-compile-failed = Failed to compile the CUDA kernel: { $error }
+cuda-load-failed = Failed to load the CUDA kernel: { $error }
+cuda-upload-failed = Failed to upload to the CUDA device: { $error }
+cuda-library-missing = The { $library } library was not found
+cuda-evaluation-failed = CUDA fitness evaluation failed: { $message }
+batch-generations-ignored = Niching and coefficient bounds breed genomes on the CPU; ignoring --batch-generations.
+seed-population-ignored = Bayesian optimization has no population to seed; ignoring --seed-population.
+starting-ga = Starting genetic algorithm optimization...
+starting-bayes = Starting Bayesian optimization...
+best-noise = Best genome found with noise: { $noise }
+evaluation = Evaluation { $evaluation }: { $noise }
+recorded-run = Recorded run with seed { $seed } to { $path }
+run-matches-recording = Run matches the recording bit for bit
+run-differs-from-recording = Run does not match the recording: { $difference }
+recorded-settings-differ = settings differ: recorded seed { $seed }, { $evaluator } evaluator, { $optimizer } optimizer ({ $evaluations } evaluations), population { $population }, { $generations } generations, elitism { $elitism }, initial std { $initial-std }, decay rate { $decay-rate } ({ $decay-schedule } schedule), { $selection } selection, niche radius { $niche-radius }, bounds { $bounds }, batch { $batch }, { $init-distribution } initialization over ±{ $init-range }
+recorded-generation-differs = generation { $generation } differs: recorded i = { $recorded-i }, x = { $recorded-x }, fitness = { $recorded-noise }; got i = { $i }, x = { $x }, fitness = { $noise }
+recorded-generations-differ = recorded { $recorded } generations, got { $generations }
+wrote-snapshots = Wrote { $count } snapshots to { $path }
+error-writing-snapshots = Error writing snapshots: { $message }
+did-not-converge = Error: the optimizer did not converge: { $reason }
+no-finite-noise = no coefficients gave a finite noise
+noise-above-max = the best noise { $noise } is above --max-noise { $max-noise }
+spread-above-max = the final population still spreads i ± { $i-spread }, x ± { $x-spread }, above --max-spread { $max-spread }
+swap-check = Swap check: { $reason }
+swap-check-kept = the quantum efficiencies predict red coefficients of { $predicted-h-alpha } for H-alpha and { $predicted-oiii } for OIII; the solver found { $h-alpha } and { $oiii }, which match the prediction
+swap-check-swapped = the quantum efficiencies predict red coefficients of { $predicted-h-alpha } for H-alpha and { $predicted-oiii } for OIII; the solver found { $h-alpha } and { $oiii }, which only match the prediction swapped
+suspected-swap-swapped = { $reason }; swapped them back
+suspected-swap-kept = { $reason }; kept them as found for --no-swap
+swapping-coefficients = Swapping the H-alpha and OIII coefficients
+
+## Run summary
+
+summary = Run summary:
+summary-input = {"  "}Input: { $path } ({ $width }x{ $height }, { $bit-depth })
+summary-unknown-bit-depth = unknown bit depth
+summary-qe = {"  "}Quantum efficiencies:
+summary-qe-camera = {"  "}Quantum efficiencies ({ $camera }):
+summary-qe-filter = {"  "}Quantum efficiencies (through { $filter }):
+summary-qe-camera-filter = {"  "}Quantum efficiencies ({ $camera } through { $filter }):
+summary-red = Red
+summary-green = Green
+summary-blue = Blue
+summary-balmer-decrement = {"  "}H-beta counted with H-alpha at a Balmer decrement of { $decrement }
+summary-ga = {"  "}Optimizer: genetic algorithm, population { $population }, { $generations } generations, initial std { $initial-std }, { $decay-schedule } decay { $decay-rate }, { $selection } selection
+summary-bayes = {"  "}Optimizer: Bayesian optimization, { $evaluations } evaluations
+summary-evaluator = {"  "}Fitness evaluation: { $evaluator }
+summary-cpu = CPU
+summary-cuda = CUDA, falling back to the GPU
+summary-hybrid = GPU and CPU
+summary-gpu = GPU, falling back to the CPU
+summary-seed = {"  "}Seed: { $seed }
+summary-random-seed = {"  "}Seed: random
+summary-background = {"  "}Background: subtracted on a { $grid }x{ $grid } grid
+red-prefers-oiii = the red channel is more sensitive to OIII than to H-alpha; check that the H-alpha and OIII values aren't swapped
+blue-prefers-h-alpha = the blue channel is more sensitive to H-alpha than to OIII; check that the H-alpha and OIII values aren't swapped
+inseparable-lines = the green and blue channels respond to both lines in the same ratio, so the lines can't be separated
+
+## Optimizing
+
+population-too-small = the population must have at least 2 individuals
+elitism-too-large = elitism ({ $elitism }) must be less than the population size ({ $population })
+niche-radius-not-positive = the niche radius must be positive
+coefficient-bounds-reversed = the coefficient minimum must be below the maximum
+init-range-not-positive = the initialization range must be positive
+seed-population-too-large = the seed population has { $genomes } genomes, more than the population of { $population }
+seed-genome-not-finite = { $path } has a genome with i = { $i }, x = { $x }; both must be finite
+generation = Generation { $generation }: { $noise }
+generation-took = Generation { $generation } took { $duration }
+generations-took = Generations { $first } to { $last } took { $duration }
+final-spread = Final population spread: i ± { $i-spread }, x ± { $x-spread }
+resuming-from-generation = Resuming from generation { $generation }
+gpu-not-recreated = Error: could not recreate the GPU context
+gpu-not-recreated-strict = Error: could not recreate the GPU context (--strict forbids falling back to the CPU)
+gpu-lost-too-often = Lost the GPU device { $times } times; giving up on the GPU.
+recreating-gpu = Recreating the GPU context...
+error-recreating-gpu = Error recreating GPU context: { $message }
+gpu-lost-fallback = fell back to the CPU after losing the GPU device
+readback-failed-using-cpu = { $message }; evaluating this generation on the CPU
+readback-fallback = evaluated a generation on the CPU after the GPU readback failed: { $message }
+
+## Exporting to PixInsight and Siril
+
+pixelmath = PixInsight PixelMath (apply to the RGB image with "Create new image" and a grayscale output):
+pixelmath-h-alpha = {"  "}H-alpha: { $expression }
+pixelmath-oiii = {"  "}OIII:    { $expression }
+siril-commands = Siril (run with the RGB image loaded):
+no-exptime = { $path } has no positive EXPTIME
+invalid-sequence = Invalid sequence { $sequence }
+no-sequence-frames = No frames of sequence { $sequence } found
+
+## Cameras and filters
+
+invalid-camera-database = Invalid camera database { $path }: { $message }
+camera-without-name = a camera has an empty name
+camera-qe-out-of-range = { $camera } has a { $channel } quantum efficiency of { $qe }, outside 0 to 1
+camera-curve-invalid = { $camera }'s { $channel } curve is invalid: { $message }
+unknown-camera = Unknown camera { $camera }; see `duosplit cameras list`, or add it with `duosplit cameras add`
+no-cameras = No cameras yet; add one with `duosplit cameras add` or `duosplit cameras import`
+camera = { $camera }: H-alpha { $h-alpha-red } { $h-alpha-green } { $h-alpha-blue }, OIII { $oiii-red } { $oiii-green } { $oiii-blue }
+camera-with-curves = { $camera }: H-alpha { $h-alpha-red } { $h-alpha-green } { $h-alpha-blue }, OIII { $oiii-red } { $oiii-green } { $oiii-blue } (with QE curves)
+imported-cameras = Imported { $added } new and { $updated } updated cameras into { $path }
+added-camera = Added { $camera } in { $path }
+updated-camera = Updated { $camera } in { $path }
+invalid-filter-database = Invalid filter database { $path }: { $message }
+filter-without-name = a filter has an empty name
+filter-without-passbands = { $filter } has no passbands
+passband-not-positive = { $filter } has a passband at { $center } nm, { $bandwidth } nm wide; both must be positive
+transmission-out-of-range = { $filter } has a transmission of { $transmission }, outside 0 to 1
+unknown-filter = Unknown filter { $filter }; see `duosplit filters list`, or import it with `duosplit filters import`
+filter = { $filter }: { $passbands }
+built-in-filter = { $filter } (built in): { $passbands }
+passband = { $center } nm ({ $bandwidth } nm wide, { $transmission }%)
+imported-filters = Imported { $added } new and { $updated } updated filters into { $path }
+
+## Composites
+
+composite-no-assignment = expected CHANNEL=EXPR, found "{ $found }"
+composite-unknown-channel = unknown channel { $channel }, expected R, G or B
+composite-channel-twice = channel { $channel } is given twice
+composite-missing-channel = expected an expression for each of R, G and B
+composite-unexpected = unexpected { $token }
+composite-unexpected-in = unexpected { $token } in "{ $expression }"
+composite-invalid-number = invalid number { $number }
+composite-unknown-name = unknown name { $name }, expected ha, oiii, min or max
+composite-ends-too-soon = the expression ends too soon
+composite-expected = expected '{ $symbol }', found { $token }
+composite-expected-at-end = expected '{ $symbol }' at the end
+
+## duosplit compare
+
+compare-noise = { $line }: noise { $noise-a } (A) vs { $noise-b } (B), { $negative-a }% vs { $negative-b }% negative pixels
+compare-difference = { $line }: B - A has mean { $mean }, RMS { $rms }, largest { $largest }; correlation { $correlation }
+error-writing-difference = Error writing difference image: { $message }
+
+## duosplit tune
+
+tuning-on = Tuning on a { $width }x{ $height } copy of the image...
+image-smaller-than-downsampling = Error: the image is smaller than the downsampling factor
+skipping-population = Skipping population { $population }: { $message }
+pilot-result = Population { $population }, initial std { $initial-std }, decay rate { $decay-rate }: mean noise { $noise }
+no-pilot-run = Error: no pilot run could be started with these settings
+recommended-settings = Recommended settings (mean pilot noise { $noise }): --population-size { $population } --initial-std { $initial-std } --decay-rate { $decay-rate }
+
+## Sensor and filter response
+
+curve-empty = the curve has no points
+curve-not-increasing = the curve's wavelengths don't increase
+curve-out-of-range = the curve is { $value } at { $wavelength } nm, outside 0 to 1
+invalid-curve = Invalid curve { $path }: { $message }
+filter-blocks-line = { $filter } blocks { $line }
+nii-merged = { $filter } passes NII with H-alpha, so the H-alpha output includes it
+nii-blocked = { $filter } resolves NII from H-alpha and blocks it
+nii-removed = Keeping the NII { $filter } passes out of H-alpha, at { $ratio } times the H-alpha flux
+nii-kept = { $filter } passes NII separately from H-alpha; without --nii-ratio the H-alpha output includes it
+
+## --timings
+
+timings = Performance summary:
+timings-read = {"  "}FITS read: { $duration }
+timings-gpu-setup = {"  "}GPU setup and image upload: { $duration }
+timings-cpu-setup = {"  "}CPU setup: { $duration }
+timings-gpu-kernels = {"  "}GPU kernels: { $duration } ({ $per-generation } per generation)
+timings-gpu-readback = {"  "}GPU readback: { $duration }
+timings-ga-cpu = {"  "}Genetic algorithm on the CPU: { $duration }
+timings-apply-gpu = {"  "}Applying coefficients on the GPU: { $duration }
+timings-apply-cpu = {"  "}Applying coefficients on the CPU: { $duration }
+timings-write = {"  "}FITS write: { $duration }
+
+## Profiles
+
+invalid-profile-database = Invalid profile database { $path }: { $message }
+profile-without-name = a profile has an empty name
+profile-stray-argument = profile { $profile } has the argument { $argument }, which isn't an option or its value
+unknown-profile = Unknown profile { $profile }; profiles are kept in { $database } in the duosplit config directory
+without-unknown-option = --without { $option }: no such option
+profile-unknown-option = profile { $profile } has the unknown option { $option }
+
+## duosplit bench
+
+generating-image = Generating { $width }x{ $height } synthetic image...
+generated-in = Generated in { $duration }
+error-writing-synthetic = Error writing synthetic FITS file: { $message }
+bench-results = Benchmark results:
+bench-image = {"  "}Image: { $width }x{ $height } ({ $pixels } pixels)
+bench-evaluated = {"  "}Evaluated { $genomes } genomes in { $duration } ({ $rate } genomes/s)
+bench-reference = {"  "}Reference coefficients: i = { $i }, x = { $x }
+bench-recovered = {"  "}Recovered coefficients: i = { $i }, x = { $x }
+bench-error = {"  "}Coefficient error: i = { $i }, x = { $x }
+bench-excess-noise = {"  "}Noise above the optimum: { $percent }%
+
+## Notifications
+
+notification-split = Split { $input } in { $seconds } s
+notification-failed = { $input } failed: { $message }
+notification-not-shown = Failed to show desktop notification: { $message }
+webhook-failed = Failed to call webhook { $url }: { $error }
+notifier-not-run = failed to run { $program }: { $error }
+notifier-failed = the notifier exited with { $status }
+
+## Mosaics
+
+invalid-mosaic = Invalid mosaic { $path }: { $message }
+mosaic-without-panels = the mosaic has no panels
+negative-feather = the feather can't be negative
+empty-panel = panel { $panel } is empty
+panel-outside-image = panel { $panel } reaches past the { $width }x{ $height } image
+pixel-outside-panels = pixel ({ $x }, { $y }) isn't in any panel
+
+## duosplit selftest
+
+selftest = Self-test: { $sensor } sensor, seed { $seed }
+selftest-passed = PASS: coefficient error { $coefficient-error }, unmixing error { $unmixing-error }
+selftest-failed = FAIL: coefficient error { $coefficient-error }, unmixing error { $unmixing-error } (expected i = { $expected-i }, x = { $expected-x }; got i = { $i }, x = { $x })
+selftests-failed = { $failures } of { $total } self-tests failed
+selftests-passed = All { $total } self-tests passed
+
+## Option values
+
+# Shown by the command line parser after its own message about the option
+expected-number = expected a number
+expected-elitism = expected a count or a fraction of the population
+elitism-fraction-out-of-range = fractions must be at least 0 and less than 1
+airmass-below-one = the airmass is at least 1, at the zenith
+balmer-decrement-not-positive = the Balmer decrement must be positive
+negative-nii-ratio = the NII ratio can't be negative
+expected-reference-frames = expected the H-alpha and OIII frames separated by a comma
+invalid-multiplier = invalid multiplier: { $error }
+expected-white-balance = expected R,G,B or auto
+multipliers-not-positive = multipliers must be positive
+expected-stretch = expected gamma:G or asinh:B
+invalid-value = invalid { $kind } value: { $error }
+value-not-positive = the { $kind } value must be positive
+unknown-stretch = unknown stretch { $stretch }, expected gamma or asinh
+unknown-line = unknown line { $line }, expected ha or oiii
+asinh-strength-not-positive = the asinh strength must be positive
+mtf-target-out-of-range = the mtf target must be between 0 and 1
+expected-preview-stretch = expected auto, asinh:B or mtf:T, optionally after ha= or oiii=
+invalid-pedestal = invalid pedestal: { $error }
+expected-stage = expected clip, pedestal:P, rescale or stretch:gamma:G|asinh:B
+expected-size = expected WIDTHxHEIGHT
+invalid-width = invalid width: { $error }
+invalid-height = invalid height: { $error }
+size-not-positive = width and height must be positive
+
+## Reading PNG, SER and BMP
+
+stdin-read-failed = Failed to read FITS stream from stdin: { $error }
+input-open-failed = Failed to open input file: { $error }
+frame-ignored = --frame only applies to SER sequences, so it is ignored for this { $format } image.
+invalid-png = Invalid PNG: { $error }
+png-too-large = The PNG is too large to decode
+png-not-rgb = Expected an RGB PNG, found { $color-type }; debayer mono sensor data before splitting it
+ser-truncated-header = The SER file ends inside its header
+ser-mono = The SER file is mono; duosplit needs RGB frames
+ser-raw = The SER file holds raw sensor data (colour id { $color }); debayer it to RGB first, as Siril's conversion does
+invalid-ser-header = Invalid SER header: { $width }x{ $height } pixels of { $depth } bits, { $frames } frames
+ser-needs-frame = The SER file holds { $frames } frames; pick the one to split with --frame
+frame-past-end = --frame { $frame } is past the end of the SER file, which holds { $frames } frames
+ser-truncated-frame = The SER file ends inside frame { $frame }
+bmp-truncated-header = The BMP file ends inside its header
+bmp-unsupported = Expected an uncompressed 24 or 32-bit RGB BMP, found { $bits } bits per pixel; save it as PNG or FITS instead
+invalid-bmp-header = Invalid BMP header: { $width }x{ $height } pixels of { $bits } bits
+bmp-truncated-pixels = The BMP file ends inside its pixels
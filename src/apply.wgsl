@@ -0,0 +1,26 @@
+// Combines the channels of every pixel with the solved coefficients, so the outputs are
+// computed from the image already on the GPU and only they are copied back
+
+struct Coefficients {
+    // Red, green and blue coefficients; w is padding
+    h_alpha: vec4f,
+    oiii: vec4f,
+}
+
+// Tightly packed RGB triplets of the pixels in this batch
+@group(0) @binding(0) var<storage, read> image: array<f32>;
+// H-alpha and OIII value of each pixel
+@group(0) @binding(1) var<storage, read_write> lines: array<vec2f>;
+@group(0) @binding(2) var<uniform> coefficients: Coefficients;
+
+override workgroup_x: u32 = 256u;
+
+@compute @workgroup_size(workgroup_x)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let idx = gid.x;
+    if (idx >= arrayLength(&lines)) {
+        return;
+    }
+    let pixel = vec3f(image[3u * idx], image[3u * idx + 1u], image[3u * idx + 2u]);
+    lines[idx] = vec2f(dot(coefficients.h_alpha.xyz, pixel), dot(coefficients.oiii.xyz, pixel));
+}
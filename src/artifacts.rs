@@ -0,0 +1,190 @@
+use crate::cli::{SolverArgs, SplitArgs};
+use crate::messages::text;
+use serde::Serialize;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MANIFEST: &str = "manifest.json";
+
+/// The directory `--artifacts` collects one run's files in, named after when the run
+/// started and its input
+pub struct Artifacts {
+    dir: PathBuf,
+    started: String,
+}
+
+/// Written last into the run's directory, listing what the run produced
+#[derive(Serialize)]
+struct Manifest<'a> {
+    version: &'a str,
+    started: &'a str,
+    finished: String,
+    arguments: Vec<String>,
+    input: String,
+    input_sha256: &'a str,
+    h_alpha_coefficients: [f32; 3],
+    oiii_coefficients: [f32; 3],
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize)]
+struct ManifestFile {
+    path: String,
+    bytes: u64,
+}
+
+impl Artifacts {
+    /// Creates a new directory for the run under `root`, numbering it if a run of the same
+    /// input started in the same second
+    pub fn create(root: &Path, input: &Path) -> Result<Self, String> {
+        fs::create_dir_all(root).map_err(|err| {
+            text(
+                "create-failed",
+                &[("path", &root.display()), ("error", &err)],
+            )
+        })?;
+        let started = timestamp(SystemTime::now());
+        let stem = input
+            .file_stem()
+            .filter(|_| input != Path::new("-"))
+            .map_or("stdin".into(), |stem| stem.to_string_lossy());
+        let name = format!("{}-{}", started, stem);
+        let mut dir = root.join(&name);
+        let mut attempt = 1;
+        loop {
+            match fs::create_dir(&dir) {
+                Ok(()) => return Ok(Self { dir, started }),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    attempt += 1;
+                    dir = root.join(format!("{}-{}", name, attempt));
+                }
+                Err(err) => {
+                    return Err(text(
+                        "create-failed",
+                        &[("path", &dir.display()), ("error", &err)],
+                    ))
+                }
+            }
+        }
+    }
+
+    /// The arguments with the outputs, the report and the recording pointed into the run's
+    /// directory; a report or recording given its own path is left where it was asked for
+    pub fn redirect(&self, cli: &SplitArgs, solver: &SolverArgs) -> (SplitArgs, SolverArgs) {
+        let mut cli = cli.clone();
+        cli.output = self.dir.clone();
+        cli.report
+            .get_or_insert_with(|| self.dir.join("report.json"));
+        let mut solver = solver.clone();
+        // A mosaic solves each panel separately, so there is no single run to record
+        if solver.verify.is_none() && cli.mosaic.is_none() {
+            solver
+                .record
+                .get_or_insert_with(|| self.dir.join("run.json"));
+        }
+        (cli, solver)
+    }
+
+    /// Lists every file in the run's directory in `manifest.json`, returning its path
+    pub fn write_manifest(
+        &self,
+        input: &Path,
+        input_sha256: &str,
+        h_alpha_coefficients: [f32; 3],
+        oiii_coefficients: [f32; 3],
+    ) -> Result<PathBuf, String> {
+        let path = self.dir.join(MANIFEST);
+        let manifest = Manifest {
+            version: env!("CARGO_PKG_VERSION"),
+            started: &self.started,
+            finished: timestamp(SystemTime::now()),
+            arguments: std::env::args().collect(),
+            input: input.display().to_string(),
+            input_sha256,
+            h_alpha_coefficients,
+            oiii_coefficients,
+            files: self.files()?,
+        };
+        let json = serde_json::to_string_pretty(&manifest).expect("manifests always serialize");
+        fs::write(&path, json).map_err(|err| {
+            text(
+                "write-failed",
+                &[("path", &path.display()), ("error", &err)],
+            )
+        })?;
+        Ok(path)
+    }
+
+    fn files(&self) -> Result<Vec<ManifestFile>, String> {
+        let read_error = |err: std::io::Error| {
+            text(
+                "read-failed",
+                &[("path", &self.dir.display()), ("error", &err)],
+            )
+        };
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir).map_err(read_error)? {
+            let entry = entry.map_err(read_error)?;
+            let metadata = entry.metadata().map_err(read_error)?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if metadata.is_file() && name != MANIFEST {
+                files.push(ManifestFile {
+                    path: name,
+                    bytes: metadata.len(),
+                });
+            }
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+}
+
+/// A compact UTC timestamp such as `20261016T171519Z`, which sorts in time order and is
+/// safe in file names
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (days, time) = (seconds / 86400, seconds % 86400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// The Gregorian date `days` after 1970-01-01, after Howard Hinnant's `civil_from_days`
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months counted from March, so the leap day comes last
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    (era * 400 + year_of_era + i64::from(month <= 2), month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn timestamps_are_utc_dates() {
+        let at = |seconds| timestamp(UNIX_EPOCH + Duration::from_secs(seconds));
+        assert_eq!(at(0), "19700101T000000Z");
+        assert_eq!(at(951782400), "20000229T000000Z");
+        assert_eq!(at(1792170919), "20261016T171519Z");
+    }
+}
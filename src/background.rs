@@ -0,0 +1,81 @@
+use crate::cli::SolverArgs;
+
+/// Coarse estimate of the sky background: the per-channel median of each cell of a grid
+/// laid over the image, interpolated bilinearly between cell centres. Subtracting it
+/// before computing the moments stops gradients and vignetting from biasing the fit.
+pub struct Background {
+    pub width: usize,
+    pub height: usize,
+    pub cols: usize,
+    pub rows: usize,
+    // Row-major cell medians, padded to RGBA to upload as a texture
+    pub cells: Vec<[f32; 4]>,
+}
+
+impl Background {
+    /// Estimates the background if `args` asks for it to be subtracted
+    pub fn from_args(image: &[[f32; 3]], width: usize, args: &SolverArgs) -> Option<Self> {
+        args.subtract_background
+            .then(|| Self::estimate(image, width, args.background_grid as usize))
+    }
+
+    pub fn estimate(image: &[[f32; 3]], width: usize, grid: usize) -> Self {
+        let height = image.len() / width;
+        let cols = grid.clamp(1, width);
+        let rows = grid.clamp(1, height);
+        let mut cells = Vec::with_capacity(cols * rows);
+        let mut values = Vec::new();
+        for row in 0..rows {
+            let (y0, y1) = (row * height / rows, (row + 1) * height / rows);
+            for col in 0..cols {
+                let (x0, x1) = (col * width / cols, (col + 1) * width / cols);
+                let mut cell = [0.0; 4];
+                for (channel, median) in cell.iter_mut().take(3).enumerate() {
+                    values.clear();
+                    for y in y0..y1 {
+                        values.extend(
+                            image[y * width + x0..y * width + x1]
+                                .iter()
+                                .map(|p| p[channel]),
+                        );
+                    }
+                    let middle = values.len() / 2;
+                    *median = *values.select_nth_unstable_by(middle, f32::total_cmp).1;
+                }
+                cells.push(cell);
+            }
+        }
+        Self {
+            width,
+            height,
+            cols,
+            rows,
+            cells,
+        }
+    }
+
+    /// Mirrors `background_at` in the moments shaders
+    pub fn at(&self, idx: usize) -> [f32; 3] {
+        let (x, y) = (
+            (idx % self.width) as f32 + 0.5,
+            (idx / self.width) as f32 + 0.5,
+        );
+        let gx =
+            (x * self.cols as f32 / self.width as f32 - 0.5).clamp(0.0, (self.cols - 1) as f32);
+        let gy =
+            (y * self.rows as f32 / self.height as f32 - 0.5).clamp(0.0, (self.rows - 1) as f32);
+        let (c0, r0) = (gx.floor() as usize, gy.floor() as usize);
+        let (c1, r1) = ((c0 + 1).min(self.cols - 1), (r0 + 1).min(self.rows - 1));
+        let (tx, ty) = (gx - gx.floor(), gy - gy.floor());
+        let cell = |col: usize, row: usize| self.cells[row * self.cols + col];
+        std::array::from_fn(|channel| {
+            let top = lerp(cell(c0, r0)[channel], cell(c1, r0)[channel], tx);
+            let bottom = lerp(cell(c0, r1)[channel], cell(c1, r1)[channel], tx);
+            lerp(top, bottom, ty)
+        })
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
@@ -0,0 +1,292 @@
+use crate::evaluator::Evaluator;
+use crate::genetics::Genome;
+use crate::gpu::BestGenome;
+use crate::messages::text;
+use crate::normal_distr::standard_normal_cdf;
+use crate::optimizer::Outcome;
+use rand::Rng;
+
+// Half-width of the square of (i, x) searched, unless coefficient bounds narrow it
+const BOUND: f64 = 5.0;
+// Random points evaluated before the surrogate takes over
+const INITIAL_POINTS: usize = 10;
+// Random candidates scored by expected improvement per evaluation
+const CANDIDATES: usize = 4000;
+// Share of the candidates drawn close to the best point so far rather than across the square
+const LOCAL_SHARE: f64 = 0.5;
+// Length scales tried when fitting the kernel, relative to the searched half-width
+const LENGTH_SCALES: [f64; 8] = [0.02, 0.05, 0.1, 0.2, 0.4, 0.8, 1.6, 3.2];
+// Noise variance added to the kernel's diagonal, relative to the standardized targets
+const JITTER: f64 = 1e-6;
+
+/// Gaussian process regression over (i, x) with a squared exponential kernel, fitted to the
+/// standardized log fitness of the evaluated points
+struct Surrogate<'a> {
+    points: &'a [[f64; 2]],
+    length_scale: f64,
+    // Lower Cholesky factor of the kernel matrix
+    factor: Vec<f64>,
+    // Kernel matrix inverse applied to the targets
+    weights: Vec<f64>,
+}
+
+impl<'a> Surrogate<'a> {
+    /// Fits the surrogate, picking the length scale with the highest marginal likelihood
+    fn fit(points: &'a [[f64; 2]], targets: &[f64], half_width: f64) -> Self {
+        LENGTH_SCALES
+            .iter()
+            .filter_map(|&scale| {
+                let length_scale = scale * half_width;
+                let n = points.len();
+                let mut kernel = vec![0.0; n * n];
+                for row in 0..n {
+                    for col in 0..n {
+                        kernel[row * n + col] = covariance(points[row], points[col], length_scale);
+                    }
+                    kernel[row * n + row] += JITTER;
+                }
+                let factor = cholesky(&kernel, n)?;
+                let weights = solve_upper(&factor, n, &solve_lower(&factor, n, targets));
+                let fit = targets
+                    .iter()
+                    .zip(&weights)
+                    .map(|(y, w)| y * w)
+                    .sum::<f64>();
+                let complexity = (0..n).map(|d| factor[d * n + d].ln()).sum::<f64>();
+                let likelihood = -0.5 * fit - complexity;
+                Some((
+                    likelihood,
+                    Self {
+                        points,
+                        length_scale,
+                        factor,
+                        weights,
+                    },
+                ))
+            })
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, surrogate)| surrogate)
+            .expect("the jittered kernel is positive definite for some length scale")
+    }
+
+    /// Predicted mean and standard deviation of the standardized log fitness at `point`
+    fn predict(&self, point: [f64; 2]) -> (f64, f64) {
+        let n = self.points.len();
+        let cross = self
+            .points
+            .iter()
+            .map(|&other| covariance(point, other, self.length_scale))
+            .collect::<Vec<_>>();
+        let mean = cross.iter().zip(&self.weights).map(|(k, w)| k * w).sum();
+        let v = solve_lower(&self.factor, n, &cross);
+        let variance = 1.0 + JITTER - v.iter().map(|v| v * v).sum::<f64>();
+        (mean, variance.max(0.0).sqrt())
+    }
+}
+
+/// Minimizes the fitness with Bayesian optimization, for images so large that each
+/// evaluation is expensive: after a few random points, each of the remaining `evaluations`
+/// goes to the point of highest expected improvement under a Gaussian process surrogate.
+/// `progress` prints the best noise after each evaluation.
+pub fn bayes_optimized_genome(
+    evaluations: u32,
+    bounds: Option<(f32, f32)>,
+    progress: bool,
+    evaluator: &dyn Evaluator,
+    rng: &mut impl Rng,
+) -> Outcome {
+    let (low, high) = search_range(bounds);
+    let random_point =
+        |rng: &mut _| -> [f64; 2] { [(); 2].map(|_| Rng::random_range(rng, low..high)) };
+    let genome = |[i, x]: [f64; 2]| Genome {
+        i: i as f32,
+        x: x as f32,
+    };
+
+    let initial = INITIAL_POINTS.min(evaluations as usize);
+    let mut points = (0..initial).map(|_| random_point(rng)).collect::<Vec<_>>();
+    let mut fitnesses = evaluator.evaluate(&points.iter().copied().map(genome).collect::<Vec<_>>());
+
+    let mut history = Vec::with_capacity(evaluations as usize);
+    let mut best = 0;
+    for (n, &fitness) in fitnesses.iter().enumerate() {
+        if fitness < fitnesses[best] {
+            best = n;
+        }
+        history.push(BestGenome {
+            genome: genome(points[best]),
+            fitness: fitnesses[best],
+        });
+        if progress {
+            println!(
+                "{}",
+                text(
+                    "evaluation",
+                    &[("evaluation", &n), ("noise", &fitnesses[best])]
+                )
+            );
+        }
+    }
+
+    for n in initial..evaluations as usize {
+        // The noise spans orders of magnitude, its logarithm is much smoother to model
+        let logs = fitnesses
+            .iter()
+            .map(|&f| (f as f64).max(f64::MIN_POSITIVE).ln())
+            .collect::<Vec<_>>();
+        let mean = logs.iter().sum::<f64>() / logs.len() as f64;
+        let std_dev = (logs.iter().map(|l| (l - mean).powi(2)).sum::<f64>() / logs.len() as f64)
+            .sqrt()
+            .max(1e-12);
+        let targets = logs
+            .iter()
+            .map(|l| (l - mean) / std_dev)
+            .collect::<Vec<_>>();
+        let surrogate = Surrogate::fit(&points, &targets, (high - low) / 2.0);
+        let incumbent = targets[best];
+
+        let local_spread = surrogate.length_scale;
+        let next = (0..CANDIDATES)
+            .map(|c| {
+                if (c as f64) < LOCAL_SHARE * CANDIDATES as f64 {
+                    points[best].map(|v| {
+                        (v + rng.random_range(-local_spread..local_spread)).clamp(low, high)
+                    })
+                } else {
+                    random_point(rng)
+                }
+            })
+            .map(|point| {
+                let (mean, std_dev) = surrogate.predict(point);
+                (expected_improvement(mean, std_dev, incumbent), point)
+            })
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, point)| point)
+            .expect("candidates are never empty");
+
+        let fitness = evaluator.evaluate(&[genome(next)])[0];
+        points.push(next);
+        fitnesses.push(fitness);
+        if fitness < fitnesses[best] {
+            best = n;
+        }
+        history.push(BestGenome {
+            genome: genome(points[best]),
+            fitness: fitnesses[best],
+        });
+        if progress {
+            println!(
+                "{}",
+                text(
+                    "evaluation",
+                    &[("evaluation", &n), ("noise", &fitnesses[best])]
+                )
+            );
+        }
+    }
+
+    Outcome {
+        genome: genome(points[best]),
+        history,
+        spread: None,
+    }
+}
+
+/// Range searched along both axes: the default square, moved or narrowed to fit the bounds
+fn search_range(bounds: Option<(f32, f32)>) -> (f64, f64) {
+    let (min, max) = bounds.map_or((f64::NEG_INFINITY, f64::INFINITY), |(min, max)| {
+        (min as f64, max as f64)
+    });
+    match (min.is_finite(), max.is_finite()) {
+        (true, true) => (min, max),
+        (true, false) => (min, (min + 2.0 * BOUND).max(BOUND)),
+        (false, true) => ((max - 2.0 * BOUND).min(-BOUND), max),
+        (false, false) => (-BOUND, BOUND),
+    }
+}
+
+fn covariance(a: [f64; 2], b: [f64; 2], length_scale: f64) -> f64 {
+    let distance_sq = (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2);
+    (-distance_sq / (2.0 * length_scale * length_scale)).exp()
+}
+
+/// Expected amount by which a point predicted as `mean` ± `std_dev` falls below `incumbent`
+fn expected_improvement(mean: f64, std_dev: f64, incumbent: f64) -> f64 {
+    if std_dev <= 0.0 {
+        return (incumbent - mean).max(0.0);
+    }
+    let z = (incumbent - mean) / std_dev;
+    let pdf = (-0.5 * z * z).exp() / (2.0 * std::f64::consts::PI).sqrt();
+    let cdf = standard_normal_cdf(z);
+    (incumbent - mean) * cdf + std_dev * pdf
+}
+
+/// Lower triangular `l` with `l * l^T` equal to the row-major `n` by `n` matrix, if it is
+/// positive definite
+fn cholesky(matrix: &[f64], n: usize) -> Option<Vec<f64>> {
+    let mut l = vec![0.0; n * n];
+    for row in 0..n {
+        for col in 0..=row {
+            let sum = (0..col)
+                .map(|k| l[row * n + k] * l[col * n + k])
+                .sum::<f64>();
+            if row == col {
+                let diagonal = matrix[row * n + row] - sum;
+                if diagonal <= 0.0 {
+                    return None;
+                }
+                l[row * n + col] = diagonal.sqrt();
+            } else {
+                l[row * n + col] = (matrix[row * n + col] - sum) / l[col * n + col];
+            }
+        }
+    }
+    Some(l)
+}
+
+/// Solves `l * y = b` by forward substitution
+fn solve_lower(l: &[f64], n: usize, b: &[f64]) -> Vec<f64> {
+    let mut y = vec![0.0; n];
+    for row in 0..n {
+        let sum = (0..row).map(|k| l[row * n + k] * y[k]).sum::<f64>();
+        y[row] = (b[row] - sum) / l[row * n + row];
+    }
+    y
+}
+
+/// Solves `l^T * x = y` by back substitution
+fn solve_upper(l: &[f64], n: usize, y: &[f64]) -> Vec<f64> {
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum = (row + 1..n).map(|k| l[k * n + row] * x[k]).sum::<f64>();
+        x[row] = (y[row] - sum) / l[row * n + row];
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::MockEvaluator;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn converges_to_mock_optimum() {
+        let evaluator = MockEvaluator::new(Genome { i: 1.3, x: -0.4 });
+        let best =
+            bayes_optimized_genome(60, None, false, &evaluator, &mut StdRng::seed_from_u64(1))
+                .genome;
+        assert!((best.i - 1.3).abs() < 0.05, "i = {}", best.i);
+        assert!((best.x + 0.4).abs() < 0.05, "x = {}", best.x);
+    }
+
+    #[test]
+    fn cholesky_solves_linear_systems() {
+        let matrix = [4.0, 2.0, 2.0, 3.0];
+        let l = cholesky(&matrix, 2).unwrap();
+        let x = solve_upper(&l, 2, &solve_lower(&l, 2, &[2.0, 1.0]));
+        assert!((4.0 * x[0] + 2.0 * x[1] - 2.0).abs() < 1e-12);
+        assert!((2.0 * x[0] + 3.0 * x[1] - 1.0).abs() < 1e-12);
+    }
+}
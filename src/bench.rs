@@ -0,0 +1,115 @@
+use crate::background::Background;
+use crate::cli::BenchArgs;
+use crate::cpu::image_moments;
+use crate::messages::text;
+use crate::solver;
+use crate::status;
+use crate::synthetic::{Scene, TEST_SENSORS};
+use crate::timings::RunTimings;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::time::Instant;
+
+pub async fn run(args: &BenchArgs) {
+    let (width, height) = args.size;
+    let (_, qe) = TEST_SENSORS[0];
+    println!(
+        "{}",
+        text(
+            "generating-image",
+            &[("width", &width), ("height", &height)]
+        )
+    );
+    let start = Instant::now();
+    let pixels = Scene::default().render(
+        width,
+        height,
+        qe,
+        &mut StdRng::seed_from_u64(args.image_seed),
+    );
+    println!(
+        "{}",
+        text(
+            "generated-in",
+            &[("duration", &format!("{:?}", start.elapsed()))]
+        )
+    );
+
+    let moments = image_moments(
+        &pixels,
+        Background::from_args(&pixels, width, &args.solver).as_ref(),
+    );
+    let reference = moments.optimal_genome(&qe);
+
+    let mut timings = RunTimings::default();
+    let mut warnings = Vec::new();
+    let best = solver::solve(
+        &pixels,
+        width,
+        qe,
+        &args.solver,
+        &mut timings,
+        &mut warnings,
+    )
+    .await;
+    status::record_all(warnings);
+    let best = best.unwrap_or_else(|failure| failure.exit());
+
+    let genomes = args.solver.population_size as f64 * args.solver.generations as f64;
+    let seconds = timings.optimization.as_secs_f64();
+    let excess_noise = moments.fitness(&best, &qe) / moments.fitness(&reference, &qe) - 1.0;
+    println!("{}", text("bench-results", &[]));
+    println!(
+        "{}",
+        text(
+            "bench-image",
+            &[
+                ("width", &width),
+                ("height", &height),
+                ("pixels", &pixels.len()),
+            ],
+        )
+    );
+    println!(
+        "{}",
+        text(
+            "bench-evaluated",
+            &[
+                ("genomes", &genomes),
+                ("duration", &format!("{:?}", timings.optimization)),
+                ("rate", &format!("{:.0}", genomes / seconds)),
+            ],
+        )
+    );
+    println!(
+        "{}",
+        text(
+            "bench-reference",
+            &[("i", &reference.i), ("x", &reference.x)]
+        )
+    );
+    println!(
+        "{}",
+        text("bench-recovered", &[("i", &best.i), ("x", &best.x)])
+    );
+    println!(
+        "{}",
+        text(
+            "bench-error",
+            &[
+                ("i", &format!("{:e}", (best.i - reference.i).abs())),
+                ("x", &format!("{:e}", (best.x - reference.x).abs())),
+            ],
+        )
+    );
+    println!(
+        "{}",
+        text(
+            "bench-excess-noise",
+            &[("percent", &format!("{:.4}", excess_noise * 100.0))]
+        )
+    );
+    if args.solver.timings {
+        timings.print_summary();
+    }
+}
@@ -0,0 +1,299 @@
+use crate::cli::{CameraAddArgs, CamerasArgs, CamerasCommand};
+use crate::database;
+use crate::messages::text;
+use crate::response::{Curve, Sensitivity};
+use crate::status::{ExitCode, Failure};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Quantum efficiencies of the cameras known to `--camera`, kept as JSON in the user's
+/// config directory so community-maintained lists can be imported into it
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDatabase {
+    pub cameras: Vec<Camera>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Camera {
+    pub name: String,
+    pub red: ChannelQe,
+    pub green: ChannelQe,
+    pub blue: ChannelQe,
+    /// Measured quantum efficiency curves, used instead of the single values when present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub curves: Option<SensorCurves>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SensorCurves {
+    pub red: Curve,
+    pub green: Curve,
+    pub blue: Curve,
+}
+
+/// Quantum efficiency of one colour channel at each line
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelQe {
+    pub ha: f32,
+    pub oiii: f32,
+}
+
+impl Camera {
+    /// How sensitive each channel is around each line
+    pub fn sensor(&self) -> [[Sensitivity; 2]; 3] {
+        match &self.curves {
+            Some(curves) => [&curves.red, &curves.green, &curves.blue]
+                .map(|curve| [0, 1].map(|_| Sensitivity::Curve(curve.clone()))),
+            None => [self.red, self.green, self.blue]
+                .map(|qe| [Sensitivity::Flat(qe.ha), Sensitivity::Flat(qe.oiii)]),
+        }
+    }
+}
+
+const DATABASE: &str = "cameras.json";
+
+impl CameraDatabase {
+    /// The user's database, empty if it hasn't been created yet
+    pub fn load() -> Result<Self, Failure> {
+        let Some((path, json)) = database::read(DATABASE)? else {
+            return Ok(Self::default());
+        };
+        Self::parse(&json).map_err(|err| {
+            Failure::new(
+                ExitCode::BadInput,
+                text(
+                    "invalid-camera-database",
+                    &[("path", &path.display()), ("message", &err)],
+                ),
+            )
+        })
+    }
+
+    fn save(&self) -> Result<PathBuf, Failure> {
+        let json = serde_json::to_string_pretty(self).expect("databases always serialize");
+        database::write(DATABASE, &json)
+    }
+
+    /// Parses and validates a database, as stored or as imported
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let database = serde_json::from_str::<Self>(json).map_err(|err| err.to_string())?;
+        for camera in &database.cameras {
+            validate(camera)?;
+        }
+        Ok(database)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Camera> {
+        self.cameras
+            .iter()
+            .find(|camera| camera.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Adds `camera`, replacing any camera of the same name; returns whether one was replaced
+    fn insert(&mut self, camera: Camera) -> bool {
+        match self
+            .cameras
+            .iter_mut()
+            .find(|existing| existing.name.eq_ignore_ascii_case(&camera.name))
+        {
+            Some(existing) => {
+                *existing = camera;
+                true
+            }
+            None => {
+                self.cameras.push(camera);
+                false
+            }
+        }
+    }
+}
+
+fn validate(camera: &Camera) -> Result<(), String> {
+    if camera.name.trim().is_empty() {
+        return Err(text("camera-without-name", &[]));
+    }
+    for (channel, qe) in [
+        (text("channel-red", &[]), camera.red),
+        (text("channel-green", &[]), camera.green),
+        (text("channel-blue", &[]), camera.blue),
+    ] {
+        for value in [qe.ha, qe.oiii] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(text(
+                    "camera-qe-out-of-range",
+                    &[
+                        ("camera", &camera.name),
+                        ("channel", &channel),
+                        ("qe", &value),
+                    ],
+                ));
+            }
+        }
+    }
+    if let Some(curves) = &camera.curves {
+        for (channel, curve) in [
+            (text("channel-red", &[]), &curves.red),
+            (text("channel-green", &[]), &curves.green),
+            (text("channel-blue", &[]), &curves.blue),
+        ] {
+            curve.validate().map_err(|err| {
+                text(
+                    "camera-curve-invalid",
+                    &[
+                        ("camera", &camera.name),
+                        ("channel", &channel),
+                        ("message", &err),
+                    ],
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Looks up a camera for `--camera`
+pub fn find(name: &str) -> Result<Camera, Failure> {
+    let database = CameraDatabase::load()?;
+    database.find(name).cloned().ok_or_else(|| {
+        Failure::new(
+            ExitCode::BadInput,
+            text("unknown-camera", &[("camera", &name)]),
+        )
+    })
+}
+
+pub fn run(args: &CamerasArgs) {
+    if let Err(failure) = run_command(&args.command) {
+        failure
+            .code
+            .exit(text("error", &[("message", &failure.message)]));
+    }
+}
+
+fn run_command(command: &CamerasCommand) -> Result<(), Failure> {
+    match command {
+        CamerasCommand::List => {
+            let database = CameraDatabase::load()?;
+            if database.cameras.is_empty() {
+                println!("{}", text("no-cameras", &[]));
+            }
+            for camera in &database.cameras {
+                let qe: [(&str, &dyn std::fmt::Display); 7] = [
+                    ("camera", &camera.name),
+                    ("h-alpha-red", &camera.red.ha),
+                    ("h-alpha-green", &camera.green.ha),
+                    ("h-alpha-blue", &camera.blue.ha),
+                    ("oiii-red", &camera.red.oiii),
+                    ("oiii-green", &camera.green.oiii),
+                    ("oiii-blue", &camera.blue.oiii),
+                ];
+                if camera.curves.is_some() {
+                    println!("{}", text("camera-with-curves", &qe));
+                } else {
+                    println!("{}", text("camera", &qe));
+                }
+            }
+        }
+        CamerasCommand::Add(args) => add(camera_from_args(args))?,
+        CamerasCommand::Import { url } => {
+            let json = database::download(url)?;
+            let imported = CameraDatabase::parse(&json).map_err(|err| {
+                Failure::new(
+                    ExitCode::BadInput,
+                    text(
+                        "invalid-camera-database",
+                        &[("path", url), ("message", &err)],
+                    ),
+                )
+            })?;
+            let mut database = CameraDatabase::load()?;
+            let (mut added, mut updated) = (0, 0);
+            for camera in imported.cameras {
+                if database.insert(camera) {
+                    updated += 1;
+                } else {
+                    added += 1;
+                }
+            }
+            let path = database.save()?;
+            println!(
+                "{}",
+                text(
+                    "imported-cameras",
+                    &[
+                        ("added", &added),
+                        ("updated", &updated),
+                        ("path", &path.display()),
+                    ],
+                )
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Validates `camera` and saves it in the user's database, replacing any of the same name
+pub fn add(camera: Camera) -> Result<(), Failure> {
+    validate(&camera).map_err(|err| Failure::new(ExitCode::BadInput, err))?;
+    let name = camera.name.clone();
+    let mut database = CameraDatabase::load()?;
+    let replaced = database.insert(camera);
+    let path = database.save()?;
+    let args: [(&str, &dyn std::fmt::Display); 2] = [("camera", &name), ("path", &path.display())];
+    if replaced {
+        println!("{}", text("updated-camera", &args));
+    } else {
+        println!("{}", text("added-camera", &args));
+    }
+    Ok(())
+}
+
+fn camera_from_args(args: &CameraAddArgs) -> Camera {
+    Camera {
+        name: args.name.clone(),
+        red: ChannelQe {
+            ha: args.red_ha_qe,
+            oiii: args.red_oiii_qe,
+        },
+        green: ChannelQe {
+            ha: args.green_ha_qe,
+            oiii: args.green_oiii_qe,
+        },
+        blue: ChannelQe {
+            ha: args.blue_ha_qe,
+            oiii: args.blue_oiii_qe,
+        },
+        curves: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_imported_databases() {
+        let json = r#"{"cameras": [{"name": "Test Cam",
+            "red": {"ha": 0.8, "oiii": 0.05},
+            "green": {"ha": 0.1, "oiii": 0.6},
+            "blue": {"ha": 0.05, "oiii": 0.5}}]}"#;
+        let database = CameraDatabase::parse(json).unwrap();
+        assert_eq!(database.find("test cam").unwrap().red.ha, 0.8);
+
+        assert!(CameraDatabase::parse(&json.replace("0.8", "80")).is_err());
+        assert!(CameraDatabase::parse(&json.replace("\"ha\": 0.8", "\"h\": 0.8")).is_err());
+        assert!(CameraDatabase::parse(&json.replace("Test Cam", " ")).is_err());
+
+        let curves = r#", "curves": {"red": [[500, 0.1], [650, 0.8]], "green": [[500, 0.6]],
+            "blue": [[500, 0.5], [400, 0.3]]}}]}"#;
+        assert!(CameraDatabase::parse(&json.replace("}]}", curves)).is_err());
+        let sorted = json.replace("}]}", &curves.replace("[400, 0.3]", "[600, 0.1]"));
+        assert!(CameraDatabase::parse(&sorted).unwrap().cameras[0]
+            .curves
+            .is_some());
+    }
+}
@@ -1,15 +1,462 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+use crate::{cameras, filters};
+use crate::composite::Composite;
+use crate::gpu::{GpuBackend, PowerClass};
+use crate::input::ReadOptions;
+use crate::linearize::Stretch;
+use crate::lines::Line;
+use crate::messages::text;
+use crate::notify::NotifyTarget;
+use crate::optimizer::{DecaySchedule, InitDistribution, Optimizer, Selection};
+use crate::output::OutputFormat;
+use crate::preview::PreviewStretch;
+use crate::response::{self, HBeta, Response, Sensitivity};
+use crate::stages::Stage;
+use crate::status::{ExitCode, Failure};
+use crate::synthetic::Pattern;
+use crate::white_balance::WhiteBalance;
 use std::path::PathBuf;
 
 #[derive(Parser)]
-#[command(version, about)]
+#[command(version, about, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Cli {
-    #[arg(help = "Path to input FITS file")]
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub split: Option<SplitArgs>,
+
+    #[command(flatten)]
+    pub solver: SolverArgs
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Benchmark the solver on a synthetic dual-band image with known optimal coefficients")]
+    Bench(BenchArgs),
+
+    #[command(about = "Check that the solver recovers the optimal coefficients of several synthetic images")]
+    Selftest(SelftestArgs),
+
+    #[command(about = "Generate a synthetic dual-band image from a one-shot-colour camera")]
+    Synth(SynthArgs),
+
+    #[command(about = "Apply two solutions to an image and compare the extractions, writing difference images and a JSON report")]
+    Compare(CompareArgs),
+
+    #[command(about = "Find genetic algorithm settings that suit an image by running short pilot runs on a downsampled copy")]
+    Tune(Box<TuneArgs>),
+
+    #[command(about = "Manage the local database of camera quantum efficiencies used by --camera")]
+    Cameras(CamerasArgs),
+
+    #[command(about = "Manage the local database of dual-band filters used by --filter")]
+    Filters(FiltersArgs),
+
+    #[command(about = "Set up a --profile for your camera and filter by answering a few questions")]
+    Init,
+}
+
+#[derive(Args, Clone)]
+pub struct SplitArgs {
+    #[arg(help = "Path to the input FITS, PNG, SER or BMP file, or - to read a FITS stream from stdin")]
+    pub input: PathBuf,
+
+    #[arg(short, long, default_value = ".", help = "Path to output directory, or - to stream both images to stdout as one multi-extension FITS file, with all messages on stderr")]
+    pub output: PathBuf,
+
+    #[arg(long, value_name = "DIR", conflicts_with = "output", help = "Collect everything the run writes (outputs, previews, report and recording) in a new timestamped directory under DIR, with a manifest.json listing them")]
+    pub artifacts: Option<PathBuf>,
+
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["export_pixelmath", "export_siril", "record", "verify"], help = "JSON description of the panels of a stitched mosaic, each solved for its own combinations, which are blended across the panel edges")]
+    pub mosaic: Option<PathBuf>,
+
+    #[arg(long = "qrh", required_unless_present_any = ["camera", "profile"], help = "The quantum efficiency of the red channel at the hydrogen-alpha wavelength (656.3 nm)")]
+    pub red_ha_qe: Option<f32>,
+
+    #[arg(long = "qgh", required_unless_present_any = ["camera", "profile"], help = "The quantum efficiency of the green channel at the hydrogen-alpha wavelength (656.3 nm)")]
+    pub green_ha_qe: Option<f32>,
+
+    #[arg(long = "qbh", required_unless_present_any = ["camera", "profile"], help = "The quantum efficiency of the blue channel at the hydrogen-alpha wavelength (656.3 nm)")]
+    pub blue_ha_qe: Option<f32>,
+
+    #[arg(long = "qro", required_unless_present_any = ["camera", "profile"], help = "The quantum efficiency of the red channel at the OIII wavelength (500.7 nm)")]
+    pub red_oiii_qe: Option<f32>,
+
+    #[arg(long = "qgo", required_unless_present_any = ["camera", "profile"], help = "The quantum efficiency of the green channel at the OIII wavelength (500.7 nm)")]
+    pub green_oiii_qe: Option<f32>,
+
+    #[arg(long = "qbo", required_unless_present_any = ["camera", "profile"], help = "The quantum efficiency of the blue channel at the OIII wavelength (500.7 nm)")]
+    pub blue_oiii_qe: Option<f32>,
+
+    #[arg(long = "qrhb", requires_all = ["green_hb_qe", "blue_hb_qe"], help = "The quantum efficiency of the red channel at the hydrogen-beta wavelength (486.1 nm), for filters such as the L-eNhance that pass H-beta alongside OIII; the H-beta is counted with H-alpha instead of leaking into OIII")]
+    pub red_hb_qe: Option<f32>,
+
+    #[arg(long = "qghb", requires_all = ["red_hb_qe", "blue_hb_qe"], help = "The quantum efficiency of the green channel at the hydrogen-beta wavelength (486.1 nm)")]
+    pub green_hb_qe: Option<f32>,
+
+    #[arg(long = "qbhb", requires_all = ["red_hb_qe", "green_hb_qe"], help = "The quantum efficiency of the blue channel at the hydrogen-beta wavelength (486.1 nm)")]
+    pub blue_hb_qe: Option<f32>,
+
+    #[arg(long, default_value_t = 2.86, value_parser = parse_balmer_decrement, help = "Ratio of H-alpha to H-beta flux assumed for every pixel with --qrhb, --qghb and --qbhb; 2.86 for unreddened gas, 4 or more behind heavy dust, where a decrement set too low leaves negative H-alpha structure in the OIII output")]
+    pub balmer_decrement: f32,
+
+    #[arg(long, value_parser = parse_nii_ratio, requires = "filter", help = "Ratio of NII (658.4 nm) to H-alpha flux of the target, to keep NII out of the H-alpha output when the filter treats it separately; otherwise the H-alpha output includes NII")]
+    pub nii_ratio: Option<f32>,
+
+    #[arg(long, help = "Name of a camera in the local database (see `duosplit cameras`) to take the quantum efficiencies from; any given individually take precedence")]
+    pub camera: Option<String>,
+
+    #[arg(long, help = "Name of a profile in the local profiles.json, bundling a camera, filter, optics and solver settings for one rig; options given on the command line replace the profile's, which also leaves out profile options that conflict with them, and giving all six quantum efficiencies leaves out its camera")]
+    pub profile: Option<String>,
+
+    #[arg(long, value_name = "OPTION", requires = "profile", help = "Leave an option of the --profile out, such as --without cpu to turn off a --cpu it sets; may be given more than once")]
+    pub without: Vec<String>,
+
+    #[arg(long, help = "Name of the dual-band filter in front of the camera (see `duosplit filters list`), whose transmission at each line scales the quantum efficiencies")]
+    pub filter: Option<String>,
+
+    #[arg(long, value_name = "FILE", help = "JSON transmission curve of the telescope or a corrector, as [[nm, transmission], ...] with wavelengths in nm or as line names (ha, oiii, hb, nii, sii), folded into the quantum efficiencies; repeat for each optical element")]
+    pub optics: Vec<PathBuf>,
+
+    #[arg(long, value_parser = parse_airmass, help = "Airmass the image was taken at, to correct the quantum efficiencies for atmospheric extinction, which dims OIII more than H-alpha; read from the AIRMASS header card when not given")]
+    pub airmass: Option<f32>,
+
+    #[arg(long = "wb", value_name = "R,G,B|auto", value_parser = parse_white_balance, help = "White balance multipliers the camera or stacker applied to the channels, divided back out before solving since they skew the quantum efficiency ratios; auto estimates them from the sky background")]
+    pub white_balance: Option<WhiteBalance>,
+
+    #[arg(long, value_name = "gamma:G|asinh:B", value_parser = parse_stretch, help = "Approximately undo the display stretch of an export before solving, for when no linear stack is available: gamma:2.2 for a gamma of 2.2, asinh:B for an arcsinh stretch of strength B; expect a less accurate split")]
+    pub linearize: Option<Stretch>,
+
+    #[arg(long, action, help = "Replace infinite, NaN and absurdly large samples with zero instead of stopping, such as pixels a stacker overflowed on")]
+    pub sanitize: bool,
+
+    #[arg(long, action, help = "Read an input without NAXIS3 as rows of interleaved RGB pixels, as some planetary and OSC tools write them; such files are detected without it, with a warning")]
+    pub interleaved: bool,
+
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), help = "The frame of a SER input to split, counting from 1; needed when the sequence holds more than one frame, such as a capture that wasn't stacked")]
+    pub frame: Option<u32>,
+
+    #[arg(long, value_name = "STAGE", value_parser = parse_stage, help = "Post-process both outputs before they are written: clip raises negative pixels to zero, pedestal:P adds P, rescale maps the outputs to 0 to 1, stretch:gamma:G or stretch:asinh:B applies a display stretch. Repeat to chain stages, which run in the order given")]
+    pub stage: Vec<Stage>,
+
+    #[arg(long, value_name = "EXPR", value_parser = Composite::parse, help = "Also write an RGB image built from the outputs after any post-processing, such as \"R=ha; G=0.7*oiii+0.3*ha; B=oiii\". Each channel is arithmetic on ha and oiii with +, -, *, /, parentheses, min(a, b) and max(a, b), evaluated on the GPU the solver ran on")]
+    pub composite_expr: Option<Composite>,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Fits, help = "File format of the output images")]
+    pub format: OutputFormat,
+
+    #[arg(long, action, help = "Also write an autostretched 10-bit AVIF preview of each line next to its output, for sharing quick results")]
+    pub preview: bool,
+
+    #[arg(long, value_name = "[LINE=]STRETCH", requires = "preview", value_parser = parse_preview_stretch, help = "How the previews are stretched: auto for a square root, asinh:B for an arcsinh stretch of strength B, or mtf:T for a midtones transfer function bringing the background up to level T, such as mtf:0.25. Prefix ha= or oiii= to stretch only that line's preview, since OIII usually needs a stronger stretch than H-alpha. May be given more than once")]
+    pub preview_stretch: Vec<(Option<Line>, PreviewStretch)>,
+
+    #[arg(long, help = "Command to run once the outputs are written, such as \"starnet++ {ha}\", where {ha} and {oiii} are the output paths; a command using {output} runs once for each output. May be given more than once")]
+    pub post_cmd: Vec<String>,
+
+    #[arg(long, value_enum, help = "Announce when the run finishes or fails")]
+    pub notify: Option<NotifyTarget>,
+
+    #[arg(long, help = "POST a JSON summary of the run to this webhook when it finishes or fails")]
+    pub notify_url: Option<String>,
+
+    #[arg(long, default_value = "{line}.fit", help = "Template for output file names, where {stem} is the input file name without its extension and {line} is h_alpha or oiii")]
+    pub naming: String,
+
+    #[arg(long, default_value = "", help = "Text added before each output file name")]
+    pub prefix: String,
+
+    #[arg(long, default_value = "", help = "Text added to each output file name before its extension")]
+    pub suffix: String,
+
+    #[arg(long, help = "Debug option: add Gaussian noise with this standard deviation to the input before solving, to see how sensitive the coefficients are to the signal-to-noise ratio")]
+    pub add_noise: Option<f32>,
+
+    #[arg(long, help = "Write a JSON report of the coefficients and diagnostics, which `duosplit compare` can read back")]
+    pub report: Option<PathBuf>,
+
+    #[arg(long, value_name = "HA,OIII", value_parser = parse_reference_frames, help = "Registered mono H-alpha and OIII frames of the same target to score the extractions against, by correlation and RMS; the scores go into the --report")]
+    pub validate_against: Option<(PathBuf, PathBuf)>,
+
+    #[arg(long, action, help = "Print PixInsight PixelMath expressions and Siril commands applying the solved combinations")]
+    pub export_pixelmath: bool,
+
+    #[arg(long, help = "Write a Siril script applying the solved combinations")]
+    pub export_siril: Option<PathBuf>,
+
+    #[arg(long, requires = "export_siril", help = "Make the Siril script process every frame of this sequence, given as the frame path without its number (e.g. process/r_pp_light_)")]
+    pub siril_sequence: Option<PathBuf>,
+
+    #[arg(long, action, requires = "siril_sequence", help = "Scale each frame of the --siril-sequence to the exposure of the first, by the EXPTIME in its header and by EGAIN (electrons per ADU) when every frame has one, so mixed-exposure sessions give consistently scaled outputs")]
+    pub normalize_exposure: bool
+}
+
+#[derive(Args, Clone)]
+pub struct SolverArgs {
+    #[arg(long, value_enum, default_value_t = Optimizer::Ga, help = "Search strategy used to find the coefficients")]
+    pub optimizer: Optimizer,
+
+    #[arg(long, default_value_t = 60, help = "Number of fitness evaluations for Bayesian optimization")]
+    pub evaluations: u32,
+
+    #[arg(short, long, visible_alias = "population", default_value_t = 100, help = "Population size for the genetic algorithm")]
+    pub population_size: usize,
+
+    #[arg(short, long, default_value_t = 250, help = "Number of generations for the genetic algorithm")]
+    pub generations: u32,
+
+    #[arg(short, long, default_value = "5", value_parser = parse_elitism, help = "Number of elite individuals to carry over each generation, or a fraction of the population such as 0.05")]
+    pub elitism: Elitism,
+
+    #[arg(short = 's', long, default_value_t = 0.5, help = "Initial standard deviation for mutation")]
+    pub initial_std: f32,
+    
+    #[arg(short, long, default_value_t = 0.1, help = "Decay rate for mutation standard deviation")]
+    pub decay_rate: f32,
+
+    #[arg(long, value_enum, default_value_t = DecaySchedule::Exp, help = "How the mutation standard deviation decays over the run")]
+    pub decay_schedule: DecaySchedule,
+
+    #[arg(long, value_enum, default_value_t = Selection::Tournament, help = "How parents are selected from each generation")]
+    pub selection: Selection,
+
+    #[arg(long, help = "Share fitness between genomes closer than this in (i, x) space, keeping the population diverse; the final population spread then estimates the coefficient uncertainty")]
+    pub niche_radius: Option<f32>,
+
+    #[arg(long, default_value_t = 1.0, help = "Half-width of the range the initial population is drawn from around the coefficients the quantum efficiencies predict, scaled by the predicted coefficient where it exceeds 1; widen it if the solution lies far from the prediction")]
+    pub init_range: f32,
+
+    #[arg(long, value_enum, default_value_t = InitDistribution::Uniform, help = "How the initial population is spread over --init-range")]
+    pub init_distribution: InitDistribution,
+
+    #[arg(long, value_name = "FILE", help = "JSON list of [i, x] red coefficient pairs the genetic algorithm's initial population starts with, such as promising genomes from earlier runs; random genomes fill the rest")]
+    pub seed_population: Option<PathBuf>,
+
+    #[arg(long, allow_negative_numbers = true, help = "Lowest red coefficient the solver may pick for either line")]
+    pub coeff_min: Option<f32>,
+
+    #[arg(long, allow_negative_numbers = true, help = "Highest red coefficient the solver may pick for either line")]
+    pub coeff_max: Option<f32>,
+
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..), help = "Expert override for the number of chunks the GPU splits the image into when summing it; picked from the image size and the device limits by default")]
+    pub chunks: Option<u32>,
+
+    #[arg(short, long, action, help = "Enable timing output")]
+    pub timings: bool,
+
+    #[arg(long, action, help = "Compute fitness on the CPU instead of the GPU")]
+    pub cpu: bool,
+
+    #[arg(long, action, conflicts_with = "cpu", help = "Split fitness evaluation between the GPU and the CPU according to their measured throughput")]
+    pub hybrid: bool,
+
+    #[cfg(feature = "cuda")]
+    #[arg(long, action, conflicts_with_all = ["cpu", "hybrid"], help = "Compute fitness with CUDA on an NVIDIA GPU, falling back to the other GPU backends")]
+    pub cuda: bool,
+
+    #[arg(short, long, default_value_t = 1, help = "Number of generations to run on the GPU per submission; above 1, the whole genetic algorithm runs on the GPU and only the best genome of each generation is read back")]
+    pub batch_generations: u32,
+
+    #[arg(long, action, help = "Print the GPU adapter's limits and features, and which of them will be used")]
+    pub gpu_info: bool,
+
+    #[arg(long, value_enum, help = "Prefer the low power (integrated) or high performance (discrete) GPU [default: WGPU_POWER_PREF, or no preference]")]
+    pub power_preference: Option<PowerClass>,
+
+    #[arg(long, value_enum, help = "Graphics API to run the GPU kernels on [default: WGPU_BACKEND, or any available]")]
+    pub backend: Option<GpuBackend>,
+
+    #[arg(long, action, help = "Fall back to a software GPU adapter (such as lavapipe or WARP) when no hardware adapter is available")]
+    pub allow_software_gpu: bool,
+
+    #[arg(long, action, help = "Subtract a coarse local background estimate from each channel before fitting, so gradients and vignetting don't bias the result")]
+    pub subtract_background: bool,
+
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..=256), help = "Number of background grid cells along each side of the image")]
+    pub background_grid: u32,
+
+    #[arg(long, action, help = "Never swap the solved H-alpha and OIII coefficients, even if the quantum efficiencies suggest they came out the wrong way round")]
+    pub no_swap: bool,
+
+    #[arg(long, action, conflicts_with = "no_swap", help = "Always swap the solved H-alpha and OIII coefficients")]
+    pub force_swap: bool,
+
+    #[arg(long, help = "Report the run as not converged, exiting with code 4 before writing any output, if the best noise found is above this")]
+    pub max_noise: Option<f32>,
+
+    #[arg(long, default_value_t = 0.25, help = "Report the run as not converged if the genetic algorithm's final population still spreads further than this standard deviation in either coefficient; ignored with niching, which keeps the population spread on purpose")]
+    pub max_spread: f32,
+
+    #[arg(long, action, help = "Treat warnings, such as precision loss, suspected swapped quantum efficiencies, stretched data or a GPU fallback, as errors and exit with a non-zero code")]
+    pub strict: bool,
+
+    #[arg(long, help = "Seed for the genetic algorithm's random number generator [default: random]")]
+    pub seed: Option<u64>,
+
+    #[arg(long, requires = "snapshot_dir", value_parser = clap::value_parser!(u32).range(1..), help = "Write small PNGs of the best H-alpha and OIII extractions every this many generations, for timelapses or debugging divergent runs")]
+    pub snapshot_every: Option<u32>,
+
+    #[arg(long, requires = "snapshot_every", help = "Directory to write the snapshots to")]
+    pub snapshot_dir: Option<PathBuf>,
+
+    #[arg(long, help = "Record the seed and the best genome of every generation to a JSON file")]
+    pub record: Option<PathBuf>,
+
+    #[arg(long, conflicts_with = "record", help = "Check that the run reproduces a recording bit for bit, reusing its seed")]
+    pub verify: Option<PathBuf>,
+
+    /// Keeps the solver's progress off stdout and stderr, for library callers
+    #[arg(skip)]
+    pub quiet: bool
+}
+
+#[derive(Args)]
+pub struct TuneArgs {
+    #[command(flatten)]
+    pub split: SplitArgs,
+
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..), help = "Factor to bin the image down by before the pilot runs")]
+    pub downsample: u32,
+
+    #[arg(long, default_value_t = 5000, help = "Fitness evaluations per pilot run, shared out between generations according to the population size")]
+    pub pilot_evaluations: u32,
+
+    #[arg(long, action, help = "Split the image with the best settings found instead of only recommending them")]
+    pub apply: bool,
+
+    #[command(flatten)]
+    pub solver: SolverArgs
+}
+
+#[derive(Args)]
+pub struct CompareArgs {
+    #[arg(help = "Path to the input FITS, PNG, SER or BMP file both solutions are applied to")]
     pub input: PathBuf,
 
-    #[arg(short, long, default_value = ".", help = "Path to output directory")]
+    #[arg(long, help = "JSON file with the first solution's h_alpha_coefficients and oiii_coefficients, such as a --report")]
+    pub a: PathBuf,
+
+    #[arg(long, help = "JSON file with the second solution's coefficients")]
+    pub b: PathBuf,
+
+    #[arg(short, long, default_value = ".", help = "Directory to write the difference images (B minus A) and compare.json to")]
+    pub output: PathBuf
+}
+
+#[derive(Args)]
+pub struct BenchArgs {
+    #[arg(long, default_value = "6248x4176", value_parser = parse_size, help = "Size of the synthetic image, as WIDTHxHEIGHT")]
+    pub size: (usize, usize),
+
+    #[arg(long, default_value_t = 0, help = "Seed for the synthetic image")]
+    pub image_seed: u64,
+
+    #[command(flatten)]
+    pub solver: SolverArgs
+}
+
+#[derive(Args)]
+pub struct SelftestArgs {
+    #[arg(long, default_value = "512x384", value_parser = parse_size, help = "Size of each synthetic image, as WIDTHxHEIGHT")]
+    pub size: (usize, usize),
+
+    #[arg(long, default_value_t = 1e-3, help = "Largest accepted error of any recovered coefficient")]
+    pub tolerance: f32,
+
+    #[command(flatten)]
+    pub solver: SolverArgs
+}
+
+#[derive(Args)]
+pub struct SynthArgs {
+    #[arg(short, long, help = "Path to the FITS file to write")]
     pub output: PathBuf,
 
+    #[arg(long, default_value = "1024x768", value_parser = parse_size, help = "Size of the image, as WIDTHxHEIGHT")]
+    pub size: (usize, usize),
+
+    #[arg(long, value_enum, default_value_t = Pattern::Blobs, help = "Shape of the hydrogen-alpha emission")]
+    pub ha_pattern: Pattern,
+
+    #[arg(long, value_enum, default_value_t = Pattern::Shells, help = "Shape of the OIII emission")]
+    pub oiii_pattern: Pattern,
+
+    #[arg(long, default_value_t = 200, help = "Number of stars")]
+    pub stars: usize,
+
+    #[arg(long, default_value_t = 0.1, help = "Strength of the sky glow gradient across the image")]
+    pub gradient: f32,
+
+    #[arg(long, default_value_t = 0.05, help = "Photon noise relative to the square root of the signal")]
+    pub noise: f32,
+
+    #[arg(long, default_value_t = 0, help = "Seed for the random scene and noise")]
+    pub seed: u64,
+
+    #[arg(long = "qrh", default_value_t = 0.8, help = "The quantum efficiency of the red channel at the hydrogen-alpha wavelength (656.3 nm)")]
+    pub red_ha_qe: f32,
+
+    #[arg(long = "qgh", default_value_t = 0.1, help = "The quantum efficiency of the green channel at the hydrogen-alpha wavelength (656.3 nm)")]
+    pub green_ha_qe: f32,
+
+    #[arg(long = "qbh", default_value_t = 0.05, help = "The quantum efficiency of the blue channel at the hydrogen-alpha wavelength (656.3 nm)")]
+    pub blue_ha_qe: f32,
+
+    #[arg(long = "qro", default_value_t = 0.05, help = "The quantum efficiency of the red channel at the OIII wavelength (500.7 nm)")]
+    pub red_oiii_qe: f32,
+
+    #[arg(long = "qgo", default_value_t = 0.6, help = "The quantum efficiency of the green channel at the OIII wavelength (500.7 nm)")]
+    pub green_oiii_qe: f32,
+
+    #[arg(long = "qbo", default_value_t = 0.5, help = "The quantum efficiency of the blue channel at the OIII wavelength (500.7 nm)")]
+    pub blue_oiii_qe: f32
+}
+
+#[derive(Args)]
+pub struct CamerasArgs {
+    #[command(subcommand)]
+    pub command: CamerasCommand
+}
+
+#[derive(Subcommand)]
+pub enum CamerasCommand {
+    #[command(about = "List the cameras in the database")]
+    List,
+
+    #[command(about = "Add a camera to the database, replacing any camera of the same name")]
+    Add(CameraAddArgs),
+
+    #[command(about = "Import the cameras of a JSON database at a URL, replacing any of the same names")]
+    Import {
+        #[arg(help = "URL of a JSON database, laid out like the local one")]
+        url: String
+    }
+}
+
+#[derive(Args)]
+pub struct FiltersArgs {
+    #[command(subcommand)]
+    pub command: FiltersCommand
+}
+
+#[derive(Subcommand)]
+pub enum FiltersCommand {
+    #[command(about = "List the filters in the database and the built-in presets")]
+    List,
+
+    #[command(about = "Import the filters of a JSON database at a URL, replacing any of the same names")]
+    Import {
+        #[arg(help = "URL of a JSON database, laid out like the local one")]
+        url: String
+    }
+}
+
+#[derive(Args)]
+pub struct CameraAddArgs {
+    #[arg(help = "Name of the camera, as given to --camera")]
+    pub name: String,
+
     #[arg(long = "qrh", help = "The quantum efficiency of the red channel at the hydrogen-alpha wavelength (656.3 nm)")]
     pub red_ha_qe: f32,
 
@@ -26,26 +473,245 @@ pub struct Cli {
     pub green_oiii_qe: f32,
 
     #[arg(long = "qbo", help = "The quantum efficiency of the blue channel at the OIII wavelength (500.7 nm)")]
-    pub blue_oiii_qe: f32,
+    pub blue_oiii_qe: f32
+}
 
-    #[arg(short, long, default_value_t = 100, help = "Population size for the genetic algorithm")]
-    pub population_size: usize,
+impl Default for SolverArgs {
+    /// The command line's defaults, for library callers of `split_arrays`
+    fn default() -> Self {
+        #[derive(Parser)]
+        struct Defaults {
+            #[command(flatten)]
+            solver: SolverArgs
+        }
+        Defaults::parse_from(["duosplit"]).solver
+    }
+}
 
-    #[arg(short, long, default_value_t = 250, help = "Number of generations for the genetic algorithm")]
-    pub generations: u32,
+impl SolverArgs {
+    /// Whether `--cuda` was given; the option only exists with the `cuda` feature
+    pub fn cuda(&self) -> bool {
+        #[cfg(feature = "cuda")]
+        return self.cuda;
+        #[cfg(not(feature = "cuda"))]
+        false
+    }
+}
 
-    #[arg(short, long, default_value_t = 5, help = "Number of elite individuals to carry over each generation")]
-    pub elitism: usize,
+impl SplitArgs {
+    /// How to read the input
+    pub fn read_options(&self) -> ReadOptions {
+        ReadOptions {
+            interleaved: self.interleaved,
+            frame: self.frame.map(|frame| frame as usize),
+        }
+    }
 
-    #[arg(short = 's', long, default_value_t = 0.5, help = "Initial standard deviation for mutation")]
-    pub initial_std: f32,
-    
-    #[arg(short, long, default_value_t = 0.1, help = "Decay rate for mutation standard deviation")]
-    pub decay_rate: f32,
+    /// The stretch of `line`'s preview: the last `--preview-stretch` for it or for both
+    pub fn preview_stretch(&self, line: Line) -> PreviewStretch {
+        self.preview_stretch
+            .iter()
+            .rev()
+            .find(|(only, _)| only.is_none_or(|only| only == line))
+            .map_or(PreviewStretch::Auto, |&(_, stretch)| stretch)
+    }
 
-    #[arg(short, long, default_value_t = 2048, help = "Number of chunks to split the image into before processing on the GPU")]
-    pub chunks: usize,
+    /// The sensor, filter and optics the image was taken through, exiting if any of them can't
+    /// be found or the filter blocks a line
+    pub fn response(&self) -> Response {
+        self.try_response()
+            .and_then(|response| {
+                response
+                    .quantum_efficiencies()
+                    .map_err(|err| Failure::new(ExitCode::BadInput, err))?;
+                Ok(response)
+            })
+            .unwrap_or_else(|failure| failure.code.exit(text("error", &[("message", &failure.message)])))
+    }
 
-    #[arg(short, long, action, help = "Enable timing output")]
-    pub timings: bool
-}
\ No newline at end of file
+    fn try_response(&self) -> Result<Response, Failure> {
+        let mut sensor = match &self.camera {
+            Some(name) => cameras::find(name)?.sensor(),
+            // clap requires every value without a camera
+            None => [(); 3].map(|()| [Sensitivity::Flat(0.0), Sensitivity::Flat(0.0)]),
+        };
+        let given = [
+            [self.red_ha_qe, self.red_oiii_qe],
+            [self.green_ha_qe, self.green_oiii_qe],
+            [self.blue_ha_qe, self.blue_oiii_qe],
+        ];
+        for (channel, values) in sensor.iter_mut().zip(given) {
+            for (sensitivity, value) in channel.iter_mut().zip(values) {
+                if let Some(value) = value {
+                    *sensitivity = Sensitivity::Flat(value);
+                }
+            }
+        }
+        Ok(Response {
+            sensor,
+            filter: self.filter.as_deref().map(filters::find).transpose()?,
+            optics: self
+                .optics
+                .iter()
+                .map(|path| response::read_curve(path))
+                .collect::<Result<_, _>>()?,
+            airmass: self.airmass,
+            h_beta: match (self.red_hb_qe, self.green_hb_qe, self.blue_hb_qe) {
+                (Some(red), Some(green), Some(blue)) => Some(HBeta {
+                    sensor: [red, green, blue].map(Sensitivity::Flat),
+                    decrement: self.balmer_decrement,
+                }),
+                // clap requires all three together
+                _ => None,
+            },
+            nii_ratio: self.nii_ratio,
+        })
+    }
+}
+
+/// Number of elite individuals, either absolute or relative to the population size
+#[derive(Copy, Clone)]
+pub enum Elitism {
+    Count(usize),
+    Fraction(f32),
+}
+
+impl Elitism {
+    pub fn count(self, population_size: usize) -> usize {
+        match self {
+            Elitism::Count(count) => count,
+            Elitism::Fraction(fraction) => (fraction * population_size as f32).round() as usize,
+        }
+    }
+}
+
+fn parse_elitism(elitism: &str) -> Result<Elitism, String> {
+    if let Ok(count) = elitism.parse() {
+        return Ok(Elitism::Count(count));
+    }
+    let fraction: f32 = elitism
+        .parse()
+        .map_err(|_| text("expected-elitism", &[]))?;
+    if !(0.0..1.0).contains(&fraction) {
+        return Err(text("elitism-fraction-out-of-range", &[]));
+    }
+    Ok(Elitism::Fraction(fraction))
+}
+
+fn parse_airmass(airmass: &str) -> Result<f32, String> {
+    let airmass: f32 = airmass.parse().map_err(|_| text("expected-number", &[]))?;
+    if airmass.is_nan() || airmass < 1.0 {
+        return Err(text("airmass-below-one", &[]));
+    }
+    Ok(airmass)
+}
+
+fn parse_balmer_decrement(decrement: &str) -> Result<f32, String> {
+    let decrement: f32 = decrement.parse().map_err(|_| text("expected-number", &[]))?;
+    if decrement.is_nan() || decrement <= 0.0 {
+        return Err(text("balmer-decrement-not-positive", &[]));
+    }
+    Ok(decrement)
+}
+
+fn parse_nii_ratio(ratio: &str) -> Result<f32, String> {
+    let ratio: f32 = ratio.parse().map_err(|_| text("expected-number", &[]))?;
+    if ratio.is_nan() || ratio < 0.0 {
+        return Err(text("negative-nii-ratio", &[]));
+    }
+    Ok(ratio)
+}
+
+fn parse_reference_frames(frames: &str) -> Result<(PathBuf, PathBuf), String> {
+    let (h_alpha, oiii) = frames
+        .split_once(',')
+        .ok_or_else(|| text("expected-reference-frames", &[]))?;
+    Ok((h_alpha.into(), oiii.into()))
+}
+
+fn parse_white_balance(white_balance: &str) -> Result<WhiteBalance, String> {
+    if white_balance == "auto" {
+        return Ok(WhiteBalance::Auto);
+    }
+    let multipliers = white_balance
+        .split(',')
+        .map(|value| value.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| text("invalid-multiplier", &[("error", &e)]))?;
+    let multipliers: [f32; 3] = multipliers
+        .try_into()
+        .map_err(|_| text("expected-white-balance", &[]))?;
+    if multipliers.iter().any(|&multiplier| multiplier.is_nan() || multiplier <= 0.0) {
+        return Err(text("multipliers-not-positive", &[]));
+    }
+    Ok(WhiteBalance::Multipliers(multipliers))
+}
+
+fn parse_stretch(stretch: &str) -> Result<Stretch, String> {
+    let (kind, value) = stretch
+        .split_once(':')
+        .ok_or_else(|| text("expected-stretch", &[]))?;
+    let value: f32 = value
+        .parse()
+        .map_err(|e| text("invalid-value", &[("kind", &kind), ("error", &e)]))?;
+    if value.is_nan() || value <= 0.0 {
+        return Err(text("value-not-positive", &[("kind", &kind)]));
+    }
+    match kind {
+        "gamma" => Ok(Stretch::Gamma(value)),
+        "asinh" => Ok(Stretch::Asinh(value)),
+        _ => Err(text("unknown-stretch", &[("stretch", &kind)])),
+    }
+}
+
+fn parse_preview_stretch(stretch: &str) -> Result<(Option<Line>, PreviewStretch), String> {
+    let (line, stretch) = match stretch.split_once('=') {
+        Some(("ha", stretch)) => (Some(Line::HAlpha), stretch),
+        Some(("oiii", stretch)) => (Some(Line::Oiii), stretch),
+        Some((line, _)) => return Err(text("unknown-line", &[("line", &line)])),
+        None => (None, stretch),
+    };
+    let (kind, value) = stretch.split_once(':').unwrap_or((stretch, ""));
+    let number = || {
+        value
+            .parse::<f32>()
+            .map_err(|e| text("invalid-value", &[("kind", &kind), ("error", &e)]))
+    };
+    let stretch = match kind {
+        "auto" if value.is_empty() => PreviewStretch::Auto,
+        "asinh" => match number()? {
+            beta if beta > 0.0 => PreviewStretch::Asinh(beta),
+            _ => return Err(text("asinh-strength-not-positive", &[])),
+        },
+        "mtf" => match number()? {
+            target if target > 0.0 && target < 1.0 => PreviewStretch::Mtf(target),
+            _ => return Err(text("mtf-target-out-of-range", &[])),
+        },
+        _ => return Err(text("expected-preview-stretch", &[])),
+    };
+    Ok((line, stretch))
+}
+
+fn parse_stage(stage: &str) -> Result<Stage, String> {
+    let (kind, value) = stage.split_once(':').unwrap_or((stage, ""));
+    match (kind, value) {
+        ("clip", "") => Ok(Stage::Clip),
+        ("rescale", "") => Ok(Stage::Rescale),
+        ("pedestal", value) => value
+            .parse()
+            .map(Stage::Pedestal)
+            .map_err(|e| text("invalid-pedestal", &[("error", &e)])),
+        ("stretch", value) => parse_stretch(value).map(Stage::Stretch),
+        _ => Err(text("expected-stage", &[])),
+    }
+}
+
+fn parse_size(size: &str) -> Result<(usize, usize), String> {
+    let (width, height) = size.split_once('x').ok_or_else(|| text("expected-size", &[]))?;
+    let width = width.parse().map_err(|e| text("invalid-width", &[("error", &e)]))?;
+    let height = height.parse().map_err(|e| text("invalid-height", &[("error", &e)]))?;
+    if width == 0 || height == 0 {
+        return Err(text("size-not-positive", &[]));
+    }
+    Ok((width, height))
+}
@@ -0,0 +1,198 @@
+use crate::cli::CompareArgs;
+use crate::combine;
+use crate::diagnostics::{line_diagnostics, LineDiagnostics};
+use crate::fits::write_fits;
+use crate::input::{read_image, ReadOptions};
+use crate::messages::text;
+use crate::report::Coefficients;
+use crate::status::ExitCode;
+use ndarray::{Array2, Zip};
+use serde::Serialize;
+use std::fs;
+
+/// How two solutions' extractions of one line differ, B minus A
+#[derive(Serialize, Debug, PartialEq)]
+pub struct LineComparison {
+    pub a: LineDiagnostics,
+    pub b: LineDiagnostics,
+    pub mean_difference: f32,
+    pub rms_difference: f32,
+    pub max_difference: f32,
+    /// Pearson correlation between the two extractions
+    pub correlation: f32,
+}
+
+#[derive(Serialize)]
+struct Comparison {
+    a: String,
+    b: String,
+    h_alpha: LineComparison,
+    oiii: LineComparison,
+}
+
+impl LineComparison {
+    pub fn new(
+        channels: (&Array2<f32>, &Array2<f32>, &Array2<f32>),
+        a: ([f32; 3], &Array2<f32>),
+        b: ([f32; 3], &Array2<f32>),
+    ) -> Self {
+        let pixels = a.1.len().max(1) as f64;
+        let mean = |image: &Array2<f32>| image.iter().map(|&v| v as f64).sum::<f64>() / pixels;
+        let (mean_a, mean_b) = (mean(a.1), mean(b.1));
+        let (mut squares, mut max) = (0.0f64, 0.0f32);
+        let (mut covariance, mut variance_a, mut variance_b) = (0.0f64, 0.0f64, 0.0f64);
+        Zip::from(a.1).and(b.1).for_each(|&a, &b| {
+            squares += ((b - a) as f64).powi(2);
+            max = max.max((b - a).abs());
+            let (a, b) = (a as f64 - mean_a, b as f64 - mean_b);
+            covariance += a * b;
+            variance_a += a * a;
+            variance_b += b * b;
+        });
+        let spread = (variance_a * variance_b).sqrt();
+        Self {
+            a: line_diagnostics(channels, a.0),
+            b: line_diagnostics(channels, b.0),
+            mean_difference: (mean_b - mean_a) as f32,
+            rms_difference: (squares / pixels).sqrt() as f32,
+            max_difference: max,
+            correlation: if spread > 0.0 {
+                (covariance / spread) as f32
+            } else {
+                1.0
+            },
+        }
+    }
+}
+
+/// Applies both solutions to the image, prints how the extractions differ and writes the
+/// difference images and a JSON report to the output directory
+pub fn run(args: &CompareArgs) {
+    let load = |path| {
+        Coefficients::load(path)
+            .unwrap_or_else(|err| ExitCode::BadInput.exit(text("error", &[("message", &err)])))
+    };
+    let (a, b) = (load(&args.a), load(&args.b));
+    println!(
+        "{}",
+        text("reading-input", &[("path", &args.input.display())])
+    );
+    let image = read_image(&args.input, ReadOptions::default()).unwrap_or_else(|failure| {
+        failure.code.exit(text(
+            "error-reading-input",
+            &[("message", &failure.message)],
+        ))
+    });
+    let (red, green, blue) = &image.channels;
+    let channels = (red, green, blue);
+
+    if let Err(err) = fs::create_dir_all(&args.output) {
+        ExitCode::Io.exit(text(
+            "error-creating",
+            &[("path", &args.output.display()), ("message", &err)],
+        ));
+    }
+    let report = Comparison {
+        a: args.a.display().to_string(),
+        b: args.b.display().to_string(),
+        h_alpha: compare_line(
+            args,
+            channels,
+            "H-alpha",
+            a.h_alpha_coefficients,
+            b.h_alpha_coefficients,
+        ),
+        oiii: compare_line(
+            args,
+            channels,
+            "OIII",
+            a.oiii_coefficients,
+            b.oiii_coefficients,
+        ),
+    };
+    let path = args.output.join("compare.json");
+    let json = serde_json::to_string_pretty(&report).expect("reports always serialize");
+    if let Err(err) = fs::write(&path, json) {
+        ExitCode::Io.exit(text(
+            "error-writing",
+            &[("path", &path.display()), ("message", &err)],
+        ));
+    }
+    println!("{}", text("wrote", &[("path", &path.display())]));
+}
+
+/// Compares the two extractions of one line and writes their difference image
+fn compare_line(
+    args: &CompareArgs,
+    channels: (&Array2<f32>, &Array2<f32>, &Array2<f32>),
+    line: &str,
+    a: [f32; 3],
+    b: [f32; 3],
+) -> LineComparison {
+    let (image_a, image_b) = (combine(channels, a), combine(channels, b));
+    let comparison = LineComparison::new(channels, (a, &image_a), (b, &image_b));
+    println!(
+        "{}",
+        text(
+            "compare-noise",
+            &[
+                ("line", &line),
+                ("noise-a", &comparison.a.noise),
+                ("noise-b", &comparison.b.noise),
+                (
+                    "negative-a",
+                    &format!("{:.2}", comparison.a.negative_fraction * 100.0),
+                ),
+                (
+                    "negative-b",
+                    &format!("{:.2}", comparison.b.negative_fraction * 100.0),
+                ),
+            ],
+        )
+    );
+    println!(
+        "{}",
+        text(
+            "compare-difference",
+            &[
+                ("line", &line),
+                ("mean", &comparison.mean_difference),
+                ("rms", &comparison.rms_difference),
+                ("largest", &comparison.max_difference),
+                ("correlation", &format!("{:.6}", comparison.correlation)),
+            ],
+        )
+    );
+    let name = line.to_lowercase().replace('-', "_");
+    let path = args.output.join(format!("{}_difference.fit", name));
+    if let Err(err) = write_fits(&path, &(image_b - image_a), &[]) {
+        ExitCode::Io.exit(text("error-writing-difference", &[("message", &err)]));
+    }
+    println!("{}", text("wrote", &[("path", &path.display())]));
+    comparison
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn compares_two_extractions() {
+        let red = array![[1.0, 2.0], [3.0, 4.0]];
+        let zero = Array2::zeros((2, 2));
+        let channels = (&red, &zero, &zero);
+        let (a, b) = ([1.0, 0.0, 0.0], [2.0, 0.0, 0.0]);
+        let comparison = LineComparison::new(
+            channels,
+            (a, &combine(channels, a)),
+            (b, &combine(channels, b)),
+        );
+        // B is twice A, so differs by A itself while correlating perfectly
+        assert_eq!(comparison.mean_difference, 2.5);
+        assert_eq!(comparison.rms_difference, 7.5f32.sqrt());
+        assert_eq!(comparison.max_difference, 4.0);
+        assert!((comparison.correlation - 1.0).abs() < 1e-6);
+        assert_eq!(comparison.b.noise, 16.0 * comparison.a.noise);
+    }
+}
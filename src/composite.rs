@@ -0,0 +1,351 @@
+use crate::messages::text;
+use std::fmt::Write;
+
+/// An RGB image built from the split lines by `--composite-expr`, such as
+/// `R=ha; G=0.7*oiii+0.3*ha; B=oiii` for an HOO-like palette
+#[derive(Clone, Debug, PartialEq)]
+pub struct Composite {
+    pub red: Expr,
+    pub green: Expr,
+    pub blue: Expr,
+}
+
+/// Arithmetic on the H-alpha and OIII value of one pixel
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f32),
+    HAlpha,
+    Oiii,
+    Negate(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    /// `min(a, b)` and `max(a, b)`
+    Function(Function, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Function {
+    Min,
+    Max,
+}
+
+impl Composite {
+    /// Parses `R=...; G=...; B=...`, giving each channel once in any order
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut channels: [Option<Expr>; 3] = [None, None, None];
+        for assignment in source.split(';').filter(|part| !part.trim().is_empty()) {
+            let (channel, expr) = assignment
+                .split_once('=')
+                .ok_or_else(|| text("composite-no-assignment", &[("found", &assignment.trim())]))?;
+            let index = match channel.trim().to_ascii_uppercase().as_str() {
+                "R" => 0,
+                "G" => 1,
+                "B" => 2,
+                other => return Err(text("composite-unknown-channel", &[("channel", &other)])),
+            };
+            if channels[index].is_some() {
+                return Err(text(
+                    "composite-channel-twice",
+                    &[("channel", &channel.trim())],
+                ));
+            }
+            channels[index] = Some(Expr::parse(expr)?);
+        }
+        match channels {
+            [Some(red), Some(green), Some(blue)] => Ok(Self { red, green, blue }),
+            _ => Err(text("composite-missing-channel", &[])),
+        }
+    }
+
+    /// The red, green and blue of a pixel with the given line values
+    pub fn evaluate(&self, h_alpha: f32, oiii: f32) -> [f32; 3] {
+        [&self.red, &self.green, &self.blue].map(|expr| expr.evaluate(h_alpha, oiii))
+    }
+
+    /// A WGSL function `composite(ha: f32, oiii: f32) -> vec3f` computing the same
+    pub fn wgsl(&self) -> String {
+        format!(
+            "fn composite(ha: f32, oiii: f32) -> vec3f {{\n    return vec3f({}, {}, {});\n}}\n",
+            self.red.wgsl(),
+            self.green.wgsl(),
+            self.blue.wgsl()
+        )
+    }
+}
+
+impl Expr {
+    fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let expr = parser.sum()?;
+        match parser.tokens.get(parser.next) {
+            None => Ok(expr),
+            Some(token) => Err(text(
+                "composite-unexpected-in",
+                &[("token", token), ("expression", &source.trim())],
+            )),
+        }
+    }
+
+    fn evaluate(&self, h_alpha: f32, oiii: f32) -> f32 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::HAlpha => h_alpha,
+            Expr::Oiii => oiii,
+            Expr::Negate(expr) => -expr.evaluate(h_alpha, oiii),
+            Expr::Binary(operator, left, right) => {
+                let (left, right) = (left.evaluate(h_alpha, oiii), right.evaluate(h_alpha, oiii));
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide => left / right,
+                }
+            }
+            Expr::Function(function, a, b) => {
+                let (a, b) = (a.evaluate(h_alpha, oiii), b.evaluate(h_alpha, oiii));
+                match function {
+                    Function::Min => a.min(b),
+                    Function::Max => a.max(b),
+                }
+            }
+        }
+    }
+
+    /// The expression in WGSL, fully parenthesized so the precedence can't change
+    fn wgsl(&self) -> String {
+        let mut source = String::new();
+        self.write_wgsl(&mut source);
+        source
+    }
+
+    fn write_wgsl(&self, source: &mut String) {
+        match self {
+            // Debug always keeps a decimal point or exponent, so the literal stays an f32
+            Expr::Number(value) => write!(source, "{:?}", value).expect("strings always format"),
+            Expr::HAlpha => source.push_str("ha"),
+            Expr::Oiii => source.push_str("oiii"),
+            Expr::Negate(expr) => {
+                source.push_str("(-");
+                expr.write_wgsl(source);
+                source.push(')');
+            }
+            Expr::Binary(operator, left, right) => {
+                source.push('(');
+                left.write_wgsl(source);
+                source.push_str(match operator {
+                    Operator::Add => " + ",
+                    Operator::Subtract => " - ",
+                    Operator::Multiply => " * ",
+                    Operator::Divide => " / ",
+                });
+                right.write_wgsl(source);
+                source.push(')');
+            }
+            Expr::Function(function, a, b) => {
+                source.push_str(match function {
+                    Function::Min => "min(",
+                    Function::Max => "max(",
+                });
+                a.write_wgsl(source);
+                source.push_str(", ");
+                b.write_wgsl(source);
+                source.push(')');
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f32),
+    Name(String),
+    Symbol(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            let mut previous = c;
+            // Digits, a decimal point and an exponent, which may have its own sign
+            while let Some(&(index, c)) = chars.peek() {
+                let exponent_sign = (c == '+' || c == '-') && matches!(previous, 'e' | 'E');
+                if !(c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign) {
+                    break;
+                }
+                end = index + c.len_utf8();
+                previous = c;
+                chars.next();
+            }
+            let number = &source[start..end];
+            let value = number
+                .parse::<f32>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .ok_or_else(|| text("composite-invalid-number", &[("number", &number)]))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut name = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Name(name));
+        } else if "+-*/(),".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return Err(text(
+                "composite-unexpected-in",
+                &[
+                    ("token", &format!("'{}'", c)),
+                    ("expression", &source.trim()),
+                ],
+            ));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, with the usual precedence: unary minus, then `*` and
+/// `/`, then `+` and `-`, each left to right
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(operator) = self.operator(&[('+', Operator::Add), ('-', Operator::Subtract)])
+        {
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.factor()?;
+        while let Some(operator) =
+            self.operator(&[('*', Operator::Multiply), ('/', Operator::Divide)])
+        {
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(self.factor()?));
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Symbol('-')) => Ok(Expr::Negate(Box::new(self.factor()?))),
+            Some(Token::Symbol('(')) => {
+                let expr = self.sum()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(Token::Name(name)) => match name.to_ascii_lowercase().as_str() {
+                "ha" => Ok(Expr::HAlpha),
+                "oiii" => Ok(Expr::Oiii),
+                "min" | "max" => {
+                    let function = if name.eq_ignore_ascii_case("min") {
+                        Function::Min
+                    } else {
+                        Function::Max
+                    };
+                    self.expect('(')?;
+                    let a = self.sum()?;
+                    self.expect(',')?;
+                    let b = self.sum()?;
+                    self.expect(')')?;
+                    Ok(Expr::Function(function, Box::new(a), Box::new(b)))
+                }
+                _ => Err(text("composite-unknown-name", &[("name", &name)])),
+            },
+            Some(token) => Err(text("composite-unexpected", &[("token", &token)])),
+            None => Err(text("composite-ends-too-soon", &[])),
+        }
+    }
+
+    /// Takes the next token if it is one of the `operators`' symbols
+    fn operator(&mut self, operators: &[(char, Operator)]) -> Option<Operator> {
+        let Some(Token::Symbol(symbol)) = self.tokens.get(self.next) else {
+            return None;
+        };
+        let &(_, operator) = operators.iter().find(|(s, _)| s == symbol)?;
+        self.next += 1;
+        Some(operator)
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        match self.tokens.get(self.next) {
+            Some(Token::Symbol(found)) if *found == symbol => {
+                self.next += 1;
+                Ok(())
+            }
+            Some(token) => Err(text(
+                "composite-expected",
+                &[("symbol", &symbol), ("token", token)],
+            )),
+            None => Err(text("composite-expected-at-end", &[("symbol", &symbol)])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_channel_expressions() {
+        let composite = Composite::parse("b = oiii; R=ha; G=0.7*oiii+0.3*ha;").unwrap();
+        assert_eq!(composite.evaluate(1.0, 2.0), [1.0, 0.7 * 2.0 + 0.3, 2.0]);
+
+        // Precedence, grouping, unary minus and functions
+        let composite =
+            Composite::parse("R=1+2*ha-oiii/2; G=-(ha+1)*2; B=max(min(ha, 0.5), 1e-1)").unwrap();
+        assert_eq!(composite.evaluate(3.0, 4.0), [5.0, -8.0, 0.5]);
+        assert_eq!(composite.evaluate(0.0, 4.0), [-1.0, -2.0, 0.1]);
+        assert_eq!(composite.blue.wgsl(), "max(min(ha, 0.5), 0.1)");
+        assert_eq!(composite.red.wgsl(), "((1.0 + (2.0 * ha)) - (oiii / 2.0))");
+
+        for invalid in [
+            "R=ha; G=oiii",
+            "R=ha; G=oiii; B=oiii; R=ha",
+            "R=ha; G=oiii; X=oiii",
+            "R=ha; G=oiii; B=sii",
+            "R=ha; G=oiii; B=(oiii",
+            "R=ha; G=oiii; B=oiii oiii",
+            "R=ha; G=oiii; B=1e99",
+            "R=ha; G=; B=oiii",
+        ] {
+            assert!(Composite::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
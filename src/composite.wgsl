@@ -0,0 +1,19 @@
+// Builds the --composite-expr image from the split lines. The composite function is
+// generated from the expression and appended to this kernel before it is compiled
+
+// H-alpha and OIII value of each pixel in this batch
+@group(0) @binding(0) var<storage, read> lines: array<vec2f>;
+// Red, green and blue of each pixel; w is padding
+@group(0) @binding(1) var<storage, read_write> colors: array<vec4f>;
+
+override workgroup_x: u32 = 256u;
+
+@compute @workgroup_size(workgroup_x)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let idx = gid.x;
+    if (idx >= arrayLength(&colors)) {
+        return;
+    }
+    let line = lines[idx];
+    colors[idx] = vec4f(composite(line.x, line.y), 0.0);
+}
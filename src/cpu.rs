@@ -0,0 +1,103 @@
+use crate::background::Background;
+use crate::evaluator::Evaluator;
+use crate::genetics::Genome;
+use crate::gpu::QEUniform;
+use crate::moments::ImageMoments;
+use std::thread;
+use wide::f32x8;
+
+const LANES: usize = 8;
+
+// Number of pixels accumulated in f32 lanes before being added to the f64 totals
+const BLOCK_SIZE: usize = 4096;
+
+pub struct CpuContext {
+    moments: ImageMoments,
+    quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+}
+
+impl CpuContext {
+    pub fn new(
+        image: &[[f32; 3]],
+        background: Option<&Background>,
+        quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+    ) -> Self {
+        Self {
+            moments: image_moments(image, background),
+            quantum_efficiencies,
+        }
+    }
+
+    pub fn compute_fitness(&self, genomes: &[Genome]) -> Vec<f32> {
+        genomes
+            .iter()
+            .map(|genome| self.moments.fitness(genome, &self.quantum_efficiencies))
+            .collect()
+    }
+}
+
+impl Evaluator for CpuContext {
+    fn evaluate(&self, genomes: &[Genome]) -> Vec<f32> {
+        self.compute_fitness(genomes)
+    }
+}
+
+pub fn image_moments(image: &[[f32; 3]], background: Option<&Background>) -> ImageMoments {
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let per_thread = image.len().div_ceil(threads).max(1);
+    let sums = thread::scope(|scope| {
+        let handles = image
+            .chunks(per_thread)
+            .enumerate()
+            .map(|(n, chunk)| scope.spawn(move || chunk_sums(chunk, n * per_thread, background)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("CPU moments thread panicked"))
+            .collect::<Vec<_>>()
+    });
+    ImageMoments::from_sums(sums, image.len())
+}
+
+/// Sums the channel products of `chunk`, which starts at pixel `start` of the image
+fn chunk_sums(chunk: &[[f32; 3]], start: usize, background: Option<&Background>) -> [f64; 6] {
+    let mut totals = [0.0f64; 6];
+    let mut red = [0.0f32; LANES];
+    let mut green = [0.0f32; LANES];
+    let mut blue = [0.0f32; LANES];
+    for (block_idx, block) in chunk.chunks(BLOCK_SIZE).enumerate() {
+        let mut acc = [f32x8::ZERO; 6];
+        for (group_idx, pixels) in block.chunks(LANES).enumerate() {
+            let first = start + block_idx * BLOCK_SIZE + group_idx * LANES;
+            // Padding lanes stay black and contribute nothing
+            for lane in 0..LANES {
+                let pixel = match (pixels.get(lane), background) {
+                    (Some(pixel), Some(background)) => {
+                        let sky = background.at(first + lane);
+                        std::array::from_fn(|c| pixel[c] - sky[c])
+                    }
+                    (Some(pixel), None) => *pixel,
+                    (None, _) => [0.0; 3],
+                };
+                red[lane] = pixel[0];
+                green[lane] = pixel[1];
+                blue[lane] = pixel[2];
+            }
+            let r = f32x8::from(red);
+            let g = f32x8::from(green);
+            let b = f32x8::from(blue);
+            acc[0] += r * r;
+            acc[1] += g * g;
+            acc[2] += b * b;
+            acc[3] += r * g;
+            acc[4] += r * b;
+            acc[5] += g * b;
+        }
+        for (total, lanes) in totals.iter_mut().zip(acc) {
+            *total += lanes.reduce_add() as f64;
+        }
+    }
+    totals
+}
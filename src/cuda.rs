@@ -0,0 +1,146 @@
+use crate::background::Background;
+use crate::cpu::image_moments;
+use crate::evaluator::Evaluator;
+use crate::genetics::Genome;
+use crate::gpu::QEUniform;
+use crate::messages::text;
+use cudarc::driver::{
+    CudaContext, CudaFunction, CudaSlice, CudaStream, LaunchConfig, PushKernelArg,
+};
+use cudarc::get_lib_name_candidates;
+use cudarc::nvrtc::compile_ptx;
+use libloading::Library;
+use std::sync::Arc;
+
+// Threads per block of the fitness kernel, one genome each
+const BLOCK_SIZE: u32 = 64;
+
+// Mirrors fit.wgsl, with the moments and quantum efficiencies in the field order of their
+// Rust structs
+const FITNESS_KERNEL: &str = r#"
+__device__ float2 j_k_from_i(float i, float a, float c, float e, float b, float d, float f) {
+    float denom = d * e - c * f;
+    return make_float2((-f - b * e * i + a * f * i) / denom, (d + b * c * i - a * d * i) / denom);
+}
+
+__device__ float noise_power(const float* m, float x, float y, float z) {
+    return x * x * m[0] + y * y * m[1] + z * z * m[2]
+        + 2.0f * (x * y * m[3] + x * z * m[4] + y * z * m[5]);
+}
+
+extern "C" __global__ void fitness(
+    const float* genomes, float* fitness, const float* moments, const float* qe, unsigned int count
+) {
+    unsigned int idx = blockIdx.x * blockDim.x + threadIdx.x;
+    if (idx >= count) {
+        return;
+    }
+    float i = genomes[2 * idx];
+    float x = genomes[2 * idx + 1];
+    float2 jk = j_k_from_i(i, qe[0], qe[2], qe[4], qe[1], qe[3], qe[5]);
+    float2 yz = j_k_from_i(x, qe[1], qe[3], qe[5], qe[0], qe[2], qe[4]);
+    fitness[idx] = noise_power(moments, i * i, jk.x * jk.x, jk.y * jk.y)
+        + noise_power(moments, x * x, yz.x * yz.x, yz.y * yz.y);
+}
+"#;
+
+/// Fitness evaluation through CUDA on NVIDIA GPUs, for drivers whose Vulkan path is slow.
+/// Like the CPU, it sums the image moments once up front; only the per-genome fitness runs
+/// on the device.
+pub struct CudaEvaluator {
+    stream: Arc<CudaStream>,
+    function: CudaFunction,
+    moments: CudaSlice<f32>,
+    quantum_efficiencies: CudaSlice<f32>,
+}
+
+impl CudaEvaluator {
+    pub fn new(
+        image: &[[f32; 3]],
+        background: Option<&Background>,
+        quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+    ) -> Result<Self, String> {
+        find_library("cuda")?;
+        find_library("nvrtc")?;
+        let context =
+            CudaContext::new(0).map_err(|e| text("cuda-open-failed", &[("error", &e)]))?;
+        println!(
+            "{}",
+            text(
+                "cuda-using",
+                &[("name", &context.name().unwrap_or_default())]
+            )
+        );
+        let ptx = compile_ptx(FITNESS_KERNEL)
+            .map_err(|e| text("cuda-compile-failed", &[("error", &e)]))?;
+        let function = context
+            .load_module(ptx)
+            .and_then(|module| module.load_function("fitness"))
+            .map_err(|e| text("cuda-load-failed", &[("error", &e)]))?;
+        let stream = context.default_stream();
+
+        let moments = image_moments(image, background);
+        let (r, g, b) = quantum_efficiencies;
+        let qe = [r.ha, r.oiii, g.ha, g.oiii, b.ha, b.oiii];
+        let upload = |data: &[f32]| {
+            stream
+                .clone_htod(data)
+                .map_err(|e| text("cuda-upload-failed", &[("error", &e)]))
+        };
+        Ok(Self {
+            moments: upload(bytemuck::cast_slice(&[moments]))?,
+            quantum_efficiencies: upload(&qe)?,
+            function,
+            stream,
+        })
+    }
+
+    pub fn compute_fitness(&self, genomes: &[Genome]) -> Result<Vec<f32>, String> {
+        let count = genomes.len() as u32;
+        let genomes = self
+            .stream
+            .clone_htod(bytemuck::cast_slice::<Genome, f32>(genomes))
+            .map_err(|e| e.to_string())?;
+        let mut fitness = self
+            .stream
+            .alloc_zeros::<f32>(count as usize)
+            .map_err(|e| e.to_string())?;
+        let config = LaunchConfig {
+            grid_dim: (count.div_ceil(BLOCK_SIZE), 1, 1),
+            block_dim: (BLOCK_SIZE, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        let mut launch = self.stream.launch_builder(&self.function);
+        launch
+            .arg(&genomes)
+            .arg(&mut fitness)
+            .arg(&self.moments)
+            .arg(&self.quantum_efficiencies)
+            .arg(&count);
+        // SAFETY: the arguments match the kernel's signature, and it only touches the
+        // first `count` genomes and fitness values
+        unsafe { launch.launch(config) }.map_err(|e| e.to_string())?;
+        self.stream.clone_dtoh(&fitness).map_err(|e| e.to_string())
+    }
+}
+
+/// cudarc panics when it can't load a CUDA library, so look for it first
+fn find_library(name: &str) -> Result<(), String> {
+    let found = get_lib_name_candidates(name).iter().any(|candidate| {
+        // SAFETY: these are the libraries cudarc itself would load
+        unsafe { Library::new(candidate) }.is_ok()
+    });
+    if found {
+        Ok(())
+    } else {
+        Err(text("cuda-library-missing", &[("library", &name)]))
+    }
+}
+
+impl Evaluator for CudaEvaluator {
+    fn evaluate(&self, genomes: &[Genome]) -> Vec<f32> {
+        self.compute_fitness(genomes).unwrap_or_else(|err| {
+            panic!("{}", text("cuda-evaluation-failed", &[("message", &err)]))
+        })
+    }
+}
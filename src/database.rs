@@ -0,0 +1,52 @@
+use crate::messages::text;
+use crate::status::{ExitCode, Failure};
+use std::fs;
+use std::path::PathBuf;
+
+/// Path of one of the JSON databases kept in the user's config directory
+fn path(file: &str) -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("duosplit").join(file))
+}
+
+/// Reads the database `file`, or `None` if it hasn't been created yet
+pub fn read(file: &str) -> Result<Option<(PathBuf, String)>, Failure> {
+    let Some(path) = path(file).filter(|path| path.exists()) else {
+        return Ok(None);
+    };
+    let json = fs::read_to_string(&path).map_err(|err| {
+        Failure::new(
+            ExitCode::Io,
+            text("read-failed", &[("path", &path.display()), ("error", &err)]),
+        )
+    })?;
+    Ok(Some((path, json)))
+}
+
+/// Writes the database `file`, creating the config directory if needed
+pub fn write(file: &str, json: &str) -> Result<PathBuf, Failure> {
+    let io = |err: String| Failure::new(ExitCode::Io, err);
+    let path = path(file).ok_or_else(|| io(text("no-config-directory", &[("file", &file)])))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| io(err.to_string()))?;
+    }
+    fs::write(&path, json).map_err(|err| {
+        io(text(
+            "write-failed",
+            &[("path", &path.display()), ("error", &err)],
+        ))
+    })?;
+    Ok(path)
+}
+
+/// Downloads a database to import
+pub fn download(url: &str) -> Result<String, Failure> {
+    ureq::get(url)
+        .call()
+        .and_then(|mut response| response.body_mut().read_to_string())
+        .map_err(|err| {
+            Failure::new(
+                ExitCode::Io,
+                text("download-failed", &[("url", &url), ("error", &err)]),
+            )
+        })
+}
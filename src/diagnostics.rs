@@ -0,0 +1,107 @@
+use crate::gpu::QEUniform;
+use ndarray::{Array2, Zip};
+use serde::Serialize;
+
+/// How the extraction of one line came out. The fitness kernel only sees the image's
+/// channel moments, so these come from a pass over the pixels for the chosen genome.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct LineDiagnostics {
+    /// The line's term of the fitness: the mean square of the per-pixel noise variance
+    pub noise: f32,
+    /// Fraction of output pixels below zero, which grows as the coefficients over-subtract
+    pub negative_fraction: f32,
+}
+
+pub fn line_diagnostics(
+    (red, green, blue): (&Array2<f32>, &Array2<f32>, &Array2<f32>),
+    coefficients: [f32; 3],
+) -> LineDiagnostics {
+    let [r, g, b] = coefficients;
+    let mut noise = 0.0f64;
+    let mut negative = 0usize;
+    Zip::from(red)
+        .and(green)
+        .and(blue)
+        .for_each(|&red, &green, &blue| {
+            let variance = r * r * red + g * g * green + b * b * blue;
+            noise += (variance * variance) as f64;
+            if r * red + g * green + b * blue < 0.0 {
+                negative += 1;
+            }
+        });
+    let pixels = red.len().max(1) as f64;
+    LineDiagnostics {
+        noise: (noise / pixels) as f32,
+        negative_fraction: (negative as f64 / pixels) as f32,
+    }
+}
+
+/// Channel fit below which the quantum efficiencies are probably off
+pub const POOR_FIT: f32 = 0.9;
+
+/// R² of each channel against its reconstruction from the two outputs under the quantum
+/// efficiencies, `qe.ha * h_alpha + qe.oiii * oiii`. Close to 1 when the quantum efficiencies
+/// describe the camera; much lower when they are far off, since no pair of lines then
+/// explains all three channels.
+pub fn channel_fits(
+    (red, green, blue): (&Array2<f32>, &Array2<f32>, &Array2<f32>),
+    (h_alpha, oiii): (&Array2<f32>, &Array2<f32>),
+    (red_qe, green_qe, blue_qe): (QEUniform, QEUniform, QEUniform),
+) -> [f32; 3] {
+    let pixels = red.len().max(1) as f64;
+    [(red, red_qe), (green, green_qe), (blue, blue_qe)].map(|(channel, qe)| {
+        let mean = channel.iter().map(|&value| value as f64).sum::<f64>() / pixels;
+        let (mut residual, mut total) = (0.0f64, 0.0f64);
+        Zip::from(channel)
+            .and(h_alpha)
+            .and(oiii)
+            .for_each(|&value, &h_alpha, &oiii| {
+                let model = qe.ha * h_alpha + qe.oiii * oiii;
+                residual += ((value - model) as f64).powi(2);
+                total += (value as f64 - mean).powi(2);
+            });
+        if total > 0.0 {
+            (1.0 - residual / total) as f32
+        } else {
+            1.0
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn counts_negative_pixels_and_noise() {
+        let red = array![[1.0, 0.0], [2.0, 0.5]];
+        let green = array![[0.0, 1.0], [1.0, 0.5]];
+        let blue = array![[0.0, 0.0], [0.0, 0.0]];
+        let diagnostics = line_diagnostics((&red, &green, &blue), [1.0, -1.0, 0.0]);
+        // Outputs are 1, -1, 1 and 0, with noise variances 1, 1, 3 and 1
+        assert_eq!(
+            diagnostics,
+            LineDiagnostics {
+                noise: 3.0,
+                negative_fraction: 0.25,
+            }
+        );
+    }
+
+    #[test]
+    fn channel_fits_drop_when_the_quantum_efficiencies_are_off() {
+        let qe = |ha, oiii| QEUniform { ha, oiii };
+        let camera = (qe(0.8, 0.05), qe(0.1, 0.6), qe(0.05, 0.5));
+        let h_alpha = array![[1.0, 2.0], [0.0, 3.0]];
+        let oiii = array![[0.5, 0.0], [2.0, 1.0]];
+        let channel = |qe: QEUniform| &h_alpha * qe.ha + &oiii * qe.oiii;
+        let (red, green, blue) = (channel(camera.0), channel(camera.1), channel(camera.2));
+        let channels = (&red, &green, &blue);
+        for fit in channel_fits(channels, (&h_alpha, &oiii), camera) {
+            assert!((fit - 1.0).abs() < 1e-6);
+        }
+        let swapped = (qe(0.05, 0.8), camera.1, camera.2);
+        assert!(channel_fits(channels, (&h_alpha, &oiii), swapped)[0] < 0.0);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::genetics::Genome;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+pub trait Evaluator: Sync {
+    /// Returns the fitness (mean squared noise) of each genome, lower being better
+    fn evaluate(&self, genomes: &[Genome]) -> Vec<f32>;
+}
+
+/// Splits every evaluation between several evaluators in proportion to their measured throughput
+pub struct HybridEvaluator<'a> {
+    members: Vec<&'a dyn Evaluator>,
+    // Genomes evaluated per second by each member, smoothed over generations; zero until measured
+    throughputs: Mutex<Vec<f64>>,
+}
+
+// Weight of the latest measurement in the throughput moving average
+const SMOOTHING: f64 = 0.3;
+
+impl<'a> HybridEvaluator<'a> {
+    pub fn new(members: Vec<&'a dyn Evaluator>) -> Self {
+        let throughputs = Mutex::new(vec![0.0; members.len()]);
+        Self {
+            members,
+            throughputs,
+        }
+    }
+
+    fn shares(&self, total: usize) -> Vec<usize> {
+        let mut throughputs = self.throughputs.lock().unwrap().clone();
+        if throughputs.contains(&0.0) {
+            throughputs.fill(1.0);
+        }
+        let sum = throughputs.iter().sum::<f64>();
+        // Every member gets at least one genome when possible so its throughput stays measured
+        let minimum = if total >= self.members.len() { 1 } else { 0 };
+        let mut shares = throughputs
+            .iter()
+            .map(|t| ((t / sum * total as f64) as usize).max(minimum))
+            .collect::<Vec<_>>();
+        while shares.iter().sum::<usize>() > total {
+            let largest = (0..shares.len()).max_by_key(|&i| shares[i]).unwrap();
+            shares[largest] -= 1;
+        }
+        let fastest = (0..throughputs.len())
+            .max_by(|&i, &j| throughputs[i].partial_cmp(&throughputs[j]).unwrap())
+            .unwrap();
+        shares[fastest] += total - shares.iter().sum::<usize>();
+        shares
+    }
+}
+
+impl Evaluator for HybridEvaluator<'_> {
+    fn evaluate(&self, genomes: &[Genome]) -> Vec<f32> {
+        let shares = self.shares(genomes.len());
+        let mut parts = Vec::with_capacity(shares.len());
+        let mut rest = genomes;
+        for &share in &shares {
+            let (part, remaining) = rest.split_at(share);
+            parts.push(part);
+            rest = remaining;
+        }
+
+        let results = thread::scope(|scope| {
+            let handles = self
+                .members
+                .iter()
+                .zip(&parts)
+                .map(|(member, part)| {
+                    scope.spawn(move || {
+                        let start = Instant::now();
+                        let fitnesses = if part.is_empty() {
+                            Vec::new()
+                        } else {
+                            member.evaluate(part)
+                        };
+                        (fitnesses, start.elapsed().as_secs_f64())
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("Evaluator thread panicked"))
+                .collect::<Vec<_>>()
+        });
+
+        let mut throughputs = self.throughputs.lock().unwrap();
+        for ((throughput, part), (_, elapsed)) in throughputs.iter_mut().zip(&parts).zip(&results) {
+            if !part.is_empty() && *elapsed > 0.0 {
+                let measured = part.len() as f64 / elapsed;
+                *throughput = if *throughput == 0.0 {
+                    measured
+                } else {
+                    (1.0 - SMOOTHING) * *throughput + SMOOTHING * measured
+                };
+            }
+        }
+
+        results
+            .into_iter()
+            .flat_map(|(fitnesses, _)| fitnesses)
+            .collect()
+    }
+}
+
+/// Scores genomes by their squared distance from a known optimum, for testing without hardware
+#[cfg(test)]
+pub struct MockEvaluator {
+    optimum: Genome,
+}
+
+#[cfg(test)]
+impl MockEvaluator {
+    pub fn new(optimum: Genome) -> Self {
+        Self { optimum }
+    }
+}
+
+#[cfg(test)]
+impl Evaluator for MockEvaluator {
+    fn evaluate(&self, genomes: &[Genome]) -> Vec<f32> {
+        genomes
+            .iter()
+            .map(|g| (g.i - self.optimum.i).powi(2) + (g.x - self.optimum.x).powi(2))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hybrid_preserves_genome_order() {
+        let optimum = Genome { i: 0.2, x: 0.4 };
+        let mock = MockEvaluator::new(optimum);
+        let hybrid = HybridEvaluator::new(vec![&mock, &mock]);
+        let genomes = (0..37)
+            .map(|n| Genome {
+                i: n as f32 * 0.1,
+                x: -(n as f32) * 0.05,
+            })
+            .collect::<Vec<_>>();
+        let expected = mock.evaluate(&genomes);
+        for _ in 0..3 {
+            assert_eq!(hybrid.evaluate(&genomes), expected);
+        }
+    }
+
+    #[test]
+    fn hybrid_shares_cover_population() {
+        let optimum = Genome { i: 0.0, x: 0.0 };
+        let mock = MockEvaluator::new(optimum);
+        let hybrid = HybridEvaluator::new(vec![&mock, &mock, &mock]);
+        *hybrid.throughputs.lock().unwrap() = vec![10.0, 1.0, 0.5];
+        for total in [0, 1, 2, 3, 10, 101] {
+            assert_eq!(hybrid.shares(total).iter().sum::<usize>(), total);
+        }
+    }
+}
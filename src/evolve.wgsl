@@ -0,0 +1,127 @@
+// One generation of the genetic algorithm, run after fit.wgsl has scored the population.
+// The rank pass sorts the population and places the elites, then the breed pass fills
+// every other slot with a mutated parent picked by the selection method. Randomness comes
+// from a PCG hash seeded per generation by the CPU, so whole runs can be queued without
+// uploading anything but the parameters.
+
+#include "types.wgsl"
+
+struct Best {
+    genome: Genome,
+    fitness: f32
+};
+
+struct Params {
+    elitism: u32,
+    generation: u32,
+    seed: u32,
+    mutation_rate: f32,
+    // 0: binary tournament, 1: rank roulette, 2: stochastic universal sampling
+    selection: u32
+};
+
+@group(0) @binding(0) var<storage, read> population: array<Genome>;
+@group(0) @binding(1) var<storage, read> fitness: array<f32>;
+@group(0) @binding(2) var<storage, read_write> next_population: array<Genome>;
+@group(0) @binding(3) var<storage, read_write> history: array<Best>;
+@group(0) @binding(4) var<uniform> params: Params;
+// Index of the genome at each rank
+@group(0) @binding(5) var<storage, read_write> order: array<u32>;
+
+override workgroup_x: u32 = 64u;
+
+var<private> rng_state: u32;
+
+fn pcg_hash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random_u32() -> u32 {
+    rng_state = pcg_hash(rng_state);
+    return rng_state;
+}
+
+// Uniform in [0, 1)
+fn random_f32() -> f32 {
+    return f32(random_u32() >> 8u) / 16777216.0;
+}
+
+// Two independent standard normal samples (Box-Muller)
+fn random_normal2() -> vec2f {
+    let u1 = 1.0 - random_f32();
+    let u2 = random_f32();
+    let r = sqrt(-2.0 * log(u1));
+    let theta = 6.283185307179586 * u2;
+    return r * vec2f(cos(theta), sin(theta));
+}
+
+// Whether genome a ranks before genome b, ties broken by index so ranks are unique
+fn ranks_before(a: u32, b: u32) -> bool {
+    return fitness[a] < fitness[b] || (fitness[a] == fitness[b] && a < b);
+}
+
+// Rank whose share of the linear rank weights n, n - 1, ..., 1 contains u; mirrors
+// rank_at in optimizer.rs
+fn rank_at(u: f32, size: u32) -> u32 {
+    let b = 2.0 * f32(size) + 1.0;
+    let rank = floor((b - sqrt(max(b * b - 8.0 * u, 0.0))) / 2.0);
+    return min(u32(max(rank, 0.0)), size - 1u);
+}
+
+@compute @workgroup_size(workgroup_x)
+fn rank(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let idx = gid.x;
+    let size = arrayLength(&population);
+    if (idx >= size) {
+        return;
+    }
+
+    var rank: u32 = 0u;
+    for (var other: u32 = 0u; other < size; other = other + 1u) {
+        if (ranks_before(other, idx)) {
+            rank = rank + 1u;
+        }
+    }
+    order[rank] = idx;
+    if (rank < params.elitism) {
+        next_population[rank] = population[idx];
+    }
+    if (rank == 0u) {
+        history[params.generation] = Best(population[idx], fitness[idx]);
+    }
+}
+
+@compute @workgroup_size(workgroup_x)
+fn breed(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let idx = gid.x;
+    let size = arrayLength(&population);
+    if (idx >= size || idx < params.elitism) {
+        return;
+    }
+
+    rng_state = pcg_hash(params.seed ^ pcg_hash(idx));
+    let total_weight = f32(size) * f32(size + 1u) / 2.0;
+    var parent_idx: u32;
+    switch params.selection {
+        case 1u: {
+            parent_idx = order[rank_at(random_f32() * total_weight, size)];
+        }
+        case 2u: {
+            // Evenly spaced pointers sharing one random offset per generation
+            let offset = f32(pcg_hash(params.seed) >> 8u) / 16777216.0;
+            let spacing = total_weight / f32(size - params.elitism);
+            parent_idx = order[rank_at((offset + f32(idx - params.elitism)) * spacing, size)];
+        }
+        default: {
+            let a = random_u32() % size;
+            // Offset by 1..size-1 so the two contestants are always distinct
+            let b = (a + 1u + random_u32() % (size - 1u)) % size;
+            parent_idx = select(b, a, fitness[a] < fitness[b]);
+        }
+    }
+    let parent = population[parent_idx];
+    let noise = random_normal2() * params.mutation_rate;
+    next_population[idx] = Genome(parent.i + noise.x, parent.x + noise.y);
+}
@@ -0,0 +1,180 @@
+use crate::fits;
+use crate::messages::text;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Formats a linear combination of the three channels, e.g. `1.2 * $T[0] - 0.3 * $T[2]`
+fn combination(coefficients: [f32; 3], channels: [&str; 3]) -> String {
+    let mut expression = String::new();
+    for (coefficient, channel) in coefficients.into_iter().zip(channels) {
+        if expression.is_empty() {
+            expression.push_str(&format!("{} * {}", coefficient, channel));
+        } else if coefficient < 0.0 {
+            expression.push_str(&format!(" - {} * {}", -coefficient, channel));
+        } else {
+            expression.push_str(&format!(" + {} * {}", coefficient, channel));
+        }
+    }
+    expression
+}
+
+/// Prints PixInsight PixelMath expressions and the equivalent Siril commands for the
+/// solved combinations, to apply them to the RGB image inside either program
+pub fn print_pixelmath(ha: [f32; 3], oiii: [f32; 3]) {
+    let target = ["$T[0]", "$T[1]", "$T[2]"];
+    println!("{}", text("pixelmath", &[]));
+    println!(
+        "{}",
+        text(
+            "pixelmath-h-alpha",
+            &[("expression", &combination(ha, target))]
+        )
+    );
+    println!(
+        "{}",
+        text(
+            "pixelmath-oiii",
+            &[("expression", &combination(oiii, target))]
+        )
+    );
+
+    println!("{}", text("siril-commands", &[]));
+    for line in siril_commands(ha, oiii, "h_alpha", "oiii") {
+        println!("  {}", line);
+    }
+}
+
+/// Siril commands splitting the loaded RGB image and saving both combinations
+fn siril_commands(ha: [f32; 3], oiii: [f32; 3], ha_name: &str, oiii_name: &str) -> Vec<String> {
+    let split = ["$r$", "$g$", "$b$"];
+    vec![
+        "split r g b".into(),
+        format!("pm \"{}\"", combination(ha, split)),
+        format!("save {}", ha_name),
+        format!("pm \"{}\"", combination(oiii, split)),
+        format!("save {}", oiii_name),
+    ]
+}
+
+/// Writes a Siril script applying the solved combinations to the currently loaded image,
+/// or to every frame of `sequence` (the path of its frames without the frame number, e.g.
+/// `process/r_pp_light_`). Frames are saved as new `Ha_` and `OIII_` sequences, scaled to
+/// the exposure of the first if `normalize` asks to.
+pub fn write_siril_script(
+    path: &Path,
+    ha: [f32; 3],
+    oiii: [f32; 3],
+    sequence: Option<&Path>,
+    normalize: bool,
+) -> Result<(), String> {
+    let mut script = vec![
+        "# Generated by duosplit".to_string(),
+        "requires 1.2.0".into(),
+    ];
+    match sequence {
+        None => script.extend(siril_commands(ha, oiii, "h_alpha", "oiii")),
+        Some(sequence) => {
+            let (directory, frames) = sequence_frames(sequence)?;
+            let scales = if normalize {
+                exposure_scales(&directory, &frames)?
+            } else {
+                vec![1.0; frames.len()]
+            };
+            script.push(format!("cd \"{}\"", directory.display()));
+            for ((frame, _), scale) in frames.iter().zip(scales) {
+                script.push(format!("load {}", frame));
+                if normalize {
+                    script.push(format!(
+                        "# Scaled by {} to the first frame's exposure",
+                        scale
+                    ));
+                }
+                script.extend(siril_commands(
+                    ha.map(|coefficient| coefficient * scale),
+                    oiii.map(|coefficient| coefficient * scale),
+                    &format!("Ha_{}", frame),
+                    &format!("OIII_{}", frame),
+                ));
+            }
+        }
+    }
+    script.push("close".into());
+    fs::write(path, script.join("\n") + "\n")
+        .map_err(|e| text("write-failed", &[("path", &path.display()), ("error", &e)]))
+}
+
+/// Factors that bring each frame to the exposure of the first: the ratio of their EXPTIME,
+/// and of their EGAIN (electrons per ADU) if every frame records it
+fn exposure_scales(directory: &Path, frames: &[(String, String)]) -> Result<Vec<f32>, String> {
+    let mut exposures = Vec::with_capacity(frames.len());
+    for (_, file) in frames {
+        let path = directory.join(file);
+        let header = fits::read_header(&path).map_err(|failure| failure.message)?;
+        let number = |key| {
+            header.number(key).map_err(|err| {
+                text(
+                    "fits-invalid",
+                    &[("path", &path.display()), ("message", &err)],
+                )
+            })
+        };
+        let exptime = number("EXPTIME")?
+            .filter(|&exptime| exptime > 0.0)
+            .ok_or_else(|| text("no-exptime", &[("path", &path.display())]))?;
+        exposures.push((exptime, number("EGAIN")?));
+    }
+    let gains = exposures.iter().all(|(_, gain)| gain.is_some());
+    let exposure = |&(exptime, gain): &(f64, Option<f64>)| match gain {
+        Some(gain) if gains => exptime / gain,
+        _ => exptime,
+    };
+    let reference = exposure(&exposures[0]);
+    Ok(exposures
+        .iter()
+        .map(|frame| (reference / exposure(frame)) as f32)
+        .collect())
+}
+
+/// Finds the directory of a sequence and the stems and file names of its FITS frames, in
+/// order
+fn sequence_frames(sequence: &Path) -> Result<(PathBuf, Vec<(String, String)>), String> {
+    let prefix = sequence
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| text("invalid-sequence", &[("sequence", &sequence.display())]))?;
+    let directory = match sequence.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let directory = directory.canonicalize().map_err(|e| {
+        text(
+            "open-failed",
+            &[("path", &directory.display()), ("error", &e)],
+        )
+    })?;
+    let entries = fs::read_dir(&directory).map_err(|e| {
+        text(
+            "list-failed",
+            &[("path", &directory.display()), ("error", &e)],
+        )
+    })?;
+
+    let mut frames = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|name| {
+            let (stem, extension) = name.rsplit_once('.')?;
+            let number = stem.strip_prefix(prefix)?;
+            let is_fits = matches!(extension, "fit" | "fits" | "fts");
+            let is_frame = !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit());
+            (is_fits && is_frame).then(|| (stem.to_string(), name.clone()))
+        })
+        .collect::<Vec<_>>();
+    if frames.is_empty() {
+        return Err(text(
+            "no-sequence-frames",
+            &[("sequence", &sequence.display())],
+        ));
+    }
+    frames.sort();
+    Ok((directory, frames))
+}
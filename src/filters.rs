@@ -0,0 +1,334 @@
+use crate::cli::{FiltersArgs, FiltersCommand};
+use crate::database;
+use crate::lines::{self, Line};
+use crate::messages::text;
+use crate::status::{ExitCode, Failure};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const H_ALPHA: f32 = Line::HAlpha.wavelength();
+const OIII: f32 = Line::Oiii.wavelength();
+const NII: f32 = Line::Nii.wavelength();
+
+/// Dual-band filters known to `--filter` beyond the built-in presets, kept next to the
+/// camera database in the same layout
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FilterDatabase {
+    pub filters: Vec<Filter>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Filter {
+    pub name: String,
+    pub passbands: Vec<Passband>,
+    /// How NII is treated; decided from the passbands when not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nii: Option<NiiHandling>,
+}
+
+/// Whether the filter's NII (658.4 nm), 2.1 nm from H-alpha, is part of the H-alpha output
+/// or a line of its own
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NiiHandling {
+    /// Counted as H-alpha, so the H-alpha output is H-alpha and NII together
+    Merged,
+    /// Weighed through its own transmission, and with `--nii-ratio` kept out of H-alpha
+    Separate,
+}
+
+/// One passband of a filter, treated as flat-topped: `transmission` within `bandwidth` (the
+/// FWHM) around `center`, nothing outside it
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Passband {
+    #[serde(deserialize_with = "lines::wavelength")]
+    pub center: f32,
+    pub bandwidth: f32,
+    pub transmission: f32,
+}
+
+/// Nominal manufacturer figures for common dual-band filters
+fn presets() -> Vec<Filter> {
+    let filter = |name: &str, bands: &[(f32, f32, f32)]| Filter {
+        name: name.into(),
+        nii: None,
+        passbands: bands
+            .iter()
+            .map(|&(center, bandwidth, transmission)| Passband {
+                center,
+                bandwidth,
+                transmission,
+            })
+            .collect(),
+    };
+    vec![
+        filter("L-eNhance", &[(493.0, 24.0, 0.9), (H_ALPHA, 10.0, 0.9)]),
+        filter("L-eXtreme", &[(OIII, 7.0, 0.9), (H_ALPHA, 7.0, 0.9)]),
+        filter("L-Ultimate", &[(OIII, 3.0, 0.85), (H_ALPHA, 3.0, 0.85)]),
+        filter("NBZ", &[(OIII, 12.0, 0.9), (H_ALPHA, 12.0, 0.9)]),
+        filter("ALP-T", &[(OIII, 5.0, 0.9), (H_ALPHA, 5.0, 0.9)]),
+    ]
+}
+
+impl Filter {
+    /// The most transmissive passband that `wavelength` falls in
+    pub fn passband_at(&self, wavelength: f32) -> Option<&Passband> {
+        self.passbands
+            .iter()
+            .filter(|band| (wavelength - band.center).abs() <= band.bandwidth / 2.0)
+            .max_by(|a, b| a.transmission.total_cmp(&b.transmission))
+    }
+
+    /// Fraction of the light at `wavelength` the filter lets through
+    pub fn transmission_at(&self, wavelength: f32) -> f32 {
+        self.passband_at(wavelength)
+            .map_or(0.0, |band| band.transmission)
+    }
+
+    /// How NII is treated: separately when the filter resolves it from H-alpha, that is
+    /// when no passband around H-alpha reaches it, unless the entry says otherwise
+    pub fn nii(&self) -> NiiHandling {
+        self.nii.unwrap_or(if self.passband_at(NII).is_none() {
+            NiiHandling::Separate
+        } else {
+            NiiHandling::Merged
+        })
+    }
+}
+
+const DATABASE: &str = "filters.json";
+
+impl FilterDatabase {
+    /// The user's database, empty if it hasn't been created yet
+    pub fn load() -> Result<Self, Failure> {
+        let Some((path, json)) = database::read(DATABASE)? else {
+            return Ok(Self::default());
+        };
+        Self::parse(&json).map_err(|err| {
+            Failure::new(
+                ExitCode::BadInput,
+                text(
+                    "invalid-filter-database",
+                    &[("path", &path.display()), ("message", &err)],
+                ),
+            )
+        })
+    }
+
+    fn save(&self) -> Result<PathBuf, Failure> {
+        let json = serde_json::to_string_pretty(self).expect("databases always serialize");
+        database::write(DATABASE, &json)
+    }
+
+    /// Parses and validates a database, as stored or as imported
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let database = serde_json::from_str::<Self>(json).map_err(|err| err.to_string())?;
+        for filter in &database.filters {
+            validate(filter)?;
+        }
+        Ok(database)
+    }
+
+    /// Adds `filter`, replacing any filter of the same name; returns whether one was replaced
+    fn insert(&mut self, filter: Filter) -> bool {
+        match self
+            .filters
+            .iter_mut()
+            .find(|existing| existing.name.eq_ignore_ascii_case(&filter.name))
+        {
+            Some(existing) => {
+                *existing = filter;
+                true
+            }
+            None => {
+                self.filters.push(filter);
+                false
+            }
+        }
+    }
+}
+
+fn validate(filter: &Filter) -> Result<(), String> {
+    if filter.name.trim().is_empty() {
+        return Err(text("filter-without-name", &[]));
+    }
+    if filter.passbands.is_empty() {
+        return Err(text(
+            "filter-without-passbands",
+            &[("filter", &filter.name)],
+        ));
+    }
+    for band in &filter.passbands {
+        if !(band.center > 0.0 && band.bandwidth > 0.0) {
+            return Err(text(
+                "passband-not-positive",
+                &[
+                    ("filter", &filter.name),
+                    ("center", &band.center),
+                    ("bandwidth", &band.bandwidth),
+                ],
+            ));
+        }
+        if !(band.transmission > 0.0 && band.transmission <= 1.0) {
+            return Err(text(
+                "transmission-out-of-range",
+                &[
+                    ("filter", &filter.name),
+                    ("transmission", &band.transmission),
+                ],
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Looks up a filter for `--filter`, preferring the user's database over the presets
+pub fn find(name: &str) -> Result<Filter, Failure> {
+    let database = FilterDatabase::load()?;
+    database
+        .filters
+        .into_iter()
+        .chain(presets())
+        .find(|filter| filter.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            Failure::new(
+                ExitCode::BadInput,
+                text("unknown-filter", &[("filter", &name)]),
+            )
+        })
+}
+
+/// Names of every filter `--filter` knows, the user's first
+pub fn names() -> Result<Vec<String>, Failure> {
+    let mut names = Vec::<String>::new();
+    for filter in FilterDatabase::load()?.filters.into_iter().chain(presets()) {
+        if !names
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(&filter.name))
+        {
+            names.push(filter.name);
+        }
+    }
+    Ok(names)
+}
+
+pub fn run(args: &FiltersArgs) {
+    if let Err(failure) = run_command(&args.command) {
+        failure
+            .code
+            .exit(text("error", &[("message", &failure.message)]));
+    }
+}
+
+fn run_command(command: &FiltersCommand) -> Result<(), Failure> {
+    match command {
+        FiltersCommand::List => {
+            let database = FilterDatabase::load()?;
+            for filter in &database.filters {
+                print_filter(filter, false);
+            }
+            for filter in presets() {
+                print_filter(&filter, true);
+            }
+        }
+        FiltersCommand::Import { url } => {
+            let json = database::download(url)?;
+            let imported = FilterDatabase::parse(&json).map_err(|err| {
+                Failure::new(
+                    ExitCode::BadInput,
+                    text(
+                        "invalid-filter-database",
+                        &[("path", url), ("message", &err)],
+                    ),
+                )
+            })?;
+            let mut database = FilterDatabase::load()?;
+            let (mut added, mut updated) = (0, 0);
+            for filter in imported.filters {
+                if database.insert(filter) {
+                    updated += 1;
+                } else {
+                    added += 1;
+                }
+            }
+            let path = database.save()?;
+            println!(
+                "{}",
+                text(
+                    "imported-filters",
+                    &[
+                        ("added", &added),
+                        ("updated", &updated),
+                        ("path", &path.display()),
+                    ],
+                )
+            );
+        }
+    }
+    Ok(())
+}
+
+fn print_filter(filter: &Filter, built_in: bool) {
+    let bands = filter
+        .passbands
+        .iter()
+        .map(|band| {
+            text(
+                "passband",
+                &[
+                    ("center", &band.center),
+                    ("bandwidth", &band.bandwidth),
+                    ("transmission", &(band.transmission * 100.0)),
+                ],
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let args: [(&str, &dyn std::fmt::Display); 2] =
+        [("filter", &filter.name), ("passbands", &bands)];
+    if built_in {
+        println!("{}", text("built-in-filter", &args));
+    } else {
+        println!("{}", text("filter", &args));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_imported_databases() {
+        let json = r#"{"filters": [{"name": "H-alpha only",
+            "passbands": [{"center": 656.3, "bandwidth": 7, "transmission": 0.9}]}]}"#;
+        let database = FilterDatabase::parse(json).unwrap();
+        assert_eq!(database.filters[0].transmission_at(658.0), 0.9);
+        assert_eq!(database.filters[0].transmission_at(500.7), 0.0);
+
+        assert!(FilterDatabase::parse(&json.replace("0.9", "90")).is_err());
+        assert!(
+            FilterDatabase::parse(&json.replace("\"bandwidth\": 7", "\"bandwidth\": 0")).is_err()
+        );
+        assert!(FilterDatabase::parse(&json.replace("center", "centre")).is_err());
+    }
+
+    #[test]
+    fn narrow_filters_resolve_nii() {
+        let preset = |name| {
+            presets()
+                .into_iter()
+                .find(|filter| filter.name == name)
+                .unwrap()
+        };
+        assert_eq!(preset("L-Ultimate").nii(), NiiHandling::Separate);
+        assert_eq!(preset("L-eXtreme").nii(), NiiHandling::Merged);
+        let overridden = Filter {
+            nii: Some(NiiHandling::Separate),
+            ..preset("L-eXtreme")
+        };
+        assert_eq!(overridden.nii(), NiiHandling::Separate);
+    }
+}
@@ -16,58 +16,54 @@
 // ax + cy + ez = 0
 // bx + dy + fz = 1
 
-// j = (d + b c i - a d i)/(d e - c f)
-// k = (-f - b e i + a f i)/(d e - c f)
+// j = (-f - b e i + a f i)/(d e - c f)
+// k = (d + b c i - a d i)/(d e - c f)
+
+// mean((Hnoise^2)^2) = i^4 mean(RR) + j^4 mean(GG) + k^4 mean(BB)
+//                      + 2 i^2 j^2 mean(RG) + 2 i^2 k^2 mean(RB) + 2 j^2 k^2 mean(GB)
+// so the fitness only needs the image's channel moments, computed once by moments.wgsl
 
 struct QE {
     ha: f32,
     oiii: f32
 };
 
-struct Genome {
-    i: f32,
-    x: f32
-};
+#include "types.wgsl"
 
 @group(0) @binding(0) var<storage, read> genomes: array<Genome>;
 @group(0) @binding(1) var<storage, read_write> fitness: array<f32>;
-@group(0) @binding(2) var<storage, read> image: array<vec3f>;
+@group(0) @binding(2) var<uniform> moments: Moments;
 @group(0) @binding(3) var<uniform> qeR: QE;
 @group(0) @binding(4) var<uniform> qeG: QE;
 @group(0) @binding(5) var<uniform> qeB: QE;
-@group(0) @binding(6) var<uniform> total_chunks: u32;
+
+override workgroup_x: u32 = 64u;
 
 fn j_k_from_i(i: f32, a: f32, c: f32, e: f32, b: f32, d: f32, f: f32) -> vec2f {
     let denom = d * e - c * f;
-    let j = (d + b * c * i - a * d * i) / denom;
-    let k = (-f - b * e * i + a * f * i) / denom;
+    let j = (-f - b * e * i + a * f * i) / denom;
+    let k = (d + b * c * i - a * d * i) / denom;
     return vec2f(j, k);
 }
 
-fn pixel_noise(a: f32, b: f32, c: f32, pixel: vec3f) -> f32 {
-    return a * a * pixel.r + b * b * pixel.g + c * c * pixel.b;
+// Mean of the squared noise of the combination with squared coefficients w
+fn noise_power(w: vec3f) -> f32 {
+    return w.x * w.x * moments.rr + w.y * w.y * moments.gg + w.z * w.z * moments.bb
+        + 2.0 * (w.x * w.y * moments.rg + w.x * w.z * moments.rb + w.y * w.z * moments.gb);
 }
 
-@compute @workgroup_size(4, 64)
+@compute @workgroup_size(workgroup_x)
 fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
     let genome_idx = gid.x;
-    let chunk = gid.y;
-    if (genome_idx >= arrayLength(&genomes) || chunk >= total_chunks) {
+    if (genome_idx >= arrayLength(&genomes)) {
         return;
     }
     let genome = genomes[genome_idx];
 
-    let chunk_size = (arrayLength(&image) + total_chunks - 1u) / total_chunks;
-    var fitness_value: f32 = 0.0;
-    for (var idx: u32 = chunk * chunk_size; idx < (chunk + 1u) * chunk_size && idx < arrayLength(&image); idx = idx + 1u) {
-        let pixel = image[idx];
-        let jk = j_k_from_i(genome.i, qeR.ha, qeG.ha, qeB.ha, qeR.oiii, qeG.oiii, qeB.oiii);
-        let yz = j_k_from_i(genome.x, qeR.oiii, qeG.oiii, qeB.oiii, qeR.ha, qeG.ha, qeB.ha);
+    let jk = j_k_from_i(genome.i, qeR.ha, qeG.ha, qeB.ha, qeR.oiii, qeG.oiii, qeB.oiii);
+    let yz = j_k_from_i(genome.x, qeR.oiii, qeG.oiii, qeB.oiii, qeR.ha, qeG.ha, qeB.ha);
+    let h = vec3f(genome.i, jk.x, jk.y);
+    let o = vec3f(genome.x, yz.x, yz.y);
 
-        let h = pixel_noise(genome.i, jk.x, jk.y, pixel);
-        let o = pixel_noise(genome.x, yz.x, yz.y, pixel);
-
-        fitness_value += h * h + o * o;
-    }
-    fitness[genome_idx * total_chunks + chunk] = fitness_value;
-}
\ No newline at end of file
+    fitness[genome_idx] = noise_power(h * h) + noise_power(o * o);
+}
@@ -0,0 +1,666 @@
+use crate::digest;
+use crate::messages::text;
+use crate::status::{ExitCode, Failure, Warning, WarningKind};
+use fitrs::{Fits, Hdu};
+use ndarray::{s, Array2, Array3};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// FITS files are made of blocks of this many bytes, holding header cards of `CARD` bytes
+const BLOCK: usize = 2880;
+const CARD: usize = 80;
+// Median, as a share of the brightest pixels, above which a channel looks stretched
+const STRETCHED_MEDIAN: f32 = 0.25;
+// Below this ratio of the mean change between samples three apart to that between
+// neighbouring samples, a single plane looks like interleaved RGB
+const INTERLEAVED_RATIO: f64 = 0.8;
+
+pub type Channels = (Array2<f32>, Array2<f32>, Array2<f32>);
+
+/// A three-channel image read from the input, which `input::read_image` reads from FITS or
+/// one of the other formats it knows
+pub struct FitsImage {
+    pub channels: Channels,
+    /// Sample format of the file, from its BITPIX header
+    pub bit_depth: Option<&'static str>,
+    /// Problems with the data that don't stop it from being split
+    pub warnings: Vec<Warning>,
+    /// Airmass the image was taken at, from its AIRMASS header
+    pub airmass: Option<f32>,
+    /// Whether the data looks stretched rather than linear
+    pub stretched: bool,
+    /// SHA-256 of the file as read, in hex
+    pub sha256: String,
+}
+
+/// The value of a card written into the outputs' headers, or of an EXR attribute
+#[derive(Clone)]
+pub enum CardValue {
+    Number(f32),
+    Text(String),
+}
+
+/// Reads the primary HDU of a single-channel FITS file, such as a mono narrowband frame
+pub fn read_mono_fits(path: &Path) -> Result<Array2<f32>, Failure> {
+    let bytes = std::fs::read(path).map_err(|e| {
+        Failure::new(
+            ExitCode::Io,
+            text(
+                "fits-open-failed",
+                &[("path", &path.display()), ("error", &e)],
+            ),
+        )
+    })?;
+    decode_mono_fits(&bytes).map_err(|message| {
+        Failure::new(
+            ExitCode::BadInput,
+            text(
+                "fits-invalid",
+                &[("path", &path.display()), ("message", &message)],
+            ),
+        )
+    })
+}
+
+/// Decodes the primary HDU of an in-memory FITS file, which must be a cube of at least three
+/// planes, or a single plane of interleaved RGB pixels. A single plane is read as such if
+/// `interleaved` says so or the data looks like it.
+pub(crate) fn decode_fits(bytes: &[u8], interleaved: bool) -> Result<FitsImage, String> {
+    let mut samples = decode_samples(bytes)?;
+    let channels = match samples.shape[..] {
+        [width, height, planes] => {
+            if planes < 3 {
+                return Err(text("fits-too-few-planes", &[("planes", &planes)]));
+            }
+            if interleaved {
+                samples.warnings.push(Warning::new(
+                    WarningKind::IgnoredOption,
+                    text("fits-interleaved-ignored", &[]),
+                ));
+            }
+            let channels = Array3::from_shape_vec((planes, height, width), samples.data)
+                .expect("Failed to reshape FITS data into 3D array");
+            let red_channel = channels.slice(s![0, .., ..]).into_owned();
+            let green_channel = channels.slice(s![1, .., ..]).into_owned();
+            let blue_channel = channels.slice(s![2, .., ..]).into_owned();
+            (red_channel, green_channel, blue_channel)
+        }
+        [row, height] if interleaved || looks_interleaved(&samples.data, row) => {
+            if !row.is_multiple_of(3) {
+                return Err(text("fits-row-not-interleaved", &[("row", &row)]));
+            }
+            if !interleaved {
+                samples.warnings.push(Warning::new(
+                    WarningKind::GuessedLayout,
+                    text("fits-guessed-interleaved", &[]),
+                ));
+            }
+            let channel = |offset: usize| {
+                Array2::from_shape_fn((height, row / 3), |(y, x)| {
+                    samples.data[y * row + 3 * x + offset]
+                })
+            };
+            (channel(0), channel(1), channel(2))
+        }
+        [_, _] => return Err(text("fits-single-plane", &[])),
+        _ => return Err(text("fits-wrong-axes", &[("axes", &samples.shape.len())])),
+    };
+    Ok(FitsImage {
+        stretched: looks_stretched(&channels),
+        channels,
+        bit_depth: bit_depth(samples.bitpix),
+        warnings: samples.warnings,
+        airmass: samples.airmass,
+        sha256: digest::sha256(bytes),
+    })
+}
+
+/// Decodes the primary HDU of an in-memory FITS file holding a single image, as two axes or
+/// a cube of one plane
+fn decode_mono_fits(bytes: &[u8]) -> Result<Array2<f32>, String> {
+    let samples = decode_samples(bytes)?;
+    let (width, height) = match samples.shape[..] {
+        [width, height] | [width, height, 1] => (width, height),
+        _ => {
+            return Err(text(
+                "fits-not-mono",
+                &[("axes", &format!("{:?}", samples.shape))],
+            ))
+        }
+    };
+    Ok(Array2::from_shape_vec((height, width), samples.data)
+        .expect("the data has one sample per pixel"))
+}
+
+/// Samples of a primary HDU scaled by BSCALE and BZERO, in the order of the file
+struct Samples {
+    /// NAXIS1, NAXIS2, ...
+    shape: Vec<usize>,
+    data: Vec<f32>,
+    bitpix: i64,
+    warnings: Vec<Warning>,
+    airmass: Option<f32>,
+}
+
+/// The keyword values of a primary header
+pub struct Header(HashMap<String, String>);
+
+impl Header {
+    /// Parses the header at the start of `bytes`, returning it with the offset of the data
+    /// that follows, or `None` if `bytes` ends before the END card
+    fn parse(bytes: &[u8]) -> Option<(Self, usize)> {
+        let mut header = HashMap::new();
+        for (index, card) in bytes.chunks_exact(CARD).enumerate() {
+            let card = String::from_utf8_lossy(card);
+            let key = card[..8].trim_end();
+            if key == "END" {
+                let data_start = ((index + 1) * CARD).next_multiple_of(BLOCK);
+                return Some((Header(header), data_start));
+            }
+            if &card[8..10] == "= " {
+                header.insert(key.to_string(), card_value(&card[10..]));
+            }
+        }
+        None
+    }
+
+    /// The numeric value of `key`, if the header has it
+    pub fn number(&self, key: &str) -> Result<Option<f64>, String> {
+        self.0
+            .get(key)
+            .map(|value| {
+                value
+                    .replace(['D', 'd'], "E")
+                    .parse::<f64>()
+                    .map_err(|_| text("fits-bad-value", &[("key", &key), ("value", value)]))
+            })
+            .transpose()
+    }
+}
+
+/// Reads only the primary header of a FITS file, without its data
+pub fn read_header(path: &Path) -> Result<Header, Failure> {
+    let io_error = |e: std::io::Error| {
+        Failure::new(
+            ExitCode::Io,
+            text(
+                "fits-read-failed",
+                &[("path", &path.display()), ("error", &e)],
+            ),
+        )
+    };
+    let mut file = std::fs::File::open(path).map_err(io_error)?;
+    let mut bytes = Vec::new();
+    loop {
+        let read = (&mut file)
+            .take(BLOCK as u64)
+            .read_to_end(&mut bytes)
+            .map_err(io_error)?;
+        if let Some((header, _)) = Header::parse(&bytes) {
+            return Ok(header);
+        }
+        if read < BLOCK {
+            return Err(Failure::new(
+                ExitCode::BadInput,
+                text("fits-no-hdu-in", &[("path", &path.display())]),
+            ));
+        }
+    }
+}
+
+fn decode_samples(bytes: &[u8]) -> Result<Samples, String> {
+    let (header, data_start) = Header::parse(bytes).ok_or_else(|| text("fits-no-hdu", &[]))?;
+    let number = |key: &str| header.number(key);
+    let integer = |key: &str| -> Result<i64, String> {
+        number(key)?
+            .map(|value| value as i64)
+            .ok_or_else(|| text("fits-missing-key", &[("key", &key)]))
+    };
+    let bitpix = integer("BITPIX")?;
+    let naxis = integer("NAXIS")?;
+    if !(1..=3).contains(&naxis) {
+        return Err(text("fits-too-many-axes", &[("axes", &naxis)]));
+    }
+    let shape = (1..=naxis)
+        .map(|axis| Ok(integer(&format!("NAXIS{}", axis))?.max(0) as usize))
+        .collect::<Result<Vec<_>, String>>()?;
+    let scale = number("BSCALE")?.unwrap_or(1.0);
+    let offset = number("BZERO")?.unwrap_or(0.0);
+    // Blank integer samples are read as zero
+    let blank = number("BLANK")?.map(|blank| blank as i64);
+
+    let length = shape.iter().product::<usize>();
+    let sample_size = bitpix.unsigned_abs() as usize / 8;
+    let data = bytes
+        .get(data_start..data_start + length * sample_size)
+        .ok_or_else(|| text("fits-truncated", &[]))?;
+    let integers = |value: i64| {
+        if Some(value) == blank {
+            0.0
+        } else {
+            value as f64
+        }
+    };
+    let mut warnings = Vec::new();
+    let data: Vec<f64> = match bitpix {
+        8 => data.iter().map(|&v| integers(v as i64)).collect(),
+        16 => data
+            .chunks_exact(2)
+            .map(|v| integers(i16::from_be_bytes([v[0], v[1]]) as i64))
+            .collect(),
+        32 => data
+            .chunks_exact(4)
+            .map(|v| integers(i32::from_be_bytes(v.try_into().unwrap()) as i64))
+            .collect(),
+        64 => data
+            .chunks_exact(8)
+            .map(|v| integers(i64::from_be_bytes(v.try_into().unwrap())))
+            .collect(),
+        -32 => data
+            .chunks_exact(4)
+            .map(|v| f32::from_be_bytes(v.try_into().unwrap()) as f64)
+            .collect(),
+        -64 => {
+            warnings.push(Warning::new(
+                WarningKind::PrecisionLoss,
+                text("fits-precision-loss", &[]),
+            ));
+            data.chunks_exact(8)
+                .map(|v| f64::from_be_bytes(v.try_into().unwrap()))
+                .collect()
+        }
+        _ => return Err(text("fits-bad-bitpix", &[("bitpix", &bitpix)])),
+    };
+
+    Ok(Samples {
+        shape,
+        data: data
+            .into_iter()
+            .map(|v| (v * scale + offset) as f32)
+            .collect(),
+        bitpix,
+        warnings,
+        // A malformed optional card shouldn't stop the image from being split
+        airmass: number("AIRMASS")
+            .ok()
+            .flatten()
+            .map(|airmass| airmass as f32),
+    })
+}
+
+/// Whether rows of `row` samples look like interleaved RGB pixels: a sample is then closer
+/// to the same channel of the next pixel, three samples on, than to the next sample, which
+/// is another channel. Mono and undebayered CFA data change no less over three samples.
+fn looks_interleaved(data: &[f32], row: usize) -> bool {
+    if !row.is_multiple_of(3) || row < 6 {
+        return false;
+    }
+    let mean_change = |lag: usize| {
+        let (mut total, mut count) = (0.0, 0);
+        for row in data.chunks_exact(row) {
+            for (a, b) in row.iter().zip(&row[lag..]) {
+                let change = (a - b).abs() as f64;
+                if change.is_finite() {
+                    total += change;
+                    count += 1;
+                }
+            }
+        }
+        total / count.max(1) as f64
+    };
+    let (neighbours, pixels) = (mean_change(1), mean_change(3));
+    neighbours > 0.0 && pixels < INTERLEAVED_RATIO * neighbours
+}
+
+/// Whether any channel's median sits so close to its bright end that the image has probably
+/// been stretched, since the sky background of linear data is far fainter than the stars
+pub(crate) fn looks_stretched((red, green, blue): &Channels) -> bool {
+    [red, green, blue].into_iter().any(|channel| {
+        let mut values = channel
+            .iter()
+            .copied()
+            .filter(|v| v.is_finite())
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            return false;
+        }
+        values.sort_unstable_by(f32::total_cmp);
+        let at = |fraction: f32| values[((values.len() - 1) as f32 * fraction) as usize];
+        let (median, peak) = (at(0.5), at(0.999));
+        peak > 0.0 && median > STRETCHED_MEDIAN * peak
+    })
+}
+
+/// Value of a header card, without its comment or the quotes around a string
+fn card_value(field: &str) -> String {
+    let field = field.trim_start();
+    if let Some(quoted) = field.strip_prefix('\'') {
+        // Two quotes in a row stand for one quote inside the string
+        let mut value = String::new();
+        let mut chars = quoted.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\'' {
+                if chars.peek() != Some(&'\'') {
+                    break;
+                }
+                chars.next();
+            }
+            value.push(c);
+        }
+        value.trim_end().to_string()
+    } else {
+        field
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    }
+}
+
+/// Describes a BITPIX value
+fn bit_depth(bitpix: i64) -> Option<&'static str> {
+    Some(match bitpix {
+        8 => "8-bit integer",
+        16 => "16-bit integer",
+        32 => "32-bit integer",
+        64 => "64-bit integer",
+        -32 => "32-bit float",
+        -64 => "64-bit float",
+        _ => return None,
+    })
+}
+
+/// Interleaves the channels into row-major RGB pixels
+pub fn interleave((red, green, blue): &Channels) -> Vec<[f32; 3]> {
+    red.iter()
+        .zip(green)
+        .zip(blue)
+        .map(|((&r, &g), &b)| [r, g, b])
+        .collect()
+}
+
+/// Averages `factor` by `factor` blocks of a row-major image, dropping partial blocks at the
+/// right and bottom edges, and returns the binned image with its width
+pub fn downsample(pixels: &[[f32; 3]], width: usize, factor: usize) -> (Vec<[f32; 3]>, usize) {
+    let height = pixels.len() / width;
+    let (binned_width, binned_height) = (width / factor, height / factor);
+    let scale = 1.0 / (factor * factor) as f32;
+    let mut binned = Vec::with_capacity(binned_width * binned_height);
+    for by in 0..binned_height {
+        for bx in 0..binned_width {
+            let mut sum = [0.0f32; 3];
+            for y in by * factor..(by + 1) * factor {
+                for pixel in &pixels[y * width + bx * factor..y * width + (bx + 1) * factor] {
+                    for channel in 0..3 {
+                        sum[channel] += pixel[channel];
+                    }
+                }
+            }
+            binned.push(sum.map(|s| s * scale));
+        }
+    }
+    (binned, binned_width)
+}
+
+pub fn write_fits(
+    path: &PathBuf,
+    data: &Array2<f32>,
+    metadata: &[(&str, CardValue)],
+) -> Result<(), String> {
+    let mut hdu = Hdu::new(
+        &[data.shape()[1], data.shape()[0]],
+        data.as_slice().unwrap().to_vec(),
+    );
+    insert_cards(&mut hdu, metadata);
+    Fits::create(path, hdu)
+        .map(|_| ())
+        .map_err(|e| text("write-failed", &[("path", &path.display()), ("error", &e)]))
+}
+
+/// Writes a row-major RGB image as a three-plane FITS cube, the layout `decode_fits` expects
+pub fn write_rgb_fits(
+    path: &PathBuf,
+    width: usize,
+    height: usize,
+    pixels: &[[f32; 3]],
+    metadata: &[(&str, CardValue)],
+) -> Result<(), String> {
+    let planes = (0..3)
+        .flat_map(|channel| pixels.iter().map(move |pixel| pixel[channel]))
+        .collect();
+    let mut hdu = Hdu::new(&[width, height, 3], planes);
+    insert_cards(&mut hdu, metadata);
+    Fits::create(path, hdu)
+        .map(|_| ())
+        .map_err(|e| text("write-failed", &[("path", &path.display()), ("error", &e)]))
+}
+
+fn insert_cards(hdu: &mut Hdu, metadata: &[(&str, CardValue)]) {
+    for (key, value) in metadata {
+        match value {
+            CardValue::Number(value) => {
+                // fitrs writes every digit of the f64, which overflows the card for most
+                // widened f32s, so widen through the shortest decimal instead
+                let value = value.to_string().parse::<f64>().unwrap_or_default();
+                hdu.insert(*key, value);
+            }
+            CardValue::Text(text) => hdu.insert(*key, text.as_str()),
+        }
+    }
+}
+
+/// Streams the images as one multi-extension FITS file: the first in the primary HDU and
+/// each of the others in an IMAGE extension, all named by their `EXTNAME` and carrying the
+/// `metadata` cards
+pub fn write_mef(
+    writer: &mut impl Write,
+    images: &[(&str, &Array2<f32>)],
+    metadata: &[(&str, CardValue)],
+) -> Result<(), String> {
+    for (index, (name, data)) in images.iter().enumerate() {
+        let mut header = Vec::new();
+        if index == 0 {
+            header.push(card("SIMPLE", "T"));
+        } else {
+            header.push(card("XTENSION", "'IMAGE   '"));
+        }
+        header.push(card("BITPIX", "-32"));
+        header.push(card("NAXIS", "2"));
+        header.push(card("NAXIS1", &data.ncols().to_string()));
+        header.push(card("NAXIS2", &data.nrows().to_string()));
+        if index == 0 {
+            header.push(card("EXTEND", "T"));
+        } else {
+            header.push(card("PCOUNT", "0"));
+            header.push(card("GCOUNT", "1"));
+        }
+        header.push(card("EXTNAME", &format!("'{:<8}'", name)));
+        for (key, value) in metadata {
+            header.push(match value {
+                CardValue::Number(value) => card(key, &value.to_string()),
+                CardValue::Text(text) => text_card(key, text),
+            });
+        }
+        header.push(format!("{:<80}", "END"));
+        let mut header = header.concat().into_bytes();
+        header.resize(header.len().next_multiple_of(BLOCK), b' ');
+
+        let mut bytes = data
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect::<Vec<_>>();
+        bytes.resize(bytes.len().next_multiple_of(BLOCK), 0);
+        writer
+            .write_all(&header)
+            .and_then(|_| writer.write_all(&bytes))
+            .map_err(|e| text("fits-write-hdu-failed", &[("name", name), ("error", &e)]))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| text("fits-flush-failed", &[("error", &e)]))
+}
+
+/// One 80 character header card, with the value right-aligned to column 30 as fixed format
+/// readers expect
+fn card(key: &str, value: &str) -> String {
+    format!("{:<8}= {:>20}{:<50}", key, value, "")
+}
+
+/// A card holding a string, which fixed format puts in quotes from column 11 with any quotes
+/// inside doubled
+fn text_card(key: &str, text: &str) -> String {
+    format!(
+        "{:<80}",
+        format!("{:<8}= '{}'", key, text.replace('\'', "''"))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fitrs::{FitsData, HeaderValue};
+
+    #[test]
+    fn mef_stream_reads_back() {
+        let h_alpha = Array2::from_shape_fn((3, 5), |(y, x)| (y * 5 + x) as f32);
+        let oiii = h_alpha.mapv(|v| -v / 2.0);
+        let path = std::env::temp_dir().join(format!("duosplit-mef-{}.fit", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        write_mef(
+            &mut file,
+            &[("H_ALPHA", &h_alpha), ("OIII", &oiii)],
+            &[
+                ("WBR", CardValue::Number(1.5)),
+                ("INSHA256", CardValue::Text("0a1b".into())),
+            ],
+        )
+        .unwrap();
+
+        let fits = Fits::open(&path).unwrap();
+        for (index, expected) in [&h_alpha, &oiii].into_iter().enumerate() {
+            let hdu = fits.get(index).unwrap();
+            assert!(matches!(
+                hdu.value("WBR"),
+                Some(HeaderValue::RealFloatingNumber(value)) if *value == 1.5
+            ));
+            assert!(matches!(
+                hdu.value("INSHA256"),
+                Some(HeaderValue::CharacterString(text)) if text == "0a1b"
+            ));
+            match hdu.read_data() {
+                FitsData::FloatingPoint32(arr) => {
+                    assert_eq!(arr.shape, vec![5, 3]);
+                    assert_eq!(arr.data, expected.iter().copied().collect::<Vec<_>>());
+                }
+                _ => panic!("HDU {} is not 32-bit float", index),
+            }
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reads_headers_alone() {
+        let path = std::env::temp_dir().join(format!("duosplit-header-{}.fit", std::process::id()));
+        let image = Array2::zeros((200, 200));
+        write_fits(
+            &path,
+            &image,
+            &[
+                ("EXPTIME", CardValue::Number(300.0)),
+                ("EGAIN", CardValue::Number(0.25)),
+            ],
+        )
+        .unwrap();
+        let header = read_header(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(header.number("EXPTIME"), Ok(Some(300.0)));
+        assert_eq!(header.number("EGAIN"), Ok(Some(0.25)));
+        assert_eq!(header.number("AIRMASS"), Ok(None));
+    }
+
+    #[test]
+    fn decodes_rgb_cubes() {
+        let path = std::env::temp_dir().join(format!("duosplit-rgb-{}.fit", std::process::id()));
+        let pixels = (0..6)
+            .map(|n| [n as f32, -(n as f32), 0.5])
+            .collect::<Vec<_>>();
+        write_rgb_fits(&path, 3, 2, &pixels, &[]).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let image = decode_fits(&bytes, false).unwrap();
+        assert_eq!(image.bit_depth, Some("32-bit float"));
+        assert_eq!(interleave(&image.channels), pixels);
+        assert!(image.warnings.is_empty());
+        // A cube has its planes already, so --interleaved is ignored with a warning
+        let image = decode_fits(&bytes, true).unwrap();
+        assert_eq!(interleave(&image.channels), pixels);
+        assert_eq!(image.warnings[0].kind, WarningKind::IgnoredOption);
+
+        // Unsigned 16-bit samples stored with the usual offset
+        let mut bytes = [
+            card("SIMPLE", "T"),
+            card("BITPIX", "16"),
+            card("NAXIS", "3"),
+            card("NAXIS1", "1"),
+            card("NAXIS2", "1"),
+            card("NAXIS3", "3"),
+            card("BZERO", "3.2768D4"),
+            card("BLANK", "-32768"),
+            format!("{:<80}", "END"),
+        ]
+        .concat()
+        .into_bytes();
+        bytes.resize(BLOCK, b' ');
+        for sample in [-32767i16, 0, 32767] {
+            bytes.extend(sample.to_be_bytes());
+        }
+        let image = decode_fits(&bytes, false).unwrap();
+        assert_eq!(image.bit_depth, Some("16-bit integer"));
+        assert_eq!(interleave(&image.channels), vec![[1.0, 32768.0, 65535.0]]);
+    }
+
+    #[test]
+    fn deinterleaves_single_planes() {
+        // Smooth channels of quite different levels, as a colour image has
+        let pixels = (0..40 * 30)
+            .map(|n| {
+                let (x, y) = ((n % 40) as f32, (n / 40) as f32);
+                [0.3 + 0.001 * x, 0.6 + 0.001 * y, 0.1 + 0.0005 * (x + y)]
+            })
+            .collect::<Vec<_>>();
+        let plane = Array2::from_shape_vec((30, 120), pixels.concat()).unwrap();
+        let path = std::env::temp_dir().join(format!("duosplit-plane-{}.fit", std::process::id()));
+        write_fits(&path, &plane, &[]).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let image = decode_fits(&bytes, false).unwrap();
+        assert_eq!(interleave(&image.channels), pixels);
+        assert_eq!(image.warnings[0].kind, WarningKind::GuessedLayout);
+        assert!(decode_fits(&bytes, true).unwrap().warnings.is_empty());
+
+        // The planes one after another look like a mono image, so need the override
+        let planar = Array2::from_shape_fn((30, 120), |(y, x)| pixels[y * 40 + x % 40][x / 40]);
+        assert!(!looks_interleaved(planar.as_slice().unwrap(), 120));
+        let mono = Array2::from_shape_fn((30, 120), |(y, x)| 0.2 + 0.001 * (x + y) as f32);
+        assert!(!looks_interleaved(mono.as_slice().unwrap(), 120));
+    }
+
+    #[test]
+    fn detects_stretched_images() {
+        // A faint sky with a few bright stars, as linear data looks
+        let linear = Array2::from_shape_fn((50, 50), |(y, x)| {
+            if (y * 50 + x) % 97 == 0 {
+                1.0
+            } else {
+                0.01 + 0.001 * (x % 7) as f32
+            }
+        });
+        let stretched = linear.mapv(|v| (v * 40.0).sqrt().min(1.0));
+        let channels = |channel: &Array2<f32>| (channel.clone(), channel.clone(), channel.clone());
+        assert!(!looks_stretched(&channels(&linear)));
+        assert!(looks_stretched(&channels(&stretched)));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::gpu::QEUniform;
+use crate::messages::text;
 use bytemuck::{Pod, Zeroable};
 use rand::Rng;
 
@@ -9,17 +11,113 @@ pub struct Genome {
 }
 
 impl Genome {
-    pub fn random(rng: &mut impl Rng) -> Self {
+    /// A genome with each coefficient drawn uniformly from its half-width in `range` either
+    /// side of `center`
+    pub fn random(rng: &mut impl Rng, center: Genome, (range_i, range_x): (f32, f32)) -> Self {
         Self {
-            i: rng.random_range(-1.0..1.0),
-            x: rng.random_range(-1.0..1.0)
+            i: center.i + rng.random_range(-range_i..range_i),
+            x: center.x + rng.random_range(-range_x..range_x),
         }
     }
 }
 
 pub fn j_k_from_i(i: f32, a: f32, c: f32, e: f32, b: f32, d: f32, f: f32) -> (f32, f32) {
     let denom = d * e - c * f;
-    let j = (d + b * c * i - a * d * i) / denom;
-    let k = (-f - b * e * i + a * f * i) / denom;
+    let j = (-f - b * e * i + a * f * i) / denom;
+    let k = (d + b * c * i - a * d * i) / denom;
     (j, k)
-}
\ No newline at end of file
+}
+
+/// Red, green and blue coefficients of the H-alpha and OIII combinations a genome stands for
+pub fn line_coefficients(
+    genome: &Genome,
+    (r, g, b): &(QEUniform, QEUniform, QEUniform),
+) -> ([f32; 3], [f32; 3]) {
+    let (ha_g, ha_b) = j_k_from_i(genome.i, r.ha, g.ha, b.ha, r.oiii, g.oiii, b.oiii);
+    let (oiii_g, oiii_b) = j_k_from_i(genome.x, r.oiii, g.oiii, b.oiii, r.ha, g.ha, b.ha);
+    ([genome.i, ha_g, ha_b], [genome.x, oiii_g, oiii_b])
+}
+
+/// Red coefficients of the minimum-norm combinations extracting H-alpha and OIII, which
+/// predict where the solved i and x lie from the quantum efficiencies alone
+pub fn predicted_red_coefficients((r, g, b): &(QEUniform, QEUniform, QEUniform)) -> (f32, f32) {
+    let dot = |a: fn(&QEUniform) -> f32, c: fn(&QEUniform) -> f32| {
+        a(r) * c(r) + a(g) * c(g) + a(b) * c(b)
+    };
+    let ha_ha = dot(|qe| qe.ha, |qe| qe.ha);
+    let oiii_oiii = dot(|qe| qe.oiii, |qe| qe.oiii);
+    let ha_oiii = dot(|qe| qe.ha, |qe| qe.oiii);
+    let det = ha_ha * oiii_oiii - ha_oiii * ha_oiii;
+    (
+        (r.ha * oiii_oiii - r.oiii * ha_oiii) / det,
+        (r.oiii * ha_ha - r.ha * ha_oiii) / det,
+    )
+}
+
+/// The predicted red coefficients as a genome to start the search from, or `None` if the
+/// quantum efficiencies can't tell the lines apart
+pub fn analytic_seed(qe: &(QEUniform, QEUniform, QEUniform)) -> Option<Genome> {
+    let (i, x) = predicted_red_coefficients(qe);
+    (i.is_finite() && x.is_finite()).then_some(Genome { i, x })
+}
+
+/// Whether the solved genome matches the predicted red coefficients better with i and x
+/// exchanged, returning the decision and an explanation of it
+pub fn should_swap(genome: &Genome, qe: &(QEUniform, QEUniform, QEUniform)) -> (bool, String) {
+    let (predicted_i, predicted_x) = predicted_red_coefficients(qe);
+    let kept = (genome.i - predicted_i).abs() + (genome.x - predicted_x).abs();
+    let swapped = (genome.x - predicted_i).abs() + (genome.i - predicted_x).abs();
+    let swap = swapped < kept;
+    let args: [(&str, &dyn std::fmt::Display); 4] = [
+        ("predicted-h-alpha", &predicted_i),
+        ("predicted-oiii", &predicted_x),
+        ("h-alpha", &genome.i),
+        ("oiii", &genome.x),
+    ];
+    let reason = if swap {
+        text("swap-check-swapped", &args)
+    } else {
+        text("swap-check-kept", &args)
+    };
+    (swap, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_one_line_and_cancels_the_other() {
+        let (a, c, e) = (0.8, 0.1, 0.05);
+        let (b, d, f) = (0.05, 0.6, 0.5);
+        let i = 1.25;
+        let (j, k) = j_k_from_i(i, a, c, e, b, d, f);
+        assert!((a * i + c * j + e * k - 1.0).abs() < 1e-5);
+        assert!((b * i + d * j + f * k).abs() < 1e-5);
+    }
+
+    #[test]
+    fn swaps_only_when_roles_are_exchanged() {
+        let qe = (
+            QEUniform {
+                ha: 0.8,
+                oiii: 0.05,
+            },
+            QEUniform { ha: 0.1, oiii: 0.6 },
+            QEUniform {
+                ha: 0.05,
+                oiii: 0.5,
+            },
+        );
+        let solved = Genome {
+            i: 1.2565,
+            x: -0.1696,
+        };
+        assert!(!should_swap(&solved, &qe).0);
+        let exchanged = Genome {
+            i: solved.x,
+            x: solved.i,
+        };
+        assert!(should_swap(&exchanged, &qe).0);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::cli::SolverArgs;
+use crate::messages::text;
+use crate::optimizer::{GaSettings, Outcome};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Everything a seeded run decided, for checking that a later run reproduces it exactly
+#[derive(Serialize, Deserialize)]
+pub struct GoldenRun {
+    pub seed: u64,
+    pub evaluator: String,
+    pub optimizer: String,
+    pub evaluations: u32,
+    pub population_size: usize,
+    pub generations: u32,
+    pub elitism: usize,
+    pub initial_std: f32,
+    pub decay_rate: f32,
+    pub decay_schedule: String,
+    pub selection: String,
+    pub niche_radius: Option<f32>,
+    pub bounds: Option<(f32, f32)>,
+    pub batch_generations: u32,
+    // Recordings from before the initialization was configurable used the defaults
+    #[serde(default = "default_init_range")]
+    pub init_range: f32,
+    #[serde(default = "default_init_distribution")]
+    pub init_distribution: String,
+    // Best genome and fitness of each generation, as (i, x, fitness)
+    pub history: Vec<(f32, f32, f32)>,
+    pub genome: (f32, f32),
+    // Why the run was judged not to have converged, if it wasn't
+    #[serde(default)]
+    pub non_convergence: Option<String>,
+}
+
+fn default_init_range() -> f32 {
+    1.0
+}
+
+fn default_init_distribution() -> String {
+    "uniform".into()
+}
+
+impl GoldenRun {
+    pub fn new(
+        seed: u64,
+        evaluator: &str,
+        settings: &GaSettings,
+        args: &SolverArgs,
+        outcome: &Outcome,
+    ) -> Self {
+        Self {
+            seed,
+            evaluator: evaluator.into(),
+            optimizer: args
+                .optimizer
+                .to_possible_value()
+                .expect("optimizers are all selectable")
+                .get_name()
+                .into(),
+            evaluations: args.evaluations,
+            population_size: settings.population_size,
+            generations: settings.generations,
+            elitism: settings.elitism,
+            initial_std: settings.initial_std,
+            decay_rate: settings.decay_rate,
+            decay_schedule: settings
+                .decay_schedule
+                .to_possible_value()
+                .expect("decay schedules are all selectable")
+                .get_name()
+                .into(),
+            selection: settings
+                .selection
+                .to_possible_value()
+                .expect("selection methods are all selectable")
+                .get_name()
+                .into(),
+            niche_radius: settings.niche_radius,
+            bounds: settings.bounds,
+            batch_generations: args.batch_generations,
+            init_range: settings.init_range,
+            init_distribution: settings
+                .init_distribution
+                .to_possible_value()
+                .expect("initial distributions are all selectable")
+                .get_name()
+                .into(),
+            history: outcome
+                .history
+                .iter()
+                .map(|best| (best.genome.i, best.genome.x, best.fitness))
+                .collect(),
+            genome: (outcome.genome.i, outcome.genome.x),
+            non_convergence: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| text("read-failed", &[("path", &path.display()), ("error", &e)]))?;
+        serde_json::from_str(&json)
+            .map_err(|e| text("parse-failed", &[("path", &path.display()), ("error", &e)]))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).expect("golden runs always serialize");
+        fs::write(path, json)
+            .map_err(|e| text("write-failed", &[("path", &path.display()), ("error", &e)]))
+    }
+
+    /// Describes the first difference from `expected`, comparing floats bit for bit
+    pub fn first_difference(&self, expected: &GoldenRun) -> Option<String> {
+        let settings = |run: &GoldenRun| {
+            (
+                run.seed,
+                run.evaluator.clone(),
+                (run.optimizer.clone(), run.evaluations),
+                run.population_size,
+                run.generations,
+                run.elitism,
+                run.initial_std.to_bits(),
+                run.decay_rate.to_bits(),
+                run.decay_schedule.clone(),
+                run.selection.clone(),
+                (
+                    run.niche_radius.map(f32::to_bits),
+                    run.bounds.map(|(min, max)| (min.to_bits(), max.to_bits())),
+                ),
+                (
+                    run.batch_generations,
+                    run.init_range.to_bits(),
+                    run.init_distribution.clone(),
+                ),
+            )
+        };
+        if settings(self) != settings(expected) {
+            return Some(text(
+                "recorded-settings-differ",
+                &[
+                    ("seed", &expected.seed),
+                    ("evaluator", &expected.evaluator),
+                    ("optimizer", &expected.optimizer),
+                    ("evaluations", &expected.evaluations),
+                    ("population", &expected.population_size),
+                    ("generations", &expected.generations),
+                    ("elitism", &expected.elitism),
+                    ("initial-std", &expected.initial_std),
+                    ("decay-rate", &expected.decay_rate),
+                    ("decay-schedule", &expected.decay_schedule),
+                    ("selection", &expected.selection),
+                    ("niche-radius", &format!("{:?}", expected.niche_radius)),
+                    ("bounds", &format!("{:?}", expected.bounds)),
+                    ("batch", &expected.batch_generations),
+                    ("init-distribution", &expected.init_distribution),
+                    ("init-range", &expected.init_range),
+                ],
+            ));
+        }
+
+        let bits = |(i, x, fitness): (f32, f32, f32)| (i.to_bits(), x.to_bits(), fitness.to_bits());
+        for (gen, (&actual, &recorded)) in self.history.iter().zip(&expected.history).enumerate() {
+            if bits(actual) != bits(recorded) {
+                return Some(text(
+                    "recorded-generation-differs",
+                    &[
+                        ("generation", &gen),
+                        ("recorded-i", &recorded.0),
+                        ("recorded-x", &recorded.1),
+                        ("recorded-noise", &recorded.2),
+                        ("i", &actual.0),
+                        ("x", &actual.1),
+                        ("noise", &actual.2),
+                    ],
+                ));
+            }
+        }
+        if self.history.len() != expected.history.len() {
+            return Some(text(
+                "recorded-generations-differ",
+                &[
+                    ("recorded", &expected.history.len()),
+                    ("generations", &self.history.len()),
+                ],
+            ));
+        }
+        None
+    }
+}
@@ -1,50 +1,269 @@
+use crate::background::Background;
+use crate::cli::SolverArgs;
+use crate::composite::Composite;
+use crate::evaluator::Evaluator;
 use crate::genetics::Genome;
+use crate::messages::text;
+use crate::moments::ImageMoments;
+use crate::optimizer::Selection;
 use bytemuck::{Pod, Zeroable};
-use wgpu::util::{BufferInitDescriptor, DeviceExt};
-use wgpu::wgt::PollType;
+use clap::ValueEnum;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::num::NonZeroU64;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use wgpu::util::{BufferInitDescriptor, DeviceExt, TextureDataOrder};
+use wgpu::wgt::{PollError, PollType};
 use wgpu::{
-    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferUsages,
+    Adapter, AdapterInfo, Backend, Backends, BindGroup, BindGroupDescriptor, BindGroupEntry,
+    BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
+    Buffer, BufferBinding, BufferBindingType, BufferDescriptor, BufferUsages, CommandEncoder,
     CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor,
-    Device, DeviceDescriptor, Instance, InstanceDescriptor, Limits, MapMode,
-    PipelineLayoutDescriptor, Queue, RequestAdapterOptions, ShaderModuleDescriptor, ShaderSource,
-    ShaderStages,
+    Device, DeviceDescriptor, DeviceType, ErrorFilter, Extent3d, Features, Instance,
+    InstanceDescriptor, Limits, MapMode, PipelineCache, PipelineCacheDescriptor,
+    PipelineCompilationOptions, PipelineLayoutDescriptor, PowerPreference, Queue,
+    RequestAdapterOptions, ShaderModuleDescriptor, ShaderSource, ShaderStages, SubmissionIndex,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureViewDescriptor, TextureViewDimension, MAP_ALIGNMENT,
 };
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[derive(Debug, Copy, Clone, Default, Pod, Zeroable)]
 pub struct QEUniform {
     pub ha: f32,
     pub oiii: f32,
 }
 
-pub struct GpuContext {
+/// Parameters of one generation of the GPU genetic algorithm
+pub struct EvolveStep {
+    pub seed: u32,
+    pub mutation_rate: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct BestGenome {
+    pub genome: Genome,
+    pub fitness: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct EvolveParams {
+    elitism: u32,
+    generation: u32,
+    seed: u32,
+    mutation_rate: f32,
+    selection: u32,
+}
+
+/// A population kept on the GPU between batches of generations
+pub struct GpuPopulation {
+    // The population is evolved from one buffer into the other each generation
+    buffers: [Buffer; 2],
+    current: usize,
+    fitness_buffer: Buffer,
+    // Index of the genome at each rank, filled by the rank pass
+    order_buffer: Buffer,
+    len: usize,
+}
+
+// Invocations per workgroup of the fitness and evolve kernels, one genome each
+const FITNESS_WORKGROUP_SIZE: u32 = 64;
+// Chunks summed per workgroup of the sequential moments kernel
+const MOMENTS_WORKGROUP_SIZE: u32 = 64;
+// Invocations cooperating on one chunk in the subgroup moments kernel
+const SUBGROUP_WORKGROUP_SIZE: u32 = 64;
+// WGSL files kernels can pull in with an `#include` line
+const SHADER_INCLUDES: &[(&str, &str)] = &[
+    ("types.wgsl", include_str!("types.wgsl")),
+    ("pixels.wgsl", include_str!("pixels.wgsl")),
+];
+// Invocations per workgroup of the apply and composite kernels, one pixel each
+const APPLY_WORKGROUP_SIZE: u32 = 256;
+// Pixels summed per chunk of the moments pass when the chunk count is picked automatically
+const TARGET_CHUNK_PIXELS: usize = 4096;
+// Longest a readback blocks the thread before yielding back to the executor
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+// Times a failed readback is retried, in twice as many pieces each time
+const MAP_RETRIES: u32 = 3;
+
+/// Kind of GPU to prefer on systems with more than one
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum PowerClass {
+    /// The most battery-friendly adapter, usually an integrated GPU
+    Low,
+    /// The fastest adapter, usually a discrete GPU
+    High,
+}
+
+/// Graphics API to run the kernels on
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum GpuBackend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl GpuBackend {
+    fn backends(self) -> Backends {
+        match self {
+            GpuBackend::Vulkan => Backends::VULKAN,
+            GpuBackend::Metal => Backends::METAL,
+            GpuBackend::Dx12 => Backends::DX12,
+            GpuBackend::Gl => Backends::GL,
+        }
+    }
+
+    fn of(backend: Backend) -> Option<Self> {
+        match backend {
+            Backend::Vulkan => Some(GpuBackend::Vulkan),
+            Backend::Metal => Some(GpuBackend::Metal),
+            Backend::Dx12 => Some(GpuBackend::Dx12),
+            Backend::Gl => Some(GpuBackend::Gl),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GpuSettings {
+    // None picks the chunk count with `auto_chunks`
+    pub chunks: Option<usize>,
+    pub print_info: bool,
+    /// Print nothing unless asked to with `print_info`
+    pub quiet: bool,
+    pub allow_software: bool,
+    pub power_preference: PowerPreference,
+    pub backends: Backends,
+}
+
+impl GpuSettings {
+    pub fn from_args(args: &SolverArgs) -> Self {
+        Self {
+            chunks: args.chunks.map(|chunks| chunks as usize),
+            print_info: args.gpu_info,
+            quiet: args.quiet,
+            allow_software: args.allow_software_gpu,
+            // Without the option, WGPU_POWER_PREF still works as in other wgpu programs
+            power_preference: match args.power_preference {
+                Some(PowerClass::Low) => PowerPreference::LowPower,
+                Some(PowerClass::High) => PowerPreference::HighPerformance,
+                None => PowerPreference::from_env().unwrap_or_default(),
+            },
+            backends: match args.backend {
+                Some(backend) => backend.backends(),
+                None => Backends::from_env().unwrap_or_default(),
+            },
+        }
+    }
+}
+
+/// A GPU device with the fitness, evolve and apply kernels built for it. Clones share the
+/// device, its kernels and its staging buffers, so several images can be solved on one
+/// device at once without building the kernels again.
+#[derive(Clone)]
+pub struct GpuDevice {
     device: Device,
     queue: Queue,
     pipeline: ComputePipeline,
     layout: BindGroupLayout,
+    rank_pipeline: ComputePipeline,
+    breed_pipeline: ComputePipeline,
+    evolve_layout: BindGroupLayout,
+    apply_pipeline: ComputePipeline,
+    apply_layout: BindGroupLayout,
+    pipeline_cache: Option<PipelineCache>,
+    cache_path: Option<PathBuf>,
+    info: AdapterInfo,
+    adapter_limits: Limits,
+    chunks: Option<usize>,
+    print_info: bool,
+    quiet: bool,
+    // Most genomes one fitness dispatch can evaluate
+    max_batch: usize,
+    staging: Arc<StagingPool>,
+    // First device loss or uncaptured error reported by wgpu, which would otherwise panic
+    lost: Arc<Mutex<Option<String>>>,
+}
+
+/// One image on a [`GpuDevice`], with the buffers only its own evaluations use
+pub struct GpuContext {
+    gpu: GpuDevice,
+    // Kept after the moments pass so the solved coefficients can be applied without another
+    // upload
     image_buffer: Buffer,
-    chunks: usize,
-    image_len: usize,
+    moments_buffer: Buffer,
     quantum_efficiencies: (Buffer, Buffer, Buffer),
+    timings: Mutex<GpuTimings>,
 }
 
-impl GpuContext {
-    pub async fn new(
-        image: Vec<[f32; 3]>,
-        chunks: usize,
-        quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
-    ) -> Result<Self, String> {
-        let instance = Instance::new(&InstanceDescriptor::from_env_or_default());
-        let adapter = instance
-            .request_adapter(&RequestAdapterOptions::default())
+/// Time spent waiting on the GPU and copying results back, accumulated over every
+/// generation evaluated so far
+#[derive(Debug, Copy, Clone, Default)]
+pub struct GpuTimings {
+    pub generations: u32,
+    pub kernels: Duration,
+    pub readback: Duration,
+}
+
+struct Readback<T> {
+    data: Vec<T>,
+    // From submission until the results were mapped
+    gpu_time: Duration,
+    // Copying the mapped results into host memory
+    copy_time: Duration,
+}
+
+impl GpuDevice {
+    /// Picks an adapter as `settings` asks and builds the kernels on its device
+    pub async fn new(settings: &GpuSettings) -> Result<Self, String> {
+        let mut descriptor = InstanceDescriptor::from_env_or_default();
+        descriptor.backends = settings.backends;
+        if descriptor.backends.is_empty() {
+            return Err(text("gpu-no-backends-left", &[]));
+        }
+        let instance = Instance::new(&descriptor);
+        let adapter = match instance
+            .request_adapter(&RequestAdapterOptions {
+                power_preference: settings.power_preference,
+                ..Default::default()
+            })
             .await
-            .unwrap();
-        let image_chunk_size = image.len() * size_of::<[f32; 3]>() / chunks;
-        if image_chunk_size > adapter.limits().max_buffer_size as usize
-            || image_chunk_size > adapter.limits().max_storage_buffer_binding_size as usize
         {
-            return Err("Image chunk size exceeds maximum buffer size for the GPU adapter. You must increase the chunk amount in order to process the image".into());
+            Ok(adapter) => adapter,
+            Err(_) if settings.allow_software => instance
+                .request_adapter(&RequestAdapterOptions {
+                    power_preference: settings.power_preference,
+                    force_fallback_adapter: true,
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| text("gpu-no-software-adapter", &[("message", &e)]))?,
+            Err(e) => return Err(text("gpu-no-adapter", &[("message", &e)])),
+        };
+        let info = adapter.get_info();
+        let mut features = adapter.features() & (Features::PIPELINE_CACHE | Features::SUBGROUP);
+        // Integrated GPUs such as Apple Silicon share memory with the CPU, so storage buffers
+        // can be mapped in place; on discrete GPUs that would be slower than a staging copy
+        if info.device_type == DeviceType::IntegratedGpu {
+            features |= adapter.features() & Features::MAPPABLE_PRIMARY_BUFFERS;
+        }
+        if settings.print_info {
+            print_adapter_info(&adapter, features);
+        } else if !settings.quiet {
+            let backend = format!("{:?}", info.backend);
+            println!(
+                "{}",
+                text("gpu-using", &[("name", &info.name), ("backend", &backend)])
+            );
         }
         let (device, queue) = adapter
             .request_device(&DeviceDescriptor {
@@ -58,116 +277,238 @@ impl GpuContext {
                         .max_uniform_buffer_binding_size,
                     ..Default::default()
                 },
+                required_features: features,
                 ..Default::default()
             })
             .await
-            .unwrap();
+            .map_err(|e| text("gpu-request-device-failed", &[("message", &e)]))?;
+        let lost = Arc::new(Mutex::new(None));
+        let on_lost = Arc::clone(&lost);
+        device.set_device_lost_callback(move |reason, message| {
+            let reason = format!("{:?}", reason);
+            on_lost.lock().unwrap().get_or_insert(text(
+                "gpu-device-lost",
+                &[("reason", &reason), ("message", &message)],
+            ));
+        });
+        let on_error = Arc::clone(&lost);
+        device.on_uncaptured_error(Arc::new(move |error: wgpu::Error| {
+            on_error
+                .lock()
+                .unwrap()
+                .get_or_insert(text("gpu-error", &[("message", &error)]));
+        }));
+
+        let cache_path = pipeline_cache_path(&adapter.get_info());
+        let pipeline_cache = load_pipeline_cache(&device, cache_path.as_deref());
+
+        device.push_error_scope(ErrorFilter::Validation);
         let alg_shader = device.create_shader_module(ShaderModuleDescriptor {
-            label: None,
-            source: ShaderSource::Wgsl(include_str!("fit.wgsl").into()),
+            label: Some("Fitness Shader"),
+            source: ShaderSource::Wgsl(compose_shader(include_str!("fit.wgsl")).into()),
         });
 
         let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: None,
             entries: &[
                 // Genomes
-                BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::COMPUTE,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
+                storage_entry(0, true),
                 // Fitness
-                BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: ShaderStages::COMPUTE,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Image
-                BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: ShaderStages::COMPUTE,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
+                storage_entry(1, false),
+                // Image moments
+                uniform_entry(2),
                 // QE uniforms (R, G, B)
-                BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: ShaderStages::COMPUTE,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
+                uniform_entry(3),
+                uniform_entry(4),
+                uniform_entry(5),
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            module: &alg_shader,
+            entry_point: "main".into(),
+            compilation_options: PipelineCompilationOptions {
+                constants: &[("workgroup_x", FITNESS_WORKGROUP_SIZE as f64)],
+                ..Default::default()
+            },
+            cache: pipeline_cache.as_ref(),
+        });
+        let evolve_shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Evolve Shader"),
+            source: ShaderSource::Wgsl(compose_shader(include_str!("evolve.wgsl")).into()),
+        });
+        let evolve_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                // Population
+                storage_entry(0, true),
+                // Fitness
+                storage_entry(1, true),
+                // Next population
+                storage_entry(2, false),
+                // Best genome of each generation
+                storage_entry(3, false),
+                // Per-generation parameters
                 BindGroupLayoutEntry {
                     binding: 4,
                     visibility: ShaderStages::COMPUTE,
                     ty: BindingType::Buffer {
                         ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                BindGroupLayoutEntry {
-                    binding: 5,
-                    visibility: ShaderStages::COMPUTE,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Slice and slices
-                BindGroupLayoutEntry {
-                    binding: 6,
-                    visibility: ShaderStages::COMPUTE,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
+                        has_dynamic_offset: true,
                         min_binding_size: None,
                     },
                     count: None,
                 },
+                // Index of the genome at each rank
+                storage_entry(5, false),
             ],
         });
-
-        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        let evolve_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&layout],
+            bind_group_layouts: &[&evolve_layout],
             push_constant_ranges: &[],
         });
-
-        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+        let [rank_pipeline, breed_pipeline] = ["rank", "breed"].map(|entry_point| {
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some("Evolve Pipeline"),
+                layout: Some(&evolve_pipeline_layout),
+                module: &evolve_shader,
+                entry_point: entry_point.into(),
+                compilation_options: PipelineCompilationOptions {
+                    constants: &[("workgroup_x", FITNESS_WORKGROUP_SIZE as f64)],
+                    ..Default::default()
+                },
+                cache: pipeline_cache.as_ref(),
+            })
+        });
+        let apply_shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Apply Shader"),
+            source: ShaderSource::Wgsl(compose_shader(include_str!("apply.wgsl")).into()),
+        });
+        let apply_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: None,
-            layout: Some(&pipeline_layout),
-            module: &alg_shader,
+            entries: &[
+                // Image
+                storage_entry(0, true),
+                // H-alpha and OIII values
+                storage_entry(1, false),
+                // Coefficients
+                uniform_entry(2),
+            ],
+        });
+        let apply_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Apply Pipeline"),
+            layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&apply_layout],
+                push_constant_ranges: &[],
+            })),
+            module: &apply_shader,
             entry_point: "main".into(),
-            compilation_options: Default::default(),
-            cache: None,
+            compilation_options: PipelineCompilationOptions {
+                constants: &[("workgroup_x", APPLY_WORKGROUP_SIZE as f64)],
+                ..Default::default()
+            },
+            cache: pipeline_cache.as_ref(),
         });
+        if let Some(err) = device.pop_error_scope().await {
+            let message = err.to_string();
+            return Err(format!(
+                "{}\n{}",
+                text("gpu-kernels-failed", &[("message", &message.trim_end())]),
+                backend_hint(&info)
+            ));
+        }
+
+        if let Some(err) = lost.lock().unwrap().take() {
+            return Err(err);
+        }
+        let max_batch = max_fitness_batch(&device.limits());
+        Ok(Self {
+            device,
+            queue,
+            layout,
+            pipeline,
+            evolve_layout,
+            rank_pipeline,
+            breed_pipeline,
+            apply_pipeline,
+            apply_layout,
+            pipeline_cache,
+            cache_path,
+            info,
+            adapter_limits: adapter.limits(),
+            chunks: settings.chunks,
+            print_info: settings.print_info,
+            quiet: settings.quiet,
+            max_batch,
+            staging: Arc::default(),
+            lost,
+        })
+    }
 
+    /// Uploads `image` and computes its moments, for evaluating genomes against it. Each
+    /// context has its own buffers, so contexts on the same device don't interfere.
+    pub async fn context(
+        &self,
+        image: &[[f32; 3]],
+        background: Option<&Background>,
+        quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+    ) -> Result<GpuContext, String> {
+        let device = &self.device;
+        let chunks = self.chunks.unwrap_or_else(|| {
+            auto_chunks(
+                image.len(),
+                device.features().contains(Features::SUBGROUP),
+                &self.adapter_limits,
+            )
+        });
+        if self.print_info {
+            println!("{}", text("gpu-moments-chunks", &[("chunks", &chunks)]));
+        }
+        let image_chunk_size = size_of_val(image) / chunks;
+        if image_chunk_size > self.adapter_limits.max_buffer_size as usize
+            || image_chunk_size > self.adapter_limits.max_storage_buffer_binding_size as usize
+        {
+            return Err(text("gpu-chunk-too-large", &[]));
+        }
         let image_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("Image Buffer"),
-            contents: bytemuck::cast_slice(&image),
+            contents: bytemuck::cast_slice(image),
             usage: BufferUsages::STORAGE,
         });
+        let moments = compute_moments(
+            device,
+            &self.queue,
+            &image_buffer,
+            background,
+            chunks,
+            self.pipeline_cache.as_ref(),
+            &self.staging,
+        )
+        .await
+        .map_err(|err| format!("{}\n{}", err, backend_hint(&self.info)))?;
+        // Saved once the moments kernel, which each image size builds anew, is in the cache
+        if let (Some(cache), Some(path)) = (&self.pipeline_cache, &self.cache_path) {
+            if let (Err(err), false) = (store_pipeline_cache(cache, path), self.quiet) {
+                let message = text("gpu-pipeline-cache-not-saved", &[("message", &err)]);
+                eprintln!("{}", text("warning", &[("message", &message)]));
+            }
+        }
+
+        let moments_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Moments Buffer"),
+            contents: bytemuck::bytes_of(&moments),
+            usage: BufferUsages::UNIFORM,
+        });
 
         let qe_red_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("QE Red Buffer"),
@@ -187,46 +528,502 @@ impl GpuContext {
             usage: BufferUsages::UNIFORM,
         });
 
-        Ok(Self {
-            device,
-            queue,
-            layout,
-            pipeline,
+        self.check()?;
+        Ok(GpuContext {
+            gpu: self.clone(),
             image_buffer,
-            chunks,
-            image_len: image.len(),
+            moments_buffer,
             quantum_efficiencies: (qe_red_buffer, qe_green_buffer, qe_blue_buffer),
+            timings: Mutex::default(),
         })
     }
 
-    pub async fn compute_fitness(&self, genomes: &[Genome]) -> Vec<f32> {
-        let genome_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Genome Buffer"),
-            contents: bytemuck::cast_slice(genomes),
+    /// Evaluates `composite` on every pixel's H-alpha and OIII values, returning its red,
+    /// green and blue. The kernel is built from the expression, so it isn't kept between
+    /// calls.
+    pub async fn composite(
+        &self,
+        composite: &Composite,
+        lines: &[[f32; 2]],
+    ) -> Result<Vec<[f32; 3]>, String> {
+        let device = &self.device;
+        device.push_error_scope(ErrorFilter::Validation);
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Composite Shader"),
+            source: ShaderSource::Wgsl(composite_shader(composite).into()),
+        });
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                // H-alpha and OIII values
+                storage_entry(0, true),
+                // Colors
+                storage_entry(1, false),
+            ],
+        });
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Composite Pipeline"),
+            layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            })),
+            module: &shader,
+            entry_point: "main".into(),
+            compilation_options: PipelineCompilationOptions {
+                constants: &[("workgroup_x", APPLY_WORKGROUP_SIZE as f64)],
+                ..Default::default()
+            },
+            cache: self.pipeline_cache.as_ref(),
+        });
+        if let Some(err) = device.pop_error_scope().await {
+            let message = err.to_string();
+            return Err(text(
+                "gpu-composite-kernel-failed",
+                &[("message", &message.trim_end())],
+            ));
+        }
+
+        let lines_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Lines Buffer"),
+            contents: bytemuck::cast_slice(lines),
             usage: BufferUsages::STORAGE,
         });
+        let line_size = size_of::<[f32; 2]>() as u64;
+        let batch = max_pixel_batch(&device.limits(), size_of::<[f32; 4]>());
+        let mut colors = Vec::with_capacity(lines.len());
+        for start in (0..lines.len()).step_by(batch) {
+            let len = batch.min(lines.len() - start) as u64;
+            self.check()?;
+            let colors_buffer = device.create_buffer(&BufferDescriptor {
+                label: Some("Colors Buffer"),
+                size: len * size_of::<[f32; 4]>() as u64,
+                usage: readback_usage(device),
+                mapped_at_creation: false,
+            });
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                layout: &layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer: &lines_buffer,
+                            offset: start as u64 * line_size,
+                            size: NonZeroU64::new(len * line_size),
+                        }),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: colors_buffer.as_entire_binding(),
+                    },
+                ],
+                label: None,
+            });
+            let mut encoder =
+                device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+            {
+                let mut cpass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                    label: None,
+                    timestamp_writes: None,
+                });
+                cpass.set_pipeline(&pipeline);
+                cpass.set_bind_group(0, &bind_group, &[]);
+                cpass.dispatch_workgroups((len as u32).div_ceil(APPLY_WORKGROUP_SIZE), 1, 1);
+            }
+            let readback = read_buffer::<[f32; 4]>(
+                device,
+                &self.queue,
+                &self.staging,
+                encoder,
+                &colors_buffer,
+            )
+            .await;
+            self.check()?;
+            colors.extend(readback?.data.into_iter().map(|[r, g, b, _]| [r, g, b]));
+        }
+        Ok(colors)
+    }
+
+    /// Fails once the device has been lost or reported an error, after which none of its
+    /// results can be trusted
+    fn check(&self) -> Result<(), String> {
+        match &*self.lost.lock().unwrap() {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl GpuContext {
+    /// Sets up a device of its own for `image`; [`GpuDevice::context`] shares one instead
+    pub async fn new(
+        image: &[[f32; 3]],
+        background: Option<&Background>,
+        settings: &GpuSettings,
+        quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+    ) -> Result<Self, String> {
+        GpuDevice::new(settings)
+            .await?
+            .context(image, background, quantum_efficiencies)
+            .await
+    }
+
+    /// The device the context is on, for more contexts to share
+    pub fn device(&self) -> &GpuDevice {
+        &self.gpu
+    }
+
+    pub fn timings(&self) -> GpuTimings {
+        *self.timings.lock().unwrap()
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.gpu.info.backend
+    }
+
+    /// Whether the device has been lost or reported an error, rather than a single
+    /// operation failing
+    pub fn is_lost(&self) -> bool {
+        self.gpu.check().is_err()
+    }
+
+    /// Evaluates `genomes`, in as many submissions as it takes to stay within the dispatch
+    /// and binding limits, so populations of any size can be evaluated
+    pub async fn compute_fitness(&self, genomes: &[Genome]) -> Result<Vec<f32>, String> {
+        let mut readback = Readback {
+            data: Vec::with_capacity(genomes.len()),
+            gpu_time: Duration::ZERO,
+            copy_time: Duration::ZERO,
+        };
+        for batch in genomes.chunks(self.gpu.max_batch) {
+            let batch_readback = self.compute_batch_fitness(batch).await?;
+            readback.data.extend(batch_readback.data);
+            readback.gpu_time += batch_readback.gpu_time;
+            readback.copy_time += batch_readback.copy_time;
+        }
+        self.record_timings(1, &readback);
+        Ok(readback.data)
+    }
+
+    async fn compute_batch_fitness(&self, genomes: &[Genome]) -> Result<Readback<f32>, String> {
+        self.gpu.check()?;
+        let genome_buffer = self.upload(
+            "Genome Buffer",
+            bytemuck::cast_slice(genomes),
+            BufferUsages::STORAGE,
+        );
+
+        let fitness_buffer = self.gpu.device.create_buffer(&BufferDescriptor {
+            label: Some("Fitness Buffer"),
+            size: (genomes.len() * size_of::<f32>()) as u64,
+            usage: readback_usage(&self.gpu.device),
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.fitness_bind_group(&genome_buffer, &fitness_buffer);
+
+        let mut encoder = self
+            .gpu
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&self.gpu.pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            let workgroup_count = (genomes.len() as u32).div_ceil(FITNESS_WORKGROUP_SIZE);
+            cpass.dispatch_workgroups(workgroup_count, 1, 1);
+        }
 
-        let fitness = vec![0.0f32; genomes.len() * self.chunks];
-        let fitness_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+        let readback = read_buffer(
+            &self.gpu.device,
+            &self.gpu.queue,
+            &self.gpu.staging,
+            encoder,
+            &fitness_buffer,
+        )
+        .await;
+        // A lost device explains a failed readback better than the readback itself
+        self.gpu.check()?;
+        readback
+    }
+
+    /// Combines the channels of every pixel into H-alpha and OIII values with the given red,
+    /// green and blue coefficients, in batches that stay within the dispatch and binding
+    /// limits
+    pub async fn apply(
+        &self,
+        h_alpha: [f32; 3],
+        oiii: [f32; 3],
+    ) -> Result<(Vec<f32>, Vec<f32>), String> {
+        let pixels = self.image_buffer.size() / size_of::<[f32; 3]>() as u64;
+        let [h_alpha, oiii] = [h_alpha, oiii].map(|[r, g, b]| [r, g, b, 0.0]);
+        let coefficients_buffer = self.upload(
+            "Coefficients Buffer",
+            bytemuck::cast_slice(&[h_alpha, oiii]),
+            BufferUsages::UNIFORM,
+        );
+        let batch = max_pixel_batch(&self.gpu.device.limits(), size_of::<[f32; 3]>()) as u64;
+        let mut lines = (
+            Vec::with_capacity(pixels as usize),
+            Vec::with_capacity(pixels as usize),
+        );
+        for start in (0..pixels).step_by(batch as usize) {
+            let len = batch.min(pixels - start);
+            let values = self
+                .apply_batch(start, len, &coefficients_buffer)
+                .await?
+                .data;
+            for [h_alpha, oiii] in values {
+                lines.0.push(h_alpha);
+                lines.1.push(oiii);
+            }
+        }
+        Ok(lines)
+    }
+
+    async fn apply_batch(
+        &self,
+        start: u64,
+        len: u64,
+        coefficients_buffer: &Buffer,
+    ) -> Result<Readback<[f32; 2]>, String> {
+        self.gpu.check()?;
+        let lines_buffer = self.gpu.device.create_buffer(&BufferDescriptor {
+            label: Some("Lines Buffer"),
+            size: len * size_of::<[f32; 2]>() as u64,
+            usage: readback_usage(&self.gpu.device),
+            mapped_at_creation: false,
+        });
+        let pixel_size = size_of::<[f32; 3]>() as u64;
+        let bind_group = self.gpu.device.create_bind_group(&BindGroupDescriptor {
+            layout: &self.gpu.apply_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &self.image_buffer,
+                        offset: start * pixel_size,
+                        size: NonZeroU64::new(len * pixel_size),
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: lines_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: coefficients_buffer.as_entire_binding(),
+                },
+            ],
+            label: None,
+        });
+
+        let mut encoder = self
+            .gpu
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&self.gpu.apply_pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch_workgroups((len as u32).div_ceil(APPLY_WORKGROUP_SIZE), 1, 1);
+        }
+
+        let readback = read_buffer(
+            &self.gpu.device,
+            &self.gpu.queue,
+            &self.gpu.staging,
+            encoder,
+            &lines_buffer,
+        )
+        .await;
+        self.gpu.check()?;
+        readback
+    }
+
+    pub fn upload_population(&self, population: &[Genome]) -> GpuPopulation {
+        let usage = BufferUsages::STORAGE | BufferUsages::COPY_SRC;
+        let current = self.upload("Population Buffer", bytemuck::cast_slice(population), usage);
+        let next = self.gpu.device.create_buffer(&BufferDescriptor {
+            label: Some("Population Buffer"),
+            size: current.size(),
+            usage,
+            mapped_at_creation: false,
+        });
+        let fitness_buffer = self.gpu.device.create_buffer(&BufferDescriptor {
             label: Some("Fitness Buffer"),
-            contents: bytemuck::cast_slice(&fitness),
-            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            size: (population.len() * size_of::<f32>()) as u64,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
         });
+        let order_buffer = self.gpu.device.create_buffer(&BufferDescriptor {
+            label: Some("Order Buffer"),
+            size: (population.len() * size_of::<u32>()) as u64,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        GpuPopulation {
+            buffers: [current, next],
+            current: 0,
+            fitness_buffer,
+            order_buffer,
+            len: population.len(),
+        }
+    }
+
+    /// Runs one generation per entry of `steps` in a single submission, returning the best
+    /// genome of each generation's evaluated population
+    pub async fn evolve(
+        &self,
+        population: &mut GpuPopulation,
+        elitism: usize,
+        selection: Selection,
+        steps: &[EvolveStep],
+    ) -> Result<Vec<BestGenome>, String> {
+        self.gpu.check()?;
+        let generations = steps.len();
+
+        let params_stride = self.gpu.device.limits().min_uniform_buffer_offset_alignment as usize;
+        let mut params = vec![0u8; params_stride * generations];
+        for (generation, step) in steps.iter().enumerate() {
+            let generation_params = EvolveParams {
+                elitism: elitism as u32,
+                generation: generation as u32,
+                seed: step.seed,
+                mutation_rate: step.mutation_rate,
+                selection: match selection {
+                    Selection::Tournament => 0,
+                    Selection::Rank => 1,
+                    Selection::Sus => 2,
+                },
+            };
+            let start = generation * params_stride;
+            params[start..start + size_of::<EvolveParams>()]
+                .copy_from_slice(bytemuck::bytes_of(&generation_params));
+        }
+        let params_buffer = self.upload("Evolve Params Buffer", &params, BufferUsages::UNIFORM);
 
-        let fitness_staging_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Fitness Staging Buffer"),
-            contents: bytemuck::cast_slice(&fitness),
-            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        let history_buffer = self.gpu.device.create_buffer(&BufferDescriptor {
+            label: Some("History Buffer"),
+            size: (generations * size_of::<BestGenome>()) as u64,
+            usage: readback_usage(&self.gpu.device),
+            mapped_at_creation: false,
         });
 
-        let chunks_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Chunks Buffer"),
-            contents: bytemuck::bytes_of(&(self.chunks as u32)),
-            usage: BufferUsages::UNIFORM,
+        let fitness_bind_groups = [0, 1]
+            .map(|c| self.fitness_bind_group(&population.buffers[c], &population.fitness_buffer));
+        let evolve_bind_groups = [0, 1].map(|c| {
+            self.gpu.device.create_bind_group(&BindGroupDescriptor {
+                layout: &self.gpu.evolve_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: population.buffers[c].as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: population.fitness_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: population.buffers[1 - c].as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: history_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer: &params_buffer,
+                            offset: 0,
+                            size: NonZeroU64::new(size_of::<EvolveParams>() as u64),
+                        }),
+                    },
+                    BindGroupEntry {
+                        binding: 5,
+                        resource: population.order_buffer.as_entire_binding(),
+                    },
+                ],
+                label: None,
+            })
         });
 
-        let bind_group = self.device.create_bind_group(&BindGroupDescriptor {
-            layout: &self.layout,
+        let workgroup_count = (population.len as u32).div_ceil(FITNESS_WORKGROUP_SIZE);
+        let mut encoder = self
+            .gpu
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: None });
+        for generation in 0..generations {
+            let current = population.current;
+            {
+                let mut cpass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                    label: None,
+                    timestamp_writes: None,
+                });
+                cpass.set_pipeline(&self.gpu.pipeline);
+                cpass.set_bind_group(0, &fitness_bind_groups[current], &[]);
+                cpass.dispatch_workgroups(workgroup_count, 1, 1);
+            }
+            {
+                let mut cpass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                    label: None,
+                    timestamp_writes: None,
+                });
+                let offset = (generation * params_stride) as u32;
+                cpass.set_bind_group(0, &evolve_bind_groups[current], &[offset]);
+                cpass.set_pipeline(&self.gpu.rank_pipeline);
+                cpass.dispatch_workgroups(workgroup_count, 1, 1);
+                cpass.set_pipeline(&self.gpu.breed_pipeline);
+                cpass.dispatch_workgroups(workgroup_count, 1, 1);
+            }
+            population.current = 1 - current;
+        }
+
+        let readback = read_buffer(
+            &self.gpu.device,
+            &self.gpu.queue,
+            &self.gpu.staging,
+            encoder,
+            &history_buffer,
+        )
+        .await;
+        // A lost device explains a failed readback better than the readback itself
+        self.gpu.check()?;
+        let readback = readback?;
+        self.record_timings(generations as u32, &readback);
+        Ok(readback.data)
+    }
+
+    /// Creates a buffer holding `contents` through the queue rather than by mapping it at
+    /// creation, which panics instead of reporting an error once the device is lost
+    fn upload(&self, label: &str, contents: &[u8], usage: BufferUsages) -> Buffer {
+        let buffer = self.gpu.device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size: contents.len() as u64,
+            usage: usage | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        self.gpu.queue.write_buffer(&buffer, 0, contents);
+        buffer
+    }
+
+    fn record_timings<T>(&self, generations: u32, readback: &Readback<T>) {
+        let mut timings = self.timings.lock().unwrap();
+        timings.generations += generations;
+        timings.kernels += readback.gpu_time;
+        timings.readback += readback.copy_time;
+    }
+
+    fn fitness_bind_group(&self, genome_buffer: &Buffer, fitness_buffer: &Buffer) -> BindGroup {
+        self.gpu.device.create_bind_group(&BindGroupDescriptor {
+            layout: &self.gpu.layout,
             entries: &[
                 BindGroupEntry {
                     binding: 0,
@@ -238,7 +1035,7 @@ impl GpuContext {
                 },
                 BindGroupEntry {
                     binding: 2,
-                    resource: self.image_buffer.as_entire_binding(),
+                    resource: self.moments_buffer.as_entire_binding(),
                 },
                 BindGroupEntry {
                     binding: 3,
@@ -252,63 +1049,691 @@ impl GpuContext {
                     binding: 5,
                     resource: self.quantum_efficiencies.2.as_entire_binding(),
                 },
-                BindGroupEntry {
-                    binding: 6,
-                    resource: chunks_buffer.as_entire_binding(),
+            ],
+            label: None,
+        })
+    }
+}
+
+/// Describes the adapter whose driver failed and names the other backends with an adapter,
+/// since a kernel one driver rejects usually works through another graphics API
+fn backend_hint(info: &AdapterInfo) -> String {
+    let backend = format!("{:?}", info.backend);
+    let mut hint = text(
+        "gpu-adapter",
+        &[
+            ("name", &info.name),
+            ("backend", &backend),
+            ("driver", &info.driver),
+            ("driver-info", &info.driver_info),
+        ],
+    );
+    let instance = Instance::new(&InstanceDescriptor {
+        backends: Backends::all(),
+        ..InstanceDescriptor::from_env_or_default()
+    });
+    let mut alternatives = instance
+        .enumerate_adapters(Backends::all())
+        .iter()
+        .filter_map(|adapter| GpuBackend::of(adapter.get_info().backend))
+        .filter(|&backend| GpuBackend::of(info.backend) != Some(backend))
+        .map(|backend| backend.to_possible_value().unwrap().get_name().to_owned())
+        .collect::<Vec<_>>();
+    alternatives.sort();
+    alternatives.dedup();
+    hint.push('\n');
+    if alternatives.is_empty() {
+        hint.push_str(&text("gpu-no-other-backend", &[]));
+    } else {
+        let options = alternatives
+            .iter()
+            .map(|backend| format!("--backend {}", backend))
+            .collect::<Vec<_>>()
+            .join(", ");
+        hint.push_str(&text("gpu-try-backend", &[("options", &options)]));
+    }
+    hint
+}
+
+/// The composite kernel with the function generated from `composite` appended
+fn composite_shader(composite: &Composite) -> String {
+    compose_shader(include_str!("composite.wgsl")) + &composite.wgsl()
+}
+
+/// Expands the `#include "file"` lines of a kernel with the shared WGSL files, each at
+/// most once, so kernels can share structs and helpers without duplicating them
+fn compose_shader(source: &str) -> String {
+    let mut composed = String::with_capacity(source.len());
+    let mut included = Vec::new();
+    include_shader(source, &mut composed, &mut included);
+    composed
+}
+
+fn include_shader(source: &str, composed: &mut String, included: &mut Vec<&'static str>) {
+    for line in source.lines() {
+        let Some(name) = line
+            .strip_prefix("#include \"")
+            .and_then(|rest| rest.strip_suffix('"'))
+        else {
+            composed.push_str(line);
+            composed.push('\n');
+            continue;
+        };
+        let &(name, included_source) = SHADER_INCLUDES
+            .iter()
+            .find(|(file, _)| *file == name)
+            .unwrap_or_else(|| panic!("Unknown shader include {}", name));
+        if !included.contains(&name) {
+            included.push(name);
+            include_shader(included_source, composed, included);
+        }
+    }
+}
+
+/// Genomes one fitness dispatch can cover, limited by the workgroups it can launch and by
+/// the size of the genome buffer it can bind
+fn max_fitness_batch(limits: &Limits) -> usize {
+    let dispatch_limit =
+        limits.max_compute_workgroups_per_dimension as usize * FITNESS_WORKGROUP_SIZE as usize;
+    let buffer_limit = limits.max_storage_buffer_binding_size as usize / size_of::<Genome>();
+    dispatch_limit.min(buffer_limit)
+}
+
+/// Pixels one apply or composite dispatch can cover, limited by the workgroups it can launch
+/// and by the size of the slices it can bind, of at most `pixel_size` bytes a pixel. Batches
+/// start at a multiple of this, so it is rounded down to keep their offsets aligned.
+fn max_pixel_batch(limits: &Limits, pixel_size: usize) -> usize {
+    let dispatch_limit =
+        limits.max_compute_workgroups_per_dimension as usize * APPLY_WORKGROUP_SIZE as usize;
+    let buffer_limit = limits.max_storage_buffer_binding_size as usize / pixel_size;
+    let batch = dispatch_limit.min(buffer_limit);
+    batch - batch % limits.min_storage_buffer_offset_alignment as usize
+}
+
+/// Chunk count for the moments pass, which is the only pass that reads the image; the
+/// fitness kernels only see its sums, so the population size plays no part. Each chunk is
+/// summed in f32, so chunks are kept to about `TARGET_CHUNK_PIXELS` pixels for precision,
+/// which also gives a large image enough chunks to fill the device. The count is capped by
+/// the workgroups one dispatch can launch and by the size of the per-chunk sums buffer.
+fn auto_chunks(pixels: usize, subgroups: bool, limits: &Limits) -> usize {
+    let chunks_per_workgroup = if subgroups {
+        1
+    } else {
+        MOMENTS_WORKGROUP_SIZE as usize
+    };
+    let dispatch_limit =
+        limits.max_compute_workgroups_per_dimension as usize * chunks_per_workgroup;
+    let buffer_limit = limits.max_storage_buffer_binding_size as usize / size_of::<ImageMoments>();
+    pixels
+        .div_ceil(TARGET_CHUNK_PIXELS)
+        .min(dispatch_limit)
+        .min(buffer_limit)
+        .max(1)
+}
+
+async fn compute_moments(
+    device: &Device,
+    queue: &Queue,
+    image_buffer: &Buffer,
+    background: Option<&Background>,
+    chunks: usize,
+    pipeline_cache: Option<&PipelineCache>,
+    staging: &StagingPool,
+) -> Result<ImageMoments, String> {
+    let subgroups = device.features().contains(Features::SUBGROUP);
+    let (shader_source, workgroup_size) = if subgroups {
+        (
+            include_str!("moments_subgroup.wgsl"),
+            SUBGROUP_WORKGROUP_SIZE,
+        )
+    } else {
+        (include_str!("moments.wgsl"), MOMENTS_WORKGROUP_SIZE)
+    };
+    device.push_error_scope(ErrorFilter::Validation);
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("Moments Shader"),
+        source: ShaderSource::Wgsl(compose_shader(shader_source).into()),
+    });
+
+    let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[
+            // Image
+            storage_entry(0, true),
+            // Per-chunk moments
+            storage_entry(1, false),
+            // Background grid
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
                 },
+                count: None,
+            },
+        ],
+    });
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[&layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some("Moments Pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader,
+        entry_point: "main".into(),
+        compilation_options: PipelineCompilationOptions {
+            constants: &[
+                ("total_chunks", chunks as f64),
+                ("workgroup_x", workgroup_size as f64),
+                ("subtract_background", background.is_some() as u8 as f64),
+                ("image_width", background.map_or(1, |b| b.width) as f64),
+                ("image_height", background.map_or(1, |b| b.height) as f64),
             ],
+            ..Default::default()
+        },
+        cache: pipeline_cache,
+    });
+    if let Some(err) = device.pop_error_scope().await {
+        let message = err.to_string();
+        return Err(text(
+            "gpu-moments-kernel-failed",
+            &[("message", &message.trim_end())],
+        ));
+    }
+
+    let moments_buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("Chunk Moments Buffer"),
+        size: (chunks * size_of::<ImageMoments>()) as u64,
+        usage: readback_usage(device),
+        mapped_at_creation: false,
+    });
+    let (cols, rows, cells) = match background {
+        Some(background) => (
+            background.cols,
+            background.rows,
+            background.cells.as_slice(),
+        ),
+        None => (1, 1, [[0.0; 4]].as_slice()),
+    };
+    let background_texture = device.create_texture_with_data(
+        queue,
+        &TextureDescriptor {
+            label: Some("Background Texture"),
+            size: Extent3d {
+                width: cols as u32,
+                height: rows as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba32Float,
+            usage: TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        TextureDataOrder::LayerMajor,
+        bytemuck::cast_slice(cells),
+    );
+    let background_view = background_texture.create_view(&TextureViewDescriptor::default());
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        layout: &layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: image_buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: moments_buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::TextureView(&background_view),
+            },
+        ],
+        label: None,
+    });
+
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+    {
+        let mut cpass = encoder.begin_compute_pass(&ComputePassDescriptor {
             label: None,
+            timestamp_writes: None,
         });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        if subgroups {
+            // One workgroup per chunk
+            cpass.dispatch_workgroups(chunks as u32, 1, 1);
+        } else {
+            cpass.dispatch_workgroups((chunks as u32).div_ceil(workgroup_size), 1, 1);
+        }
+    }
 
-        let mut encoder = self
-            .device
-            .create_command_encoder(&CommandEncoderDescriptor { label: None });
-        {
-            let mut cpass = encoder.begin_compute_pass(&ComputePassDescriptor {
-                label: None,
-                timestamp_writes: None,
-            });
-            cpass.set_pipeline(&self.pipeline);
-            cpass.set_bind_group(0, &bind_group, &[]);
-            let workgroup_count_x = ((genomes.len() as f32) / 4.0).ceil() as u32;
-            let workgroup_count_y = ((self.chunks as f32) / 64.0).ceil() as u32;
-            cpass.dispatch_workgroups(workgroup_count_x, workgroup_count_y, 1);
+    let sums = read_buffer::<f32>(device, queue, staging, encoder, &moments_buffer).await?;
+    Ok(ImageMoments::from_sums(
+        sums.data
+            .chunks_exact(6)
+            .map(|chunk| std::array::from_fn(|i| chunk[i] as f64)),
+        image_buffer.size() as usize / size_of::<[f32; 3]>(),
+    ))
+}
+
+fn print_adapter_info(adapter: &Adapter, enabled: Features) {
+    let info = adapter.get_info();
+    let limits = adapter.limits();
+    let features = adapter.features();
+    let yes_no = |feature| {
+        if features.contains(feature) {
+            text("gpu-info-yes", &[])
+        } else {
+            text("gpu-info-no", &[])
+        }
+    };
+    let on_off = |feature| {
+        if enabled.contains(feature) {
+            text("gpu-info-on", &[])
+        } else {
+            text("gpu-info-off", &[])
         }
+    };
+    let (backend, device_type) = (
+        format!("{:?}", info.backend),
+        format!("{:?}", info.device_type),
+    );
 
-        encoder.copy_buffer_to_buffer(
-            &fitness_buffer,
-            0,
-            &fitness_staging_buffer,
-            0,
-            (fitness.len() * size_of::<f32>()) as u64,
-        );
+    let lines = [
+        text(
+            "gpu-info-adapter",
+            &[
+                ("name", &info.name),
+                ("backend", &backend),
+                ("device-type", &device_type),
+            ],
+        ),
+        text(
+            "gpu-info-driver",
+            &[("driver", &info.driver), ("driver-info", &info.driver_info)],
+        ),
+        text("gpu-info-limits", &[]),
+        text(
+            "gpu-info-max-buffer-size",
+            &[("size", &limits.max_buffer_size)],
+        ),
+        text(
+            "gpu-info-max-storage-binding",
+            &[("size", &limits.max_storage_buffer_binding_size)],
+        ),
+        text(
+            "gpu-info-max-uniform-binding",
+            &[("size", &limits.max_uniform_buffer_binding_size)],
+        ),
+        text(
+            "gpu-info-max-workgroup-size",
+            &[
+                ("x", &limits.max_compute_workgroup_size_x),
+                ("y", &limits.max_compute_workgroup_size_y),
+                ("z", &limits.max_compute_workgroup_size_z),
+            ],
+        ),
+        text(
+            "gpu-info-max-invocations",
+            &[("count", &limits.max_compute_invocations_per_workgroup)],
+        ),
+        text(
+            "gpu-info-max-workgroups",
+            &[("count", &limits.max_compute_workgroups_per_dimension)],
+        ),
+        text("gpu-info-features", &[]),
+        text(
+            "gpu-info-f16",
+            &[("available", &yes_no(Features::SHADER_F16))],
+        ),
+        text(
+            "gpu-info-timestamp-queries",
+            &[("available", &yes_no(Features::TIMESTAMP_QUERY))],
+        ),
+        text(
+            "gpu-info-subgroups",
+            &[("available", &yes_no(Features::SUBGROUP))],
+        ),
+        text(
+            "gpu-info-pipeline-cache",
+            &[("available", &yes_no(Features::PIPELINE_CACHE))],
+        ),
+        text(
+            "gpu-info-mappable-buffers",
+            &[("available", &yes_no(Features::MAPPABLE_PRIMARY_BUFFERS))],
+        ),
+        text("gpu-info-used", &[]),
+        text(
+            "gpu-info-subgroup-moments",
+            &[("enabled", &on_off(Features::SUBGROUP))],
+        ),
+        text(
+            "gpu-info-disk-cache",
+            &[("enabled", &on_off(Features::PIPELINE_CACHE))],
+        ),
+        text(
+            "gpu-info-direct-readback",
+            &[("enabled", &on_off(Features::MAPPABLE_PRIMARY_BUFFERS))],
+        ),
+    ];
+    for line in lines {
+        println!("{}", line);
+    }
+}
+
+/// Usage of a storage buffer that is read back: mappable in place when unified memory allows
+/// it, copied out through a staging buffer otherwise
+fn readback_usage(device: &Device) -> BufferUsages {
+    if device
+        .features()
+        .contains(Features::MAPPABLE_PRIMARY_BUFFERS)
+    {
+        BufferUsages::STORAGE | BufferUsages::MAP_READ
+    } else {
+        BufferUsages::STORAGE | BufferUsages::COPY_SRC
+    }
+}
+
+/// Finishes `encoder` and reads `source` back, through a staging buffer unless it can be
+/// mapped in place
+async fn read_buffer<T: Pod>(
+    device: &Device,
+    queue: &Queue,
+    staging: &StagingPool,
+    mut encoder: CommandEncoder,
+    source: &Buffer,
+) -> Result<Readback<T>, String> {
+    let staging_buffer = if source.usage().contains(BufferUsages::MAP_READ) {
+        None
+    } else {
+        let buffer = staging.take(device, source.size());
+        encoder.copy_buffer_to_buffer(source, 0, &buffer, 0, source.size());
+        Some(buffer)
+    };
+    let mapped_buffer = staging_buffer.as_ref().unwrap_or(source);
 
-        let index = self.queue.submit(Some(encoder.finish()));
+    let submitted = Instant::now();
+    let index = queue.submit(Some(encoder.finish()));
 
-        let buffer_slice = fitness_staging_buffer.slice(..);
+    // Some drivers intermittently fail to map large buffers. The results stay in the
+    // buffer, so the retries map it again in smaller pieces.
+    let mut pieces = 1;
+    let (result, mapped) = loop {
+        match read_pieces(device, mapped_buffer, source.size(), pieces, &index).await {
+            Ok(read) => break read,
+            Err(err) if pieces < 1 << MAP_RETRIES => {
+                pieces *= 2;
+                let message = text("gpu-map-retry", &[("message", &err), ("pieces", &pieces)]);
+                eprintln!("{}", text("warning", &[("message", &message)]));
+            }
+            Err(err) => return Err(err),
+        }
+    };
+    if let Some(buffer) = staging_buffer {
+        staging.give(buffer);
+    }
+    Ok(Readback {
+        data: result,
+        gpu_time: mapped - submitted,
+        copy_time: mapped.elapsed(),
+    })
+}
+
+/// Maps the first `size` bytes of `buffer` in `pieces` consecutive ranges and copies them
+/// out, returning them with when the first piece was mapped
+async fn read_pieces<T: Pod>(
+    device: &Device,
+    buffer: &Buffer,
+    size: u64,
+    pieces: u64,
+    index: &SubmissionIndex,
+) -> Result<(Vec<T>, Instant), String> {
+    let mut data = vec![T::zeroed(); size as usize / size_of::<T>()];
+    let bytes = bytemuck::cast_slice_mut::<T, u8>(&mut data);
+    let mut mapped = None;
+    for range in piece_ranges(size, pieces) {
+        let slice = buffer.slice(range.clone());
         let (send, recv) = flume::bounded(1);
-        buffer_slice.map_async(MapMode::Read, move |v| send.send(v).unwrap());
-        self.device
-            .poll(PollType::Wait {
-                submission_index: index.into(),
-                timeout: None,
-            })
-            .unwrap();
+        slice.map_async(MapMode::Read, move |v| send.send(v).unwrap());
+        // Wait in short slices and yield in between, so other tasks on the executor keep
+        // running while the GPU works instead of the whole thread blocking on the device
+        let map_result = loop {
+            if let Ok(result) = recv.try_recv() {
+                break result;
+            }
+            match device.poll(PollType::Wait {
+                submission_index: Some(index.clone()),
+                timeout: Some(POLL_INTERVAL),
+            }) {
+                Ok(_) | Err(PollError::Timeout) => {}
+                Err(err) => return Err(text("gpu-poll-failed", &[("message", &err)])),
+            }
+            YieldNow(false).await;
+        };
+        map_result.map_err(|err| text("gpu-map-failed", &[("message", &err)]))?;
+        mapped.get_or_insert_with(Instant::now);
+        bytes[range.start as usize..range.end as usize].copy_from_slice(&slice.get_mapped_range());
+        buffer.unmap();
+    }
+    Ok((data, mapped.unwrap_or_else(Instant::now)))
+}
 
-        recv.recv_async()
-            .await
-            .expect("Failed to receive map result")
-            .expect("Failed to map buffer");
-
-        let data = buffer_slice.get_mapped_range();
-        let result = bytemuck::cast_slice(&data).to_vec();
-        drop(data);
-        fitness_staging_buffer.unmap();
-
-        result
-            .chunks(self.chunks)
-            .map(|chunk| chunk.iter().sum::<f32>())
-            .map(|fit| fit / (self.image_len as f32))
-            .collect()
+/// Splits `size` bytes into `pieces` ranges whose starts are aligned for mapping
+fn piece_ranges(size: u64, pieces: u64) -> impl Iterator<Item = Range<u64>> {
+    let piece = size
+        .div_ceil(pieces)
+        .next_multiple_of(MAP_ALIGNMENT)
+        .max(MAP_ALIGNMENT);
+    (0..size)
+        .step_by(piece as usize)
+        .map(move |start| start..(start + piece).min(size))
+}
+
+/// MAP_READ buffers kept between readbacks, so a long run doesn't allocate a staging buffer
+/// every generation. Buffers grow to the largest readback seen, such as the fitness of the
+/// whole population, and are reused for anything that fits.
+#[derive(Default)]
+struct StagingPool(Mutex<Vec<Buffer>>);
+
+impl StagingPool {
+    fn take(&self, device: &Device, size: u64) -> Buffer {
+        let mut buffers = self.0.lock().unwrap();
+        match buffers.iter().position(|buffer| buffer.size() >= size) {
+            Some(index) => buffers.swap_remove(index),
+            None => device.create_buffer(&BufferDescriptor {
+                label: Some("Staging Buffer"),
+                size,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+        }
+    }
+
+    /// Returns an unmapped buffer to the pool, replacing any smaller one it can stand in for
+    fn give(&self, buffer: Buffer) {
+        let mut buffers = self.0.lock().unwrap();
+        buffers.retain(|pooled| pooled.size() > buffer.size());
+        buffers.push(buffer);
+    }
+}
+
+/// Future that returns control to the executor once before completing
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+fn storage_entry(binding: u32, read_only: bool) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn uniform_entry(binding: u32) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn pipeline_cache_path(info: &AdapterInfo) -> Option<PathBuf> {
+    let key = wgpu::util::pipeline_cache_key(info)?;
+    Some(dirs::cache_dir()?.join("duosplit").join(key))
+}
+
+fn load_pipeline_cache(device: &Device, path: Option<&Path>) -> Option<PipelineCache> {
+    if !device.features().contains(Features::PIPELINE_CACHE) {
+        return None;
+    }
+    let data = path.and_then(|path| fs::read(path).ok());
+    // SAFETY: the data was produced by `PipelineCache::get_data` for an adapter with the same
+    // cache key, and `fallback` makes wgpu discard it if the driver rejects it
+    let cache = unsafe {
+        device.create_pipeline_cache(&PipelineCacheDescriptor {
+            label: Some("Pipeline Cache"),
+            data: data.as_deref(),
+            fallback: true,
+        })
+    };
+    Some(cache)
+}
+
+fn store_pipeline_cache(cache: &PipelineCache, path: &Path) -> io::Result<()> {
+    let Some(data) = cache.get_data() else {
+        return Ok(());
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write then rename so a concurrent run never reads a half-written cache
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, data)?;
+    fs::rename(&temp, path)
+}
+
+impl Evaluator for GpuContext {
+    fn evaluate(&self, genomes: &[Genome]) -> Vec<f32> {
+        pollster::block_on(self.compute_fitness(genomes)).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernels_compose_into_valid_wgsl() {
+        for kernel in [
+            include_str!("fit.wgsl"),
+            include_str!("evolve.wgsl"),
+            include_str!("moments.wgsl"),
+            include_str!("moments_subgroup.wgsl"),
+            include_str!("apply.wgsl"),
+        ] {
+            let source = compose_shader(kernel);
+            assert!(!source.contains("#include"));
+            if let Err(err) = wgpu::naga::front::wgsl::parse_str(&source) {
+                panic!("{}", err.emit_to_string(&source));
+            }
+        }
+        let composite = Composite::parse("R=ha; G=max(0.7*oiii+0.3*ha, 0); B=-oiii/2").unwrap();
+        let source = composite_shader(&composite);
+        if let Err(err) = wgpu::naga::front::wgsl::parse_str(&source) {
+            panic!("{}", err.emit_to_string(&source));
+        }
+    }
+
+    #[test]
+    fn devices_and_contexts_can_be_shared_between_threads() {
+        fn shareable<T: Send + Sync>() {}
+        shareable::<GpuDevice>();
+        shareable::<GpuContext>();
+    }
+
+    #[test]
+    fn readback_pieces_cover_the_buffer() {
+        for (size, pieces) in [(4, 1), (4000, 1), (4000, 8), (4004, 3), (12, 8)] {
+            let ranges = piece_ranges(size, pieces).collect::<Vec<_>>();
+            assert!(ranges.len() as u64 <= pieces);
+            assert_eq!(ranges.first().unwrap().start, 0);
+            assert_eq!(ranges.last().unwrap().end, size);
+            for pair in ranges.windows(2) {
+                assert_eq!(pair[0].end, pair[1].start);
+                assert_eq!(pair[1].start % MAP_ALIGNMENT, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn auto_chunks_follow_image_size_and_limits() {
+        let limits = Limits::default();
+        assert_eq!(auto_chunks(100, false, &limits), 1);
+        assert_eq!(auto_chunks(6248 * 4176, false, &limits), 6371);
+        // One workgroup per chunk can't launch as many chunks as one invocation per chunk
+        let huge = 1 << 30;
+        assert_eq!(
+            auto_chunks(huge, true, &limits),
+            limits.max_compute_workgroups_per_dimension as usize
+        );
+        assert!(auto_chunks(huge, false, &limits) > auto_chunks(huge, true, &limits));
+    }
+
+    #[test]
+    fn fitness_batches_fit_one_dispatch() {
+        let limits = Limits::default();
+        assert_eq!(max_fitness_batch(&limits), 65535 * 64);
+        let small = Limits {
+            max_storage_buffer_binding_size: 1 << 20,
+            ..limits
+        };
+        assert_eq!(max_fitness_batch(&small), (1 << 20) / size_of::<Genome>());
+    }
+
+    #[test]
+    fn apply_batches_fit_one_dispatch_and_stay_aligned() {
+        let limits = Limits::default();
+        // The binding limit allows fewer pixels than one dispatch can launch
+        let batch = max_pixel_batch(&limits, 12);
+        assert!(batch <= (128 << 20) / 12);
+        assert_eq!(batch * 12 % 256, 0);
+        let batch = max_pixel_batch(&limits, 16);
+        assert!(batch <= (128 << 20) / 16);
+        assert_eq!(batch * 8 % 256, 0);
+        let small = Limits {
+            max_compute_workgroups_per_dimension: 100,
+            ..limits
+        };
+        assert_eq!(max_pixel_batch(&small, 12), 100 * 256);
     }
 }
@@ -0,0 +1,118 @@
+use crate::messages::text;
+use std::path::Path;
+use std::process::Command;
+
+/// Runs a `--post-cmd` template on the outputs. `{ha}` and `{oiii}` stand for the two output
+/// paths, while a template using `{output}` instead runs once for each of them. The template
+/// is split into arguments like a shell would, honouring quotes and backslashes, and
+/// placeholders are filled in after splitting so paths with spaces stay one argument.
+pub fn run_post_command(template: &str, h_alpha: &Path, oiii: &Path) -> Result<(), String> {
+    let words = split_words(template)?;
+    let per_output = words.iter().any(|word| word.contains("{output}"));
+    let outputs: &[Option<&Path>] = if per_output {
+        &[Some(h_alpha), Some(oiii)]
+    } else {
+        &[None]
+    };
+    for output in outputs {
+        let fill = |word: &String| {
+            let word = word
+                .replace("{ha}", &h_alpha.to_string_lossy())
+                .replace("{oiii}", &oiii.to_string_lossy());
+            match output {
+                Some(output) => word.replace("{output}", &output.to_string_lossy()),
+                None => word,
+            }
+        };
+        let args = words.iter().map(fill).collect::<Vec<_>>();
+        let (program, args) = args
+            .split_first()
+            .ok_or_else(|| text("empty-command", &[("command", &template)]))?;
+        println!(
+            "{}",
+            text("running-command", &[("command", &shown(program, args))])
+        );
+        let status = Command::new(program)
+            .args(args)
+            .status()
+            .map_err(|e| text("command-not-run", &[("program", program), ("error", &e)]))?;
+        if !status.success() {
+            return Err(text(
+                "command-failed",
+                &[("command", &shown(program, args)), ("status", &status)],
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The command as it could be typed, quoting arguments with spaces
+fn shown(program: &str, args: &[String]) -> String {
+    std::iter::once(program)
+        .chain(args.iter().map(String::as_str))
+        .map(|arg| {
+            if arg.contains(char::is_whitespace) {
+                format!("\"{}\"", arg)
+            } else {
+                arg.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Splits a command line into words at unquoted whitespace. Single quotes keep everything
+/// literally, double quotes and bare words let a backslash escape the next character.
+fn split_words(command: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => {
+                            return Err(text("unclosed-single-quote", &[("command", &command)]))
+                        }
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => {
+                            return Err(text("unclosed-double-quote", &[("command", &command)]))
+                        }
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_like_a_shell() {
+        assert_eq!(
+            split_words(r#"starnet++ "{ha}" 'a b' c\ d "" e"#).unwrap(),
+            vec!["starnet++", "{ha}", "a b", "c d", "", "e"]
+        );
+        assert!(split_words("echo 'unclosed").is_err());
+    }
+}
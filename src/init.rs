@@ -0,0 +1,264 @@
+use crate::cameras::{self, Camera, CameraDatabase, ChannelQe};
+use crate::filters;
+use crate::messages::text;
+use crate::profiles::{Profile, ProfileDatabase};
+use crate::status::{ExitCode, Failure};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+/// Questions asked on `output` and answered a line at a time from `input`
+struct Prompt<R, W> {
+    input: R,
+    output: W,
+}
+
+/// What `duosplit init` settles on: the profile, and the camera to add for it if it's new
+struct Setup {
+    camera: Option<Camera>,
+    profile: Profile,
+}
+
+impl<R: BufRead, W: Write> Prompt<R, W> {
+    /// Asks `question`, returning the trimmed answer, or `default` when it's left empty
+    fn ask(&mut self, question: &str, default: Option<&str>) -> Result<String, Failure> {
+        let io =
+            |err: io::Error| Failure::new(ExitCode::Io, text("init-io-failed", &[("error", &err)]));
+        match default {
+            Some(default) => write!(self.output, "{} [{}]: ", question, default),
+            None => write!(self.output, "{}: ", question),
+        }
+        .and_then(|()| self.output.flush())
+        .map_err(io)?;
+        let mut answer = String::new();
+        if self.input.read_line(&mut answer).map_err(io)? == 0 {
+            return Err(Failure::new(
+                ExitCode::BadInput,
+                text("init-no-answer", &[]),
+            ));
+        }
+        let answer = answer.trim();
+        Ok(match (answer, default) {
+            ("", Some(default)) => default.into(),
+            _ => answer.into(),
+        })
+    }
+
+    fn say(&mut self, line: &str) -> Result<(), Failure> {
+        writeln!(self.output, "{}", line)
+            .map_err(|err| Failure::new(ExitCode::Io, text("init-io-failed", &[("error", &err)])))
+    }
+
+    /// Lists `options` by number and asks for one, returning its index
+    fn choose(&mut self, question: &str, options: &[String]) -> Result<usize, Failure> {
+        for (index, option) in options.iter().enumerate() {
+            self.say(&format!("  {}. {}", index + 1, option))?;
+        }
+        loop {
+            let answer = self.ask(question, Some("1"))?;
+            match answer.parse::<usize>() {
+                Ok(number) if (1..=options.len()).contains(&number) => return Ok(number - 1),
+                _ => self.say(&text("init-choose-number", &[("count", &options.len())]))?,
+            }
+        }
+    }
+
+    /// Asks for a quantum efficiency until one between 0 and 1 is given
+    fn quantum_efficiency(&mut self, channel: &str, line: &str) -> Result<f32, Failure> {
+        let question = text(
+            "init-quantum-efficiency",
+            &[("channel", &channel), ("line", &line)],
+        );
+        loop {
+            match self.ask(&question, None)?.parse::<f32>() {
+                Ok(value) if (0.0..=1.0).contains(&value) => return Ok(value),
+                _ => self.say(&text("init-enter-fraction", &[]))?,
+            }
+        }
+    }
+
+    /// Asks for the name and quantum efficiencies of a camera missing from the database
+    fn new_camera(&mut self) -> Result<Camera, Failure> {
+        let name = loop {
+            let name = self.ask(&text("init-camera-name", &[]), None)?;
+            if !name.is_empty() {
+                break name;
+            }
+        };
+        self.say(&text("init-quantum-efficiency-chart", &[]))?;
+        let mut channel = |channel: &str| -> Result<ChannelQe, Failure> {
+            Ok(ChannelQe {
+                ha: self.quantum_efficiency(channel, &text("init-h-alpha", &[]))?,
+                oiii: self.quantum_efficiency(channel, &text("init-oiii", &[]))?,
+            })
+        };
+        Ok(Camera {
+            red: channel(&text("init-red", &[]))?,
+            green: channel(&text("init-green", &[]))?,
+            blue: channel(&text("init-blue", &[]))?,
+            name,
+            curves: None,
+        })
+    }
+
+    /// Asks for transmission curves until an empty answer, checking that each file exists
+    fn optics(&mut self) -> Result<Vec<PathBuf>, Failure> {
+        let mut optics = Vec::new();
+        loop {
+            let answer = self.ask(&text("init-optics", &[]), None)?;
+            if answer.is_empty() {
+                return Ok(optics);
+            }
+            // Absolute, so the profile works from any directory
+            match fs::canonicalize(&answer) {
+                Ok(path) => optics.push(path),
+                Err(err) => self.say(&text(
+                    "init-cannot-use",
+                    &[("path", &answer), ("message", &err)],
+                ))?,
+            }
+        }
+    }
+}
+
+/// Walks through choosing a camera, filter and optics for a new profile
+fn wizard<R: BufRead, W: Write>(
+    prompt: &mut Prompt<R, W>,
+    cameras: &[String],
+    filters: &[String],
+) -> Result<Setup, Failure> {
+    prompt.say(&text("init-intro", &[]))?;
+    let name = prompt.ask(&text("init-profile-name", &[]), Some("default"))?;
+
+    let mut options = cameras.to_vec();
+    options.push(text("init-camera-not-listed", &[]));
+    let chosen = if cameras.is_empty() {
+        prompt.say(&text("init-no-cameras", &[]))?;
+        0
+    } else {
+        prompt.say(&text("init-which-camera", &[]))?;
+        prompt.choose(&text("init-camera", &[]), &options)?
+    };
+    let (camera_name, camera) = match cameras.get(chosen) {
+        Some(name) => (name.clone(), None),
+        None => {
+            let camera = prompt.new_camera()?;
+            (camera.name.clone(), Some(camera))
+        }
+    };
+
+    let mut options = vec![text("init-no-filter", &[])];
+    options.extend(filters.iter().cloned());
+    prompt.say(&text("init-which-filter", &[]))?;
+    let filter = match prompt.choose(&text("init-filter", &[]), &options)? {
+        0 => None,
+        index => Some(filters[index - 1].clone()),
+    };
+
+    let optics = prompt.optics()?;
+    Ok(Setup {
+        camera,
+        profile: Profile {
+            name,
+            camera: camera_name,
+            filter,
+            optics,
+            arguments: Vec::new(),
+        },
+    })
+}
+
+/// Runs `duosplit init`, saving the profile and any new camera in the config directory
+pub fn run() {
+    if let Err(failure) = run_wizard() {
+        failure
+            .code
+            .exit(text("error", &[("message", &failure.message)]));
+    }
+}
+
+fn run_wizard() -> Result<(), Failure> {
+    let cameras = CameraDatabase::load()?
+        .cameras
+        .into_iter()
+        .map(|camera| camera.name)
+        .collect::<Vec<_>>();
+    let filters = filters::names()?;
+    let mut prompt = Prompt {
+        input: io::stdin().lock(),
+        output: io::stdout(),
+    };
+    let Setup { camera, profile } = wizard(&mut prompt, &cameras, &filters)?;
+
+    if let Some(camera) = camera {
+        cameras::add(camera)?;
+    }
+    let name = profile.name.clone();
+    let mut profiles = ProfileDatabase::load()?;
+    let replaced = profiles.insert(profile);
+    let path = profiles.save()?;
+    let id = if replaced {
+        "init-updated-profile"
+    } else {
+        "init-added-profile"
+    };
+    println!(
+        "{}",
+        text(id, &[("name", &name), ("path", &path.display())])
+    );
+    let name = if name.contains(char::is_whitespace) {
+        format!("\"{}\"", name)
+    } else {
+        name
+    };
+    println!("{}", text("init-usage", &[("name", &name)]));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(input: &str) -> Result<Setup, Failure> {
+        let mut prompt = Prompt {
+            input: input.as_bytes(),
+            output: Vec::new(),
+        };
+        wizard(
+            &mut prompt,
+            &["Known Cam".into()],
+            &["L-eNhance".into(), "L-eXtreme".into()],
+        )
+    }
+
+    #[test]
+    fn wizard_builds_a_profile_from_the_answers() {
+        let setup = answer("\n1\n3\n\n").unwrap();
+        assert!(setup.camera.is_none());
+        assert_eq!(setup.profile.name, "default");
+        assert_eq!(setup.profile.camera, "Known Cam");
+        assert_eq!(setup.profile.filter.as_deref(), Some("L-eXtreme"));
+        assert!(setup.profile.optics.is_empty());
+
+        // Out of range answers are asked again
+        let setup =
+            answer("rig\n2\nNew Cam\n0.8\n0.05\n1.5\n0.1\n0.6\n0.05\n0.5\n9\n1\n\n").unwrap();
+        let camera = setup.camera.unwrap();
+        assert_eq!(camera.name, "New Cam");
+        assert_eq!(
+            (camera.red.ha, camera.green.ha, camera.blue.oiii),
+            (0.8, 0.1, 0.5)
+        );
+        assert_eq!(setup.profile.camera, "New Cam");
+        assert_eq!(setup.profile.filter, None);
+
+        let mut prompt = Prompt {
+            input: "\nNew Cam\n0.8\n0.05\n0.1\n0.6\n0.05\n0.5\n1\n\n".as_bytes(),
+            output: Vec::new(),
+        };
+        let setup = wizard(&mut prompt, &[], &[]).unwrap();
+        assert_eq!(setup.camera.unwrap().name, "New Cam");
+
+        assert!(answer("rig\n").is_err());
+    }
+}
@@ -0,0 +1,479 @@
+use crate::digest;
+use crate::fits::{self, looks_stretched, Channels, FitsImage};
+use crate::messages::text;
+use crate::status::{ExitCode, Failure, Warning, WarningKind};
+use ndarray::Array2;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+// The fixed-size header at the start of a SER file, ahead of its frames
+const SER_HEADER: usize = 178;
+// SER colour ids of frames that are already RGB, with the samples in either order
+const SER_RGB: i32 = 100;
+const SER_BGR: i32 = 101;
+// The file header and the smallest info header of a BMP, ahead of any colour masks
+const BMP_HEADER: usize = 14 + 40;
+// BMP compression ids of uncompressed pixels, with or without colour masks
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+
+/// How to read an input, from the split's options
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ReadOptions {
+    /// Read a single FITS plane as rows of interleaved RGB pixels
+    pub interleaved: bool,
+    /// The frame of a SER sequence to split, counting from 1
+    pub frame: Option<usize>,
+}
+
+/// A format the input can be in, recognised by the bytes it starts with
+struct Reader {
+    name: &'static str,
+    signature: &'static [u8],
+    /// Whether the format holds several frames, for `--frame` to pick from
+    sequence: bool,
+    decode: fn(&[u8], ReadOptions) -> Result<FitsImage, String>,
+}
+
+/// The readers tried in turn; an input none of them recognises is read as FITS, whose
+/// errors say best what is wrong with it
+const READERS: &[Reader] = &[
+    Reader {
+        name: "FITS",
+        signature: b"SIMPLE",
+        sequence: false,
+        decode: |bytes, options| fits::decode_fits(bytes, options.interleaved),
+    },
+    Reader {
+        name: "PNG",
+        signature: b"\x89PNG\r\n\x1a\n",
+        sequence: false,
+        decode: decode_png,
+    },
+    Reader {
+        name: "SER",
+        signature: b"LUCAM-RECORDER",
+        sequence: true,
+        decode: decode_ser,
+    },
+    Reader {
+        name: "BMP",
+        signature: b"BM",
+        sequence: false,
+        decode: decode_bmp,
+    },
+];
+
+/// Reads the image to split from a FITS, PNG, SER or BMP file, or from a FITS stream on
+/// stdin when the path is `-`
+pub fn read_image(path: &impl AsRef<Path>, options: ReadOptions) -> Result<FitsImage, Failure> {
+    let path = path.as_ref();
+    let bytes = if path == Path::new("-") {
+        let mut bytes = Vec::new();
+        std::io::stdin()
+            .lock()
+            .read_to_end(&mut bytes)
+            .map_err(|e| Failure::new(ExitCode::Io, text("stdin-read-failed", &[("error", &e)])))?;
+        bytes
+    } else {
+        std::fs::read(path)
+            .map_err(|e| Failure::new(ExitCode::Io, text("input-open-failed", &[("error", &e)])))?
+    };
+    decode_image(&bytes, options).map_err(|message| Failure::new(ExitCode::BadInput, message))
+}
+
+/// Decodes an in-memory input with the reader its first bytes call for
+fn decode_image(bytes: &[u8], options: ReadOptions) -> Result<FitsImage, String> {
+    let reader = READERS
+        .iter()
+        .find(|reader| bytes.starts_with(reader.signature))
+        .unwrap_or(&READERS[0]);
+    let mut image = (reader.decode)(bytes, options)?;
+    if options.frame.is_some() && !reader.sequence {
+        image.warnings.push(Warning::new(
+            WarningKind::IgnoredOption,
+            text("frame-ignored", &[("format", &reader.name)]),
+        ));
+    }
+    Ok(image)
+}
+
+/// Decodes an RGB or RGBA PNG of 8 or 16 bits per sample, keeping the integer sample values
+/// as FITS would hold them
+fn decode_png(bytes: &[u8], _: ReadOptions) -> Result<FitsImage, String> {
+    let mut decoder = png::Decoder::new(Cursor::new(bytes));
+    // Palettes are expanded to RGB; 16-bit samples are kept
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|err| text("invalid-png", &[("error", &err)]))?;
+    let size = reader
+        .output_buffer_size()
+        .ok_or_else(|| text("png-too-large", &[]))?;
+    let mut buffer = vec![0; size];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|err| text("invalid-png", &[("error", &err)]))?;
+    let samples = match info.color_type {
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        _ => {
+            return Err(text(
+                "png-not-rgb",
+                &[("color-type", &format!("{:?}", info.color_type))],
+            ))
+        }
+    };
+    let (sample_bytes, bit_depth) = match info.bit_depth {
+        png::BitDepth::Sixteen => (2, "16-bit integer"),
+        _ => (1, "8-bit integer"),
+    };
+    let (width, height) = (info.width as usize, info.height as usize);
+    let rows = buffer[..info.buffer_size()]
+        .chunks_exact(info.line_size)
+        .map(|row| {
+            row.chunks_exact(sample_bytes * samples)
+                .take(width)
+                .map(|pixel| {
+                    [0, 1, 2].map(|channel| {
+                        let sample = &pixel[channel * sample_bytes..(channel + 1) * sample_bytes];
+                        // PNG samples are big-endian
+                        sample
+                            .iter()
+                            .fold(0u32, |value, &byte| value << 8 | byte as u32)
+                            as f32
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    Ok(image_from_rows(&rows, width, height, bit_depth, bytes))
+}
+
+/// Decodes one frame of a SER sequence, as written by planetary and EAA capture programs;
+/// a sequence of more than one frame needs `--frame` to say which
+fn decode_ser(bytes: &[u8], options: ReadOptions) -> Result<FitsImage, String> {
+    if bytes.len() < SER_HEADER {
+        return Err(text("ser-truncated-header", &[]));
+    }
+    let field = |offset: usize| {
+        i32::from_le_bytes(
+            bytes[offset..offset + 4]
+                .try_into()
+                .expect("header fields are four bytes"),
+        )
+    };
+    let (color, little_endian, width, height, depth, frames) = (
+        field(18),
+        field(22),
+        field(26),
+        field(30),
+        field(34),
+        field(38),
+    );
+    let order = match color {
+        SER_RGB => [0, 1, 2],
+        SER_BGR => [2, 1, 0],
+        0 => return Err(text("ser-mono", &[])),
+        _ => return Err(text("ser-raw", &[("color", &color)])),
+    };
+    let invalid = || {
+        text(
+            "invalid-ser-header",
+            &[
+                ("width", &width),
+                ("height", &height),
+                ("depth", &depth),
+                ("frames", &frames),
+            ],
+        )
+    };
+    if width <= 0 || height <= 0 || !(1..=16).contains(&depth) || frames < 0 {
+        return Err(invalid());
+    }
+    let (width, height, frames) = (width as usize, height as usize, frames as usize);
+    let frame = match (options.frame, frames) {
+        (None, 1) => 1,
+        (None, _) => return Err(text("ser-needs-frame", &[("frames", &frames)])),
+        (Some(frame), _) if frame > frames => {
+            return Err(text(
+                "frame-past-end",
+                &[("frame", &frame), ("frames", &frames)],
+            ))
+        }
+        (Some(frame), _) => frame,
+    };
+    let (sample_bytes, bit_depth) = if depth <= 8 {
+        (1, "8-bit integer")
+    } else {
+        (2, "16-bit integer")
+    };
+    // The header sizes come from the file, so a corrupt one mustn't overflow
+    let frame_bytes = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(3 * sample_bytes))
+        .ok_or_else(invalid)?;
+    let start = (frame - 1)
+        .checked_mul(frame_bytes)
+        .and_then(|offset| offset.checked_add(SER_HEADER))
+        .ok_or_else(invalid)?;
+    let end = start.checked_add(frame_bytes).ok_or_else(invalid)?;
+    let data = bytes
+        .get(start..end)
+        .ok_or_else(|| text("ser-truncated-frame", &[("frame", &frame)]))?;
+    // The specification has this flag backwards from what capture programs write, and
+    // readers follow the programs: zero means little-endian samples
+    let sample = |bytes: &[u8]| match bytes {
+        [byte] => *byte as f32,
+        [a, b] if little_endian == 0 => u16::from_le_bytes([*a, *b]) as f32,
+        [a, b] => u16::from_be_bytes([*a, *b]) as f32,
+        _ => unreachable!("SER samples are one or two bytes"),
+    };
+    let rows = data
+        .chunks_exact(width * 3 * sample_bytes)
+        .map(|row| {
+            row.chunks_exact(3 * sample_bytes)
+                .map(|pixel| {
+                    order.map(|channel| {
+                        sample(&pixel[channel * sample_bytes..(channel + 1) * sample_bytes])
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    Ok(image_from_rows(&rows, width, height, bit_depth, bytes))
+}
+
+/// Decodes an uncompressed 24 or 32-bit BMP, as some capture and stacking programs save
+/// their previews; its samples are 8-bit, like those of an 8-bit PNG
+fn decode_bmp(bytes: &[u8], _: ReadOptions) -> Result<FitsImage, String> {
+    if bytes.len() < BMP_HEADER {
+        return Err(text("bmp-truncated-header", &[]));
+    }
+    let field = |offset: usize, size: usize| {
+        bytes[offset..offset + size]
+            .iter()
+            .rev()
+            .fold(0u32, |value, &byte| value << 8 | byte as u32)
+    };
+    let (data_start, info_size) = (field(10, 4) as usize, field(14, 4) as usize);
+    let (width, height) = (field(18, 4) as i32, field(22, 4) as i32);
+    let (bits, compression) = (field(28, 2), field(30, 4));
+    // Core headers of 12 bytes hold 16-bit sizes, and no current program writes them
+    if info_size < 40 {
+        return Err(text("bmp-unsupported", &[("bits", &bits)]));
+    }
+    // Colour masks follow a 40-byte header, and are part of the larger ones
+    let masks = match (bits, compression) {
+        (24 | 32, BI_RGB) => [0xff0000, 0xff00, 0xff],
+        (32, BI_BITFIELDS) if bytes.len() >= BMP_HEADER + 12 => {
+            [field(54, 4), field(58, 4), field(62, 4)]
+        }
+        _ => return Err(text("bmp-unsupported", &[("bits", &bits)])),
+    };
+    // Only byte-wide masks keep the samples 8-bit
+    let shifts = masks.map(|mask| mask.trailing_zeros());
+    if masks
+        .iter()
+        .zip(shifts)
+        .any(|(&mask, shift)| shift > 24 || mask >> shift != 0xff)
+    {
+        return Err(text("bmp-unsupported", &[("bits", &bits)]));
+    }
+    let invalid = || {
+        text(
+            "invalid-bmp-header",
+            &[("width", &width), ("height", &height), ("bits", &bits)],
+        )
+    };
+    // A negative height stores the rows top first, a positive one bottom first
+    if width <= 0 || height == 0 || height == i32::MIN {
+        return Err(invalid());
+    }
+    let (width, top_down) = (width as usize, height < 0);
+    let height = height.unsigned_abs() as usize;
+    let pixel_bytes = bits as usize / 8;
+    // Rows are padded to a multiple of four bytes
+    let row_bytes = width
+        .checked_mul(pixel_bytes)
+        .and_then(|bytes| bytes.checked_next_multiple_of(4))
+        .ok_or_else(invalid)?;
+    let end = row_bytes
+        .checked_mul(height)
+        .and_then(|size| size.checked_add(data_start))
+        .ok_or_else(invalid)?;
+    let data = bytes
+        .get(data_start..end)
+        .ok_or_else(|| text("bmp-truncated-pixels", &[]))?;
+    let mut rows = data
+        .chunks_exact(row_bytes)
+        .map(|row| {
+            row.chunks_exact(pixel_bytes)
+                .take(width)
+                .map(|pixel| {
+                    // Pixels are little-endian, so a 24-bit one is stored blue first
+                    let value = pixel
+                        .iter()
+                        .rev()
+                        .fold(0u32, |value, &byte| value << 8 | byte as u32);
+                    shifts.map(|shift| (value >> shift & 0xff) as f32)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    if !top_down {
+        rows.reverse();
+    }
+    Ok(image_from_rows(
+        &rows,
+        width,
+        height,
+        "8-bit integer",
+        bytes,
+    ))
+}
+
+/// An image from rows of RGB pixels stored top row first, turned over so the first row is
+/// the bottom one, as in FITS
+fn image_from_rows(
+    rows: &[Vec<[f32; 3]>],
+    width: usize,
+    height: usize,
+    bit_depth: &'static str,
+    bytes: &[u8],
+) -> FitsImage {
+    let channel = |index: usize| {
+        Array2::from_shape_fn((height, width), |(y, x)| rows[height - 1 - y][x][index])
+    };
+    let channels: Channels = (channel(0), channel(1), channel(2));
+    FitsImage {
+        stretched: looks_stretched(&channels),
+        channels,
+        bit_depth: Some(bit_depth),
+        warnings: Vec::new(),
+        airmass: None,
+        sha256: digest::sha256(bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fits::interleave;
+
+    #[test]
+    fn reads_png_and_ser_inputs() {
+        // A 2x2 16-bit PNG, top row first
+        let top = [[1u16, 2, 3], [400, 500, 600]];
+        let bottom = [[7u16, 8, 9], [65535, 0, 12]];
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 2, 2);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Sixteen);
+        let data = [top, bottom]
+            .concat()
+            .iter()
+            .flatten()
+            .flat_map(|sample| sample.to_be_bytes())
+            .collect::<Vec<_>>();
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&data).unwrap();
+        writer.finish().unwrap();
+        let expected = [bottom, top]
+            .concat()
+            .iter()
+            .map(|pixel| pixel.map(f32::from))
+            .collect::<Vec<_>>();
+        let image = decode_image(&png, ReadOptions::default()).unwrap();
+        assert_eq!(interleave(&image.channels), expected);
+        assert_eq!(image.bit_depth, Some("16-bit integer"));
+        assert!(image.warnings.is_empty());
+        let options = ReadOptions {
+            frame: Some(2),
+            ..ReadOptions::default()
+        };
+        let image = decode_image(&png, options).unwrap();
+        assert_eq!(image.warnings[0].kind, WarningKind::IgnoredOption);
+
+        // The same pixels scaled down to 8 bits
+        let small = |pixel: &[u16; 3]| pixel.map(|sample| (sample >> 8) as u8);
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 2, 2);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let data = [top, bottom]
+            .concat()
+            .iter()
+            .flat_map(small)
+            .collect::<Vec<_>>();
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&data).unwrap();
+        writer.finish().unwrap();
+        let expected_small = [bottom, top]
+            .concat()
+            .iter()
+            .map(|pixel| small(pixel).map(f32::from))
+            .collect::<Vec<_>>();
+        let image = decode_image(&png, ReadOptions::default()).unwrap();
+        assert_eq!(interleave(&image.channels), expected_small);
+        assert_eq!(image.bit_depth, Some("8-bit integer"));
+
+        // A 24-bit BMP of the 8-bit pixels, bottom row first, each row padded to 8 bytes
+        let mut bmp = b"BM".to_vec();
+        for field in [0, 0, BMP_HEADER as u32, 40, 2, 2] {
+            bmp.extend(field.to_le_bytes());
+        }
+        bmp.extend([1u16, 24].map(u16::to_le_bytes).concat());
+        bmp.resize(BMP_HEADER, 0);
+        for row in [bottom, top] {
+            for pixel in row {
+                let [red, green, blue] = small(&pixel);
+                bmp.extend([blue, green, red]);
+            }
+            bmp.extend([0, 0]);
+        }
+        let image = decode_image(&bmp, ReadOptions::default()).unwrap();
+        assert_eq!(interleave(&image.channels), expected_small);
+        assert_eq!(image.bit_depth, Some("8-bit integer"));
+
+        // Two frames of the same pixels in BGR order, the second brighter by 1
+        let mut ser = b"LUCAM-RECORDER".to_vec();
+        for field in [0, SER_BGR, 0, 2, 2, 16, 2] {
+            ser.extend(i32::to_le_bytes(field));
+        }
+        ser.resize(SER_HEADER, 0);
+        for offset in [0, 1] {
+            for pixel in [top, bottom].concat() {
+                for sample in [pixel[2], pixel[1], pixel[0]] {
+                    ser.extend(sample.saturating_add(offset).to_le_bytes());
+                }
+            }
+        }
+        let image = decode_image(&ser, ReadOptions::default());
+        assert!(image.is_err());
+        let options = ReadOptions {
+            frame: Some(2),
+            ..ReadOptions::default()
+        };
+        let image = decode_image(&ser, options).unwrap();
+        let brighter = expected
+            .iter()
+            .map(|pixel| pixel.map(|sample| (sample + 1.0).min(65535.0)))
+            .collect::<Vec<_>>();
+        assert_eq!(interleave(&image.channels), brighter);
+        let options = ReadOptions {
+            frame: Some(3),
+            ..ReadOptions::default()
+        };
+        assert!(decode_image(&ser, options).is_err());
+
+        // A header whose frame size overflows is rejected rather than wrapping around
+        let mut huge = ser.clone();
+        huge[26..34].copy_from_slice(&[i32::MAX.to_le_bytes(), i32::MAX.to_le_bytes()].concat());
+        let options = ReadOptions {
+            frame: Some(2),
+            ..ReadOptions::default()
+        };
+        assert!(decode_image(&huge, options).is_err());
+    }
+}
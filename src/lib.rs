@@ -0,0 +1,179 @@
+//! Splits dual-band images from one-shot-colour cameras into H-alpha and OIII. The
+//! `duosplit` binary is the main interface; [`split_arrays`] offers the same solver to
+//! in-memory pipelines, such as live stacking, whose frames never touch disk.
+
+use crate::cli::SolverArgs;
+use crate::fits::interleave;
+use crate::genetics::line_coefficients;
+use crate::gpu::QEUniform;
+use crate::status::{Failure, Warning};
+use crate::timings::RunTimings;
+use ndarray::{Array2, ArrayView2};
+
+pub mod artifacts;
+pub mod background;
+pub mod bayes;
+pub mod bench;
+pub mod cameras;
+pub mod cli;
+pub mod compare;
+pub mod composite;
+pub mod cpu;
+#[cfg(feature = "cuda")]
+pub mod cuda;
+pub mod database;
+pub mod diagnostics;
+pub mod digest;
+pub mod evaluator;
+pub mod export;
+pub mod filters;
+pub mod fits;
+pub mod genetics;
+pub mod golden;
+pub mod gpu;
+pub mod hooks;
+pub mod init;
+pub mod input;
+pub mod linearize;
+pub mod lines;
+pub mod messages;
+pub mod moments;
+pub mod mosaic;
+pub mod naming;
+pub mod normal_distr;
+pub mod notify;
+pub mod optimizer;
+pub mod output;
+pub mod pipe;
+pub mod preview;
+pub mod profiles;
+pub mod recovery;
+pub mod report;
+pub mod response;
+pub mod sanitize;
+pub mod selftest;
+pub mod snapshot;
+pub mod solver;
+pub mod stages;
+pub mod status;
+pub mod summary;
+pub mod synthetic;
+pub mod timings;
+pub mod tune;
+pub mod validation;
+pub mod white_balance;
+
+/// Quantum efficiencies of the red, green and blue channels at H-alpha and OIII
+pub type QeMatrix = (QEUniform, QEUniform, QEUniform);
+
+/// Both lines extracted from a frame, with the combinations that extracted them
+pub struct SplitResult {
+    pub h_alpha: Array2<f32>,
+    pub oiii: Array2<f32>,
+    /// Red, green and blue coefficients of the H-alpha combination
+    pub h_alpha_coefficients: [f32; 3],
+    /// Red, green and blue coefficients of the OIII combination
+    pub oiii_coefficients: [f32; 3],
+    /// What the solver warned about, such as falling back to the CPU
+    pub warnings: Vec<Warning>,
+}
+
+/// Solves for the combinations of `red`, `green` and `blue` that best extract H-alpha and
+/// OIII and applies them. `opts` are the solver settings of the command line, whose
+/// defaults `SolverArgs::default()` gives; set `opts.quiet` to keep the solver's progress
+/// off stdout and stderr. A frame the solver can't run on or doesn't converge for gives a
+/// [`Failure`], whose exit code the command line would have stopped with.
+///
+/// # Panics
+/// If the channels differ in shape.
+pub fn split_arrays(
+    red: ArrayView2<f32>,
+    green: ArrayView2<f32>,
+    blue: ArrayView2<f32>,
+    qe: &QeMatrix,
+    opts: &SolverArgs,
+) -> Result<SplitResult, Failure> {
+    assert!(
+        red.dim() == green.dim() && red.dim() == blue.dim(),
+        "the channels must have the same shape"
+    );
+    let channels = (red.to_owned(), green.to_owned(), blue.to_owned());
+    let pixels = interleave(&channels);
+    let mut warnings = Vec::new();
+    let genome = pollster::block_on(solver::solve(
+        &pixels,
+        red.ncols(),
+        *qe,
+        opts,
+        &mut RunTimings::default(),
+        &mut warnings,
+    ))?;
+    let (h_alpha_coefficients, oiii_coefficients) = line_coefficients(&genome, qe);
+    let (red, green, blue) = &channels;
+    Ok(SplitResult {
+        h_alpha: combine((red, green, blue), h_alpha_coefficients),
+        oiii: combine((red, green, blue), oiii_coefficients),
+        h_alpha_coefficients,
+        oiii_coefficients,
+        warnings,
+    })
+}
+
+/// The weighted sum of the channels with red, green and blue `coefficients`
+pub fn combine(
+    (red, green, blue): (&Array2<f32>, &Array2<f32>, &Array2<f32>),
+    [r, g, b]: [f32; 3],
+) -> Array2<f32> {
+    r * red + g * green + b * blue
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::ExitCode;
+    use crate::synthetic::Scene;
+    use ndarray::Array2;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn splits_arrays_in_memory() {
+        let qe = (
+            QEUniform { ha: 0.8, oiii: 0.05 },
+            QEUniform { ha: 0.1, oiii: 0.6 },
+            QEUniform { ha: 0.05, oiii: 0.5 },
+        );
+        let (width, height) = (64, 48);
+        let pixels = Scene::default().render(width, height, qe, &mut StdRng::seed_from_u64(1));
+        let channel = |index: usize| {
+            Array2::from_shape_fn((height, width), |(y, x)| pixels[y * width + x][index])
+        };
+        let (red, green, blue) = (channel(0), channel(1), channel(2));
+        let opts = SolverArgs {
+            cpu: true,
+            seed: Some(1),
+            quiet: true,
+            ..SolverArgs::default()
+        };
+        let result = split_arrays(red.view(), green.view(), blue.view(), &qe, &opts).unwrap();
+
+        assert_eq!(result.h_alpha.dim(), (height, width));
+        assert_eq!(
+            result.oiii,
+            combine((&red, &green, &blue), result.oiii_coefficients)
+        );
+        // The OIII combination cancels H-alpha, whatever the solver picked
+        let [r, g, b] = result.oiii_coefficients;
+        assert!((r * qe.0.ha + g * qe.1.ha + b * qe.2.ha).abs() < 1e-4);
+
+        // A frame that doesn't converge fails the call rather than the process
+        let opts = SolverArgs {
+            max_noise: Some(0.0),
+            ..opts
+        };
+        let failure = split_arrays(red.view(), green.view(), blue.view(), &qe, &opts)
+            .err()
+            .unwrap();
+        assert_eq!(failure.code, ExitCode::NonConvergence);
+    }
+}
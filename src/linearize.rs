@@ -0,0 +1,123 @@
+use crate::cli::SplitArgs;
+use crate::fits::Channels;
+use crate::messages::text;
+use crate::status::{self, ExitCode, WarningKind};
+use std::fmt;
+
+/// A display stretch applied to data scaled to 0 to 1 by its brightest pixel, which
+/// `--linearize` inverts
+#[derive(Clone, Copy)]
+pub enum Stretch {
+    /// `x^(1 / gamma)`
+    Gamma(f32),
+    /// `asinh(beta x) / asinh(beta)`
+    Asinh(f32),
+}
+
+impl fmt::Display for Stretch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stretch::Gamma(gamma) => write!(f, "gamma {}", gamma),
+            Stretch::Asinh(beta) => write!(f, "asinh {}", beta),
+        }
+    }
+}
+
+impl Stretch {
+    /// Stretches a value between 0 and 1
+    pub fn apply(self, value: f32) -> f32 {
+        match self {
+            Stretch::Gamma(gamma) => value.max(0.0).powf(1.0 / gamma),
+            Stretch::Asinh(beta) => (value * beta).asinh() / beta.asinh(),
+        }
+    }
+
+    fn invert(self, value: f32) -> f32 {
+        match self {
+            Stretch::Gamma(gamma) => value.max(0.0).powf(gamma),
+            Stretch::Asinh(beta) => (value * beta.asinh()).sinh() / beta,
+        }
+    }
+}
+
+/// Warns about stretched data, or inverts the stretch `args` gives with a warning of its
+/// own since no inversion recovers the linear data exactly
+pub fn from_args(args: &SplitArgs, strict: bool, stretched: bool, channels: &mut Channels) {
+    let Some(stretch) = args.linearize else {
+        if stretched {
+            status::warn(
+                strict,
+                ExitCode::BadInput,
+                WarningKind::Stretched,
+                text("stretched", &[]),
+            );
+        }
+        return;
+    };
+    if !stretched {
+        status::warn(
+            strict,
+            ExitCode::BadInput,
+            WarningKind::Stretched,
+            text("not-stretched", &[]),
+        );
+    }
+    status::warn(
+        strict,
+        ExitCode::BadInput,
+        WarningKind::Stretched,
+        text("inverting-stretch", &[("stretch", &stretch)]),
+    );
+    invert(stretch, channels);
+}
+
+/// Inverts `stretch` on each pixel, relative to the brightest pixel of any channel
+pub fn invert(stretch: Stretch, (red, green, blue): &mut Channels) {
+    let peak = [&*red, &*green, &*blue]
+        .iter()
+        .flat_map(|channel| channel.iter())
+        .copied()
+        .filter(|value| value.is_finite())
+        .fold(0.0, f32::max);
+    if peak <= 0.0 {
+        return;
+    }
+    for channel in [red, green, blue] {
+        channel.mapv_inplace(|value| stretch.invert(value / peak) * peak);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array2;
+
+    #[test]
+    fn inverts_gamma_and_asinh_stretches() {
+        let linear = Array2::from_shape_fn((4, 4), |(y, x)| (y * 4 + x) as f32 * 100.0);
+        let peak = 1500.0;
+        for (stretch, forward) in [
+            (
+                Stretch::Gamma(2.2),
+                Box::new(|x: f32| x.powf(1.0 / 2.2)) as Box<dyn Fn(f32) -> f32>,
+            ),
+            (
+                Stretch::Asinh(50.0),
+                Box::new(|x: f32| (50.0 * x).asinh() / 50f32.asinh()),
+            ),
+        ] {
+            let stretched = linear.mapv(|value| forward(value / peak) * peak);
+            let mut channels = (stretched.clone(), stretched.clone(), stretched);
+            invert(stretch, &mut channels);
+            for (restored, original) in channels.0.iter().zip(&linear) {
+                assert!(
+                    (restored - original).abs() < 0.05,
+                    "{}: {} for {}",
+                    stretch,
+                    restored,
+                    original
+                );
+            }
+        }
+    }
+}
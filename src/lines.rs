@@ -0,0 +1,86 @@
+use serde::{Deserialize, Deserializer};
+
+/// Emission lines known by name, wherever a wavelength can be given: in QE and
+/// transmission curves and in filter passbands
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Line {
+    #[serde(rename = "ha")]
+    HAlpha,
+    Oiii,
+    #[serde(rename = "hb")]
+    HBeta,
+    Nii,
+    Sii,
+}
+
+impl Line {
+    /// Wavelength in nanometres, in air
+    pub const fn wavelength(self) -> f32 {
+        match self {
+            Line::HAlpha => 656.3,
+            Line::Oiii => 500.7,
+            Line::HBeta => 486.1,
+            Line::Nii => 658.4,
+            Line::Sii => 671.6,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Line::HAlpha => "H-alpha",
+            Line::Oiii => "OIII",
+            Line::HBeta => "H-beta",
+            Line::Nii => "NII",
+            Line::Sii => "SII",
+        }
+    }
+}
+
+/// A wavelength in nanometres, or the name of a line standing for its wavelength
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+pub enum Wavelength {
+    Nm(f32),
+    Line(Line),
+}
+
+impl Wavelength {
+    pub fn nm(self) -> f32 {
+        match self {
+            Wavelength::Nm(nm) => nm,
+            Wavelength::Line(line) => line.wavelength(),
+        }
+    }
+}
+
+/// Deserializes a wavelength given in nanometres or by line name, for
+/// `#[serde(deserialize_with)]`
+pub fn wavelength<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    Wavelength::deserialize(deserializer).map(Wavelength::nm)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filters::Passband;
+    use crate::response::Curve;
+
+    #[test]
+    fn wavelengths_can_be_given_by_line() {
+        let curve = serde_json::from_str::<Curve>(
+            r#"[["hb", 0.4], ["oiii", 0.5], [600, 0.7], ["sii", 0.8]]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            curve.0,
+            vec![[486.1, 0.4], [500.7, 0.5], [600.0, 0.7], [671.6, 0.8]]
+        );
+        let band = serde_json::from_str::<Passband>(
+            r#"{"center": "nii", "bandwidth": 3, "transmission": 0.9}"#,
+        )
+        .unwrap();
+        assert_eq!(band.center, 658.4);
+
+        assert!(serde_json::from_str::<Curve>(r#"[["hgamma", 0.4]]"#).is_err());
+    }
+}
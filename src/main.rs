@@ -1,249 +1,611 @@
-use crate::cli::Cli;
-use crate::genetics::{j_k_from_i, Genome};
-use crate::gpu::{GpuContext, QEUniform};
-use crate::normal_distr::NormalDistribution;
 use clap::Parser;
-use fitrs::{Fits, FitsData, Hdu, HeaderValue};
-use ndarray::{s, Array2, Array3};
-use rand::{rng, Rng};
+use duosplit::artifacts::Artifacts;
+use duosplit::cli::{Cli, Command, SolverArgs, SplitArgs};
+use duosplit::composite::Composite;
+use duosplit::diagnostics::{channel_fits, line_diagnostics, POOR_FIT};
+use duosplit::fits::{interleave, write_mef, CardValue, Channels};
+use duosplit::genetics::line_coefficients;
+use duosplit::gpu::{GpuContext, GpuDevice, QEUniform};
+use duosplit::input::read_image;
+use duosplit::lines::Line;
+use duosplit::messages::text;
+use duosplit::mosaic::{Mosaic, PanelSolution};
+use duosplit::output::OutputFormat;
+use duosplit::report::Report;
+use duosplit::stages::Chain;
+use duosplit::status::{ExitCode, WarningKind};
+use duosplit::timings::RunTimings;
+use duosplit::{
+    bench, cameras, combine, compare, export, filters, hooks, init, linearize, naming, notify,
+    pipe, preview, profiles, sanitize, selftest, solver, status, summary, synthetic, tune,
+    validation, white_balance,
+};
+use ndarray::Array2;
+use rand::rngs::StdRng;
+use rand::{rng, Rng, SeedableRng};
+use std::fs::File;
 use std::path::{Path, PathBuf};
-use std::process::exit;
 use std::time::Instant;
 
-mod cli;
-mod genetics;
-mod gpu;
-mod normal_distr;
-
 #[pollster::main]
 async fn main() {
-    let cli = Cli::parse();
-
-    println!("Reading FITS file: {}", cli.input.display());
-    let (red_channel, green_channel, blue_channel) = match read_fits(&cli.input) {
-        Ok(value) => value,
-        Err(err) => {
-            eprintln!("Error reading FITS file: {}", err);
-            exit(1);
+    let mut cli = Cli::parse();
+    let args = std::env::args_os().collect::<Vec<_>>();
+    match profiles::expand(&cli, &args) {
+        Ok(Some(expanded)) => cli = Cli::parse_from(expanded),
+        Ok(None) => {}
+        Err(failure) => failure
+            .code
+            .exit(text("error", &[("message", &failure.message)])),
+    }
+    match (&cli.command, &cli.split) {
+        (Some(Command::Bench(args)), _) => bench::run(args).await,
+        (Some(Command::Selftest(args)), _) => selftest::run(args).await,
+        (Some(Command::Synth(args)), _) => synthetic::run(args),
+        (Some(Command::Compare(args)), _) => compare::run(args),
+        (Some(Command::Cameras(args)), _) => cameras::run(args),
+        (Some(Command::Filters(args)), _) => filters::run(args),
+        (Some(Command::Init), _) => init::run(),
+        (Some(Command::Tune(args)), _) => {
+            let tuned = tune::run(args).await;
+            if args.apply {
+                split(&args.split, &tuned).await;
+            }
         }
-    };
-
-    println!("Setting up GPU context...");
-    let mut pixels = Vec::new();
-    let flat_red = red_channel.flatten();
-    let flat_green = green_channel.flatten();
-    let flat_blue = blue_channel.flatten();
-    for i in 0..flat_red.len() {
-        pixels.push([flat_red[i], flat_green[i], flat_blue[i]]);
+        (None, Some(split_args)) => split(split_args, &cli.solver).await,
+        (None, None) => unreachable!("clap requires the split arguments without a subcommand"),
     }
+}
 
-    let qe_red = QEUniform {
-        ha: cli.red_ha_qe,
-        oiii: cli.red_oiii_qe,
-    };
-    let qe_green = QEUniform {
-        ha: cli.green_ha_qe,
-        oiii: cli.green_oiii_qe,
-    };
-    let qe_blue = QEUniform {
-        ha: cli.blue_ha_qe,
-        oiii: cli.blue_oiii_qe,
+/// Where the split images go
+enum Output {
+    /// One multi-extension FITS stream on what was stdout, for `--output -`
+    Stdout(File),
+    /// Separate H-alpha and OIII files
+    Files(PathBuf, PathBuf),
+}
+
+async fn split(cli: &SplitArgs, solver: &SolverArgs) {
+    let artifacts = cli.artifacts.as_ref().map(|root| {
+        Artifacts::create(root, &cli.input)
+            .unwrap_or_else(|err| ExitCode::Io.exit(text("error", &[("message", &err)])))
+    });
+    let redirected = artifacts
+        .as_ref()
+        .map(|artifacts| artifacts.redirect(cli, solver));
+    let (cli, solver) = match &redirected {
+        Some((cli, solver)) => (cli, solver),
+        None => (cli, solver),
     };
-    let context = match GpuContext::new(pixels, cli.chunks, (qe_red, qe_green, qe_blue)).await {
-        Ok(ctx) => ctx,
-        Err(err) => {
-            eprintln!("Error setting up GPU context: {}", err);
-            exit(1);
+    notify::install(cli);
+    let mut timings = RunTimings::default();
+    let output = if cli.output == Path::new("-") {
+        if cli.format != OutputFormat::Fits
+            || cli.preview
+            || cli.composite_expr.is_some()
+            || !cli.post_cmd.is_empty()
+        {
+            let message = text("stdout-fits-only", &[]);
+            ExitCode::BadInput.exit(text("error", &[("message", &message)]));
+        }
+        match pipe::take_stdout() {
+            Ok(stream) => Output::Stdout(stream),
+            Err(err) => ExitCode::Io.exit(text("error", &[("message", &err)])),
+        }
+    } else {
+        match (
+            naming::output_path(cli, "h_alpha"),
+            naming::output_path(cli, "oiii"),
+        ) {
+            (Ok(h_alpha_path), Ok(oiii_path)) => Output::Files(h_alpha_path, oiii_path),
+            (Err(err), _) | (_, Err(err)) => {
+                ExitCode::BadInput.exit(text("error", &[("message", &err)]))
+            }
         }
     };
 
-    println!("Starting genetic algorithm optimization...");
-    let best_genome = optimized_genome(&cli, context).await;
-
-    let ha_r = best_genome.i;
-    let (ha_g, ha_b) = j_k_from_i(
-        ha_r,
-        cli.red_ha_qe,
-        cli.green_ha_qe,
-        cli.blue_ha_qe,
-        cli.red_oiii_qe,
-        cli.green_oiii_qe,
-        cli.blue_oiii_qe,
+    let mut response = cli.response();
+
+    println!(
+        "{}",
+        text("reading-input", &[("path", &cli.input.display())])
     );
-    let h_alpha = ha_r * &red_channel + ha_g * &green_channel + ha_b * &blue_channel;
-
-    let oiii_r = best_genome.x;
-    let (oiii_g, oiii_b) = j_k_from_i(
-        oiii_r,
-        cli.red_oiii_qe,
-        cli.green_oiii_qe,
-        cli.blue_oiii_qe,
-        cli.red_ha_qe,
-        cli.green_ha_qe,
-        cli.blue_ha_qe,
+    let start = Instant::now();
+    let image = read_image(&cli.input, cli.read_options()).unwrap_or_else(|failure| {
+        failure.code.exit(text(
+            "error-reading-input",
+            &[("message", &failure.message)],
+        ))
+    });
+    for warning in &image.warnings {
+        status::warn(
+            solver.strict,
+            ExitCode::BadInput,
+            warning.kind,
+            &warning.message,
+        );
+    }
+    let (mut channels, bit_depth) = (image.channels, image.bit_depth);
+    sanitize::from_args(cli, &mut channels);
+    response.airmass = cli.airmass.or(image.airmass);
+    if let Some(airmass) = response.airmass {
+        println!(
+            "{}",
+            text("correcting-extinction", &[("airmass", &airmass)])
+        );
+    }
+    if let Some(summary) = response.nii_summary() {
+        println!("{}", summary);
+    }
+    let quantum_efficiencies = response
+        .quantum_efficiencies()
+        .expect("the response was checked against both lines");
+    timings.read = start.elapsed();
+    let mosaic = cli.mosaic.as_ref().map(|path| {
+        Mosaic::load(path, channels.0.dim()).unwrap_or_else(|failure| {
+            failure
+                .code
+                .exit(text("error", &[("message", &failure.message)]))
+        })
+    });
+    linearize::from_args(cli, solver.strict, image.stretched, &mut channels);
+    let white_balance = white_balance::undo_from_args(cli, &mut channels, quantum_efficiencies);
+    if let Some(sigma) = cli.add_noise {
+        println!("{}", text("adding-noise", &[("sigma", &sigma)]));
+        let seed = solver.seed.unwrap_or_else(|| rng().random());
+        synthetic::add_noise(&mut channels, sigma, &mut StdRng::seed_from_u64(seed));
+    }
+
+    summary::print_run_summary(
+        cli,
+        solver,
+        quantum_efficiencies,
+        (channels.0.ncols(), channels.0.nrows()),
+        bit_depth,
     );
-    let oiii = oiii_r * &red_channel + oiii_g * &green_channel + oiii_b * &blue_channel;
 
-    println!("Best genome results:");
+    let Solved {
+        h_alpha: [ha_r, ha_g, ha_b],
+        oiii: [oiii_r, oiii_g, oiii_b],
+        lines: (mut h_alpha, mut oiii),
+        panels,
+        device,
+    } = match &mosaic {
+        Some(mosaic) => {
+            solve_mosaic(
+                mosaic,
+                &channels,
+                quantum_efficiencies,
+                solver,
+                &mut timings,
+            )
+            .await
+        }
+        None => solve_whole(&channels, quantum_efficiencies, solver, &mut timings).await,
+    };
+    let (red_channel, green_channel, blue_channel) = channels;
+    let channels = (&red_channel, &green_channel, &blue_channel);
+
+    if panels.is_some() {
+        println!("{}", text("mosaic-results", &[]));
+    } else {
+        println!("{}", text("best-genome-results", &[]));
+    }
+    println!(
+        "{}",
+        text(
+            "h-alpha-coefficients",
+            &[("r", &ha_r), ("g", &ha_g), ("b", &ha_b)]
+        )
+    );
     println!(
-        "H-alpha coefficients: r = {}, g = {}, b = {}",
-        ha_r, ha_g, ha_b
+        "{}",
+        text(
+            "oiii-coefficients",
+            &[("r", &oiii_r), ("g", &oiii_g), ("b", &oiii_b)]
+        )
     );
+    let [h_alpha_diagnostics, oiii_diagnostics] = [
+        ("H-alpha", [ha_r, ha_g, ha_b]),
+        ("OIII", [oiii_r, oiii_g, oiii_b]),
+    ]
+    .map(|(line, coefficients)| {
+        let diagnostics = line_diagnostics(channels, coefficients);
+        let negative = format!("{:.2}", diagnostics.negative_fraction * 100.0);
+        println!(
+            "{}",
+            text(
+                "line-diagnostics",
+                &[
+                    ("line", &line),
+                    ("noise", &diagnostics.noise),
+                    ("negative", &negative)
+                ]
+            )
+        );
+        diagnostics
+    });
+    let fits = channel_fits(channels, (&h_alpha, &oiii), quantum_efficiencies);
+    let [red_fit, green_fit, blue_fit] = fits;
+    let [red, green, blue] = fits.map(|fit| format!("{:.4}", fit));
     println!(
-        "OIII coefficients: r = {}, g = {}, b = {}",
-        oiii_r, oiii_g, oiii_b
+        "{}",
+        text(
+            "channel-fits",
+            &[("red", &red), ("green", &green), ("blue", &blue)]
+        )
     );
+    if red_fit.min(green_fit).min(blue_fit) < POOR_FIT {
+        println!("{}", text("poor-channel-fit", &[]));
+    }
+    let validation = validation::from_args(cli, &h_alpha, &oiii)
+        .unwrap_or_else(|err| ExitCode::BadInput.exit(text("error", &[("message", &err)])));
+    if let Some(validation) = &validation {
+        for (line, score) in [("H-alpha", validation.h_alpha), ("OIII", validation.oiii)] {
+            let correlation = format!("{:.6}", score.correlation);
+            println!(
+                "{}",
+                text(
+                    "reference-score",
+                    &[
+                        ("line", &line),
+                        ("correlation", &correlation),
+                        ("rms", &score.rms),
+                        ("scale", &score.scale)
+                    ]
+                )
+            );
+        }
+    }
+    if cli.export_pixelmath {
+        export::print_pixelmath([ha_r, ha_g, ha_b], [oiii_r, oiii_g, oiii_b]);
+    }
+    if let Some(script) = &cli.export_siril {
+        let sequence = cli.siril_sequence.as_deref();
+        match export::write_siril_script(
+            script,
+            [ha_r, ha_g, ha_b],
+            [oiii_r, oiii_g, oiii_b],
+            sequence,
+            cli.normalize_exposure,
+        ) {
+            Ok(()) => println!(
+                "{}",
+                text("wrote-siril-script", &[("path", &script.display())])
+            ),
+            Err(err) => ExitCode::Io.exit(text("error-writing-siril-script", &[("message", &err)])),
+        }
+    }
+    if let Some(path) = &cli.report {
+        let report = Report {
+            input: cli.input.display().to_string(),
+            input_sha256: image.sha256.clone(),
+            h_alpha_coefficients: [ha_r, ha_g, ha_b],
+            oiii_coefficients: [oiii_r, oiii_g, oiii_b],
+            h_alpha: h_alpha_diagnostics,
+            oiii: oiii_diagnostics,
+            channel_fits: fits,
+            balmer_decrement: cli.red_hb_qe.map(|_| cli.balmer_decrement),
+            validation,
+            panels,
+            warnings: status::warnings(),
+        };
+        match report.save(path) {
+            Ok(()) => println!("{}", text("wrote-report", &[("path", &path.display())])),
+            Err(err) => ExitCode::Io.exit(text("error-writing-report", &[("message", &err)])),
+        }
+    }
 
-    if let Err(err) = write_fits(&cli.output.join("h_alpha.fit"), &h_alpha) {
-        eprintln!("Error writing H-alpha FITS file: {}", err);
-        exit(1);
+    let stages = Chain::from_args(cli);
+    if !stages.is_empty() {
+        println!("{}", text("post-processing", &[("stages", &stages)]));
+        stages.apply(&mut h_alpha);
+        stages.apply(&mut oiii);
+    }
+    let composite = match &cli.composite_expr {
+        Some(composite) => {
+            Some(render_composite(composite, device.as_ref(), &h_alpha, &oiii).await)
+        }
+        None => None,
+    };
+
+    // Recorded in the outputs so the split can be traced back to its input, and to the
+    // balance undone in it
+    let mut metadata = vec![("INSHA256", CardValue::Text(image.sha256.clone()))];
+    if let Some([red, green, blue]) = white_balance {
+        metadata.extend([
+            ("WBR", CardValue::Number(red)),
+            ("WBG", CardValue::Number(green)),
+            ("WBB", CardValue::Number(blue)),
+        ]);
+    }
+    let start = Instant::now();
+    // The template already rendered for both lines, so it renders for the composite too
+    let composite_path = composite.as_ref().map(|_| {
+        naming::output_path(cli, "composite").expect("the naming template renders every line")
+    });
+    let mut outputs = match &output {
+        Output::Stdout(_) => vec![PathBuf::from("-")],
+        Output::Files(h_alpha_path, oiii_path) => vec![h_alpha_path.clone(), oiii_path.clone()],
+    };
+    outputs.extend(composite_path.clone());
+    match output {
+        Output::Stdout(mut stream) => {
+            if let Err(err) = write_mef(
+                &mut stream,
+                &[("H_ALPHA", &h_alpha), ("OIII", &oiii)],
+                &metadata,
+            ) {
+                ExitCode::Io.exit(text("error-writing-fits-stream", &[("message", &err)]));
+            }
+        }
+        Output::Files(h_alpha_path, oiii_path) => {
+            if let Err(err) = cli.format.write(&h_alpha_path, &h_alpha, &metadata) {
+                ExitCode::Io.exit(text("error-writing-h-alpha", &[("message", &err)]));
+            }
+
+            if let Err(err) = cli.format.write(&oiii_path, &oiii, &metadata) {
+                ExitCode::Io.exit(text("error-writing-oiii", &[("message", &err)]));
+            }
+
+            if let (Some(colors), Some(path)) = (&composite, &composite_path) {
+                if let Err(err) = cli
+                    .format
+                    .write_rgb(path, h_alpha.ncols(), colors, &metadata)
+                {
+                    ExitCode::Io.exit(text("error-writing-composite", &[("message", &err)]));
+                }
+            }
+
+            if cli.preview {
+                for (line, path, image) in [
+                    (Line::HAlpha, &h_alpha_path, &h_alpha),
+                    (Line::Oiii, &oiii_path, &oiii),
+                ] {
+                    let path = path.with_extension("avif");
+                    let stretch = cli.preview_stretch(line);
+                    if let Err(err) = preview::write_avif_preview(&path, image, stretch) {
+                        ExitCode::Io.exit(text("error-writing-preview", &[("message", &err)]));
+                    }
+                    println!("{}", text("wrote-preview", &[("path", &path.display())]));
+                }
+            }
+
+            for command in &cli.post_cmd {
+                if let Err(err) = hooks::run_post_command(command, &h_alpha_path, &oiii_path) {
+                    ExitCode::PostCommand.exit(text("error", &[("message", &err)]));
+                }
+            }
+        }
+    }
+    timings.write = start.elapsed();
+
+    if let Some(artifacts) = &artifacts {
+        match artifacts.write_manifest(
+            &cli.input,
+            &image.sha256,
+            [ha_r, ha_g, ha_b],
+            [oiii_r, oiii_g, oiii_b],
+        ) {
+            Ok(path) => println!("{}", text("wrote-manifest", &[("path", &path.display())])),
+            Err(err) => ExitCode::Io.exit(text("error-writing-manifest", &[("message", &err)])),
+        }
     }
 
-    if let Err(err) = write_fits(&cli.output.join("oiii.fit"), &oiii) {
-        eprintln!("Error writing OIII FITS file: {}", err);
-        exit(1);
+    if solver.timings {
+        timings.print_summary();
     }
+    println!("{}", text("done", &[]));
+    notify::succeeded(&outputs, [ha_r, ha_g, ha_b], [oiii_r, oiii_g, oiii_b]);
+}
 
-    println!("Done!");
+/// The combinations a split settled on and the outputs they give
+struct Solved {
+    h_alpha: [f32; 3],
+    oiii: [f32; 3],
+    lines: (Array2<f32>, Array2<f32>),
+    // Each panel's own combinations, for a mosaic
+    panels: Option<Vec<PanelSolution>>,
+    // The GPU the solver ran on, if it is still usable, for the composite
+    device: Option<GpuDevice>,
 }
 
-fn read_fits(path: &impl AsRef<Path>) -> Result<(Array2<f32>, Array2<f32>, Array2<f32>), String> {
-    let image = Fits::open(path).map_err(|e| format!("Failed to open FITS file: {}", e))?;
-    let hdu = image.get(0).ok_or("No HDU found in FITS file")?;
-    let scale = hdu
-        .value("BSCALE")
-        .map(|v| match v {
-            HeaderValue::IntegerNumber(i) => *i as f64,
-            HeaderValue::RealFloatingNumber(f) => *f,
-            _ => panic!("Unexpected BSCALE type"),
-        })
-        .unwrap_or(1.0);
-    let offset = hdu
-        .value("BZERO")
-        .map(|v| match v {
-            HeaderValue::IntegerNumber(i) => *i as f64,
-            HeaderValue::RealFloatingNumber(f) => *f,
-            _ => panic!("Unexpected BZERO type"),
-        })
-        .unwrap_or(0.0);
-    let (shape, data) = match hdu.read_data() {
-        FitsData::Characters(arr) => (
-            arr.shape,
-            arr.data.into_iter().map(|v| v as u64 as f64).collect(),
-        ),
-        FitsData::IntegersI32(arr) => (
-            arr.shape,
-            arr.data
-                .into_iter()
-                .map(|v| v.unwrap_or(0) as f64)
-                .collect(),
-        ),
-        FitsData::IntegersU32(arr) => (
-            arr.shape,
-            arr.data
-                .into_iter()
-                .map(|v| v.unwrap_or(0) as f64)
-                .collect(),
-        ),
-        FitsData::FloatingPoint32(arr) => {
-            (arr.shape, arr.data.into_iter().map(|v| v as f64).collect())
-        }
-        FitsData::FloatingPoint64(arr) => {
-            eprintln!(
-                "Warning: Converting FITS data from 64 bit to 32 bit; this may lose precision."
-            );
-            (arr.shape, arr.data)
+/// Solves for one combination of each line over the whole image and applies them
+async fn solve_whole(
+    channels: &Channels,
+    quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+    solver: &SolverArgs,
+    timings: &mut RunTimings,
+) -> Solved {
+    let pixels = interleave(channels);
+    let mut warnings = Vec::new();
+    let solution = solver::solve_keeping_gpu(
+        &pixels,
+        channels.0.ncols(),
+        quantum_efficiencies,
+        solver,
+        timings,
+        &mut warnings,
+        None,
+    )
+    .await;
+    status::record_all(warnings);
+    let (best_genome, gpu) = solution.unwrap_or_else(|failure| failure.exit());
+    let (h_alpha, oiii) = line_coefficients(&best_genome, &quantum_efficiencies);
+    let device = gpu.as_ref().map(|gpu| gpu.device().clone());
+    let start = Instant::now();
+    let lines = match apply_on_gpu(gpu, channels.0.dim(), h_alpha, oiii).await {
+        Some(lines) => {
+            timings.applied_on_gpu = true;
+            lines
+        }
+        None => {
+            let channels = (&channels.0, &channels.1, &channels.2);
+            (combine(channels, h_alpha), combine(channels, oiii))
         }
     };
-
-    let channels = Array3::from_shape_vec((shape[2], shape[1], shape[0]), data)
-        .expect("Failed to reshape FITS data into 3D array")
-        .mapv(|v| (v * scale + offset) as f32);
-    let red_channel = channels.slice(s![0, .., ..]).into_owned();
-    let green_channel = channels.slice(s![1, .., ..]).into_owned();
-    let blue_channel = channels.slice(s![2, .., ..]).into_owned();
-    Ok((red_channel, green_channel, blue_channel))
+    timings.apply = start.elapsed();
+    Solved {
+        h_alpha,
+        oiii,
+        lines,
+        panels: None,
+        device,
+    }
 }
 
-async fn optimized_genome(cli: &Cli, context: GpuContext) -> Genome {
-    let mut rng = rng();
-    let mut population = Vec::with_capacity(cli.population_size);
-    for _ in 0..cli.population_size {
-        population.push(Genome::random(&mut rng));
+/// Solves each panel of a mosaic for its own combinations, then blends them across the
+/// panel edges
+async fn solve_mosaic(
+    mosaic: &Mosaic,
+    channels: &Channels,
+    quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+    solver: &SolverArgs,
+    timings: &mut RunTimings,
+) -> Solved {
+    let mut panels = Vec::with_capacity(mosaic.panels.len());
+    // Set up with the first panel, then shared so the kernels are built only once
+    let mut device = None;
+    for panel in &mosaic.panels {
+        println!(
+            "{}",
+            text(
+                "solving-panel",
+                &[
+                    ("panel", &panel.name),
+                    ("width", &panel.width),
+                    ("height", &panel.height),
+                    ("x", &panel.x),
+                    ("y", &panel.top(channels.0.nrows()))
+                ]
+            )
+        );
+        let cropped = (
+            panel.crop(&channels.0),
+            panel.crop(&channels.1),
+            panel.crop(&channels.2),
+        );
+        let mut warnings = Vec::new();
+        let solution = solver::solve_keeping_gpu(
+            &interleave(&cropped),
+            panel.width,
+            quantum_efficiencies,
+            solver,
+            timings,
+            &mut warnings,
+            device.as_ref(),
+        )
+        .await;
+        status::record_all(warnings);
+        let (genome, gpu) = solution.unwrap_or_else(|failure| failure.exit());
+        device = device.or_else(|| gpu.map(|gpu| gpu.device().clone()));
+        let (h_alpha, oiii) = line_coefficients(&genome, &quantum_efficiencies);
+        println!(
+            "{}",
+            text(
+                "panel-coefficients",
+                &[
+                    ("panel", &panel.name),
+                    ("h-alpha-r", &h_alpha[0]),
+                    ("h-alpha-g", &h_alpha[1]),
+                    ("h-alpha-b", &h_alpha[2]),
+                    ("oiii-r", &oiii[0]),
+                    ("oiii-g", &oiii[1]),
+                    ("oiii-b", &oiii[2])
+                ]
+            )
+        );
+        panels.push(PanelSolution {
+            name: panel.name.clone(),
+            h_alpha_coefficients: h_alpha,
+            oiii_coefficients: oiii,
+        });
     }
 
-    let mut fitnesses = Vec::new();
-    for gen in 0..cli.generations {
-        let start = Instant::now();
-        fitnesses = context.compute_fitness(&population).await;
+    let start = Instant::now();
+    let channels = (&channels.0, &channels.1, &channels.2);
+    let blend = |line: fn(&PanelSolution) -> [f32; 3]| {
+        let coefficients = panels.iter().map(line).collect::<Vec<_>>();
+        mosaic.blend(channels, &coefficients)
+    };
+    let lines = (
+        blend(|panel| panel.h_alpha_coefficients),
+        blend(|panel| panel.oiii_coefficients),
+    );
+    timings.apply = start.elapsed();
 
-        let elite_indices = {
-            let mut indices = (0..cli.population_size).collect::<Vec<usize>>();
-            indices.sort_by(|&i, &j| fitnesses[i].partial_cmp(&fitnesses[j]).unwrap());
-            indices[..cli.elitism].to_vec()
-        };
-        let elites = elite_indices
-            .iter()
-            .map(|&i| population[i])
-            .collect::<Vec<Genome>>();
-
-        let mut new_population = elites.clone();
-        let mutation_rate = cli.initial_std * (-cli.decay_rate * gen as f32).exp();
-        while new_population.len() < cli.population_size {
-            let idx1 = rng.random_range(0..cli.population_size);
-            let mut idx2 = rng.random_range(0..cli.population_size);
-            while idx2 == idx1 {
-                idx2 = rng.random_range(0..cli.population_size);
+    // Diagnostics and anything else needing a single combination get the panels' mean
+    let areas = mosaic
+        .panels
+        .iter()
+        .map(|panel| (panel.width * panel.height) as f32)
+        .collect::<Vec<_>>();
+    let total = areas.iter().sum::<f32>();
+    let mean = |line: fn(&PanelSolution) -> [f32; 3]| {
+        let mut mean = [0.0; 3];
+        for (panel, area) in panels.iter().zip(&areas) {
+            for (mean, coefficient) in mean.iter_mut().zip(line(panel)) {
+                *mean += coefficient * area / total;
             }
-            let parent = if fitnesses[idx1] < fitnesses[idx2] {
-                population[idx1]
-            } else {
-                population[idx2]
-            };
-            let child = Genome {
-                i: parent.i + rng.sample(NormalDistribution::new(0.0, mutation_rate)),
-                x: parent.x + rng.sample(NormalDistribution::new(0.0, mutation_rate)),
-            };
-            new_population.push(child);
-        }
-
-        population = new_population;
-        let (_, best_fitness) = best_genome_and_fitness(&population, &fitnesses);
-        println!("Generation {}: {}", gen, best_fitness);
-        if cli.timings {
-            let duration = Instant::now() - start;
-            println!("Generation {} took {:?}", gen, duration);
-        }
-    }
-
-    let (best_genome, best_fitness) = best_genome_and_fitness(&population, &fitnesses);
-    println!("Best genome found with noise: {}", best_fitness);
-    if best_genome.i < best_genome.x {
-        println!("Warning: H-alpha component is less than OIII component; they may be swapped.");
-        Genome {
-            i: best_genome.x,
-            x: best_genome.i,
         }
-    } else {
-        best_genome
+        mean
+    };
+    Solved {
+        h_alpha: mean(|panel| panel.h_alpha_coefficients),
+        oiii: mean(|panel| panel.oiii_coefficients),
+        lines,
+        panels: Some(panels),
+        device,
     }
 }
 
-fn best_genome_and_fitness(population: &Vec<Genome>, fitnesses: &Vec<f32>) -> (Genome, f32) {
-    let (best_idx, _) = fitnesses
+/// Evaluates `--composite-expr` on every pixel, on `device` if there is one, falling back
+/// to the CPU if it fails
+async fn render_composite(
+    composite: &Composite,
+    device: Option<&GpuDevice>,
+    h_alpha: &Array2<f32>,
+    oiii: &Array2<f32>,
+) -> Vec<[f32; 3]> {
+    let lines = h_alpha
+        .iter()
+        .zip(oiii)
+        .map(|(&h_alpha, &oiii)| [h_alpha, oiii])
+        .collect::<Vec<_>>();
+    if let Some(device) = device {
+        match device.composite(composite, &lines).await {
+            Ok(colors) => return colors,
+            Err(err) => {
+                let message = text("gpu-composite-failed", &[("message", &err)]);
+                eprintln!("{}", text("warning", &[("message", &message)]));
+                status::record(WarningKind::GpuFallback, message);
+            }
+        }
+    }
+    lines
         .iter()
-        .enumerate()
-        .min_by(|&(_, a), &(_, b)| a.partial_cmp(b).unwrap())
-        .unwrap();
-    (population[best_idx], fitnesses[best_idx])
+        .map(|&[h_alpha, oiii]| composite.evaluate(h_alpha, oiii))
+        .collect()
 }
 
-fn write_fits(path: &PathBuf, data: &Array2<f32>) -> Result<(), String> {
-    let hdu = Hdu::new(
-        &[data.shape()[1], data.shape()[0]],
-        data.as_slice().unwrap().to_vec(),
-    );
-    Fits::create(path, hdu)
-        .map(|_| ())
-        .map_err(|e| format!("Failed to write to {}: {}", path.to_str().unwrap(), e))
+/// Applies the coefficients on the GPU the solver ran on, or returns `None` for the CPU to
+/// do it if there was none or it failed
+async fn apply_on_gpu(
+    gpu: Option<GpuContext>,
+    (height, width): (usize, usize),
+    h_alpha: [f32; 3],
+    oiii: [f32; 3],
+) -> Option<(Array2<f32>, Array2<f32>)> {
+    let (h_alpha, oiii) = gpu?
+        .apply(h_alpha, oiii)
+        .await
+        .map_err(|err| {
+            let message = text("gpu-apply-failed", &[("message", &err)]);
+            eprintln!("{}", text("warning", &[("message", &message)]));
+            status::record(WarningKind::GpuFallback, message);
+        })
+        .ok()?;
+    let image =
+        |values| Array2::from_shape_vec((height, width), values).expect("one value per pixel");
+    Some((image(h_alpha), image(oiii)))
 }
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{Display, Write};
+use std::sync::OnceLock;
+
+/// The message catalogs by language tag, in the subset of Fluent syntax `parse` reads.
+/// English is the baseline: a translation is a copy of `locales/en.ftl` with the text after
+/// each `=` translated, added here, and any message it lacks is shown in English
+const CATALOGS: &[(&str, &str)] = &[("en", include_str!("../locales/en.ftl"))];
+
+/// Variables naming the user's language, the first one set winning, as for gettext
+const LANGUAGE_VARIABLES: [&str; 4] = ["DUOSPLIT_LANG", "LC_ALL", "LC_MESSAGES", "LANG"];
+
+// The messages in the user's language, filled in with English where they aren't translated
+static MESSAGES: OnceLock<HashMap<&'static str, String>> = OnceLock::new();
+
+/// The message `id` in the user's language, with each `{ $name }` in it replaced by the
+/// value given for `name` in `args`
+pub fn text(id: &str, args: &[(&str, &dyn Display)]) -> String {
+    let messages = MESSAGES.get_or_init(|| {
+        let variable = LANGUAGE_VARIABLES
+            .iter()
+            .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()));
+        load(variable.as_deref().and_then(catalog))
+    });
+    match messages.get(id) {
+        Some(pattern) => format(pattern, args),
+        // Checked by the tests, but a missing message shouldn't lose what happened
+        None => id.to_string(),
+    }
+}
+
+/// The catalog for a locale such as `de_DE.UTF-8`, trying the language and region before
+/// the language alone
+fn catalog(locale: &str) -> Option<&'static str> {
+    let tag = locale
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .replace('_', "-");
+    let language = tag.split('-').next().unwrap_or_default();
+    let source = [tag.as_str(), language].into_iter().find_map(|wanted| {
+        CATALOGS
+            .iter()
+            .find(|(tag, _)| tag.eq_ignore_ascii_case(wanted))
+            .map(|(_, source)| *source)
+    });
+    source
+}
+
+fn load(translation: Option<&'static str>) -> HashMap<&'static str, String> {
+    let mut messages = HashMap::new();
+    // Translations are checked by the tests, so a broken one only loses its own messages
+    for source in [Some(CATALOGS[0].1), translation].into_iter().flatten() {
+        messages.extend(parse(source).unwrap_or_default());
+    }
+    messages
+}
+
+/// Reads `id = text` messages, skipping blank lines and `#` comments; indented lines
+/// continue the message above them on a new line
+fn parse(source: &str) -> Result<Vec<(&str, String)>, String> {
+    let mut messages: Vec<(&str, String)> = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) {
+            let Some((_, text)) = messages.last_mut() else {
+                return Err(format!("line {} continues no message", number + 1));
+            };
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(trimmed);
+            continue;
+        }
+        let (id, text) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {} isn't a message", number + 1))?;
+        let id = id.trim();
+        if !is_identifier(id) {
+            return Err(format!("line {} has the invalid id {:?}", number + 1, id));
+        }
+        if messages.iter().any(|(existing, _)| *existing == id) {
+            return Err(format!("{} is given twice", id));
+        }
+        messages.push((id, text.trim().to_string()));
+    }
+    Ok(messages)
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Fills in `pattern`'s placeables, leaving any without a value as they were. A string
+/// literal such as `{"  "}` stands for its text, for the spaces the parser would trim.
+fn format(pattern: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut formatted = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(open) = rest.find('{') {
+        formatted.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let Some(length) = rest.find('}') else {
+            break;
+        };
+        let placeable = &rest[..=length];
+        let value =
+            placeable_name(placeable).and_then(|name| args.iter().find(|(arg, _)| *arg == name));
+        match (value, string_literal(placeable)) {
+            (Some((_, value)), _) => write!(formatted, "{}", value).expect("strings always format"),
+            (None, Some(literal)) => formatted.push_str(literal),
+            (None, None) => formatted.push_str(placeable),
+        }
+        rest = &rest[length + 1..];
+    }
+    formatted.push_str(rest);
+    formatted
+}
+
+/// The text of a string literal placeable such as `{ "  " }`
+fn string_literal(placeable: &str) -> Option<&str> {
+    placeable
+        .strip_prefix('{')?
+        .strip_suffix('}')?
+        .trim()
+        .strip_prefix('"')?
+        .strip_suffix('"')
+}
+
+/// The variable `{ $name }` refers to
+fn placeable_name(placeable: &str) -> Option<&str> {
+    let name = placeable
+        .strip_prefix('{')?
+        .strip_suffix('}')?
+        .trim()
+        .strip_prefix('$')?;
+    is_identifier(name).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::fs;
+    use std::path::Path;
+
+    fn placeables(pattern: &str) -> BTreeSet<&str> {
+        pattern
+            .match_indices('{')
+            .filter_map(|(open, _)| {
+                let length = pattern[open..].find('}')?;
+                placeable_name(&pattern[open..=open + length])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn translations_match_the_english_messages() {
+        let english = parse(CATALOGS[0].1).unwrap();
+        let english = english
+            .iter()
+            .map(|(id, text)| (*id, placeables(text)))
+            .collect::<HashMap<_, _>>();
+        for (tag, source) in CATALOGS {
+            let messages = parse(source).unwrap_or_else(|err| panic!("{}: {}", tag, err));
+            for (id, text) in &messages {
+                let placeables = placeables(text);
+                assert_eq!(english.get(id), Some(&placeables), "{} in {}", id, tag);
+            }
+        }
+
+        // Every message the code asks for is in the English catalog
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        for entry in fs::read_dir(src).unwrap() {
+            let code = fs::read_to_string(entry.unwrap().path()).unwrap();
+            for (start, _) in code.match_indices("text(\"") {
+                let id = code[start + 6..].split('"').next().unwrap();
+                assert!(english.contains_key(id), "{} isn't in en.ftl", id);
+            }
+        }
+    }
+
+    #[test]
+    fn fills_in_messages_in_the_chosen_language() {
+        let messages =
+            parse("# A comment\n\ngreeting = Hello { $name },\n  { $count } times\n").unwrap();
+        assert_eq!(messages[0].0, "greeting");
+        assert_eq!(
+            format(&messages[0].1, &[("name", &"Ha"), ("count", &3)]),
+            "Hello Ha,\n3 times"
+        );
+        assert_eq!(format("{ $missing } {", &[]), "{ $missing } {");
+        assert_eq!(format("{\"  \"}Indented", &[]), "  Indented");
+        assert!(parse("  orphan").is_err());
+        assert!(parse("a = 1\na = 2").is_err());
+
+        assert_eq!(catalog("en_GB.UTF-8"), Some(CATALOGS[0].1));
+        assert_eq!(catalog("EN"), Some(CATALOGS[0].1));
+        assert_eq!(catalog("C"), None);
+        let english = load(None);
+        assert_eq!(load(Some("done = Fertig!"))["done"], "Fertig!");
+        assert_eq!(load(Some("done = Fertig!")).len(), english.len());
+    }
+}
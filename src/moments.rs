@@ -0,0 +1,152 @@
+use crate::genetics::{j_k_from_i, Genome};
+use crate::gpu::QEUniform;
+use bytemuck::{Pod, Zeroable};
+
+/// Mean products of every pair of channels over the image, which is all the fitness
+/// function needs to know about the pixels
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Pod, Zeroable)]
+pub struct ImageMoments {
+    pub rr: f32,
+    pub gg: f32,
+    pub bb: f32,
+    pub rg: f32,
+    pub rb: f32,
+    pub gb: f32,
+}
+
+impl ImageMoments {
+    /// Combines per-chunk sums (in the field order of this struct) into means
+    pub fn from_sums(sums: impl IntoIterator<Item = [f64; 6]>, pixels: usize) -> Self {
+        let mut total = [0.0f64; 6];
+        for sum in sums {
+            for (t, s) in total.iter_mut().zip(sum) {
+                *t += s;
+            }
+        }
+        let mean = total.map(|t| (t / pixels as f64) as f32);
+        Self {
+            rr: mean[0],
+            gg: mean[1],
+            bb: mean[2],
+            rg: mean[3],
+            rb: mean[4],
+            gb: mean[5],
+        }
+    }
+
+    /// Mirrors `main` in fit.wgsl
+    pub fn fitness(&self, genome: &Genome, (r, g, b): &(QEUniform, QEUniform, QEUniform)) -> f32 {
+        let (j, k) = j_k_from_i(genome.i, r.ha, g.ha, b.ha, r.oiii, g.oiii, b.oiii);
+        let (y, z) = j_k_from_i(genome.x, r.oiii, g.oiii, b.oiii, r.ha, g.ha, b.ha);
+        self.noise_power([genome.i * genome.i, j * j, k * k])
+            + self.noise_power([genome.x * genome.x, y * y, z * z])
+    }
+
+    /// Global minimum of `fitness`, found by direct search rather than the genetic
+    /// algorithm so it can serve as a reference for it
+    pub fn optimal_genome(&self, (r, g, b): &(QEUniform, QEUniform, QEUniform)) -> Genome {
+        // The H-alpha and OIII terms of the fitness are independent, so each is minimized on its own
+        Genome {
+            i: minimize(|i| {
+                let (j, k) = j_k_from_i(i, r.ha, g.ha, b.ha, r.oiii, g.oiii, b.oiii);
+                self.noise_power([i * i, j * j, k * k])
+            }),
+            x: minimize(|x| {
+                let (y, z) = j_k_from_i(x, r.oiii, g.oiii, b.oiii, r.ha, g.ha, b.ha);
+                self.noise_power([x * x, y * y, z * z])
+            }),
+        }
+    }
+
+    fn noise_power(&self, w: [f32; 3]) -> f32 {
+        w[0] * w[0] * self.rr
+            + w[1] * w[1] * self.gg
+            + w[2] * w[2] * self.bb
+            + 2.0 * (w[0] * w[1] * self.rg + w[0] * w[2] * self.rb + w[1] * w[2] * self.gb)
+    }
+}
+
+// Half-width of the coefficient range searched by `minimize`, and the number of samples
+// used to bracket the minimum before refining it
+const SEARCH_RANGE: f32 = 10.0;
+const SEARCH_STEPS: usize = 20_000;
+
+/// Minimizes `f` over the search range with a coarse scan followed by golden-section search.
+/// The noise terms are quartics in their coefficient, so the scan isolates the global minimum.
+fn minimize(f: impl Fn(f32) -> f32) -> f32 {
+    let step = 2.0 * SEARCH_RANGE / SEARCH_STEPS as f32;
+    let coarse = (0..=SEARCH_STEPS)
+        .map(|n| -SEARCH_RANGE + n as f32 * step)
+        .min_by(|&a, &b| f(a).total_cmp(&f(b)))
+        .unwrap();
+
+    let ratio = (5.0f32.sqrt() - 1.0) / 2.0;
+    let (mut low, mut high) = (coarse - step, coarse + step);
+    for _ in 0..50 {
+        let left = high - ratio * (high - low);
+        let right = low + ratio * (high - low);
+        if f(left) < f(right) {
+            high = right;
+        } else {
+            low = left;
+        }
+    }
+    (low + high) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fitness_matches_per_pixel_noise() {
+        let qe = (
+            QEUniform {
+                ha: 0.8,
+                oiii: 0.05,
+            },
+            QEUniform { ha: 0.1, oiii: 0.6 },
+            QEUniform {
+                ha: 0.05,
+                oiii: 0.5,
+            },
+        );
+        let image = (0..500)
+            .map(|n| {
+                let n = n as f32;
+                [
+                    (n * 0.37).sin().abs(),
+                    (n * 0.11).cos().abs(),
+                    (n * 0.05).sin().abs(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let sums = image
+            .iter()
+            .map(|&[r, g, b]| [r * r, g * g, b * b, r * g, r * b, g * b].map(|v| v as f64));
+        let moments = ImageMoments::from_sums(sums, image.len());
+
+        let genome = Genome { i: 1.2, x: -0.2 };
+        let (r, g, b) = &qe;
+        let (j, k) = j_k_from_i(genome.i, r.ha, g.ha, b.ha, r.oiii, g.oiii, b.oiii);
+        let (y, z) = j_k_from_i(genome.x, r.oiii, g.oiii, b.oiii, r.ha, g.ha, b.ha);
+        let expected = image
+            .iter()
+            .map(|&[pr, pg, pb]| {
+                let h = genome.i.powi(2) * pr + j * j * pg + k * k * pb;
+                let o = genome.x.powi(2) * pr + y * y * pg + z * z * pb;
+                (h * h + o * o) as f64
+            })
+            .sum::<f64>()
+            / image.len() as f64;
+
+        let actual = moments.fitness(&genome, &qe) as f64;
+        assert!(
+            (actual - expected).abs() < 1e-4 * expected,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+}
@@ -0,0 +1,24 @@
+// Sums of the products of every pair of channels over each chunk of the image.
+// These are all the fitness needs from the pixels (see fit.wgsl), so this runs once
+// per image instead of once per genome per generation.
+
+#include "types.wgsl"
+#include "pixels.wgsl"
+
+@compute @workgroup_size(workgroup_x)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let chunk = gid.x;
+    if (chunk >= total_chunks) {
+        return;
+    }
+
+    let chunk_size = (pixel_count() + total_chunks - 1u) / total_chunks;
+    var squares = vec3f(0.0);
+    var products = vec3f(0.0);
+    for (var idx: u32 = chunk * chunk_size; idx < (chunk + 1u) * chunk_size && idx < pixel_count(); idx = idx + 1u) {
+        let pixel = read_pixel(idx);
+        squares += pixel * pixel;
+        products += pixel.rrg * pixel.gbb;
+    }
+    moments[chunk] = Moments(squares.r, squares.g, squares.b, products.x, products.y, products.z);
+}
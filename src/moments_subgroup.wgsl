@@ -0,0 +1,52 @@
+// Variant of moments.wgsl for adapters supporting subgroup operations. Each workgroup
+// reduces one chunk: invocations stride over the chunk's pixels, then the partial
+// sums are combined with subgroupAdd instead of one invocation walking the whole chunk.
+
+#include "types.wgsl"
+#include "pixels.wgsl"
+
+// One slot per subgroup; subgroups have at least 4 invocations, so this covers
+// workgroups of up to 256 invocations
+var<workgroup> subgroup_squares: array<vec3f, 64>;
+var<workgroup> subgroup_products: array<vec3f, 64>;
+
+@compute @workgroup_size(workgroup_x)
+fn main(
+    @builtin(workgroup_id) wid: vec3<u32>,
+    @builtin(local_invocation_index) lid: u32,
+    @builtin(subgroup_invocation_id) sid: u32,
+    @builtin(subgroup_id) subgroup_id: u32,
+    @builtin(num_subgroups) num_subgroups: u32,
+) {
+    let chunk = wid.x;
+    let chunk_size = (pixel_count() + total_chunks - 1u) / total_chunks;
+    let chunk_end = min((chunk + 1u) * chunk_size, pixel_count());
+    var squares = vec3f(0.0);
+    var products = vec3f(0.0);
+    for (var idx: u32 = chunk * chunk_size + lid; idx < chunk_end; idx = idx + workgroup_x) {
+        let pixel = read_pixel(idx);
+        squares += pixel * pixel;
+        products += pixel.rrg * pixel.gbb;
+    }
+
+    let subgroup_square_sum = subgroupAdd(squares);
+    let subgroup_product_sum = subgroupAdd(products);
+    if (sid == 0u) {
+        subgroup_squares[subgroup_id] = subgroup_square_sum;
+        subgroup_products[subgroup_id] = subgroup_product_sum;
+    }
+    workgroupBarrier();
+
+    if (lid == 0u) {
+        var total_squares = vec3f(0.0);
+        var total_products = vec3f(0.0);
+        for (var s: u32 = 0u; s < num_subgroups; s = s + 1u) {
+            total_squares += subgroup_squares[s];
+            total_products += subgroup_products[s];
+        }
+        moments[chunk] = Moments(
+            total_squares.r, total_squares.g, total_squares.b,
+            total_products.x, total_products.y, total_products.z
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use crate::messages::text;
+use crate::status::{ExitCode, Failure};
+use ndarray::{s, Array2};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Pixels over which neighbouring panels' solutions are blended by default
+const DEFAULT_FEATHER: f32 = 64.0;
+
+/// The panels of a stitched mosaic, each solved on its own for `--mosaic`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mosaic {
+    pub panels: Vec<Panel>,
+    /// Width in pixels of the band around each panel edge where its solution fades into its
+    /// neighbours'; 0 switches sharply at the edges
+    #[serde(default = "default_feather")]
+    pub feather: f32,
+}
+
+fn default_feather() -> f32 {
+    DEFAULT_FEATHER
+}
+
+/// A rectangle of the image, in pixels from the top left in the description; loading turns
+/// `y` into the first row from the bottom, as the channels are stored
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Panel {
+    pub name: String,
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// The combinations solved for one panel, as written to the report
+#[derive(Serialize, Clone)]
+pub struct PanelSolution {
+    pub name: String,
+    pub h_alpha_coefficients: [f32; 3],
+    pub oiii_coefficients: [f32; 3],
+}
+
+impl Mosaic {
+    /// Reads a mosaic description, checking that its panels cover an image of `(height, width)`
+    pub fn load(path: &Path, (height, width): (usize, usize)) -> Result<Self, Failure> {
+        let json = fs::read_to_string(path).map_err(|err| {
+            Failure::new(
+                ExitCode::Io,
+                text("read-failed", &[("path", &path.display()), ("error", &err)]),
+            )
+        })?;
+        Self::parse(&json, (height, width)).map_err(|err| {
+            Failure::new(
+                ExitCode::BadInput,
+                text(
+                    "invalid-mosaic",
+                    &[("path", &path.display()), ("message", &err)],
+                ),
+            )
+        })
+    }
+
+    fn parse(json: &str, (height, width): (usize, usize)) -> Result<Self, String> {
+        let mut mosaic = serde_json::from_str::<Self>(json).map_err(|err| err.to_string())?;
+        mosaic.validate((height, width))?;
+        // The channels are stored bottom row first, as in FITS
+        for panel in &mut mosaic.panels {
+            panel.y = height - panel.y - panel.height;
+        }
+        Ok(mosaic)
+    }
+
+    fn validate(&self, (height, width): (usize, usize)) -> Result<(), String> {
+        if self.panels.is_empty() {
+            return Err(text("mosaic-without-panels", &[]));
+        }
+        if self.feather.is_nan() || self.feather < 0.0 {
+            return Err(text("negative-feather", &[]));
+        }
+        for panel in &self.panels {
+            if panel.width == 0 || panel.height == 0 {
+                return Err(text("empty-panel", &[("panel", &panel.name)]));
+            }
+            if panel.x + panel.width > width || panel.y + panel.height > height {
+                return Err(text(
+                    "panel-outside-image",
+                    &[
+                        ("panel", &panel.name),
+                        ("width", &width),
+                        ("height", &height),
+                    ],
+                ));
+            }
+        }
+        let mut covered = Array2::from_elem((height, width), false);
+        for panel in &self.panels {
+            covered
+                .slice_mut(s![
+                    panel.y..panel.y + panel.height,
+                    panel.x..panel.x + panel.width
+                ])
+                .fill(true);
+        }
+        match covered.indexed_iter().find(|(_, &covered)| !covered) {
+            Some(((y, x), _)) => Err(text("pixel-outside-panels", &[("x", &x), ("y", &y)])),
+            None => Ok(()),
+        }
+    }
+
+    /// Combines the channels with each panel's `coefficients`, blending neighbouring panels
+    /// across the feather around their edges
+    pub fn blend(
+        &self,
+        (red, green, blue): (&Array2<f32>, &Array2<f32>, &Array2<f32>),
+        coefficients: &[[f32; 3]],
+    ) -> Array2<f32> {
+        Array2::from_shape_fn(red.dim(), |(y, x)| {
+            let pixel = [red[[y, x]], green[[y, x]], blue[[y, x]]];
+            let (mut total, mut weights) = (0.0, 0.0);
+            for (panel, [r, g, b]) in self.panels.iter().zip(coefficients) {
+                let weight = panel.weight(x, y, self.feather);
+                if weight > 0.0 {
+                    total += weight * (r * pixel[0] + g * pixel[1] + b * pixel[2]);
+                    weights += weight;
+                }
+            }
+            // Every pixel is in a panel, which weighs it at least a half
+            total / weights
+        })
+    }
+}
+
+impl Panel {
+    /// The panel's first row counted from the top, as the description gives it
+    pub fn top(&self, image_height: usize) -> usize {
+        image_height - self.y - self.height
+    }
+
+    /// The panel cut out of `channel`
+    pub fn crop(&self, channel: &Array2<f32>) -> Array2<f32> {
+        channel
+            .slice(s![
+                self.y..self.y + self.height,
+                self.x..self.x + self.width
+            ])
+            .to_owned()
+    }
+
+    /// How much the panel's solution counts at pixel `(x, y)`: 1 well inside, 0 well
+    /// outside, and a half on its edge, ramping over `feather` pixels
+    fn weight(&self, x: usize, y: usize, feather: f32) -> f32 {
+        let distance = self.signed_distance(x as f32 + 0.5, y as f32 + 0.5);
+        if feather == 0.0 {
+            return if distance > 0.0 { 1.0 } else { 0.0 };
+        }
+        (0.5 + distance / feather).clamp(0.0, 1.0)
+    }
+
+    /// Distance from a point to the panel's edge, positive inside and negative outside
+    fn signed_distance(&self, x: f32, y: f32) -> f32 {
+        let (left, top) = (self.x as f32, self.y as f32);
+        let (right, bottom) = (left + self.width as f32, top + self.height as f32);
+        let inside = (x - left).min(right - x).min(y - top).min(bottom - y);
+        if inside > 0.0 {
+            return inside;
+        }
+        let dx = (left - x).max(x - right).max(0.0);
+        let dy = (top - y).max(y - bottom).max(0.0);
+        -dx.hypot(dy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn panel(name: &str, x: usize, width: usize) -> Panel {
+        Panel {
+            name: name.into(),
+            x,
+            y: 0,
+            width,
+            height: 4,
+        }
+    }
+
+    #[test]
+    fn counts_panel_rows_from_the_top() {
+        let json = r#"{"feather": 0, "panels": [
+            {"name": "north", "x": 0, "y": 0, "width": 4, "height": 2},
+            {"name": "south", "x": 0, "y": 2, "width": 4, "height": 6}
+        ]}"#;
+        let mosaic = Mosaic::parse(json, (8, 4)).unwrap();
+        // The top two rows of the image are the last two stored
+        let rows = Array2::from_shape_fn((8, 4), |(y, _)| y as f32);
+        assert_eq!(mosaic.panels[0].crop(&rows).column(0).to_vec(), [6.0, 7.0]);
+        assert_eq!(mosaic.panels[1].crop(&rows).dim(), (6, 4));
+        assert_eq!(mosaic.panels[1].top(8), 2);
+        let ones = Array2::from_elem((8, 4), 1.0);
+        let blended = mosaic.blend((&ones, &ones, &ones), &[[1.0, 0.0, 0.0], [3.0, 0.0, 0.0]]);
+        assert_eq!(
+            blended.column(0).to_vec(),
+            [3.0, 3.0, 3.0, 3.0, 3.0, 3.0, 1.0, 1.0]
+        );
+
+        // An uncovered pixel is reported from the top too
+        let gap = r#"{"panels": [{"name": "north", "x": 0, "y": 0, "width": 4, "height": 2}]}"#;
+        let err = Mosaic::parse(gap, (8, 4)).err().unwrap();
+        assert_eq!(err, text("pixel-outside-panels", &[("x", &0), ("y", &2)]));
+    }
+
+    #[test]
+    fn blends_panel_solutions_across_edges() {
+        let mosaic = Mosaic {
+            panels: vec![panel("west", 0, 50), panel("east", 50, 50)],
+            feather: 10.0,
+        };
+        assert!(mosaic.validate((4, 100)).is_ok());
+        let ones = Array2::from_elem((4, 100), 1.0);
+        let blended = mosaic.blend((&ones, &ones, &ones), &[[1.0, 0.0, 0.0], [3.0, 0.0, 0.0]]);
+        assert_eq!(blended[[2, 0]], 1.0);
+        assert_eq!(blended[[2, 99]], 3.0);
+        // Either side of the shared edge mixes both, evenly right at it
+        assert!((blended[[2, 49]] + blended[[2, 50]] - 4.0).abs() < 1e-5);
+        assert!(blended[[2, 49]] > 1.0 && blended[[2, 50]] < 3.0);
+        assert!(blended.row(2).windows(2).into_iter().all(|w| w[0] <= w[1]));
+
+        let gap = Mosaic {
+            panels: vec![panel("west", 0, 40), panel("east", 50, 50)],
+            feather: 10.0,
+        };
+        assert!(gap.validate((4, 100)).is_err());
+        assert!(mosaic.validate((4, 90)).is_err());
+    }
+}
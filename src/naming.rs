@@ -0,0 +1,76 @@
+use crate::cli::SplitArgs;
+use crate::messages::text;
+use crate::output::OutputFormat;
+use std::path::{Path, PathBuf};
+
+/// Path of the output image of `line`, rendered from the naming template with the prefix
+/// and suffix around its file stem
+pub fn output_path(cli: &SplitArgs, line: &str) -> Result<PathBuf, String> {
+    let stem = cli
+        .input
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    let name = render(&cli.naming, &stem, line)?;
+    let name = Path::new(&name);
+    let mut file_name = format!(
+        "{}{}{}",
+        cli.prefix,
+        name.file_stem().unwrap_or_default().to_string_lossy(),
+        cli.suffix
+    );
+    // Only FITS files may keep the template's extension, such as .fits or .fts
+    let extension = match (cli.format, name.extension()) {
+        (OutputFormat::Fits, Some(extension)) => Some(extension.to_string_lossy()),
+        (OutputFormat::Fits, None) => None,
+        (format, _) => Some(format.extension().into()),
+    };
+    if let Some(extension) = extension {
+        file_name.push('.');
+        file_name.push_str(&extension);
+    }
+    Ok(cli.output.join(file_name))
+}
+
+/// Fills in `{stem}` and `{line}`, rejecting templates that would give both lines the same
+/// name
+fn render(template: &str, stem: &str, line: &str) -> Result<String, String> {
+    if !template.contains("{line}") {
+        return Err(text("template-without-line", &[("template", &template)]));
+    }
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| text("template-unclosed", &[("template", &template)]))?;
+        rendered.push_str(match &rest[start + 1..start + end] {
+            "stem" => stem,
+            "line" => line,
+            other => {
+                return Err(text(
+                    "template-unknown-placeholder",
+                    &[("placeholder", &other)],
+                ))
+            }
+        });
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_placeholders() {
+        assert_eq!(
+            render("{stem}_{line}.fit", "m27", "oiii").unwrap(),
+            "m27_oiii.fit"
+        );
+        assert!(render("{stem}.fit", "m27", "oiii").is_err());
+        assert!(render("{line}_{date}.fit", "m27", "oiii").is_err());
+    }
+}
@@ -1,23 +1,216 @@
-use std::f32::consts::PI;
 use rand::distr::Distribution;
 use rand::Rng;
 
-pub struct NormalDistribution {
-    mean: f32,
-    std_dev: f32,
+/// Normal distribution sampled with the Marsaglia polar method, for `f32` or `f64`
+pub struct NormalDistribution<T = f32> {
+    mean: T,
+    std_dev: T,
 }
 
-impl NormalDistribution {
-    pub fn new(mean: f32, std_dev: f32) -> Self {
+impl<T> NormalDistribution<T> {
+    pub fn new(mean: T, std_dev: T) -> Self {
         NormalDistribution { mean, std_dev }
     }
 }
 
-impl Distribution<f32> for NormalDistribution {
+macro_rules! impl_normal {
+    ($t:ty) => {
+        impl NormalDistribution<$t> {
+            /// Two independent samples, both of the values one polar draw produces
+            pub fn sample_pair<R: Rng + ?Sized>(&self, rng: &mut R) -> [$t; 2] {
+                loop {
+                    let u = 2.0 * rng.random::<$t>() - 1.0;
+                    let v = 2.0 * rng.random::<$t>() - 1.0;
+                    let s = u * u + v * v;
+                    // Rejecting s = 0 keeps the logarithm finite
+                    if s > 0.0 && s < 1.0 {
+                        let factor = (-2.0 * s.ln() / s).sqrt() * self.std_dev;
+                        return [self.mean + u * factor, self.mean + v * factor];
+                    }
+                }
+            }
+        }
+
+        impl Distribution<$t> for NormalDistribution<$t> {
+            /// One sample, discarding the other of its draw; loops drawing many should use
+            /// `sample_pair`
+            fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> $t {
+                self.sample_pair(rng)[0]
+            }
+        }
+    };
+}
+
+impl_normal!(f32);
+impl_normal!(f64);
+
+/// Normal distribution restricted to `[low, high]`, sampled by inverting its CDF so no
+/// draws are ever rejected
+pub struct TruncatedNormal {
+    mean: f64,
+    std_dev: f64,
+    low: f64,
+    high: f64,
+    cdf_low: f64,
+    cdf_high: f64,
+}
+
+impl TruncatedNormal {
+    pub fn new(mean: f32, std_dev: f32, low: f32, high: f32) -> Self {
+        let (mean, std_dev, low, high) = (mean as f64, std_dev as f64, low as f64, high as f64);
+        let cdf = |bound: f64| standard_normal_cdf((bound - mean) / std_dev);
+        TruncatedNormal {
+            mean,
+            std_dev,
+            low,
+            high,
+            cdf_low: cdf(low),
+            cdf_high: cdf(high),
+        }
+    }
+}
+
+impl Distribution<f32> for TruncatedNormal {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f32 {
-        let u1: f32 = rng.random();
-        let u2: f32 = rng.random();
-        let z0 = (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos();
-        self.mean + z0 * self.std_dev
+        let u = self.cdf_low + rng.random::<f64>() * (self.cdf_high - self.cdf_low);
+        // Clamping catches both a zero deviation and rounding in the far tails
+        let value = self.mean + self.std_dev * standard_normal_quantile(u);
+        value.clamp(self.low, self.high) as f32
+    }
+}
+
+pub fn standard_normal_cdf(z: f64) -> f64 {
+    0.5 * erfc(-z / std::f64::consts::SQRT_2)
+}
+
+/// Complementary error function from Numerical Recipes, with a relative error below 1.2e-7
+/// even far into the tails
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.5 * x.abs());
+    let poly = -1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let tail = t * (-x * x + poly).exp();
+    if x >= 0.0 {
+        tail
+    } else {
+        2.0 - tail
+    }
+}
+
+/// Acklam's rational approximation of the inverse of `standard_normal_cdf`, accurate to
+/// about 1e-9
+fn standard_normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < P_LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - P_LOW {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn moments(samples: &[f64]) -> (f64, f64) {
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n;
+        (mean, variance.sqrt())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn quantile_inverts_cdf() {
+        for z in [-3.0, -1.5, -0.2, 0.0, 0.7, 2.5] {
+            let inverted = standard_normal_quantile(standard_normal_cdf(z));
+            assert!((inverted - z).abs() < 1e-5, "{} became {}", z, inverted);
+        }
+    }
+
+    #[test]
+    fn truncated_samples_stay_in_bounds() {
+        let mut rng = StdRng::seed_from_u64(1);
+        // Mostly outside the bounds, where rejection sampling would loop for a long time
+        let truncated = TruncatedNormal::new(3.0, 0.5, -1.0, 1.0);
+        let samples = (0..10_000)
+            .map(|_| rng.sample(&truncated))
+            .collect::<Vec<_>>();
+        assert!(samples.iter().all(|s| (-1.0..=1.0).contains(s)));
+        // Piled up against the upper bound
+        assert!(samples.iter().filter(|&&s| s > 0.5).count() > 9_000);
+    }
+
+    #[test]
+    fn matches_mean_and_std_dev() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let single = NormalDistribution::new(2.0f32, 0.5);
+        let samples = (0..100_000)
+            .flat_map(|_| single.sample_pair(&mut rng))
+            .map(f64::from)
+            .collect::<Vec<_>>();
+        let (mean, std_dev) = moments(&samples);
+        assert!((mean - 2.0).abs() < 0.01, "mean = {}", mean);
+        assert!((std_dev - 0.5).abs() < 0.01, "std dev = {}", std_dev);
+
+        let double = NormalDistribution::new(-1.0f64, 3.0);
+        let samples = (0..200_000)
+            .map(|_| rng.sample(&double))
+            .collect::<Vec<_>>();
+        let (mean, std_dev) = moments(&samples);
+        assert!((mean + 1.0).abs() < 0.03, "mean = {}", mean);
+        assert!((std_dev - 3.0).abs() < 0.03, "std dev = {}", std_dev);
+    }
+}
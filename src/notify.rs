@@ -0,0 +1,169 @@
+use crate::cli::SplitArgs;
+use crate::messages::text;
+use crate::status::{self, Warning};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// Where to announce that a run finished
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum NotifyTarget {
+    /// A desktop notification
+    Desktop,
+}
+
+struct Notifier {
+    desktop: bool,
+    url: Option<String>,
+    input: String,
+    start: Instant,
+}
+
+// Set once per run, so failures reported through `ExitCode::exit` anywhere can notify too
+static NOTIFIER: OnceLock<Notifier> = OnceLock::new();
+
+/// Summary of a run, sent as the webhook's JSON payload
+#[derive(Serialize)]
+struct RunReport<'a> {
+    status: &'a str,
+    exit_code: i32,
+    input: &'a str,
+    outputs: Vec<String>,
+    h_alpha_coefficients: Option<[f32; 3]>,
+    oiii_coefficients: Option<[f32; 3]>,
+    message: Option<&'a str>,
+    warnings: Vec<Warning>,
+    duration_seconds: f64,
+}
+
+/// Arranges for the end of the run to be announced as `--notify` and `--notify-url` ask
+pub fn install(cli: &SplitArgs) {
+    if cli.notify.is_none() && cli.notify_url.is_none() {
+        return;
+    }
+    let _ = NOTIFIER.set(Notifier {
+        desktop: cli.notify == Some(NotifyTarget::Desktop),
+        url: cli.notify_url.clone(),
+        input: cli.input.display().to_string(),
+        start: Instant::now(),
+    });
+}
+
+/// Announces a successful run and the coefficients it found
+pub fn succeeded(outputs: &[PathBuf], h_alpha: [f32; 3], oiii: [f32; 3]) {
+    let Some(notifier) = NOTIFIER.get() else {
+        return;
+    };
+    notifier.send(RunReport {
+        status: "success",
+        exit_code: 0,
+        input: &notifier.input,
+        outputs: outputs.iter().map(|p| p.display().to_string()).collect(),
+        h_alpha_coefficients: Some(h_alpha),
+        oiii_coefficients: Some(oiii),
+        message: None,
+        warnings: status::warnings(),
+        duration_seconds: notifier.start.elapsed().as_secs_f64(),
+    });
+}
+
+/// Announces a run ending with an error
+pub fn failed(exit_code: i32, message: &str) {
+    let Some(notifier) = NOTIFIER.get() else {
+        return;
+    };
+    notifier.send(RunReport {
+        status: "failure",
+        exit_code,
+        input: &notifier.input,
+        outputs: Vec::new(),
+        h_alpha_coefficients: None,
+        oiii_coefficients: None,
+        message: Some(message),
+        warnings: status::warnings(),
+        duration_seconds: notifier.start.elapsed().as_secs_f64(),
+    });
+}
+
+impl Notifier {
+    /// Delivers the report, only warning on failure since the run itself is already over
+    fn send(&self, report: RunReport) {
+        if self.desktop {
+            let body = match report.message {
+                Some(message) => text(
+                    "notification-failed",
+                    &[("input", &report.input), ("message", &message)],
+                ),
+                None => text(
+                    "notification-split",
+                    &[
+                        ("input", &report.input),
+                        ("seconds", &format!("{:.0}", report.duration_seconds)),
+                    ],
+                ),
+            };
+            if let Err(err) = desktop_notification("duosplit", &body) {
+                let message = text("notification-not-shown", &[("message", &err)]);
+                eprintln!("{}", text("warning", &[("message", &message)]));
+            }
+        }
+        if let Some(url) = &self.url {
+            let json = serde_json::to_string(&report).expect("reports always serialize");
+            if let Err(err) = ureq::post(url)
+                .header("Content-Type", "application/json")
+                .send(json)
+            {
+                let message = text("webhook-failed", &[("url", url), ("error", &err)]);
+                eprintln!("{}", text("warning", &[("message", &message)]));
+            }
+        }
+    }
+}
+
+/// Shows a notification with the platform's own tool, so no notification daemon library is
+/// needed
+fn desktop_notification(title: &str, body: &str) -> Result<(), String> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification {:?} with title {:?}",
+            body, title
+        ));
+        command
+    } else if cfg!(windows) {
+        let mut command = Command::new("powershell");
+        let quote = |text: &str| text.replace('\'', "''");
+        command.args(["-NoProfile", "-Command"]).arg(format!(
+            "Add-Type -AssemblyName System.Windows.Forms; \
+             $icon = New-Object System.Windows.Forms.NotifyIcon; \
+             $icon.Icon = [System.Drawing.SystemIcons]::Information; \
+             $icon.Visible = $true; \
+             $icon.ShowBalloonTip(10000, '{}', '{}', 'Info'); \
+             Start-Sleep -Seconds 5",
+            quote(title),
+            quote(body)
+        ));
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.args([title, body]);
+        command
+    };
+    let status = command.status().map_err(|e| {
+        text(
+            "notifier-not-run",
+            &[
+                ("program", &command.get_program().to_string_lossy()),
+                ("error", &e),
+            ],
+        )
+    })?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(text("notifier-failed", &[("status", &status)]))
+    }
+}
@@ -0,0 +1,797 @@
+use crate::cli::SolverArgs;
+use crate::evaluator::Evaluator;
+use crate::genetics::Genome;
+use crate::gpu::{BestGenome, EvolveStep, GpuPopulation};
+use crate::messages::text;
+use crate::normal_distr::{NormalDistribution, TruncatedNormal};
+use crate::recovery::RecoveringGpu;
+use crate::status::{ExitCode, Failure};
+use clap::ValueEnum;
+use rand::Rng;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+// Generations between drops of the step schedule
+const STEP_LENGTH: u32 = 25;
+
+pub struct GaSettings {
+    pub population_size: usize,
+    pub generations: u32,
+    pub elitism: usize,
+    pub initial_std: f32,
+    pub decay_rate: f32,
+    pub decay_schedule: DecaySchedule,
+    pub selection: Selection,
+    pub niche_radius: Option<f32>,
+    // Half-width of the range the initial population is drawn from, relative to the seed
+    pub init_range: f32,
+    pub init_distribution: InitDistribution,
+    // The analytic seed the initial population is centred on and scaled from, or zero
+    pub init_seed: Option<Genome>,
+    // Genomes the initial population starts with, before the random ones
+    pub seed_population: Vec<Genome>,
+    // Lowest and highest allowed value of both coefficients
+    pub bounds: Option<(f32, f32)>,
+    // Print the best fitness of every generation
+    pub progress: bool,
+    pub timings: bool,
+}
+
+/// How parents are picked from the scored population
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+pub enum Selection {
+    /// The fitter of two random genomes
+    Tournament,
+    /// Roulette wheel weighted linearly by rank, the best genome weighing as much as the
+    /// population size and the worst weighing 1
+    Rank,
+    /// Stochastic universal sampling over the same rank weights, using evenly spaced
+    /// pointers so each genome is picked close to its expected number of times
+    Sus,
+}
+
+/// How the initial population is spread over its range
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+pub enum InitDistribution {
+    /// Evenly over the whole range
+    Uniform,
+    /// Normally around the centre with a standard deviation of half the range, so most
+    /// genomes start near the centre but a few reach past the range
+    Normal,
+}
+
+/// Search strategy used to minimize the fitness
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+pub enum Optimizer {
+    /// Genetic algorithm
+    Ga,
+    /// Bayesian optimization with a Gaussian process surrogate, for when evaluations are
+    /// expensive
+    Bayes,
+}
+
+/// How the mutation standard deviation shrinks over the run
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+pub enum DecaySchedule {
+    /// Shrink by the decay rate every generation
+    Exp,
+    /// Follow a half cosine down to where the exponential schedule ends, exploring for longer
+    Cosine,
+    /// Shrink in steps, holding the rate for 25 generations at a time
+    Step,
+    /// Shrink by the decay rate only in generations that don't improve the best fitness
+    Plateau,
+}
+
+/// Mutation standard deviation of each generation, following the decay schedule
+struct MutationSchedule<'a> {
+    settings: &'a GaSettings,
+    // Current rate of the plateau schedule
+    plateau_rate: f32,
+    best_fitness: f32,
+}
+
+/// Result of a genetic algorithm run
+pub struct Outcome {
+    pub genome: Genome,
+    // Best genome of each generation's evaluated population
+    pub history: Vec<BestGenome>,
+    // Standard deviations of i and x across the final population, when it is on the CPU
+    pub spread: Option<(f32, f32)>,
+}
+
+impl GaSettings {
+    /// Resolves the settings in `args`, rejecting combinations the algorithm can't run with
+    pub fn from_args(args: &SolverArgs) -> Result<Self, String> {
+        let elitism = args.elitism.count(args.population_size);
+        if args.population_size < 2 {
+            return Err(text("population-too-small", &[]));
+        }
+        if elitism >= args.population_size {
+            return Err(text(
+                "elitism-too-large",
+                &[("elitism", &elitism), ("population", &args.population_size)],
+            ));
+        }
+        if args.niche_radius.is_some_and(|radius| radius <= 0.0) {
+            return Err(text("niche-radius-not-positive", &[]));
+        }
+        let bounds = match (args.coeff_min, args.coeff_max) {
+            (None, None) => None,
+            (min, max) => Some((
+                min.unwrap_or(f32::NEG_INFINITY),
+                max.unwrap_or(f32::INFINITY),
+            )),
+        };
+        if bounds.is_some_and(|(min, max)| min >= max) {
+            return Err(text("coefficient-bounds-reversed", &[]));
+        }
+        if args.init_range.is_nan() || args.init_range <= 0.0 {
+            return Err(text("init-range-not-positive", &[]));
+        }
+        let seed_population = match &args.seed_population {
+            Some(path) => read_seed_population(path)?,
+            None => Vec::new(),
+        };
+        if seed_population.len() > args.population_size {
+            return Err(text(
+                "seed-population-too-large",
+                &[
+                    ("genomes", &seed_population.len()),
+                    ("population", &args.population_size),
+                ],
+            ));
+        }
+        Ok(Self {
+            population_size: args.population_size,
+            generations: args.generations,
+            elitism,
+            initial_std: args.initial_std,
+            decay_rate: args.decay_rate,
+            decay_schedule: args.decay_schedule,
+            selection: args.selection,
+            niche_radius: args.niche_radius,
+            init_range: args.init_range,
+            init_distribution: args.init_distribution,
+            init_seed: None,
+            seed_population,
+            bounds,
+            progress: !args.quiet,
+            timings: args.timings,
+        })
+    }
+}
+
+impl<'a> MutationSchedule<'a> {
+    fn new(settings: &'a GaSettings) -> Self {
+        Self {
+            settings,
+            plateau_rate: settings.initial_std,
+            best_fitness: f32::INFINITY,
+        }
+    }
+
+    fn rate(&self, gen: u32) -> f32 {
+        let GaSettings {
+            initial_std,
+            decay_rate,
+            generations,
+            ..
+        } = *self.settings;
+        match self.settings.decay_schedule {
+            DecaySchedule::Exp => initial_std * (-decay_rate * gen as f32).exp(),
+            DecaySchedule::Cosine => {
+                let last = generations.saturating_sub(1).max(1) as f32;
+                let final_std = initial_std * (-decay_rate * last).exp();
+                let progress = gen as f32 / last;
+                final_std
+                    + (initial_std - final_std)
+                        * 0.5
+                        * (1.0 + (std::f32::consts::PI * progress).cos())
+            }
+            DecaySchedule::Step => {
+                initial_std * (-decay_rate * (gen - gen % STEP_LENGTH) as f32).exp()
+            }
+            DecaySchedule::Plateau => self.plateau_rate,
+        }
+    }
+
+    /// Records the best fitness of a generation, decaying the plateau schedule if it stalled
+    fn observe(&mut self, fitness: f32) {
+        if fitness < self.best_fitness {
+            self.best_fitness = fitness;
+        } else {
+            self.plateau_rate *= (-self.settings.decay_rate).exp();
+        }
+    }
+}
+
+/// Reads a `--seed-population` file, a JSON list of `[i, x]` pairs
+fn read_seed_population(path: &Path) -> Result<Vec<Genome>, String> {
+    let json = fs::read_to_string(path)
+        .map_err(|e| text("read-failed", &[("path", &path.display()), ("error", &e)]))?;
+    let pairs = serde_json::from_str::<Vec<[f32; 2]>>(&json)
+        .map_err(|e| text("parse-failed", &[("path", &path.display()), ("error", &e)]))?;
+    if let Some([i, x]) = pairs
+        .iter()
+        .find(|pair| !pair.iter().all(|v| v.is_finite()))
+    {
+        return Err(text(
+            "seed-genome-not-finite",
+            &[("path", &path.display()), ("i", i), ("x", x)],
+        ));
+    }
+    Ok(pairs.into_iter().map(|[i, x]| Genome { i, x }).collect())
+}
+
+/// The first generation: the seed population, then genomes drawn around the analytic seed
+/// as `--init-range` and `--init-distribution` ask. A coefficient seeded further than 1 from
+/// zero gets a proportionally wider range.
+fn initial_population(settings: &GaSettings, rng: &mut impl Rng) -> Vec<Genome> {
+    let center = settings.init_seed.unwrap_or(Genome { i: 0.0, x: 0.0 });
+    let range = |seed: f32| settings.init_range * seed.abs().max(1.0);
+    let (range_i, range_x) = (range(center.i), range(center.x));
+    let normal = NormalDistribution::new(0.0f32, 0.5);
+    let random = settings.population_size - settings.seed_population.len();
+    let mut population = settings.seed_population.clone();
+    population.extend((0..random).map(|_| match settings.init_distribution {
+        InitDistribution::Uniform => Genome::random(rng, center, (range_i, range_x)),
+        InitDistribution::Normal => {
+            let [i, x] = normal.sample_pair(rng);
+            Genome {
+                i: center.i + i * range_i,
+                x: center.x + x * range_x,
+            }
+        }
+    }));
+    population
+}
+
+pub fn optimized_genome(
+    settings: &GaSettings,
+    evaluator: &dyn Evaluator,
+    rng: &mut impl Rng,
+) -> Outcome {
+    let mut population = initial_population(settings, rng);
+    if let Some((min, max)) = settings.bounds {
+        for genome in &mut population {
+            genome.i = genome.i.clamp(min, max);
+            genome.x = genome.x.clamp(min, max);
+        }
+    }
+
+    let mut history = Vec::with_capacity(settings.generations as usize);
+    let mut schedule = MutationSchedule::new(settings);
+    let mut best_genome = population[0];
+    for gen in 0..settings.generations {
+        let start = Instant::now();
+        let fitnesses = evaluator.evaluate(&population);
+
+        let order = rank_order(&fitnesses);
+        let elites = order[..settings.elitism]
+            .iter()
+            .map(|&i| population[i])
+            .collect::<Vec<Genome>>();
+        let (generation_best, best_fitness) = best_genome_and_fitness(&population, &fitnesses);
+        best_genome = generation_best;
+        history.push(BestGenome {
+            genome: best_genome,
+            fitness: best_fitness,
+        });
+
+        schedule.observe(best_fitness);
+
+        // Elites are kept by their own fitness, parents are selected by the shared one
+        let shared = settings
+            .niche_radius
+            .map(|radius| shared_fitnesses(&population, &fitnesses, radius));
+        let (fitnesses, order) = match shared {
+            Some(shared) => {
+                let order = rank_order(&shared);
+                (shared, order)
+            }
+            None => (fitnesses, order),
+        };
+
+        let mut new_population = elites.clone();
+        let mutation_rate = schedule.rate(gen);
+        let mutation = NormalDistribution::new(0.0, mutation_rate);
+        let offspring = settings.population_size - settings.elitism;
+        let total_weight = rank_weight_total(settings.population_size);
+        // Only drawn for SUS, so tournament runs keep their random sequence
+        let sus_offset = match settings.selection {
+            Selection::Sus => rng.random::<f32>(),
+            _ => 0.0,
+        };
+        for child_idx in 0..offspring {
+            let parent = match settings.selection {
+                Selection::Tournament => {
+                    let (idx1, idx2) = tournament_pair(settings.population_size, rng);
+                    if fitnesses[idx1] < fitnesses[idx2] {
+                        population[idx1]
+                    } else {
+                        population[idx2]
+                    }
+                }
+                Selection::Rank => {
+                    let u = rng.random::<f32>() * total_weight;
+                    population[order[rank_at(u, settings.population_size)]]
+                }
+                Selection::Sus => {
+                    let u = (sus_offset + child_idx as f32) * total_weight / offspring as f32;
+                    population[order[rank_at(u, settings.population_size)]]
+                }
+            };
+            let child = match settings.bounds {
+                Some((min, max)) => Genome {
+                    i: rng.sample(TruncatedNormal::new(parent.i, mutation_rate, min, max)),
+                    x: rng.sample(TruncatedNormal::new(parent.x, mutation_rate, min, max)),
+                },
+                None => {
+                    let [di, dx] = mutation.sample_pair(rng);
+                    Genome {
+                        i: parent.i + di,
+                        x: parent.x + dx,
+                    }
+                }
+            };
+            new_population.push(child);
+        }
+
+        population = new_population;
+        if settings.progress {
+            println!(
+                "{}",
+                text(
+                    "generation",
+                    &[("generation", &gen), ("noise", &best_fitness)]
+                )
+            );
+        }
+        if settings.timings {
+            let duration = Instant::now() - start;
+            println!(
+                "{}",
+                text(
+                    "generation-took",
+                    &[
+                        ("generation", &gen),
+                        ("duration", &format!("{:?}", duration))
+                    ]
+                )
+            );
+        }
+    }
+
+    let spread = population_spread(&population);
+    if settings.niche_radius.is_some() && settings.progress {
+        println!(
+            "{}",
+            text(
+                "final-spread",
+                &[("i-spread", &spread.0), ("x-spread", &spread.1)]
+            )
+        );
+    }
+    Outcome {
+        genome: best_genome,
+        history,
+        spread: Some(spread),
+    }
+}
+
+/// Runs the genetic algorithm entirely on the GPU, queueing `batch` generations per
+/// submission and only reading back the best genome of each generation in between.
+/// `rng` only provides the initial population and a seed per generation. The plateau
+/// schedule can only react to stalls between submissions, so it holds its rate within one.
+/// The population lives on the device, so if the device is lost the batch is retried on
+/// the recreated one with a population regrown around the best genome so far, and the run
+/// fails if no device could be recreated.
+pub fn gpu_optimized_genome(
+    settings: &GaSettings,
+    gpu: &RecoveringGpu,
+    batch: u32,
+    rng: &mut impl Rng,
+) -> Result<Outcome, Failure> {
+    let population = initial_population(settings, rng);
+    let mut gpu_population = upload(gpu, &population)?;
+
+    let mut history = Vec::with_capacity(settings.generations as usize);
+    let mut schedule = MutationSchedule::new(settings);
+    let mut best_genome = population[0];
+    let mut gen = 0;
+    while gen < settings.generations {
+        let start = Instant::now();
+        let count = batch.min(settings.generations - gen);
+        let steps = (gen..gen + count)
+            .map(|g| EvolveStep {
+                seed: rng.random(),
+                mutation_rate: schedule.rate(g),
+            })
+            .collect::<Vec<_>>();
+
+        let Some(batch_history) = gpu.evolve(
+            &mut gpu_population,
+            settings.elitism,
+            settings.selection,
+            &steps,
+        ) else {
+            let mutation = NormalDistribution::new(0.0, schedule.rate(gen));
+            let regrown = (0..settings.population_size)
+                .map(|idx| match idx {
+                    0 => best_genome,
+                    _ => {
+                        let [di, dx] = mutation.sample_pair(rng);
+                        Genome {
+                            i: best_genome.i + di,
+                            x: best_genome.x + dx,
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+            if settings.progress {
+                println!(
+                    "{}",
+                    text("resuming-from-generation", &[("generation", &gen)])
+                );
+            }
+            gpu_population = upload(gpu, &regrown)?;
+            continue;
+        };
+        for (offset, best) in batch_history.iter().enumerate() {
+            if settings.progress {
+                println!(
+                    "{}",
+                    text(
+                        "generation",
+                        &[
+                            ("generation", &(gen + offset as u32)),
+                            ("noise", &best.fitness)
+                        ]
+                    )
+                );
+            }
+            schedule.observe(best.fitness);
+        }
+        if let Some(last) = batch_history.last() {
+            best_genome = last.genome;
+        }
+        if settings.timings {
+            let duration = Instant::now() - start;
+            println!(
+                "{}",
+                text(
+                    "generations-took",
+                    &[
+                        ("first", &gen),
+                        ("last", &(gen + count - 1)),
+                        ("duration", &format!("{:?}", duration)),
+                    ]
+                )
+            );
+        }
+        gen += count;
+        history.extend(batch_history);
+    }
+
+    Ok(Outcome {
+        genome: best_genome,
+        history,
+        spread: None,
+    })
+}
+
+/// The batched algorithm breeds on the GPU, so it cannot carry on without one
+fn upload(gpu: &RecoveringGpu, population: &[Genome]) -> Result<GpuPopulation, Failure> {
+    gpu.upload_population(population)
+        .ok_or_else(|| Failure::new(ExitCode::GpuUnavailable, text("gpu-not-recreated", &[])))
+}
+
+/// Picks two distinct indices for a binary tournament
+fn tournament_pair(population_size: usize, rng: &mut impl Rng) -> (usize, usize) {
+    let idx1 = rng.random_range(0..population_size);
+    let mut idx2 = rng.random_range(0..population_size);
+    while idx2 == idx1 {
+        idx2 = rng.random_range(0..population_size);
+    }
+    (idx1, idx2)
+}
+
+/// Indices of the population from fittest to least fit
+fn rank_order(fitnesses: &[f32]) -> Vec<usize> {
+    let mut indices = (0..fitnesses.len()).collect::<Vec<usize>>();
+    indices.sort_by(|&i, &j| fitnesses[i].partial_cmp(&fitnesses[j]).unwrap());
+    indices
+}
+
+/// Fitness sharing: scales each genome's noise by its niche count, the number of genomes
+/// within `radius` of it in (i, x) space weighted linearly by closeness, so crowded
+/// genomes look worse and the population stays spread across distinct optima
+fn shared_fitnesses(population: &[Genome], fitnesses: &[f32], radius: f32) -> Vec<f32> {
+    population
+        .iter()
+        .zip(fitnesses)
+        .map(|(genome, &fitness)| {
+            let niche_count = population
+                .iter()
+                .map(|other| {
+                    let distance = (genome.i - other.i).hypot(genome.x - other.x);
+                    (1.0 - distance / radius).max(0.0)
+                })
+                .sum::<f32>();
+            fitness * niche_count
+        })
+        .collect()
+}
+
+/// Standard deviations of i and x across the population
+fn population_spread(population: &[Genome]) -> (f32, f32) {
+    let n = population.len() as f32;
+    let std_dev = |values: Vec<f32>| {
+        let mean = values.iter().sum::<f32>() / n;
+        (values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / n).sqrt()
+    };
+    (
+        std_dev(population.iter().map(|g| g.i).collect()),
+        std_dev(population.iter().map(|g| g.x).collect()),
+    )
+}
+
+/// Sum of the linear rank weights of a population
+fn rank_weight_total(population_size: usize) -> f32 {
+    population_size as f32 * (population_size + 1) as f32 / 2.0
+}
+
+/// Rank whose share of the linear rank weights n, n - 1, ..., 1 contains `u`, found by
+/// inverting their cumulative sum; mirrors `rank_at` in evolve.wgsl
+fn rank_at(u: f32, population_size: usize) -> usize {
+    let b = 2.0 * population_size as f32 + 1.0;
+    let rank = ((b - (b * b - 8.0 * u).max(0.0).sqrt()) / 2.0).floor();
+    (rank.max(0.0) as usize).min(population_size - 1)
+}
+
+fn best_genome_and_fitness(population: &[Genome], fitnesses: &[f32]) -> (Genome, f32) {
+    let (best_idx, _) = fitnesses
+        .iter()
+        .enumerate()
+        .min_by(|&(_, a), &(_, b)| a.partial_cmp(b).unwrap())
+        .unwrap();
+    (population[best_idx], fitnesses[best_idx])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Command};
+    use crate::evaluator::MockEvaluator;
+    use clap::Parser;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn settings() -> GaSettings {
+        GaSettings {
+            population_size: 50,
+            generations: 60,
+            elitism: 3,
+            initial_std: 0.5,
+            decay_rate: 0.05,
+            decay_schedule: DecaySchedule::Exp,
+            selection: Selection::Tournament,
+            niche_radius: None,
+            init_range: 1.0,
+            init_distribution: InitDistribution::Uniform,
+            init_seed: None,
+            seed_population: Vec::new(),
+            bounds: None,
+            progress: false,
+            timings: false,
+        }
+    }
+
+    #[test]
+    fn schedules_start_at_initial_std() {
+        for schedule in DecaySchedule::value_variants() {
+            let settings = GaSettings {
+                decay_schedule: *schedule,
+                ..settings()
+            };
+            let rate = MutationSchedule::new(&settings).rate(0);
+            assert_eq!(rate, settings.initial_std, "{:?}", schedule);
+        }
+    }
+
+    #[test]
+    fn plateau_only_decays_on_stalls() {
+        let settings = GaSettings {
+            decay_schedule: DecaySchedule::Plateau,
+            ..settings()
+        };
+        let mut schedule = MutationSchedule::new(&settings);
+        schedule.observe(2.0);
+        schedule.observe(1.0);
+        assert_eq!(schedule.rate(2), settings.initial_std);
+        schedule.observe(1.0);
+        assert!(schedule.rate(3) < settings.initial_std);
+    }
+
+    fn settings_from(args: &[&str]) -> Result<GaSettings, String> {
+        let cli = Cli::try_parse_from(["duosplit", "bench"].iter().chain(args)).unwrap();
+        match cli.command {
+            Some(Command::Bench(bench)) => GaSettings::from_args(&bench.solver),
+            _ => unreachable!("parsed the bench subcommand"),
+        }
+    }
+
+    #[test]
+    fn resolves_elitism_fraction() {
+        let settings = settings_from(&["-p", "200", "-e", "0.05"]).unwrap();
+        assert_eq!(settings.elitism, 10);
+    }
+
+    #[test]
+    fn rejects_inconsistent_population() {
+        assert!(settings_from(&["-p", "1", "-e", "0"]).is_err());
+        assert!(settings_from(&["-p", "10", "-e", "10"]).is_err());
+        assert!(settings_from(&["-p", "10", "-e", "9"]).is_ok());
+    }
+
+    #[test]
+    fn rank_weights_map_to_ranks() {
+        // Weights 4, 3, 2, 1 cover [0, 4), [4, 7), [7, 9) and [9, 10)
+        let ranks = [0.0, 3.9, 4.1, 6.9, 7.1, 8.9, 9.1, 9.99].map(|u| rank_at(u, 4));
+        assert_eq!(ranks, [0, 0, 1, 1, 2, 2, 3, 3]);
+    }
+
+    #[test]
+    fn rank_selections_converge() {
+        for selection in [Selection::Rank, Selection::Sus] {
+            let settings = GaSettings {
+                selection,
+                ..settings()
+            };
+            let evaluator = MockEvaluator::new(Genome { i: 0.8, x: -0.3 });
+            let best =
+                optimized_genome(&settings, &evaluator, &mut StdRng::seed_from_u64(1)).genome;
+            assert!(
+                (best.i - 0.8).abs() < 0.01,
+                "{:?}: i = {}",
+                selection,
+                best.i
+            );
+            assert!(
+                (best.x + 0.3).abs() < 0.01,
+                "{:?}: x = {}",
+                selection,
+                best.x
+            );
+        }
+    }
+
+    #[test]
+    fn sharing_penalizes_crowded_genomes() {
+        let population = [
+            Genome { i: 0.0, x: 0.0 },
+            Genome { i: 0.01, x: 0.0 },
+            Genome { i: 1.0, x: 1.0 },
+        ];
+        let shared = shared_fitnesses(&population, &[1.0, 1.0, 1.0], 0.1);
+        assert!(shared[0] > shared[2] && shared[1] > shared[2]);
+        assert_eq!(shared[2], 1.0);
+    }
+
+    #[test]
+    fn converges_with_niching() {
+        let settings = GaSettings {
+            niche_radius: Some(0.01),
+            ..settings()
+        };
+        let evaluator = MockEvaluator::new(Genome { i: 0.8, x: -0.3 });
+        let best = optimized_genome(&settings, &evaluator, &mut StdRng::seed_from_u64(1)).genome;
+        assert!((best.i - 0.8).abs() < 0.02, "i = {}", best.i);
+        assert!((best.x + 0.3).abs() < 0.02, "x = {}", best.x);
+    }
+
+    #[test]
+    fn respects_coefficient_bounds() {
+        let settings = GaSettings {
+            bounds: Some((-0.2, 0.5)),
+            ..settings()
+        };
+        // The unbounded optimum lies outside, so the best bounded genome sits on the edge
+        let evaluator = MockEvaluator::new(Genome { i: 0.8, x: -0.1 });
+        let outcome = optimized_genome(&settings, &evaluator, &mut StdRng::seed_from_u64(1));
+        assert!(outcome
+            .history
+            .iter()
+            .all(|best| (-0.2..=0.5).contains(&best.genome.i)
+                && (-0.2..=0.5).contains(&best.genome.x)));
+        assert!(
+            (outcome.genome.i - 0.5).abs() < 0.01,
+            "i = {}",
+            outcome.genome.i
+        );
+    }
+
+    #[test]
+    fn initial_population_follows_range() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let wide = GaSettings {
+            init_range: 4.0,
+            population_size: 1000,
+            ..settings()
+        };
+        let population = initial_population(&wide, &mut rng);
+        assert!(population
+            .iter()
+            .all(|g| g.i.abs() < 4.0 && g.x.abs() < 4.0));
+        assert!(population.iter().any(|g| g.i.abs() > 3.0));
+
+        let normal = GaSettings {
+            init_distribution: InitDistribution::Normal,
+            init_range: 4.0,
+            population_size: 1000,
+            ..settings()
+        };
+        let population = initial_population(&normal, &mut rng);
+        let within = population.iter().filter(|g| g.i.abs() < 2.0).count();
+        // About 68% lie within one standard deviation, half the range
+        assert!((600..760).contains(&within), "{} within", within);
+
+        // An optimum outside the default range is still found from a range that covers it
+        let evaluator = MockEvaluator::new(Genome { i: 3.2, x: -2.5 });
+        let best = optimized_genome(&wide, &evaluator, &mut rng).genome;
+        assert!((best.i - 3.2).abs() < 0.01 && (best.x + 2.5).abs() < 0.01);
+
+        // A seed centres the range and widens it for coefficients far from zero
+        let seeded = GaSettings {
+            init_seed: Some(Genome { i: 4.0, x: -0.5 }),
+            population_size: 1000,
+            ..settings()
+        };
+        let population = initial_population(&seeded, &mut rng);
+        assert!(population
+            .iter()
+            .all(|g| (0.0..8.0).contains(&g.i) && (-1.5..0.5).contains(&g.x)));
+        assert!(population.iter().any(|g| g.i < 1.0) && population.iter().any(|g| g.i > 7.0));
+    }
+
+    #[test]
+    fn seed_population_starts_the_run() {
+        let seeds = vec![Genome { i: 3.2, x: -2.5 }, Genome { i: 0.0, x: 0.0 }];
+        let settings = GaSettings {
+            seed_population: seeds.clone(),
+            ..settings()
+        };
+        let population = initial_population(&settings, &mut StdRng::seed_from_u64(1));
+        assert_eq!(population.len(), settings.population_size);
+        assert_eq!((population[0].i, population[0].x), (3.2, -2.5));
+        // A seed at the optimum is the first generation's best, far outside the random range
+        let evaluator = MockEvaluator::new(seeds[0]);
+        let outcome = optimized_genome(&settings, &evaluator, &mut StdRng::seed_from_u64(1));
+        assert_eq!(outcome.history[0].fitness, 0.0);
+    }
+
+    #[test]
+    fn converges_to_mock_optimum() {
+        let evaluator = MockEvaluator::new(Genome { i: 0.8, x: -0.3 });
+        let best = optimized_genome(&settings(), &evaluator, &mut StdRng::seed_from_u64(1)).genome;
+        assert!((best.i - 0.8).abs() < 0.01, "i = {}", best.i);
+        assert!((best.x + 0.3).abs() < 0.01, "x = {}", best.x);
+    }
+
+    #[test]
+    fn seeded_runs_are_deterministic() {
+        let evaluator = MockEvaluator::new(Genome { i: 0.5, x: 0.1 });
+        let a = optimized_genome(&settings(), &evaluator, &mut StdRng::seed_from_u64(7)).genome;
+        let b = optimized_genome(&settings(), &evaluator, &mut StdRng::seed_from_u64(7)).genome;
+        assert_eq!((a.i, a.x), (b.i, b.x));
+    }
+
+    #[test]
+    fn reports_best_of_evaluated_population() {
+        let population = [Genome { i: 0.0, x: 0.0 }, Genome { i: 1.0, x: 1.0 }];
+        let (best, fitness) = best_genome_and_fitness(&population, &[2.0, 1.0]);
+        assert_eq!((best.i, fitness), (1.0, 1.0));
+    }
+}
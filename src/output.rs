@@ -0,0 +1,131 @@
+use crate::fits::{write_fits, write_rgb_fits, CardValue};
+use crate::messages::text;
+use clap::ValueEnum;
+use ndarray::Array2;
+use std::path::PathBuf;
+
+/// File formats the split images can be written in
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// 32-bit float FITS
+    Fits,
+    /// 32-bit float OpenEXR with a single luminance channel, for compositing tools
+    Exr,
+}
+
+impl OutputFormat {
+    /// Extension given to output files, unless the naming template picks one for FITS
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Fits => "fit",
+            OutputFormat::Exr => "exr",
+        }
+    }
+
+    /// Writes `data` with `metadata` as FITS header cards or EXR attributes
+    pub fn write(
+        self,
+        path: &PathBuf,
+        data: &Array2<f32>,
+        metadata: &[(&str, CardValue)],
+    ) -> Result<(), String> {
+        match self {
+            OutputFormat::Fits => write_fits(path, data, metadata),
+            OutputFormat::Exr => write_exr(path, data, metadata),
+        }
+    }
+
+    /// Writes a row-major RGB image of `width` pixels a row, as a three-plane FITS cube or
+    /// an EXR with R, G and B channels
+    pub fn write_rgb(
+        self,
+        path: &PathBuf,
+        width: usize,
+        pixels: &[[f32; 3]],
+        metadata: &[(&str, CardValue)],
+    ) -> Result<(), String> {
+        let height = pixels.len() / width;
+        match self {
+            OutputFormat::Fits => write_rgb_fits(path, width, height, pixels, metadata),
+            OutputFormat::Exr => write_rgb_exr(path, width, pixels, metadata),
+        }
+    }
+}
+
+fn write_exr(
+    path: &PathBuf,
+    data: &Array2<f32>,
+    metadata: &[(&str, CardValue)],
+) -> Result<(), String> {
+    use exr::prelude::*;
+
+    let (height, width) = data.dim();
+    // FITS rows run bottom to top and EXR rows top to bottom, so flip to keep the orientation
+    let channels = SpecificChannels::build()
+        .with_channel("Y")
+        .with_pixel_fn(|Vec2(x, y)| (data[[height - 1 - y, x]],));
+    let mut image = Image::from_channels((width, height), channels);
+    insert_attributes(&mut image.attributes, metadata);
+    image
+        .write()
+        .to_file(path)
+        .map_err(|e| text("write-failed", &[("path", &path.display()), ("error", &e)]))
+}
+
+fn write_rgb_exr(
+    path: &PathBuf,
+    width: usize,
+    pixels: &[[f32; 3]],
+    metadata: &[(&str, CardValue)],
+) -> Result<(), String> {
+    use exr::prelude::*;
+
+    let height = pixels.len() / width;
+    // Flipped to top to bottom like the single line outputs
+    let channels = SpecificChannels::rgb(|Vec2(x, y)| {
+        let [r, g, b] = pixels[(height - 1 - y) * width + x];
+        (r, g, b)
+    });
+    let mut image = Image::from_channels((width, height), channels);
+    insert_attributes(&mut image.attributes, metadata);
+    image
+        .write()
+        .to_file(path)
+        .map_err(|e| text("write-failed", &[("path", &path.display()), ("error", &e)]))
+}
+
+fn insert_attributes(
+    attributes: &mut exr::meta::header::ImageAttributes,
+    metadata: &[(&str, CardValue)],
+) {
+    use exr::prelude::*;
+
+    for (key, value) in metadata {
+        let value = match value {
+            CardValue::Number(value) => AttributeValue::F32(*value),
+            CardValue::Text(text) => AttributeValue::Text(Text::from(text.as_str())),
+        };
+        attributes.other.insert(Text::from(*key), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exr_round_trips_flipped() {
+        let data = Array2::from_shape_fn((2, 3), |(y, x)| (y * 3 + x) as f32 / 4.0);
+        let path = std::env::temp_dir().join(format!("duosplit-{}.exr", std::process::id()));
+        OutputFormat::Exr.write(&path, &data, &[]).unwrap();
+
+        let image = exr::prelude::read_first_flat_layer_from_file(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let samples = image.layer_data.channel_data.list[0]
+            .sample_data
+            .values_as_f32()
+            .collect::<Vec<_>>();
+        // The bottom FITS row comes first in the EXR
+        assert_eq!(samples, vec![0.75, 1.0, 1.25, 0.0, 0.25, 0.5]);
+    }
+}
@@ -0,0 +1,37 @@
+use crate::messages::text;
+use std::fs::File;
+
+/// Takes stdout over for a FITS stream and points file descriptor 1 at stderr instead, so
+/// progress messages printed with `println!` cannot corrupt the stream
+#[cfg(unix)]
+pub fn take_stdout() -> Result<File, String> {
+    use std::io::Write;
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    std::io::stdout()
+        .flush()
+        .map_err(|e| text("stdout-flush-failed", &[("error", &e)]))?;
+    let stdout = std::io::stdout().as_raw_fd();
+    // SAFETY: dup and dup2 only duplicate descriptors this process already owns, and the
+    // duplicate is handed straight to a `File` which becomes its only owner
+    unsafe {
+        let stream = libc::dup(stdout);
+        if stream < 0 {
+            return Err(text(
+                "stdout-dup-failed",
+                &[("error", &std::io::Error::last_os_error())],
+            ));
+        }
+        if libc::dup2(std::io::stderr().as_raw_fd(), stdout) < 0 {
+            let err = std::io::Error::last_os_error();
+            libc::close(stream);
+            return Err(text("stdout-redirect-failed", &[("error", &err)]));
+        }
+        Ok(File::from_raw_fd(stream))
+    }
+}
+
+#[cfg(not(unix))]
+pub fn take_stdout() -> Result<File, String> {
+    Err(text("stdout-unix-only", &[]))
+}
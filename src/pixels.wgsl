@@ -0,0 +1,42 @@
+// Image and background bindings shared by the moments kernels, and reading a pixel
+// with the background subtracted
+
+#include "types.wgsl"
+
+// Tightly packed RGB triplets; an array<vec3f> would have a 16 byte stride
+@group(0) @binding(0) var<storage, read> image: array<f32>;
+@group(0) @binding(1) var<storage, read_write> moments: array<Moments>;
+// Per-cell background medians (see background.rs); a single unused texel when disabled
+@group(0) @binding(2) var background: texture_2d<f32>;
+
+override total_chunks: u32;
+override workgroup_x: u32 = 64u;
+override subtract_background: bool = false;
+override image_width: u32 = 1u;
+override image_height: u32 = 1u;
+
+fn pixel_count() -> u32 {
+    return arrayLength(&image) / 3u;
+}
+
+// Bilinear interpolation between the centres of the background cells around a pixel
+fn background_at(idx: u32) -> vec3f {
+    let size = vec2f(f32(image_width), f32(image_height));
+    let grid = vec2f(textureDimensions(background));
+    let pixel = vec2f(f32(idx % image_width), f32(idx / image_width)) + 0.5;
+    let cell = clamp(pixel * grid / size - 0.5, vec2f(0.0), grid - 1.0);
+    let c0 = vec2u(floor(cell));
+    let c1 = min(c0 + 1u, vec2u(grid) - 1u);
+    let t = cell - floor(cell);
+    let top = mix(textureLoad(background, c0, 0), textureLoad(background, vec2u(c1.x, c0.y), 0), t.x);
+    let bottom = mix(textureLoad(background, vec2u(c0.x, c1.y), 0), textureLoad(background, c1, 0), t.x);
+    return mix(top, bottom, t.y).rgb;
+}
+
+fn read_pixel(idx: u32) -> vec3f {
+    let pixel = vec3f(image[3u * idx], image[3u * idx + 1u], image[3u * idx + 2u]);
+    if (subtract_background) {
+        return pixel - background_at(idx);
+    }
+    return pixel;
+}
@@ -0,0 +1,164 @@
+use crate::linearize::Stretch;
+use crate::messages::text;
+use ndarray::Array2;
+use ravif::{Encoder, MatrixCoefficients, PixelRange};
+use std::fmt;
+use std::path::Path;
+
+// Fractions of the darkest and brightest pixels clipped by the autostretch
+const CLIP: f32 = 0.005;
+// AVIF encoder quality out of 100 and speed from 1 (slowest) to 10
+const QUALITY: f32 = 90.0;
+const SPEED: u8 = 6;
+
+/// How a preview brightens the faint end once the extremes are clipped, set for each line
+/// with `--preview-stretch`
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum PreviewStretch {
+    /// A square root, which shows nebulosity without any tuning
+    #[default]
+    Auto,
+    /// `asinh(beta x) / asinh(beta)`, stronger the larger beta is
+    Asinh(f32),
+    /// A midtones transfer function bringing the median, mostly background, up to the
+    /// target level, like PixInsight's screen transfer function
+    Mtf(f32),
+}
+
+impl fmt::Display for PreviewStretch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PreviewStretch::Auto => write!(f, "auto"),
+            PreviewStretch::Asinh(beta) => write!(f, "asinh:{}", beta),
+            PreviewStretch::Mtf(target) => write!(f, "mtf:{}", target),
+        }
+    }
+}
+
+/// Maps linear values to levels between 0 and 1, clipping the extremes and brightening the
+/// faint end with a square root so nebulosity is visible without a separate stretch
+pub fn autostretch(image: &[f32]) -> Vec<f32> {
+    stretch(image, PreviewStretch::Auto)
+}
+
+/// Maps linear values to levels between 0 and 1, clipping the extremes and brightening the
+/// faint end as `stretch` says
+pub fn stretch(image: &[f32], stretch: PreviewStretch) -> Vec<f32> {
+    let mut sorted = image
+        .iter()
+        .copied()
+        .filter(|v| v.is_finite())
+        .collect::<Vec<_>>();
+    if sorted.is_empty() {
+        return vec![0.0; image.len()];
+    }
+    sorted.sort_unstable_by(f32::total_cmp);
+    let at = |fraction: f32| sorted[((sorted.len() - 1) as f32 * fraction) as usize];
+    let (low, high) = (at(CLIP), at(1.0 - CLIP));
+    let range = (high - low).max(f32::MIN_POSITIVE);
+    let midtones = match stretch {
+        PreviewStretch::Mtf(target) => {
+            midtones_balance(((at(0.5) - low) / range).clamp(0.0, 1.0), target)
+        }
+        _ => 0.5,
+    };
+    image
+        .iter()
+        .map(|&v| {
+            let level = ((v - low) / range).clamp(0.0, 1.0);
+            // NaN survives the clamp, show it as black
+            if level.is_nan() {
+                return 0.0;
+            }
+            match stretch {
+                PreviewStretch::Auto => level.sqrt(),
+                PreviewStretch::Asinh(beta) => Stretch::Asinh(beta).apply(level),
+                PreviewStretch::Mtf(_) => midtones_transfer(midtones, level),
+            }
+        })
+        .collect()
+}
+
+/// The midtones transfer function with balance `m`, which maps 0 to 0, 1 to 1 and `m` to a
+/// half
+fn midtones_transfer(m: f32, x: f32) -> f32 {
+    // Rounding can take the ends just outside 0 and 1
+    ((m - 1.0) * x / ((2.0 * m - 1.0) * x - m)).clamp(0.0, 1.0)
+}
+
+/// The balance at which the midtones transfer function maps `x` to `target`, kept just
+/// inside 0 and 1 where the function would be undefined
+fn midtones_balance(x: f32, target: f32) -> f32 {
+    let m = x * (target - 1.0) / (2.0 * target * x - target - x);
+    if m.is_nan() {
+        0.5
+    } else {
+        m.clamp(1e-4, 1.0 - 1e-4)
+    }
+}
+
+/// Rows of `data` from top to bottom as image viewers show them, since FITS rows run
+/// bottom to top
+pub fn top_down_rows(data: &Array2<f32>) -> Vec<f32> {
+    data.rows().into_iter().rev().flatten().copied().collect()
+}
+
+/// Writes a 10-bit AVIF preview of a line image, stretched by `stretch`, for sharing
+pub fn write_avif_preview(
+    path: &Path,
+    data: &Array2<f32>,
+    stretch: PreviewStretch,
+) -> Result<(), String> {
+    let (height, width) = data.dim();
+    let levels = self::stretch(&top_down_rows(data), stretch)
+        .into_iter()
+        .map(|level| (level * 1023.0).round() as u16);
+    // Identity coefficients store the planes as they are, so three equal planes are grey
+    let encoded = Encoder::new()
+        .with_quality(QUALITY)
+        .with_speed(SPEED)
+        .encode_raw_planes_10_bit(
+            width,
+            height,
+            levels.map(|level| [level; 3]),
+            None::<[u16; 0]>,
+            PixelRange::Full,
+            MatrixCoefficients::Identity,
+        )
+        .map_err(|e| text("encode-failed", &[("path", &path.display()), ("error", &e)]))?;
+    std::fs::write(path, encoded.avif_file)
+        .map_err(|e| text("write-failed", &[("path", &path.display()), ("error", &e)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stretches_bring_the_background_up() {
+        // Mostly dark background with a few bright stars
+        let image = (0..1000)
+            .map(|i| {
+                if i % 100 == 0 {
+                    1.0
+                } else {
+                    0.01 + (i % 7) as f32 * 0.001
+                }
+            })
+            .collect::<Vec<_>>();
+        let median = |levels: Vec<f32>| {
+            let mut sorted = levels;
+            sorted.sort_unstable_by(f32::total_cmp);
+            sorted[sorted.len() / 2]
+        };
+        let linear = median(stretch(&image, PreviewStretch::Asinh(1e-6)));
+        let asinh = median(stretch(&image, PreviewStretch::Asinh(500.0)));
+        assert!(asinh > linear * 5.0, "{} against {}", asinh, linear);
+        for target in [0.1, 0.25, 0.5] {
+            let levels = stretch(&image, PreviewStretch::Mtf(target));
+            assert!((median(levels.clone()) - target).abs() < 1e-3);
+            assert!(levels.iter().all(|level| (0.0..=1.0).contains(level)));
+        }
+        assert!((midtones_transfer(0.2, 0.2) - 0.5).abs() < 1e-6);
+    }
+}
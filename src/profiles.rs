@@ -0,0 +1,346 @@
+use crate::cli::{Cli, Command};
+use crate::database;
+use crate::messages::text;
+use crate::status::{ExitCode, Failure};
+use clap::parser::ValueSource;
+use clap::{Arg, CommandFactory};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+const DATABASE: &str = "profiles.json";
+// The quantum efficiency options, which leave nothing for a camera to supply when all given
+const QUANTUM_EFFICIENCIES: [&str; 6] = [
+    "red_ha_qe",
+    "green_ha_qe",
+    "blue_ha_qe",
+    "red_oiii_qe",
+    "green_oiii_qe",
+    "blue_oiii_qe",
+];
+
+/// Named rigs for `--profile`, kept as JSON in the user's config directory alongside the
+/// camera and filter databases
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileDatabase {
+    pub profiles: Vec<Profile>,
+}
+
+/// A camera with the filter and optics in front of it, and the solver settings that suit it
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    pub camera: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub optics: Vec<PathBuf>,
+    /// Further options, as given on the command line, such as `["--generations", "400"]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arguments: Vec<String>,
+}
+
+impl Profile {
+    /// The profile as command line options
+    fn to_args(&self) -> Vec<String> {
+        let mut args = vec!["--camera".into(), self.camera.clone()];
+        if let Some(filter) = &self.filter {
+            args.extend(["--filter".into(), filter.clone()]);
+        }
+        for optics in &self.optics {
+            args.extend(["--optics".into(), optics.display().to_string()]);
+        }
+        args.extend(self.arguments.iter().cloned());
+        args
+    }
+}
+
+impl ProfileDatabase {
+    /// The user's database, empty if it hasn't been created yet
+    pub fn load() -> Result<Self, Failure> {
+        let Some((path, json)) = database::read(DATABASE)? else {
+            return Ok(Self::default());
+        };
+        Self::parse(&json).map_err(|err| {
+            Failure::new(
+                ExitCode::BadInput,
+                text(
+                    "invalid-profile-database",
+                    &[("path", &path.display()), ("message", &err)],
+                ),
+            )
+        })
+    }
+
+    pub fn save(&self) -> Result<PathBuf, Failure> {
+        let json = serde_json::to_string_pretty(self).expect("databases always serialize");
+        database::write(DATABASE, &json)
+    }
+
+    /// Parses and validates a database
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let database = serde_json::from_str::<Self>(json).map_err(|err| err.to_string())?;
+        for profile in &database.profiles {
+            if profile.name.trim().is_empty() {
+                return Err(text("profile-without-name", &[]));
+            }
+            // A value has to follow its option, or it would be taken for the input
+            let mut previous: Option<&str> = None;
+            for arg in &profile.arguments {
+                let after_option =
+                    previous.is_some_and(|option| option.starts_with('-') && !option.contains('='));
+                if !arg.starts_with('-') && !after_option {
+                    return Err(text(
+                        "profile-stray-argument",
+                        &[("profile", &profile.name), ("argument", arg)],
+                    ));
+                }
+                previous = Some(arg);
+            }
+        }
+        Ok(database)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Profile> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Adds `profile`, replacing any profile of the same name; returns whether one was
+    /// replaced
+    pub fn insert(&mut self, profile: Profile) -> bool {
+        match self
+            .profiles
+            .iter_mut()
+            .find(|existing| existing.name.eq_ignore_ascii_case(&profile.name))
+        {
+            Some(existing) => {
+                *existing = profile;
+                true
+            }
+            None => {
+                self.profiles.push(profile);
+                false
+            }
+        }
+    }
+}
+
+/// The command line with the options of the `--profile` it names merged in, or `None`
+/// without a profile
+pub fn expand(cli: &Cli, args: &[OsString]) -> Result<Option<Vec<OsString>>, Failure> {
+    // Profiles apply to splits, including the one `tune --apply` runs
+    let (name, position) = match (&cli.command, &cli.split) {
+        (Some(Command::Tune(tune)), _) => (&tune.split.profile, 2),
+        (None, Some(split)) => (&split.profile, 1),
+        _ => return Ok(None),
+    };
+    let Some(name) = name else {
+        return Ok(None);
+    };
+    let database = ProfileDatabase::load()?;
+    let profile = database.find(name).ok_or_else(|| {
+        Failure::new(
+            ExitCode::BadInput,
+            text(
+                "unknown-profile",
+                &[("profile", name), ("database", &DATABASE)],
+            ),
+        )
+    })?;
+    merge(args, position, profile)
+        .map(Some)
+        .map_err(|message| Failure::new(ExitCode::BadInput, message))
+}
+
+/// `args` with the profile's options inserted at `position`, leaving out those the user gave
+/// a value for, those that conflict with the user's options and those named by `--without`
+fn merge(args: &[OsString], position: usize, profile: &Profile) -> Result<Vec<OsString>, String> {
+    let root = Cli::command();
+    let matches = root
+        .clone()
+        .try_get_matches_from(args)
+        .map_err(|err| err.to_string())?;
+    let (command, matches) = match matches.subcommand() {
+        Some((name, matches)) => (
+            root.find_subcommand(name)
+                .expect("clap matched the subcommand"),
+            matches,
+        ),
+        None => (&root, &matches),
+    };
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let user = command
+        .get_arguments()
+        .filter(|arg| given(arg.get_id().as_str()))
+        .collect::<Vec<_>>();
+    let without = matches
+        .get_many::<String>("without")
+        .into_iter()
+        .flatten()
+        .map(|name| {
+            let option = format!("--{}", name.trim_start_matches('-'));
+            find_option(command, &option)
+                .ok_or_else(|| text("without-unknown-option", &[("option", name)]))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let own_quantum_efficiencies = QUANTUM_EFFICIENCIES.iter().all(|id| given(id));
+
+    let mut kept = Vec::new();
+    for group in option_groups(profile.to_args()) {
+        let arg = find_option(command, &group[0]).ok_or_else(|| {
+            text(
+                "profile-unknown-option",
+                &[("profile", &profile.name), ("option", &group[0])],
+            )
+        })?;
+        let conflicts = user.iter().any(|other| {
+            command.get_arg_conflicts_with(arg).contains(other)
+                || command.get_arg_conflicts_with(other).contains(&arg)
+        });
+        let replaced = given(arg.get_id().as_str())
+            || conflicts
+            || without.contains(&arg)
+            || (arg.get_id() == "camera" && own_quantum_efficiencies);
+        if !replaced {
+            kept.extend(group);
+        }
+    }
+    let mut merged = args.to_vec();
+    merged.splice(position..position, kept.into_iter().map(OsString::from));
+    Ok(merged)
+}
+
+/// Splits command line arguments into each option followed by its values
+fn option_groups(args: Vec<String>) -> Vec<Vec<String>> {
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    for arg in args {
+        // Negative numbers and - for stdin are values, not options
+        let value = !arg.starts_with('-') || arg == "-" || arg.parse::<f64>().is_ok();
+        match groups.last_mut() {
+            Some(group) if value => group.push(arg),
+            _ => groups.push(vec![arg]),
+        }
+    }
+    groups
+}
+
+/// The argument of `command` an option such as `--generations=400` or `-g` stands for
+fn find_option<'a>(command: &'a clap::Command, option: &str) -> Option<&'a Arg> {
+    let name = option.split('=').next().unwrap_or_default();
+    match name.strip_prefix("--") {
+        Some(long) => command.get_arguments().find(|arg| {
+            arg.get_long() == Some(long)
+                || arg
+                    .get_all_aliases()
+                    .is_some_and(|aliases| aliases.contains(&long))
+        }),
+        None => {
+            let short = name.strip_prefix('-')?.chars().next()?;
+            command
+                .get_arguments()
+                .find(|arg| arg.get_short() == Some(short))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn command_line_options_override_the_profile() {
+        let json = r#"{"profiles": [{"name": "Rig1", "camera": "Test Cam",
+            "filter": "L-eXtreme", "optics": ["flattener.json"],
+            "arguments": ["--generations", "400", "--cpu"]}]}"#;
+        let database = ProfileDatabase::parse(json).unwrap();
+        let profile = database.find("rig1").unwrap();
+        let parse = |args: &[&str], position| {
+            let args = args.iter().map(OsString::from).collect::<Vec<_>>();
+            Cli::try_parse_from(merge(&args, position, profile).unwrap()).unwrap()
+        };
+
+        let cli = parse(&["duosplit", "image.fit", "--profile", "rig1"], 1);
+        let split = cli.split.unwrap();
+        assert_eq!(split.camera.as_deref(), Some("Test Cam"));
+        assert_eq!(split.filter.as_deref(), Some("L-eXtreme"));
+        assert_eq!(split.optics, vec![PathBuf::from("flattener.json")]);
+        assert_eq!(cli.solver.generations, 400);
+        assert!(cli.solver.cpu);
+
+        let cli = parse(
+            &[
+                "duosplit",
+                "image.fit",
+                "--profile",
+                "rig1",
+                "-g",
+                "50",
+                "--filter",
+                "Other",
+            ],
+            1,
+        );
+        assert_eq!(cli.solver.generations, 50);
+        assert_eq!(cli.split.unwrap().filter.as_deref(), Some("Other"));
+
+        let cli = parse(&["duosplit", "tune", "image.fit", "--profile", "rig1"], 2);
+        let Some(Command::Tune(tune)) = cli.command else {
+            panic!("expected tune");
+        };
+        assert_eq!(tune.split.camera.as_deref(), Some("Test Cam"));
+        assert_eq!(tune.solver.generations, 400);
+
+        assert!(ProfileDatabase::parse(&json.replace("\"--cpu\"", "\"image.fit\"")).is_err());
+        assert!(ProfileDatabase::parse(&json.replace("Rig1", " ")).is_err());
+    }
+
+    #[test]
+    fn command_line_options_replace_conflicting_profile_options() {
+        let json = r#"{"profiles": [{"name": "Rig1", "camera": "Test Cam",
+            "optics": ["flattener.json"],
+            "arguments": ["--cpu", "--coeff-min", "-3", "--stage", "clip"]}]}"#;
+        let database = ProfileDatabase::parse(json).unwrap();
+        let profile = database.find("rig1").unwrap();
+        let parse = |extra: &[&str]| {
+            let mut args = vec!["duosplit", "image.fit", "--profile", "rig1"];
+            args.extend(extra);
+            let args = args.iter().map(OsString::from).collect::<Vec<_>>();
+            Cli::try_parse_from(merge(&args, 1, profile)?).map_err(|err| err.to_string())
+        };
+
+        let cli = parse(&[]).unwrap();
+        assert!(cli.solver.cpu);
+        assert_eq!(cli.solver.coeff_min, Some(-3.0));
+
+        let cli = parse(&["--hybrid"]).unwrap();
+        assert!(cli.solver.hybrid && !cli.solver.cpu);
+        #[cfg(feature = "cuda")]
+        {
+            let cli = parse(&["--cuda"]).unwrap();
+            assert!(cli.solver.cuda() && !cli.solver.cpu);
+        }
+        assert!(!parse(&["--without", "cpu"]).unwrap().solver.cpu);
+        assert!(parse(&["--without", "nope"]).is_err());
+
+        // Lists replace the profile's rather than adding to them
+        let split = parse(&["--optics", "reducer.json", "--stage", "rescale"])
+            .unwrap()
+            .split
+            .unwrap();
+        assert_eq!(split.optics, vec![PathBuf::from("reducer.json")]);
+        assert_eq!(split.stage.len(), 1);
+
+        // All six quantum efficiencies stand in for the profile's camera
+        let qes = ["--qrh", "0.8", "--qgh", "0.1", "--qbh", "0.05"];
+        let split = parse(&qes).unwrap().split.unwrap();
+        assert_eq!(split.camera.as_deref(), Some("Test Cam"));
+        let qes = [&qes[..], &["--qro", "0.05", "--qgo", "0.6", "--qbo", "0.5"]].concat();
+        let split = parse(&qes).unwrap().split.unwrap();
+        assert_eq!(split.camera, None);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::background::Background;
+use crate::cpu::CpuContext;
+use crate::evaluator::Evaluator;
+use crate::genetics::Genome;
+use crate::gpu::{
+    BestGenome, EvolveStep, GpuContext, GpuPopulation, GpuSettings, GpuTimings, QEUniform,
+};
+use crate::messages::text;
+use crate::optimizer::Selection;
+use crate::status::{ExitCode, Failure, Warning, WarningKind};
+use std::sync::{Mutex, Once, OnceLock, RwLock};
+use wgpu::{Backend, Backends};
+
+// Lost devices recreated before the run gives up on the GPU
+const MAX_RECOVERIES: u32 = 3;
+
+/// A GPU context that is recreated when its device is lost mid-run, as after a driver reset
+/// or a Windows TDR. Recovery retries the backend the device was on, then every other
+/// allowed backend, and only then falls back to the CPU. The run's warnings and, under
+/// `--strict`, its failure are kept for the solver to hand back
+pub struct RecoveringGpu<'a> {
+    pixels: &'a [[f32; 3]],
+    background: Option<&'a Background>,
+    settings: GpuSettings,
+    quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+    strict: bool,
+    state: RwLock<State>,
+    cpu: OnceLock<CpuContext>,
+    // Set once the GPU is gone for good, rather than for a single generation
+    fallen_back: Once,
+    warnings: Mutex<Vec<Warning>>,
+    // Set when `--strict` forbids falling back to the CPU
+    failure: OnceLock<Failure>,
+}
+
+struct State {
+    // None once no backend could be recreated
+    context: Option<GpuContext>,
+    recoveries: u32,
+    // Accumulated by the contexts that have since been lost
+    lost_timings: GpuTimings,
+}
+
+impl<'a> RecoveringGpu<'a> {
+    pub fn new(
+        context: GpuContext,
+        pixels: &'a [[f32; 3]],
+        background: Option<&'a Background>,
+        settings: GpuSettings,
+        quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+        strict: bool,
+    ) -> Self {
+        Self {
+            pixels,
+            background,
+            settings,
+            quantum_efficiencies,
+            strict,
+            state: RwLock::new(State {
+                context: Some(context),
+                recoveries: 0,
+                lost_timings: GpuTimings::default(),
+            }),
+            cpu: OnceLock::new(),
+            fallen_back: Once::new(),
+            warnings: Mutex::new(Vec::new()),
+            failure: OnceLock::new(),
+        }
+    }
+
+    /// The warnings of the run so far, such as falling back to the CPU
+    pub fn take_warnings(&self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings.lock().unwrap())
+    }
+
+    /// Why the run can't be trusted, if the GPU was lost for good under `--strict`. The
+    /// optimizer carries on with infinite fitnesses until it returns.
+    pub fn take_failure(&mut self) -> Option<Failure> {
+        self.failure.take()
+    }
+
+    pub fn timings(&self) -> GpuTimings {
+        let state = self.state.read().unwrap();
+        let mut timings = state.lost_timings;
+        if let Some(context) = &state.context {
+            let current = context.timings();
+            timings.generations += current.generations;
+            timings.kernels += current.kernels;
+            timings.readback += current.readback;
+        }
+        timings
+    }
+
+    /// Uploads a population to the current device, or returns `None` if the GPU is gone
+    pub fn upload_population(&self, population: &[Genome]) -> Option<GpuPopulation> {
+        let state = self.state.read().unwrap();
+        state
+            .context
+            .as_ref()
+            .map(|context| context.upload_population(population))
+    }
+
+    /// Runs [`GpuContext::evolve`] on the current device. If the device is lost it is
+    /// recreated and `None` is returned: `population` lived on the lost device and has to
+    /// be uploaded again. A failed readback also returns `None`, keeping the device.
+    pub fn evolve(
+        &self,
+        population: &mut GpuPopulation,
+        elitism: usize,
+        selection: Selection,
+        steps: &[EvolveStep],
+    ) -> Option<Vec<BestGenome>> {
+        let state = self.state.read().unwrap();
+        let context = state.context.as_ref()?;
+        let result = pollster::block_on(context.evolve(population, elitism, selection, steps));
+        let (recoveries, lost) = (state.recoveries, context.is_lost());
+        drop(state);
+        result
+            .map_err(|err| {
+                if lost {
+                    self.recover(&err, recoveries);
+                } else if !self.settings.quiet {
+                    // Only the readback failed, so the device carries on with a new population
+                    eprintln!("{}", text("warning", &[("message", &err)]));
+                }
+            })
+            .ok()
+    }
+
+    /// The current context, once the run is done with recovery, or `None` if the GPU is gone
+    pub fn into_context(self) -> Option<GpuContext> {
+        self.state.into_inner().unwrap().context
+    }
+
+    /// Replaces the lost context, unless another thread already has since it failed
+    fn recover(&self, err: &str, seen: u32) {
+        let mut state = self.state.write().unwrap();
+        if state.recoveries != seen {
+            return;
+        }
+        let Some(lost) = state.context.take() else {
+            return;
+        };
+        let timings = lost.timings();
+        state.lost_timings.generations += timings.generations;
+        state.lost_timings.kernels += timings.kernels;
+        state.lost_timings.readback += timings.readback;
+        state.recoveries += 1;
+        if !self.settings.quiet {
+            eprintln!("{}", err);
+        }
+        if state.recoveries > MAX_RECOVERIES {
+            if !self.settings.quiet {
+                eprintln!(
+                    "{}",
+                    text("gpu-lost-too-often", &[("times", &MAX_RECOVERIES)])
+                );
+            }
+        } else {
+            state.context = self.recreate(lost.backend());
+        }
+    }
+
+    fn recreate(&self, lost: Backend) -> Option<GpuContext> {
+        let lost = Backends::from(lost);
+        for backends in [lost, self.settings.backends - lost] {
+            if backends.is_empty() {
+                continue;
+            }
+            if !self.settings.quiet {
+                eprintln!("{}", text("recreating-gpu", &[]));
+            }
+            let settings = GpuSettings {
+                backends,
+                print_info: false,
+                ..self.settings.clone()
+            };
+            match pollster::block_on(GpuContext::new(
+                self.pixels,
+                self.background,
+                &settings,
+                self.quantum_efficiencies,
+            )) {
+                Ok(context) => return Some(context),
+                Err(err) if !self.settings.quiet => {
+                    eprintln!("{}", text("error-recreating-gpu", &[("message", &err)]))
+                }
+                Err(_) => {}
+            }
+        }
+        None
+    }
+
+    /// The CPU to carry on with once the GPU is gone for good, or `None` if `--strict`
+    /// forbids it
+    fn fall_back(&self) -> Option<&CpuContext> {
+        self.fallen_back.call_once(|| {
+            if self.strict {
+                let failure = Failure::new(
+                    ExitCode::GpuUnavailable,
+                    text("gpu-not-recreated-strict", &[]),
+                );
+                let _ = self.failure.set(failure);
+                return;
+            }
+            if !self.settings.quiet {
+                eprintln!("{}", text("falling-back-to-cpu", &[]));
+            }
+            self.warn(WarningKind::GpuFallback, text("gpu-lost-fallback", &[]));
+        });
+        match self.failure.get() {
+            Some(_) => None,
+            None => Some(self.cpu_context()),
+        }
+    }
+
+    fn warn(&self, kind: WarningKind, message: String) {
+        self.warnings
+            .lock()
+            .unwrap()
+            .push(Warning::new(kind, message));
+    }
+
+    fn cpu_context(&self) -> &CpuContext {
+        self.cpu.get_or_init(|| {
+            CpuContext::new(self.pixels, self.background, self.quantum_efficiencies)
+        })
+    }
+}
+
+impl Evaluator for RecoveringGpu<'_> {
+    fn evaluate(&self, genomes: &[Genome]) -> Vec<f32> {
+        // The population is still on the host, so the same genomes are simply evaluated
+        // again on whatever replaced the lost device
+        loop {
+            let state = self.state.read().unwrap();
+            let Some(context) = &state.context else {
+                drop(state);
+                return match self.fall_back() {
+                    Some(cpu) => cpu.compute_fitness(genomes),
+                    None => vec![f32::INFINITY; genomes.len()],
+                };
+            };
+            let result = pollster::block_on(context.compute_fitness(genomes));
+            let (recoveries, lost) = (state.recoveries, context.is_lost());
+            drop(state);
+            match result {
+                Ok(fitnesses) => return fitnesses,
+                // The device is fine but its results couldn't be read back, so only this
+                // generation is evaluated on the CPU
+                Err(err) if !lost && !self.strict => {
+                    if !self.settings.quiet {
+                        eprintln!(
+                            "{}",
+                            text(
+                                "warning",
+                                &[(
+                                    "message",
+                                    &text("readback-failed-using-cpu", &[("message", &err)])
+                                )]
+                            )
+                        );
+                    }
+                    self.warn(
+                        WarningKind::GpuFallback,
+                        text("readback-fallback", &[("message", &err)]),
+                    );
+                    return self.cpu_context().compute_fitness(genomes);
+                }
+                Err(err) => self.recover(&err, recoveries),
+            }
+        }
+    }
+}
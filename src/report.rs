@@ -0,0 +1,66 @@
+use crate::diagnostics::LineDiagnostics;
+use crate::messages::text;
+use crate::mosaic::PanelSolution;
+use crate::status::Warning;
+use crate::validation::Validation;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// What a split found, written as JSON by `--report`
+#[derive(Serialize)]
+pub struct Report {
+    pub input: String,
+    /// SHA-256 of the input file, to tell which master the outputs came from
+    pub input_sha256: String,
+    pub h_alpha_coefficients: [f32; 3],
+    pub oiii_coefficients: [f32; 3],
+    pub h_alpha: LineDiagnostics,
+    pub oiii: LineDiagnostics,
+    /// R² of the red, green and blue channels rebuilt from the outputs
+    pub channel_fits: [f32; 3],
+    /// H-alpha over H-beta flux assumed when H-beta was counted with H-alpha
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balmer_decrement: Option<f32>,
+    /// Scores against `--validate-against`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validation: Option<Validation>,
+    /// Each panel's own combinations with `--mosaic`, whose area-weighted mean the
+    /// coefficients above are
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub panels: Option<Vec<PanelSolution>>,
+    /// Every warning the run gave
+    pub warnings: Vec<Warning>,
+}
+
+/// The two combinations of a solution, as read back from a report or a webhook summary
+#[derive(Deserialize, Clone, Copy)]
+pub struct Coefficients {
+    pub h_alpha_coefficients: [f32; 3],
+    pub oiii_coefficients: [f32; 3],
+}
+
+impl Report {
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).expect("reports always serialize");
+        fs::write(path, json).map_err(|err| {
+            text(
+                "write-failed",
+                &[("path", &path.display()), ("error", &err)],
+            )
+        })
+    }
+}
+
+impl Coefficients {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|err| text("read-failed", &[("path", &path.display()), ("error", &err)]))?;
+        serde_json::from_str(&json).map_err(|err| {
+            text(
+                "parse-failed",
+                &[("path", &path.display()), ("error", &err)],
+            )
+        })
+    }
+}
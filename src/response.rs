@@ -0,0 +1,434 @@
+use crate::filters::{Filter, NiiHandling};
+use crate::gpu::QEUniform;
+use crate::lines::{Line, Wavelength};
+use crate::messages::text;
+use crate::status::{ExitCode, Failure};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Width in nanometres integrated over around a line when no filter narrows it down, about
+/// the narrowest passbands in common use
+const LINE_WIDTH: f32 = 3.0;
+
+/// Samples taken across each bandpass
+const SAMPLES: usize = 64;
+
+/// Atmospheric extinction in magnitudes per airmass at a typical observatory site, as
+/// `[nm, mag]` pairs
+const EXTINCTION: [[f32; 2]; 10] = [
+    [350.0, 0.55],
+    [400.0, 0.33],
+    [450.0, 0.22],
+    [500.0, 0.16],
+    [550.0, 0.13],
+    [600.0, 0.11],
+    [650.0, 0.08],
+    [700.0, 0.06],
+    [800.0, 0.04],
+    [900.0, 0.03],
+];
+
+/// The lines a dual-band image holds, in the order of each channel's sensitivities
+const LINES: [Line; 2] = [Line::HAlpha, Line::Oiii];
+
+/// A quantity sampled at increasing wavelengths in nanometres, as `[[nm, value], ...]`,
+/// interpolated linearly in between and held constant past either end. A wavelength can
+/// also be given as a line name, such as `"ha"`.
+#[derive(Serialize, Clone)]
+#[serde(transparent)]
+pub struct Curve(pub Vec<[f32; 2]>);
+
+impl<'de> Deserialize<'de> for Curve {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let points = Vec::<(Wavelength, f32)>::deserialize(deserializer)?;
+        Ok(Curve(
+            points
+                .into_iter()
+                .map(|(wavelength, value)| [wavelength.nm(), value])
+                .collect(),
+        ))
+    }
+}
+
+impl Curve {
+    pub fn at(&self, wavelength: f32) -> f32 {
+        let points = &self.0;
+        let after = points.partition_point(|&[nm, _]| nm < wavelength);
+        if after == 0 {
+            return points[0][1];
+        }
+        if after == points.len() {
+            return points[after - 1][1];
+        }
+        let ([x0, y0], [x1, y1]) = (points[after - 1], points[after]);
+        y0 + (y1 - y0) * (wavelength - x0) / (x1 - x0)
+    }
+
+    /// Checks that the curve has points, in order, with values between 0 and 1
+    pub fn validate(&self) -> Result<(), String> {
+        if self.0.is_empty() {
+            return Err(text("curve-empty", &[]));
+        }
+        if self.0.windows(2).any(|pair| pair[0][0] >= pair[1][0]) {
+            return Err(text("curve-not-increasing", &[]));
+        }
+        match self
+            .0
+            .iter()
+            .find(|[_, value]| !(0.0..=1.0).contains(value))
+        {
+            Some([nm, value]) => Err(text(
+                "curve-out-of-range",
+                &[("value", value), ("wavelength", nm)],
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reads an `--optics` transmission curve
+pub fn read_curve(path: &Path) -> Result<Curve, Failure> {
+    let json = fs::read_to_string(path).map_err(|err| {
+        Failure::new(
+            ExitCode::Io,
+            text("read-failed", &[("path", &path.display()), ("error", &err)]),
+        )
+    })?;
+    serde_json::from_str::<Curve>(&json)
+        .map_err(|err| err.to_string())
+        .and_then(|curve| curve.validate().map(|()| curve))
+        .map_err(|err| {
+            Failure::new(
+                ExitCode::BadInput,
+                text(
+                    "invalid-curve",
+                    &[("path", &path.display()), ("message", &err)],
+                ),
+            )
+        })
+}
+
+/// How sensitive one channel is around one line: a single number, or a measured curve
+#[derive(Clone)]
+pub enum Sensitivity {
+    Flat(f32),
+    Curve(Curve),
+}
+
+impl Sensitivity {
+    fn at(&self, wavelength: f32) -> f32 {
+        match self {
+            Sensitivity::Flat(value) => *value,
+            Sensitivity::Curve(curve) => curve.at(wavelength),
+        }
+    }
+}
+
+/// Everything between the sky and the pixel values: the sensor's red, green and blue
+/// channels around H-alpha and OIII, an optional filter, the transmission of any telescope
+/// or corrector optics, and the atmosphere at the airmass the image was taken at
+pub struct Response {
+    pub sensor: [[Sensitivity; 2]; 3],
+    pub filter: Option<Filter>,
+    pub optics: Vec<Curve>,
+    pub airmass: Option<f32>,
+    pub h_beta: Option<HBeta>,
+    /// NII over H-alpha flux of the target, to keep NII the filter treats separately out
+    /// of the H-alpha output
+    pub nii_ratio: Option<f32>,
+}
+
+/// H-beta let through alongside OIII, as by the L-eNhance. It comes from the same gas as
+/// H-alpha at a nearly fixed ratio, so it is folded into the H-alpha response rather than
+/// solved for, which keeps it out of the OIII extraction.
+pub struct HBeta {
+    /// How sensitive the red, green and blue channels are around H-beta
+    pub sensor: [Sensitivity; 3],
+    /// H-alpha over H-beta flux, 2.86 for unreddened gas
+    pub decrement: f32,
+}
+
+impl Response {
+    /// The six effective quantum efficiencies the solver works with, each the sensor, filter
+    /// and optics response averaged over the line's bandpass
+    pub fn quantum_efficiencies(&self) -> Result<(QEUniform, QEUniform, QEUniform), String> {
+        let [red, green, blue] = [0, 1, 2].map(|channel| -> Result<QEUniform, String> {
+            Ok(QEUniform {
+                ha: self.effective(channel, 0)?
+                    + self.h_beta_leak(channel)
+                    + self.nii_leak(channel),
+                oiii: self.effective(channel, 1)?,
+            })
+        });
+        Ok((red?, green?, blue?))
+    }
+
+    /// The response of `channel` to the line at `index` in [`LINES`]
+    fn effective(&self, channel: usize, index: usize) -> Result<f32, String> {
+        let line = LINES[index];
+        let band = self.band(line).ok_or_else(|| {
+            let filter = self.filter.as_ref().expect("only a filter blocks lines");
+            text(
+                "filter-blocks-line",
+                &[("filter", &filter.name), ("line", &line.name())],
+            )
+        })?;
+        Ok(self.average(band, &self.sensor[channel][index]))
+    }
+
+    /// The response of `channel` to the H-beta accompanying a unit of H-alpha
+    fn h_beta_leak(&self, channel: usize) -> f32 {
+        let (Some(h_beta), Some(band)) = (&self.h_beta, self.band(Line::HBeta)) else {
+            return 0.0;
+        };
+        // The decrement is a ratio of energies, while the sensor counts photons, which carry
+        // more energy at H-beta
+        let photons = Line::HBeta.wavelength() / Line::HAlpha.wavelength() / h_beta.decrement;
+        self.average(band, &h_beta.sensor[channel]) * photons
+    }
+
+    /// The response of `channel` to the NII accompanying a unit of H-alpha, when the filter
+    /// treats it separately and the ratio is known. Merged NII is part of the H-alpha signal
+    /// instead.
+    fn nii_leak(&self, channel: usize) -> f32 {
+        let (Some(ratio), Some(filter)) = (self.nii_ratio, &self.filter) else {
+            return 0.0;
+        };
+        let separate = filter.nii() == NiiHandling::Separate;
+        let Some(band) = self.band(Line::Nii).filter(|_| separate) else {
+            return 0.0;
+        };
+        let photons = Line::Nii.wavelength() / Line::HAlpha.wavelength() * ratio;
+        self.average(band, &self.sensor[channel][0]) * photons
+    }
+
+    /// How the filter's NII is handled, for the run's output
+    pub fn nii_summary(&self) -> Option<String> {
+        let filter = self.filter.as_ref()?;
+        Some(match (filter.nii(), self.nii_ratio) {
+            (NiiHandling::Merged, _) => text("nii-merged", &[("filter", &filter.name)]),
+            (NiiHandling::Separate, _) if self.band(Line::Nii).is_none() => {
+                text("nii-blocked", &[("filter", &filter.name)])
+            }
+            (NiiHandling::Separate, Some(ratio)) => text(
+                "nii-removed",
+                &[("filter", &filter.name), ("ratio", &ratio)],
+            ),
+            (NiiHandling::Separate, None) => text("nii-kept", &[("filter", &filter.name)]),
+        })
+    }
+
+    /// The wavelengths in nanometres integrated over around `line`, or `None` if the filter
+    /// blocks it
+    fn band(&self, line: Line) -> Option<(f32, f32)> {
+        let wavelength = line.wavelength();
+        match &self.filter {
+            Some(filter) => filter.passband_at(wavelength).map(|band| {
+                (
+                    band.center - band.bandwidth / 2.0,
+                    band.center + band.bandwidth / 2.0,
+                )
+            }),
+            None => Some((wavelength - LINE_WIDTH / 2.0, wavelength + LINE_WIDTH / 2.0)),
+        }
+    }
+
+    /// The sensor, filter, optics and atmosphere response averaged over `from` to `to`
+    fn average(&self, (from, to): (f32, f32), sensitivity: &Sensitivity) -> f32 {
+        let extinction = Curve(EXTINCTION.to_vec());
+        let step = (to - from) / SAMPLES as f32;
+        // Summed in f64 so a flat response comes out exactly as given
+        let total = (0..SAMPLES)
+            .map(|sample| {
+                let nm = from + (sample as f32 + 0.5) * step;
+                let filter = self
+                    .filter
+                    .as_ref()
+                    .map_or(1.0, |filter| filter.transmission_at(nm));
+                let optics = self
+                    .optics
+                    .iter()
+                    .map(|curve| curve.at(nm) as f64)
+                    .product::<f64>();
+                let atmosphere = self.airmass.map_or(1.0, |airmass| {
+                    10f64.powf(-0.4 * extinction.at(nm) as f64 * airmass as f64)
+                });
+                sensitivity.at(nm) as f64 * filter as f64 * optics * atmosphere
+            })
+            .sum::<f64>();
+        (total / SAMPLES as f64) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::Passband;
+
+    fn flat(ha: f32, oiii: f32) -> [Sensitivity; 2] {
+        [Sensitivity::Flat(ha), Sensitivity::Flat(oiii)]
+    }
+
+    #[test]
+    fn combines_sensor_filter_and_optics() {
+        let filter = Filter {
+            name: "Test".into(),
+            nii: None,
+            passbands: vec![
+                Passband {
+                    center: 500.7,
+                    bandwidth: 10.0,
+                    transmission: 0.9,
+                },
+                Passband {
+                    center: 656.3,
+                    bandwidth: 10.0,
+                    transmission: 0.8,
+                },
+            ],
+        };
+        let response = Response {
+            sensor: [flat(0.8, 0.05), flat(0.1, 0.6), flat(0.05, 0.5)],
+            filter: Some(filter.clone()),
+            optics: vec![Curve(vec![[400.0, 0.5], [700.0, 1.0]])],
+            airmass: None,
+            h_beta: None,
+            nii_ratio: None,
+        };
+        let (red, green, _) = response.quantum_efficiencies().unwrap();
+        // The optics curve is linear, so its average over a symmetric band is its centre value
+        let optics = |nm: f32| 0.5 + 0.5 * (nm - 400.0) / 300.0;
+        assert!((red.ha - 0.8 * 0.8 * optics(656.3)).abs() < 1e-4);
+        assert!((green.oiii - 0.6 * 0.9 * optics(500.7)).abs() < 1e-4);
+
+        // A sensor curve rising through the passband averages to its value at the centre
+        let rising = Sensitivity::Curve(Curve(vec![[495.7, 0.4], [505.7, 0.6]]));
+        let response = Response {
+            sensor: [
+                flat(0.8, 0.05),
+                [Sensitivity::Flat(0.1), rising],
+                flat(0.05, 0.5),
+            ],
+            filter: Some(filter),
+            optics: Vec::new(),
+            airmass: None,
+            h_beta: None,
+            nii_ratio: None,
+        };
+        let (_, green, _) = response.quantum_efficiencies().unwrap();
+        assert!((green.oiii - 0.5 * 0.9).abs() < 1e-4);
+
+        let blocking = Filter {
+            name: "H-alpha only".into(),
+            nii: None,
+            passbands: vec![Passband {
+                center: 656.3,
+                bandwidth: 7.0,
+                transmission: 0.9,
+            }],
+        };
+        let response = Response {
+            sensor: [flat(0.8, 0.05), flat(0.1, 0.6), flat(0.05, 0.5)],
+            filter: Some(blocking),
+            optics: Vec::new(),
+            airmass: None,
+            h_beta: None,
+            nii_ratio: None,
+        };
+        assert!(response.quantum_efficiencies().is_err());
+    }
+
+    #[test]
+    fn h_beta_counts_towards_h_alpha() {
+        let mut response = Response {
+            sensor: [flat(0.8, 0.05), flat(0.1, 0.6), flat(0.05, 0.5)],
+            filter: None,
+            optics: Vec::new(),
+            airmass: None,
+            h_beta: Some(HBeta {
+                sensor: [0.05, 0.45, 0.5].map(Sensitivity::Flat),
+                decrement: 2.86,
+            }),
+            nii_ratio: None,
+        };
+        let (_, green, blue) = response.quantum_efficiencies().unwrap();
+        let photons = 486.1 / 656.3 / 2.86;
+        assert!((green.ha - (0.1 + 0.45 * photons)).abs() < 1e-5);
+        assert_eq!(blue.oiii, 0.5);
+
+        // A filter without an H-beta passband lets none of it through
+        response.filter = Some(Filter {
+            name: "Narrow".into(),
+            nii: None,
+            passbands: vec![
+                Passband {
+                    center: 500.7,
+                    bandwidth: 7.0,
+                    transmission: 1.0,
+                },
+                Passband {
+                    center: 656.3,
+                    bandwidth: 7.0,
+                    transmission: 1.0,
+                },
+            ],
+        });
+        let (_, green, _) = response.quantum_efficiencies().unwrap();
+        assert_eq!(green.ha, 0.1);
+    }
+
+    #[test]
+    fn separate_nii_counts_towards_h_alpha() {
+        let wide = Filter {
+            name: "Wide".into(),
+            nii: Some(NiiHandling::Separate),
+            passbands: vec![
+                Passband {
+                    center: 500.7,
+                    bandwidth: 7.0,
+                    transmission: 1.0,
+                },
+                Passband {
+                    center: 656.3,
+                    bandwidth: 7.0,
+                    transmission: 1.0,
+                },
+            ],
+        };
+        let mut response = Response {
+            sensor: [flat(0.8, 0.05), flat(0.1, 0.6), flat(0.05, 0.5)],
+            filter: Some(wide.clone()),
+            optics: Vec::new(),
+            airmass: None,
+            h_beta: None,
+            nii_ratio: Some(0.3),
+        };
+        let (red, _, _) = response.quantum_efficiencies().unwrap();
+        assert!((red.ha - 0.8 * (1.0 + 0.3 * 658.4 / 656.3)).abs() < 1e-5);
+
+        // Merged NII is already part of the H-alpha output
+        response.filter = Some(Filter { nii: None, ..wide });
+        let (red, _, _) = response.quantum_efficiencies().unwrap();
+        assert_eq!(red.ha, 0.8);
+    }
+
+    #[test]
+    fn extinction_dims_oiii_more_than_h_alpha() {
+        let mut response = Response {
+            sensor: [flat(0.8, 0.05), flat(0.1, 0.6), flat(0.05, 0.5)],
+            filter: None,
+            optics: Vec::new(),
+            airmass: Some(2.0),
+            h_beta: None,
+            nii_ratio: None,
+        };
+        let (high, _, _) = response.quantum_efficiencies().unwrap();
+        response.airmass = None;
+        let (above, _, _) = response.quantum_efficiencies().unwrap();
+        let (ha, oiii) = (high.ha / above.ha, high.oiii / above.oiii);
+        assert!(oiii < ha && ha < 1.0);
+        // Two airmasses at about 0.08 mag each around H-alpha
+        assert!((ha - 10f32.powf(-0.4 * 0.16)).abs() < 0.01);
+    }
+}
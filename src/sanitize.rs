@@ -0,0 +1,115 @@
+use crate::cli::SplitArgs;
+use crate::fits::Channels;
+use crate::messages::text;
+use crate::status::{self, ExitCode, WarningKind};
+
+/// Largest sample magnitude accepted. The moments pass squares samples and sums them in f32
+/// over thousands of pixels, which overflows long before `f32::MAX`.
+const MAX_MAGNITUDE: f32 = 1e15;
+
+/// Counts of samples that would break the solve
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Anomalies {
+    /// Infinities and NaNs, which poison the fitness of every genome
+    pub non_finite: usize,
+    /// Finite samples beyond `MAX_MAGNITUDE`, which overflow the fitness sums
+    pub out_of_range: usize,
+    /// Subnormal samples, harmless to the result but slow on most CPUs
+    pub denormal: usize,
+}
+
+/// Flushes denormal samples to zero, and replaces non-finite and out-of-range samples with
+/// zero too when `replace` is set, returning how many of each were found
+pub fn scan(channels: &mut Channels, replace: bool) -> Anomalies {
+    let mut anomalies = Anomalies::default();
+    let (red, green, blue) = channels;
+    for channel in [red, green, blue] {
+        channel.mapv_inplace(|value| {
+            if !value.is_finite() {
+                anomalies.non_finite += 1;
+            } else if value.abs() > MAX_MAGNITUDE {
+                anomalies.out_of_range += 1;
+            } else if value.is_subnormal() {
+                anomalies.denormal += 1;
+                return 0.0;
+            } else {
+                return value;
+            }
+            if replace {
+                0.0
+            } else {
+                value
+            }
+        });
+    }
+    anomalies
+}
+
+/// Checks the converted samples, stopping with an error on any that would poison the solve
+/// unless `--sanitize` asks for them to be zeroed
+pub fn from_args(args: &SplitArgs, channels: &mut Channels) {
+    let anomalies = scan(channels, args.sanitize);
+    if anomalies.denormal > 0 {
+        println!(
+            "{}",
+            text("flushed-denormals", &[("count", &anomalies.denormal)])
+        );
+    }
+    let bad = anomalies.non_finite + anomalies.out_of_range;
+    if bad == 0 {
+        return;
+    }
+    let found = text(
+        "bad-samples",
+        &[
+            ("non-finite", &anomalies.non_finite),
+            ("out-of-range", &anomalies.out_of_range),
+            ("limit", &format!("{:e}", MAX_MAGNITUDE)),
+        ],
+    );
+    if args.sanitize {
+        println!("{}", text("replaced-samples", &[("found", &found)]));
+        status::record(
+            WarningKind::Sanitized,
+            text("sanitized", &[("found", &found)]),
+        );
+    } else {
+        ExitCode::BadInput.exit(text("poisoned-samples", &[("found", &found)]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array2;
+
+    #[test]
+    fn finds_and_replaces_bad_samples() {
+        let mut red = Array2::from_elem((2, 2), 1.0);
+        red[[0, 0]] = f32::NAN;
+        red[[0, 1]] = f32::NEG_INFINITY;
+        let mut green = Array2::from_elem((2, 2), 1.0);
+        green[[1, 0]] = 1e20;
+        let mut blue = Array2::from_elem((2, 2), 1.0);
+        blue[[1, 1]] = 1e-40;
+        let channels = (red, green, blue);
+
+        let mut kept = channels.clone();
+        let expected = Anomalies {
+            non_finite: 2,
+            out_of_range: 1,
+            denormal: 1,
+        };
+        assert_eq!(scan(&mut kept, false), expected);
+        assert!(kept.0[[0, 0]].is_nan());
+        assert_eq!(kept.1[[1, 0]], 1e20);
+        assert_eq!(kept.2[[1, 1]], 0.0);
+
+        let mut replaced = channels;
+        assert_eq!(scan(&mut replaced, true), expected);
+        for channel in [&replaced.0, &replaced.1, &replaced.2] {
+            assert!(channel.iter().all(|&value| value == 0.0 || value == 1.0));
+        }
+        assert_eq!(scan(&mut replaced, false), Anomalies::default());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::background::Background;
+use crate::cli::SelftestArgs;
+use crate::cpu::image_moments;
+use crate::genetics::j_k_from_i;
+use crate::messages::text;
+use crate::solver;
+use crate::status;
+use crate::synthetic::{Scene, TEST_SENSORS};
+use crate::timings::RunTimings;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::process::exit;
+
+// Synthetic images solved for each test sensor
+const SEEDS: [u64; 2] = [1, 2];
+
+/// Solves synthetic images of every test sensor and checks the coefficients against the
+/// direct-search optimum, exiting with an error if any of them are off
+pub async fn run(args: &SelftestArgs) {
+    let (width, height) = args.size;
+    let mut failures = 0;
+    for (name, qe) in TEST_SENSORS {
+        for seed in SEEDS {
+            println!(
+                "{}",
+                text("selftest", &[("sensor", &name), ("seed", &seed)])
+            );
+            let pixels =
+                Scene::default().render(width, height, qe, &mut StdRng::seed_from_u64(seed));
+            let reference = image_moments(
+                &pixels,
+                Background::from_args(&pixels, width, &args.solver).as_ref(),
+            )
+            .optimal_genome(&qe);
+            let mut warnings = Vec::new();
+            let best = solver::solve(
+                &pixels,
+                width,
+                qe,
+                &args.solver,
+                &mut RunTimings::default(),
+                &mut warnings,
+            )
+            .await;
+            status::record_all(warnings);
+            let best = best.unwrap_or_else(|failure| failure.exit());
+
+            // The coefficients must also unmix the sensor response: each line is kept whole
+            // and the other line is cancelled
+            let (r, g, b) = qe;
+            let (j, k) = j_k_from_i(best.i, r.ha, g.ha, b.ha, r.oiii, g.oiii, b.oiii);
+            let (y, z) = j_k_from_i(best.x, r.oiii, g.oiii, b.oiii, r.ha, g.ha, b.ha);
+            let unmixing_error = [
+                best.i * r.ha + j * g.ha + k * b.ha - 1.0,
+                best.i * r.oiii + j * g.oiii + k * b.oiii,
+                best.x * r.ha + y * g.ha + z * b.ha,
+                best.x * r.oiii + y * g.oiii + z * b.oiii - 1.0,
+            ]
+            .into_iter()
+            .map(f32::abs)
+            .fold(0.0, f32::max);
+            let coefficient_error = (best.i - reference.i)
+                .abs()
+                .max((best.x - reference.x).abs());
+
+            let coefficient_error_text = format!("{:e}", coefficient_error);
+            let unmixing_error_text = format!("{:e}", unmixing_error);
+            if coefficient_error <= args.tolerance && unmixing_error <= args.tolerance {
+                println!(
+                    "{}",
+                    text(
+                        "selftest-passed",
+                        &[
+                            ("coefficient-error", &coefficient_error_text),
+                            ("unmixing-error", &unmixing_error_text),
+                        ],
+                    )
+                );
+            } else {
+                println!(
+                    "{}",
+                    text(
+                        "selftest-failed",
+                        &[
+                            ("coefficient-error", &coefficient_error_text),
+                            ("unmixing-error", &unmixing_error_text),
+                            ("expected-i", &reference.i),
+                            ("expected-x", &reference.x),
+                            ("i", &best.i),
+                            ("x", &best.x),
+                        ],
+                    )
+                );
+                failures += 1;
+            }
+        }
+    }
+
+    let total = TEST_SENSORS.len() * SEEDS.len();
+    if failures > 0 {
+        eprintln!(
+            "{}",
+            text(
+                "selftests-failed",
+                &[("failures", &failures), ("total", &total)]
+            )
+        );
+        exit(1);
+    }
+    println!("{}", text("selftests-passed", &[("total", &total)]));
+}
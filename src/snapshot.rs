@@ -0,0 +1,73 @@
+use crate::fits::downsample;
+use crate::genetics::{line_coefficients, Genome};
+use crate::gpu::{BestGenome, QEUniform};
+use crate::messages::text;
+use crate::preview::{autostretch, top_down_rows};
+use ndarray::Array2;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+// Snapshots are binned down to at most this many pixels across
+const SNAPSHOT_WIDTH: usize = 256;
+
+/// Writes small PNGs of the H-alpha and OIII extractions of the best genome of every
+/// `every`th generation, and of the last one, to `dir`, returning how many were written.
+/// `swap` exchanges the lines as the final result will.
+pub fn write_snapshots(
+    dir: &Path,
+    every: u32,
+    history: &[BestGenome],
+    pixels: &[[f32; 3]],
+    width: usize,
+    quantum_efficiencies: &(QEUniform, QEUniform, QEUniform),
+    swap: bool,
+) -> Result<usize, String> {
+    fs::create_dir_all(dir)
+        .map_err(|e| text("create-failed", &[("path", &dir.display()), ("error", &e)]))?;
+    let factor = width.div_ceil(SNAPSHOT_WIDTH).max(1);
+    let (binned, binned_width) = downsample(pixels, width, factor);
+    let height = binned.len() / binned_width.max(1);
+
+    let mut written = 0;
+    for (gen, best) in history.iter().enumerate() {
+        if gen % every as usize != 0 && gen != history.len() - 1 {
+            continue;
+        }
+        let genome = if swap {
+            Genome {
+                i: best.genome.x,
+                x: best.genome.i,
+            }
+        } else {
+            best.genome
+        };
+        let (h_alpha, oiii) = line_coefficients(&genome, quantum_efficiencies);
+        for (line, [r, g, b]) in [("h_alpha", h_alpha), ("oiii", oiii)] {
+            let image = Array2::from_shape_fn((height, binned_width), |(y, x)| {
+                let pixel = binned[y * binned_width + x];
+                r * pixel[0] + g * pixel[1] + b * pixel[2]
+            });
+            let levels = autostretch(&top_down_rows(&image))
+                .into_iter()
+                .map(|level| (level * 255.0).round() as u8)
+                .collect::<Vec<_>>();
+            let path = dir.join(format!("gen_{:05}_{}.png", gen, line));
+            write_png(&path, binned_width, height, &levels)?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+fn write_png(path: &Path, width: usize, height: usize, data: &[u8]) -> Result<(), String> {
+    let error = |e: &dyn std::fmt::Display| {
+        text("write-failed", &[("path", &path.display()), ("error", e)])
+    };
+    let file = File::create(path).map_err(|e| error(&e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| error(&e))?;
+    writer.write_image_data(data).map_err(|e| error(&e))
+}
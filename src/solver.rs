@@ -0,0 +1,408 @@
+use crate::background::Background;
+use crate::bayes::bayes_optimized_genome;
+use crate::cli::SolverArgs;
+use crate::cpu::CpuContext;
+#[cfg(feature = "cuda")]
+use crate::cuda::CudaEvaluator;
+use crate::evaluator::{Evaluator, HybridEvaluator};
+use crate::genetics::{analytic_seed, should_swap, Genome};
+use crate::golden::GoldenRun;
+use crate::gpu::{GpuContext, GpuDevice, GpuSettings, QEUniform};
+use crate::messages::text;
+use crate::optimizer::{gpu_optimized_genome, optimized_genome, GaSettings, Optimizer, Outcome};
+use crate::recovery::RecoveringGpu;
+use crate::snapshot;
+use crate::status::{ExitCode, Failure, Warning, WarningKind};
+use crate::timings::RunTimings;
+use rand::rngs::StdRng;
+use rand::{rng, Rng, SeedableRng};
+use std::time::Instant;
+
+/// Sets up the GPU and/or CPU evaluators requested by `args` and runs the genetic
+/// algorithm on `pixels`, recording setup and optimization times in `timings` and the
+/// run's warnings in `warnings`. Progress is printed unless `args.quiet` is set; the
+/// caller decides what a failure ends.
+pub async fn solve(
+    pixels: &[[f32; 3]],
+    width: usize,
+    quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+    args: &SolverArgs,
+    timings: &mut RunTimings,
+    warnings: &mut Vec<Warning>,
+) -> Result<Genome, Failure> {
+    solve_keeping_gpu(
+        pixels,
+        width,
+        quantum_efficiencies,
+        args,
+        timings,
+        warnings,
+        None,
+    )
+    .await
+    .map(|(genome, _)| genome)
+}
+
+/// Like [`solve`], but also hands back the GPU context the run used, which still holds
+/// `pixels`, so the solved coefficients can be applied without uploading the image again.
+/// Given a `device` from an earlier run, the run shares it rather than setting up its own.
+pub async fn solve_keeping_gpu(
+    pixels: &[[f32; 3]],
+    width: usize,
+    quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+    args: &SolverArgs,
+    timings: &mut RunTimings,
+    warnings: &mut Vec<Warning>,
+    device: Option<&GpuDevice>,
+) -> Result<(Genome, Option<GpuContext>), Failure> {
+    let say = |message: String| {
+        if !args.quiet {
+            println!("{}", message);
+        }
+    };
+    let settings = GaSettings::from_args(args).map_err(|err| {
+        Failure::new(
+            ExitCode::BadInput,
+            text("invalid-solver-settings", &[("message", &err)]),
+        )
+    })?;
+    let settings = GaSettings {
+        init_seed: analytic_seed(&quantum_efficiencies),
+        ..settings
+    };
+    let golden = args
+        .verify
+        .as_ref()
+        .map(|path| {
+            GoldenRun::load(path).map_err(|err| {
+                Failure::new(
+                    ExitCode::Io,
+                    text("error-loading-recording", &[("message", &err)]),
+                )
+            })
+        })
+        .transpose()?;
+    let seed = args
+        .seed
+        .or(golden.as_ref().map(|golden| golden.seed))
+        .unwrap_or_else(|| rng().random());
+    let mut rng = StdRng::seed_from_u64(seed);
+    let background = Background::from_args(pixels, width, args);
+
+    let cuda = cuda_evaluator(
+        pixels,
+        background.as_ref(),
+        quantum_efficiencies,
+        args,
+        warnings,
+    )?;
+    let mut gpu = if args.cpu || cuda.is_some() {
+        None
+    } else {
+        say(text("setting-up-gpu", &[]));
+        let start = Instant::now();
+        let gpu_settings = GpuSettings::from_args(args);
+        let context = match device {
+            Some(device) => {
+                device
+                    .context(pixels, background.as_ref(), quantum_efficiencies)
+                    .await
+            }
+            None => {
+                GpuContext::new(
+                    pixels,
+                    background.as_ref(),
+                    &gpu_settings,
+                    quantum_efficiencies,
+                )
+                .await
+            }
+        };
+        let gpu = match context {
+            Ok(ctx) => Some(RecoveringGpu::new(
+                ctx,
+                pixels,
+                background.as_ref(),
+                gpu_settings,
+                quantum_efficiencies,
+                args.strict,
+            )),
+            Err(err) if args.strict => {
+                return Err(Failure::new(
+                    ExitCode::GpuUnavailable,
+                    text("error-setting-up-gpu-strict", &[("message", &err)]),
+                ))
+            }
+            Err(err) => {
+                if !args.quiet {
+                    eprintln!("{}", text("error-setting-up-gpu", &[("message", &err)]));
+                    eprintln!("{}", text("falling-back-to-cpu", &[]));
+                }
+                warnings.push(Warning::new(
+                    WarningKind::GpuFallback,
+                    text("gpu-fallback", &[("message", &err)]),
+                ));
+                None
+            }
+        };
+        timings.gpu_setup = Some(start.elapsed());
+        gpu
+    };
+    let cpu = if (gpu.is_none() && cuda.is_none()) || args.hybrid {
+        say(text("setting-up-cpu", &[]));
+        let start = Instant::now();
+        let cpu = CpuContext::new(pixels, background.as_ref(), quantum_efficiencies);
+        timings.cpu_setup = Some(start.elapsed());
+        Some(cpu)
+    } else {
+        None
+    };
+
+    let batched = args.batch_generations > 1 && args.optimizer == Optimizer::Ga;
+    // Niching and bounded mutation are only implemented on the CPU
+    let cpu_breeding = settings.niche_radius.is_some() || settings.bounds.is_some();
+    if batched && cpu_breeding {
+        warn(
+            args,
+            warnings,
+            ExitCode::BadInput,
+            WarningKind::IgnoredOption,
+            text("batch-generations-ignored", &[]),
+        )?;
+    }
+    if args.optimizer == Optimizer::Bayes && !settings.seed_population.is_empty() {
+        warn(
+            args,
+            warnings,
+            ExitCode::BadInput,
+            WarningKind::IgnoredOption,
+            text("seed-population-ignored", &[]),
+        )?;
+    }
+    let mut optimize = |evaluator: &dyn Evaluator| match args.optimizer {
+        Optimizer::Ga => {
+            say(text("starting-ga", &[]));
+            optimized_genome(&settings, evaluator, &mut rng)
+        }
+        Optimizer::Bayes => {
+            say(text("starting-bayes", &[]));
+            bayes_optimized_genome(
+                args.evaluations,
+                settings.bounds,
+                settings.progress,
+                evaluator,
+                &mut rng,
+            )
+        }
+    };
+    let start = Instant::now();
+    let (evaluator, outcome) = match (&cuda, &gpu, &cpu) {
+        (Some(cuda), _, _) => ("cuda", optimize(cuda.as_ref())),
+        (None, Some(gpu), Some(cpu)) => ("hybrid", optimize(&HybridEvaluator::new(vec![gpu, cpu]))),
+        (None, Some(gpu), None) if batched && !cpu_breeding => {
+            say(text("starting-ga", &[]));
+            (
+                "gpu-batched",
+                gpu_optimized_genome(&settings, gpu, args.batch_generations, &mut rng)?,
+            )
+        }
+        (None, Some(gpu), None) => ("gpu", optimize(gpu)),
+        (None, None, Some(cpu)) => ("cpu", optimize(cpu)),
+        (None, None, None) => {
+            unreachable!("the CPU context is created whenever no GPU is available")
+        }
+    };
+    timings.optimization = start.elapsed();
+    if let Some(gpu) = &mut gpu {
+        warnings.extend(gpu.take_warnings());
+        if let Some(failure) = gpu.take_failure() {
+            return Err(failure);
+        }
+    }
+    if let Some(best) = outcome.history.last() {
+        say(text("best-noise", &[("noise", &best.fitness)]));
+    }
+    timings.gpu = gpu.as_ref().map(RecoveringGpu::timings);
+
+    let non_convergence = non_convergence(&outcome, &settings, args);
+    let run = GoldenRun {
+        non_convergence: non_convergence.clone(),
+        ..GoldenRun::new(seed, evaluator, &settings, args, &outcome)
+    };
+    if let Some(path) = &args.record {
+        run.save(path).map_err(|err| {
+            Failure::new(
+                ExitCode::Io,
+                text("error-saving-recording", &[("message", &err)]),
+            )
+        })?;
+        say(text(
+            "recorded-run",
+            &[("seed", &seed), ("path", &path.display())],
+        ));
+    }
+    if let Some(golden) = &golden {
+        match run.first_difference(golden) {
+            None => say(text("run-matches-recording", &[])),
+            Some(difference) => {
+                return Err(Failure::new(
+                    ExitCode::Failed,
+                    text("run-differs-from-recording", &[("difference", &difference)]),
+                ))
+            }
+        }
+    }
+    let genome = outcome.genome;
+    let (suspected, reason) = should_swap(&genome, &quantum_efficiencies);
+    let swap = if args.no_swap {
+        false
+    } else {
+        suspected || args.force_swap
+    };
+    if let (Some(every), Some(dir)) = (args.snapshot_every, &args.snapshot_dir) {
+        let count = snapshot::write_snapshots(
+            dir,
+            every,
+            &outcome.history,
+            pixels,
+            width,
+            &quantum_efficiencies,
+            swap,
+        )
+        .map_err(|err| {
+            Failure::new(
+                ExitCode::Io,
+                text("error-writing-snapshots", &[("message", &err)]),
+            )
+        })?;
+        say(text(
+            "wrote-snapshots",
+            &[("count", &count), ("path", &dir.display())],
+        ));
+    }
+    // Snapshots come first, they are most useful for runs that went wrong
+    if let Some(reason) = non_convergence {
+        let failure = Failure::new(
+            ExitCode::NonConvergence,
+            text("did-not-converge", &[("reason", &reason)]),
+        );
+        warnings.push(Warning::new(WarningKind::NonConvergence, reason));
+        return Err(failure);
+    }
+
+    say(text("swap-check", &[("reason", &reason)]));
+    if suspected && !args.force_swap {
+        let warning = if swap {
+            text("suspected-swap-swapped", &[("reason", &reason)])
+        } else {
+            text("suspected-swap-kept", &[("reason", &reason)])
+        };
+        warnings.push(Warning::new(WarningKind::SuspectedSwap, warning));
+    }
+    let genome = if swap {
+        say(text("swapping-coefficients", &[]));
+        Genome {
+            i: genome.x,
+            x: genome.i,
+        }
+    } else {
+        genome
+    };
+    Ok((genome, gpu.and_then(RecoveringGpu::into_context)))
+}
+
+/// Prints a warning and adds it to `warnings`, or fails with `code` if `--strict` turned
+/// warnings into errors
+fn warn(
+    args: &SolverArgs,
+    warnings: &mut Vec<Warning>,
+    code: ExitCode,
+    kind: WarningKind,
+    message: String,
+) -> Result<(), Failure> {
+    if args.strict {
+        return Err(Failure::new(
+            code,
+            text("error-strict", &[("message", &message)]),
+        ));
+    }
+    if !args.quiet {
+        eprintln!("{}", text("warning", &[("message", &message)]));
+    }
+    warnings.push(Warning::new(kind, message));
+    Ok(())
+}
+
+/// The CUDA evaluator, if `--cuda` was given and a CUDA device could be set up
+#[cfg(feature = "cuda")]
+fn cuda_evaluator(
+    pixels: &[[f32; 3]],
+    background: Option<&Background>,
+    quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+    args: &SolverArgs,
+    warnings: &mut Vec<Warning>,
+) -> Result<Option<Box<dyn Evaluator>>, Failure> {
+    if !args.cuda {
+        return Ok(None);
+    }
+    if !args.quiet {
+        println!("{}", text("setting-up-cuda", &[]));
+    }
+    match CudaEvaluator::new(pixels, background, quantum_efficiencies) {
+        Ok(cuda) => Ok(Some(Box::new(cuda))),
+        Err(err) => {
+            warn(
+                args,
+                warnings,
+                ExitCode::GpuUnavailable,
+                WarningKind::GpuFallback,
+                text("cuda-fallback", &[("message", &err)]),
+            )?;
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(not(feature = "cuda"))]
+fn cuda_evaluator(
+    _pixels: &[[f32; 3]],
+    _background: Option<&Background>,
+    _quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+    _args: &SolverArgs,
+    _warnings: &mut Vec<Warning>,
+) -> Result<Option<Box<dyn Evaluator>>, Failure> {
+    Ok(None)
+}
+
+/// Why the coefficients in `outcome` can't be trusted, if they can't: the best noise is not
+/// finite or above `--max-noise`, or the final population never drew together
+fn non_convergence(outcome: &Outcome, settings: &GaSettings, args: &SolverArgs) -> Option<String> {
+    let best = outcome
+        .history
+        .last()
+        .map_or(f32::INFINITY, |best| best.fitness);
+    if !best.is_finite() {
+        return Some(text("no-finite-noise", &[]));
+    }
+    if let Some(max_noise) = args.max_noise.filter(|&max_noise| best > max_noise) {
+        return Some(text(
+            "noise-above-max",
+            &[("noise", &best), ("max-noise", &max_noise)],
+        ));
+    }
+    match outcome.spread {
+        Some((i_spread, x_spread))
+            if settings.niche_radius.is_none() && i_spread.max(x_spread) > args.max_spread =>
+        {
+            Some(text(
+                "spread-above-max",
+                &[
+                    ("i-spread", &i_spread),
+                    ("x-spread", &x_spread),
+                    ("max-spread", &args.max_spread),
+                ],
+            ))
+        }
+        _ => None,
+    }
+}
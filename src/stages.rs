@@ -0,0 +1,126 @@
+use crate::cli::SplitArgs;
+use crate::linearize::Stretch;
+use ndarray::Array2;
+use std::fmt;
+
+/// A transform applied to each output image before it is written. The built-in stages are
+/// picked with `--stage`; library users can add their own to a chain.
+pub trait OutputStage: fmt::Display {
+    fn apply(&self, image: &mut Array2<f32>);
+}
+
+/// The built-in stages
+#[derive(Clone, Copy)]
+pub enum Stage {
+    /// Raises negative pixels to zero
+    Clip,
+    /// Adds a constant, so over-subtracted pixels stay positive
+    Pedestal(f32),
+    /// Maps the darkest pixel to 0 and the brightest to 1
+    Rescale,
+    /// Applies a display stretch relative to the brightest pixel
+    Stretch(Stretch),
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stage::Clip => write!(f, "clip"),
+            Stage::Pedestal(pedestal) => write!(f, "pedestal {}", pedestal),
+            Stage::Rescale => write!(f, "rescale"),
+            Stage::Stretch(stretch) => write!(f, "{} stretch", stretch),
+        }
+    }
+}
+
+impl OutputStage for Stage {
+    fn apply(&self, image: &mut Array2<f32>) {
+        match *self {
+            Stage::Clip => image.mapv_inplace(|value| value.max(0.0)),
+            Stage::Pedestal(pedestal) => image.mapv_inplace(|value| value + pedestal),
+            Stage::Rescale => {
+                let (min, max) = finite_range(image);
+                if max > min {
+                    image.mapv_inplace(|value| (value - min) / (max - min));
+                }
+            }
+            Stage::Stretch(stretch) => {
+                let (_, peak) = finite_range(image);
+                if peak > 0.0 {
+                    image.mapv_inplace(|value| stretch.apply(value / peak) * peak);
+                }
+            }
+        }
+    }
+}
+
+fn finite_range(image: &Array2<f32>) -> (f32, f32) {
+    image
+        .iter()
+        .copied()
+        .filter(|value| value.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| {
+            (min.min(value), max.max(value))
+        })
+}
+
+/// Stages run in order over every output
+#[derive(Default)]
+pub struct Chain(pub Vec<Box<dyn OutputStage>>);
+
+impl Chain {
+    /// The stages `--stage` lists, in the order given
+    pub fn from_args(args: &SplitArgs) -> Self {
+        Self(
+            args.stage
+                .iter()
+                .map(|&stage| Box::new(stage) as Box<dyn OutputStage>)
+                .collect(),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn apply(&self, image: &mut Array2<f32>) {
+        for stage in &self.0 {
+            stage.apply(image);
+        }
+    }
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stages = self
+            .0
+            .iter()
+            .map(|stage| stage.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "{}", stages.join(", then "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn chains_stages_in_order() {
+        let chain = Chain(vec![
+            Box::new(Stage::Clip),
+            Box::new(Stage::Pedestal(1.0)),
+            Box::new(Stage::Rescale),
+        ]);
+        let mut image = array![[-2.0, 0.0], [1.0, 3.0]];
+        chain.apply(&mut image);
+        // Clipped to 0, 0, 1, 3, then lifted to 1, 1, 2, 4 and rescaled
+        assert_eq!(image, array![[0.0, 0.0], [1.0 / 3.0, 1.0]]);
+        assert_eq!(chain.to_string(), "clip, then pedestal 1, then rescale");
+
+        let mut image = array![[0.25, 1.0]];
+        Stage::Stretch(Stretch::Gamma(2.0)).apply(&mut image);
+        assert_eq!(image, array![[0.5, 1.0]]);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::messages::text;
+use crate::notify;
+use serde::Serialize;
+use std::fmt::Display;
+use std::process::exit;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Exit codes that let scripts tell failures apart
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExitCode {
+    /// Other failures, such as a failed self-test or a run that doesn't match its recording
+    Failed = 1,
+    /// Invalid arguments or an image duosplit can't use, the code clap also uses
+    BadInput = 2,
+    /// No GPU could be set up and `--strict` forbids falling back to the CPU
+    GpuUnavailable = 3,
+    /// The optimizer didn't find usable coefficients
+    NonConvergence = 4,
+    /// A file or stream couldn't be read or written
+    Io = 5,
+    /// A `--post-cmd` command couldn't be run or failed
+    PostCommand = 6,
+}
+
+impl ExitCode {
+    /// Prints `message` to stderr and exits with this code
+    pub fn exit(self, message: impl Display) -> ! {
+        eprintln!("{}", message);
+        notify::failed(self as i32, &message.to_string());
+        exit(self as i32)
+    }
+}
+
+/// An error together with the exit code it should end the run with
+#[derive(Debug)]
+pub struct Failure {
+    pub code: ExitCode,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn new(code: ExitCode, message: impl Into<String>) -> Self {
+        Failure {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Prints the message to stderr and exits with the code, ending the command
+    pub fn exit(self) -> ! {
+        self.code.exit(self.message)
+    }
+}
+
+/// What a warning is about, so automation reading the reports can react to some kinds
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WarningKind {
+    /// The input was converted to a narrower sample type
+    PrecisionLoss,
+    /// The quantum efficiencies or the solution look like H-alpha and OIII were swapped
+    SuspectedSwap,
+    /// The quantum efficiencies can't tell the lines apart
+    InseparableLines,
+    /// The input looks stretched, or a stretch was inverted
+    Stretched,
+    /// The layout of the input's samples was guessed, as for RGB interleaved in one plane
+    GuessedLayout,
+    /// Bad samples were replaced by `--sanitize`
+    Sanitized,
+    /// Part of the run fell back from the GPU
+    GpuFallback,
+    /// An option was ignored because it doesn't apply
+    IgnoredOption,
+    /// The optimizer didn't find usable coefficients
+    NonConvergence,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(kind: WarningKind, message: impl Into<String>) -> Self {
+        Warning {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+// Every warning of the run, for the JSON report and the webhook
+static WARNINGS: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+/// Prints a warning, or exits with `code` if `--strict` turned warnings into errors
+pub fn warn(strict: bool, code: ExitCode, kind: WarningKind, message: impl Display) {
+    if strict {
+        code.exit(text("error-strict", &[("message", &message)]));
+    }
+    eprintln!("{}", text("warning", &[("message", &message)]));
+    record(kind, message);
+}
+
+/// Collects a warning that has been shown some other way
+pub fn record(kind: WarningKind, message: impl Display) {
+    lock().push(Warning::new(kind, message.to_string()));
+}
+
+/// Collects the warnings a library call handed back, such as the solver's
+pub fn record_all(warnings: Vec<Warning>) {
+    lock().extend(warnings);
+}
+
+/// The warnings of the run so far, in the order they were given
+pub fn warnings() -> Vec<Warning> {
+    lock().clone()
+}
+
+fn lock() -> MutexGuard<'static, Vec<Warning>> {
+    // A panic while holding the lock can't leave the list half-updated
+    WARNINGS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_serialize_with_their_kind() {
+        let warning = Warning::new(WarningKind::PrecisionLoss, "lost bits");
+        assert_eq!(
+            serde_json::to_string(&warning).unwrap(),
+            r#"{"kind":"precision-loss","message":"lost bits"}"#
+        );
+    }
+}
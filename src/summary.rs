@@ -0,0 +1,135 @@
+use crate::cli::{SolverArgs, SplitArgs};
+use crate::gpu::QEUniform;
+use crate::messages::text;
+use crate::optimizer::Optimizer;
+use crate::status::{self, ExitCode, WarningKind};
+use clap::ValueEnum;
+
+/// Prints what a run is about to do, so mistakes such as swapped quantum efficiencies show
+/// up before the solver starts rather than after it finishes
+pub fn print_run_summary(
+    cli: &SplitArgs,
+    solver: &SolverArgs,
+    (red, green, blue): (QEUniform, QEUniform, QEUniform),
+    (width, height): (usize, usize),
+    bit_depth: Option<&str>,
+) {
+    println!("{}", text("summary", &[]));
+    let bit_depth = bit_depth.map_or_else(|| text("summary-unknown-bit-depth", &[]), String::from);
+    println!(
+        "{}",
+        text(
+            "summary-input",
+            &[
+                ("path", &cli.input.display()),
+                ("width", &width),
+                ("height", &height),
+                ("bit-depth", &bit_depth),
+            ],
+        )
+    );
+    let heading = match (&cli.camera, &cli.filter) {
+        (Some(camera), Some(filter)) => text(
+            "summary-qe-camera-filter",
+            &[("camera", camera), ("filter", filter)],
+        ),
+        (Some(camera), None) => text("summary-qe-camera", &[("camera", camera)]),
+        (None, Some(filter)) => text("summary-qe-filter", &[("filter", filter)]),
+        (None, None) => text("summary-qe", &[]),
+    };
+    println!("{}", heading);
+    println!("    {:<8}{:<9}OIII", "", "H-alpha");
+    for (name, qe) in [
+        (text("summary-red", &[]), red),
+        (text("summary-green", &[]), green),
+        (text("summary-blue", &[]), blue),
+    ] {
+        println!("    {:<8}{:<9}{}", name, qe.ha, qe.oiii);
+    }
+    if cli.red_hb_qe.is_some() {
+        println!(
+            "{}",
+            text(
+                "summary-balmer-decrement",
+                &[("decrement", &cli.balmer_decrement)]
+            )
+        );
+    }
+    for (kind, warning) in qe_warnings((red, green, blue)) {
+        if solver.strict {
+            ExitCode::BadInput.exit(text("error-strict", &[("message", &warning)]));
+        }
+        println!("  {}", text("warning", &[("message", &warning)]));
+        status::record(kind, warning);
+    }
+
+    let optimizer = match solver.optimizer {
+        Optimizer::Ga => text(
+            "summary-ga",
+            &[
+                ("population", &solver.population_size),
+                ("generations", &solver.generations),
+                ("initial-std", &solver.initial_std),
+                ("decay-schedule", &name(solver.decay_schedule)),
+                ("decay-rate", &solver.decay_rate),
+                ("selection", &name(solver.selection)),
+            ],
+        ),
+        Optimizer::Bayes => text("summary-bayes", &[("evaluations", &solver.evaluations)]),
+    };
+    println!("{}", optimizer);
+    let evaluator = if solver.cpu {
+        text("summary-cpu", &[])
+    } else if solver.cuda() {
+        text("summary-cuda", &[])
+    } else if solver.hybrid {
+        text("summary-hybrid", &[])
+    } else {
+        text("summary-gpu", &[])
+    };
+    println!(
+        "{}",
+        text("summary-evaluator", &[("evaluator", &evaluator)])
+    );
+    match solver.seed {
+        Some(seed) => println!("{}", text("summary-seed", &[("seed", &seed)])),
+        None => println!("{}", text("summary-random-seed", &[])),
+    }
+    if solver.subtract_background {
+        println!(
+            "{}",
+            text("summary-background", &[("grid", &solver.background_grid)])
+        );
+    }
+}
+
+/// Signs that the quantum efficiencies were entered wrongly
+fn qe_warnings(
+    (red, green, blue): (QEUniform, QEUniform, QEUniform),
+) -> Vec<(WarningKind, String)> {
+    let mut warnings = Vec::new();
+    if red.ha < red.oiii {
+        warnings.push((WarningKind::SuspectedSwap, text("red-prefers-oiii", &[])));
+    }
+    if blue.ha > blue.oiii {
+        warnings.push((
+            WarningKind::SuspectedSwap,
+            text("blue-prefers-h-alpha", &[]),
+        ));
+    }
+    // j_k_from_i divides by this, the green and blue responses must tell the lines apart
+    let determinant = green.oiii * blue.ha - green.ha * blue.oiii;
+    if determinant.abs() < 1e-6 {
+        warnings.push((
+            WarningKind::InseparableLines,
+            text("inseparable-lines", &[]),
+        ));
+    }
+    warnings
+}
+
+fn name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_string())
+}
@@ -0,0 +1,318 @@
+use crate::cli::SynthArgs;
+use crate::fits::{write_rgb_fits, Channels};
+use crate::gpu::QEUniform;
+use crate::messages::text;
+use crate::normal_distr::NormalDistribution;
+use crate::status::ExitCode;
+use clap::ValueEnum;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Emission added everywhere, so no pixel is free of signal
+const BACKGROUND: f32 = 0.05;
+// Number of features drawn by each emission pattern
+const FEATURES: usize = 4;
+// Standard deviation of the star point spread function, in pixels
+const STAR_SIGMA: f32 = 1.5;
+
+type QuantumEfficiencies = (QEUniform, QEUniform, QEUniform);
+
+/// Representative quantum efficiencies of colour sensors at the H-alpha and OIII lines
+pub const TEST_SENSORS: [(&str, QuantumEfficiencies); 3] = [
+    (
+        "high red response",
+        (
+            QEUniform {
+                ha: 0.8,
+                oiii: 0.05,
+            },
+            QEUniform { ha: 0.1, oiii: 0.6 },
+            QEUniform {
+                ha: 0.05,
+                oiii: 0.5,
+            },
+        ),
+    ),
+    (
+        "strong green leak",
+        (
+            QEUniform { ha: 0.6, oiii: 0.1 },
+            QEUniform { ha: 0.2, oiii: 0.8 },
+            QEUniform {
+                ha: 0.03,
+                oiii: 0.4,
+            },
+        ),
+    ),
+    (
+        "weak blue",
+        (
+            QEUniform {
+                ha: 0.7,
+                oiii: 0.02,
+            },
+            QEUniform {
+                ha: 0.08,
+                oiii: 0.7,
+            },
+            QEUniform {
+                ha: 0.02,
+                oiii: 0.2,
+            },
+        ),
+    ),
+];
+
+/// Shape of the emission in one band
+#[derive(Copy, Clone, ValueEnum)]
+pub enum Pattern {
+    /// Only the uniform background emission
+    Flat,
+    /// Diffuse Gaussian clouds
+    Blobs,
+    /// Rings, like planetary nebulae or supernova remnants
+    Shells,
+    /// Thin straight ridges
+    Filaments,
+}
+
+enum Feature {
+    Blob {
+        x: f32,
+        y: f32,
+        radius: f32,
+        brightness: f32,
+    },
+    Shell {
+        x: f32,
+        y: f32,
+        radius: f32,
+        width: f32,
+        brightness: f32,
+    },
+    Filament {
+        // Unit normal of the ridge line and its signed distance from the origin
+        normal: (f32, f32),
+        offset: f32,
+        width: f32,
+        brightness: f32,
+    },
+}
+
+impl Feature {
+    fn random(pattern: Pattern, width: usize, height: usize, rng: &mut impl Rng) -> Option<Self> {
+        let size = width.min(height) as f32;
+        let x = rng.random_range(0.0..width as f32);
+        let y = rng.random_range(0.0..height as f32);
+        let brightness = rng.random_range(0.1..0.6);
+        match pattern {
+            Pattern::Flat => None,
+            Pattern::Blobs => Some(Self::Blob {
+                x,
+                y,
+                radius: rng.random_range(0.05..0.3) * size,
+                brightness,
+            }),
+            Pattern::Shells => Some(Self::Shell {
+                x,
+                y,
+                radius: rng.random_range(0.1..0.4) * size,
+                width: rng.random_range(0.005..0.03) * size,
+                brightness,
+            }),
+            Pattern::Filaments => {
+                let angle = rng.random_range(0.0..std::f32::consts::PI);
+                let normal = (angle.cos(), angle.sin());
+                Some(Self::Filament {
+                    normal,
+                    offset: x * normal.0 + y * normal.1,
+                    width: rng.random_range(0.002..0.02) * size,
+                    brightness,
+                })
+            }
+        }
+    }
+
+    fn emission(&self, px: f32, py: f32) -> f32 {
+        let (distance_sq, spread, brightness) = match *self {
+            Self::Blob {
+                x,
+                y,
+                radius,
+                brightness,
+            } => ((px - x).powi(2) + (py - y).powi(2), radius, brightness),
+            Self::Shell {
+                x,
+                y,
+                radius,
+                width,
+                brightness,
+            } => {
+                let distance = ((px - x).powi(2) + (py - y).powi(2)).sqrt();
+                ((distance - radius).powi(2), width, brightness)
+            }
+            Self::Filament {
+                normal,
+                offset,
+                width,
+                brightness,
+            } => (
+                (px * normal.0 + py * normal.1 - offset).powi(2),
+                width,
+                brightness,
+            ),
+        };
+        brightness * (-distance_sq / (2.0 * spread * spread)).exp()
+    }
+}
+
+/// Description of a synthetic dual-band capture
+pub struct Scene {
+    pub ha: Pattern,
+    pub oiii: Pattern,
+    pub stars: usize,
+    // Sky glow added across the image, from zero at one edge to this at the opposite one
+    pub gradient: f32,
+    // Photon noise standard deviation relative to the square root of the signal
+    pub noise: f32,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            ha: Pattern::Blobs,
+            oiii: Pattern::Blobs,
+            stars: 0,
+            gradient: 0.0,
+            noise: 0.05,
+        }
+    }
+}
+
+impl Scene {
+    /// Renders a row-major image of the scene as seen through a dual-band filter by a
+    /// one-shot-colour camera with the given quantum efficiencies
+    pub fn render(
+        &self,
+        width: usize,
+        height: usize,
+        (qe_red, qe_green, qe_blue): QuantumEfficiencies,
+        rng: &mut impl Rng,
+    ) -> Vec<[f32; 3]> {
+        let ha_features = (0..FEATURES)
+            .filter_map(|_| Feature::random(self.ha, width, height, rng))
+            .collect::<Vec<_>>();
+        let oiii_features = (0..FEATURES)
+            .filter_map(|_| Feature::random(self.oiii, width, height, rng))
+            .collect::<Vec<_>>();
+
+        // Stars and sky glow pass through both filter bands alike
+        let mut broadband = vec![0.0f32; width * height];
+        let angle = rng.random_range(0.0..std::f32::consts::TAU);
+        let (dx, dy) = (angle.cos(), angle.sin());
+        let extent = width as f32 * dx.abs() + height as f32 * dy.abs();
+        let origin = (dx.min(0.0) * width as f32 + dy.min(0.0) * height as f32).abs();
+        for (idx, value) in broadband.iter_mut().enumerate() {
+            let (x, y) = ((idx % width) as f32, (idx / width) as f32);
+            *value = self.gradient * (x * dx + y * dy + origin) / extent;
+        }
+        for _ in 0..self.stars {
+            add_star(&mut broadband, width, height, rng);
+        }
+
+        let noise = NormalDistribution::new(0.0, self.noise);
+        // Each polar draw gives two samples, the second kept for the next channel
+        let mut spare = None;
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let sky = broadband[y * width + x];
+                let (x, y) = (x as f32, y as f32);
+                let ha = BACKGROUND + ha_features.iter().map(|f| f.emission(x, y)).sum::<f32>();
+                let oiii = BACKGROUND + oiii_features.iter().map(|f| f.emission(x, y)).sum::<f32>();
+                pixels.push([qe_red, qe_green, qe_blue].map(|qe| {
+                    let signal = qe.ha * (ha + sky) + qe.oiii * (oiii + sky);
+                    let sample = spare.take().unwrap_or_else(|| {
+                        let [sample, next] = noise.sample_pair(rng);
+                        spare = Some(next);
+                        sample
+                    });
+                    signal + signal.sqrt() * sample
+                }));
+            }
+        }
+        pixels
+    }
+}
+
+fn add_star(broadband: &mut [f32], width: usize, height: usize, rng: &mut impl Rng) {
+    let (cx, cy) = (
+        rng.random_range(0.0..width as f32),
+        rng.random_range(0.0..height as f32),
+    );
+    // Few bright stars and many faint ones
+    let peak = 0.05 + 2.0 * rng.random::<f32>().powi(4);
+    let reach = (4.0 * STAR_SIGMA).ceil() as isize;
+    for y in (cy as isize - reach).max(0)..(cy as isize + reach + 1).min(height as isize) {
+        for x in (cx as isize - reach).max(0)..(cx as isize + reach + 1).min(width as isize) {
+            let distance_sq = (x as f32 - cx).powi(2) + (y as f32 - cy).powi(2);
+            broadband[y as usize * width + x as usize] +=
+                peak * (-distance_sq / (2.0 * STAR_SIGMA * STAR_SIGMA)).exp();
+        }
+    }
+}
+
+/// Adds Gaussian noise with standard deviation `sigma` to every value of the channels
+pub fn add_noise(channels: &mut Channels, sigma: f32, rng: &mut impl Rng) {
+    let noise = NormalDistribution::new(0.0, sigma);
+    let (red, green, blue) = channels;
+    for channel in [red, green, blue] {
+        for pair in channel
+            .as_slice_mut()
+            .expect("FITS channels are contiguous")
+            .chunks_mut(2)
+        {
+            let samples = noise.sample_pair(rng);
+            for (value, sample) in pair.iter_mut().zip(samples) {
+                *value += sample;
+            }
+        }
+    }
+}
+
+pub fn run(args: &SynthArgs) {
+    let (width, height) = args.size;
+    let scene = Scene {
+        ha: args.ha_pattern,
+        oiii: args.oiii_pattern,
+        stars: args.stars,
+        gradient: args.gradient,
+        noise: args.noise,
+    };
+    let qe = (
+        QEUniform {
+            ha: args.red_ha_qe,
+            oiii: args.red_oiii_qe,
+        },
+        QEUniform {
+            ha: args.green_ha_qe,
+            oiii: args.green_oiii_qe,
+        },
+        QEUniform {
+            ha: args.blue_ha_qe,
+            oiii: args.blue_oiii_qe,
+        },
+    );
+    println!(
+        "{}",
+        text(
+            "generating-image",
+            &[("width", &width), ("height", &height)]
+        )
+    );
+    let pixels = scene.render(width, height, qe, &mut StdRng::seed_from_u64(args.seed));
+    if let Err(err) = write_rgb_fits(&args.output, width, height, &pixels, &[]) {
+        ExitCode::Io.exit(text("error-writing-synthetic", &[("message", &err)]));
+    }
+    println!("{}", text("wrote", &[("path", &args.output.display())]));
+}
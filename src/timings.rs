@@ -0,0 +1,75 @@
+use crate::gpu::GpuTimings;
+use crate::messages::text;
+use std::time::Duration;
+
+/// Wall-clock time of each stage of a run, printed at the end when `--timings` is set
+#[derive(Default)]
+pub struct RunTimings {
+    pub read: Duration,
+    pub gpu_setup: Option<Duration>,
+    pub cpu_setup: Option<Duration>,
+    pub optimization: Duration,
+    pub gpu: Option<GpuTimings>,
+    pub apply: Duration,
+    pub applied_on_gpu: bool,
+    pub write: Duration,
+}
+
+impl RunTimings {
+    pub fn print_summary(&self) {
+        let duration = |duration: Duration| format!("{:?}", duration);
+        println!("{}", text("timings", &[]));
+        println!(
+            "{}",
+            text("timings-read", &[("duration", &duration(self.read))])
+        );
+        if let Some(gpu_setup) = self.gpu_setup {
+            println!(
+                "{}",
+                text("timings-gpu-setup", &[("duration", &duration(gpu_setup))])
+            );
+        }
+        if let Some(cpu_setup) = self.cpu_setup {
+            println!(
+                "{}",
+                text("timings-cpu-setup", &[("duration", &duration(cpu_setup))])
+            );
+        }
+        // Whatever the optimization did not spend waiting on the GPU was spent on the CPU
+        let mut ga_cpu = self.optimization;
+        if let Some(gpu) = self.gpu.filter(|gpu| gpu.generations > 0) {
+            println!(
+                "{}",
+                text(
+                    "timings-gpu-kernels",
+                    &[
+                        ("duration", &duration(gpu.kernels)),
+                        ("per-generation", &duration(gpu.kernels / gpu.generations)),
+                    ]
+                )
+            );
+            println!(
+                "{}",
+                text(
+                    "timings-gpu-readback",
+                    &[("duration", &duration(gpu.readback))]
+                )
+            );
+            ga_cpu = ga_cpu.saturating_sub(gpu.kernels + gpu.readback);
+        }
+        println!(
+            "{}",
+            text("timings-ga-cpu", &[("duration", &duration(ga_cpu))])
+        );
+        let apply = duration(self.apply);
+        if self.applied_on_gpu {
+            println!("{}", text("timings-apply-gpu", &[("duration", &apply)]));
+        } else {
+            println!("{}", text("timings-apply-cpu", &[("duration", &apply)]));
+        }
+        println!(
+            "{}",
+            text("timings-write", &[("duration", &duration(self.write))])
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use crate::background::Background;
+use crate::cli::{SolverArgs, TuneArgs};
+use crate::cpu::CpuContext;
+use crate::evaluator::Evaluator;
+use crate::fits::{downsample, interleave};
+use crate::genetics::analytic_seed;
+use crate::gpu::{GpuContext, GpuSettings};
+use crate::input::read_image;
+use crate::messages::text;
+use crate::optimizer::{optimized_genome, GaSettings};
+use crate::status::{self, ExitCode};
+use crate::{linearize, sanitize, synthetic, white_balance};
+use rand::rngs::StdRng;
+use rand::{rng, Rng, SeedableRng};
+
+// Settings tried by the pilot runs, every combination of them
+const POPULATION_SIZES: [usize; 3] = [50, 100, 200];
+const INITIAL_STDS: [f32; 3] = [0.1, 0.5, 1.0];
+const DECAY_RATES: [f32; 3] = [0.02, 0.05, 0.1];
+// Each combination is scored by its mean result over these seeds
+const PILOT_SEEDS: [u64; 2] = [1, 2];
+
+/// Runs short genetic algorithms with different population sizes, initial mutation
+/// standard deviations and decay rates on a binned copy of the image, prints how each did
+/// and returns `args.solver` with the best of them
+pub async fn run(args: &TuneArgs) -> SolverArgs {
+    println!(
+        "{}",
+        text("reading-input", &[("path", &args.split.input.display())])
+    );
+    let image =
+        read_image(&args.split.input, args.split.read_options()).unwrap_or_else(|failure| {
+            failure.code.exit(text(
+                "error-reading-input",
+                &[("message", &failure.message)],
+            ))
+        });
+    for warning in &image.warnings {
+        status::warn(
+            args.solver.strict,
+            ExitCode::BadInput,
+            warning.kind,
+            &warning.message,
+        );
+    }
+    let mut response = args.split.response();
+    response.airmass = args.split.airmass.or(image.airmass);
+    let quantum_efficiencies = response
+        .quantum_efficiencies()
+        .expect("the response was checked against both lines");
+    let mut channels = image.channels;
+    sanitize::from_args(&args.split, &mut channels);
+    linearize::from_args(
+        &args.split,
+        args.solver.strict,
+        image.stretched,
+        &mut channels,
+    );
+    white_balance::undo_from_args(&args.split, &mut channels, quantum_efficiencies);
+    if let Some(sigma) = args.split.add_noise {
+        let seed = args.solver.seed.unwrap_or_else(|| rng().random());
+        synthetic::add_noise(&mut channels, sigma, &mut StdRng::seed_from_u64(seed));
+    }
+    let width = channels.0.ncols();
+    let factor = args.downsample as usize;
+    let (pixels, width) = downsample(&interleave(&channels), width, factor);
+    println!(
+        "{}",
+        text(
+            "tuning-on",
+            &[
+                ("width", &width),
+                ("height", &(pixels.len() / width.max(1)))
+            ]
+        )
+    );
+    if pixels.is_empty() {
+        ExitCode::BadInput.exit(text("image-smaller-than-downsampling", &[]));
+    }
+
+    let background = Background::from_args(&pixels, width, &args.solver);
+    let gpu = if args.solver.cpu {
+        None
+    } else {
+        let settings = GpuSettings::from_args(&args.solver);
+        match GpuContext::new(
+            &pixels,
+            background.as_ref(),
+            &settings,
+            quantum_efficiencies,
+        )
+        .await
+        {
+            Ok(ctx) => Some(ctx),
+            Err(err) if args.solver.strict => ExitCode::GpuUnavailable
+                .exit(text("error-setting-up-gpu-strict", &[("message", &err)])),
+            Err(err) => {
+                eprintln!("{}", text("error-setting-up-gpu", &[("message", &err)]));
+                eprintln!("{}", text("falling-back-to-cpu", &[]));
+                None
+            }
+        }
+    };
+    let cpu;
+    let evaluator: &dyn Evaluator = match &gpu {
+        Some(gpu) => gpu,
+        None => {
+            cpu = CpuContext::new(&pixels, background.as_ref(), quantum_efficiencies);
+            &cpu
+        }
+    };
+
+    let mut best: Option<(f32, SolverArgs)> = None;
+    for population_size in POPULATION_SIZES {
+        for initial_std in INITIAL_STDS {
+            for decay_rate in DECAY_RATES {
+                let mut candidate = args.solver.clone();
+                candidate.population_size = population_size;
+                candidate.initial_std = initial_std;
+                candidate.decay_rate = decay_rate;
+                candidate.generations = (args.pilot_evaluations / population_size as u32).max(1);
+                let settings = match GaSettings::from_args(&candidate) {
+                    Ok(settings) => GaSettings {
+                        progress: false,
+                        init_seed: analytic_seed(&quantum_efficiencies),
+                        ..settings
+                    },
+                    Err(err) => {
+                        println!(
+                            "{}",
+                            text(
+                                "skipping-population",
+                                &[("population", &population_size), ("message", &err)]
+                            )
+                        );
+                        continue;
+                    }
+                };
+
+                let score = PILOT_SEEDS
+                    .iter()
+                    .map(|&seed| {
+                        let mut rng = StdRng::seed_from_u64(seed);
+                        let outcome = optimized_genome(&settings, evaluator, &mut rng);
+                        outcome.history.last().map_or(f32::INFINITY, |b| b.fitness)
+                    })
+                    .sum::<f32>()
+                    / PILOT_SEEDS.len() as f32;
+                println!(
+                    "{}",
+                    text(
+                        "pilot-result",
+                        &[
+                            ("population", &population_size),
+                            ("initial-std", &initial_std),
+                            ("decay-rate", &decay_rate),
+                            ("noise", &score),
+                        ]
+                    )
+                );
+                if best
+                    .as_ref()
+                    .is_none_or(|(best_score, _)| score < *best_score)
+                {
+                    best = Some((score, candidate));
+                }
+            }
+        }
+    }
+
+    let Some((score, best)) = best else {
+        ExitCode::BadInput.exit(text("no-pilot-run", &[]));
+    };
+    // The pilots only ran long enough to compare settings, so keep the requested length
+    let tuned = SolverArgs {
+        generations: args.solver.generations,
+        ..best
+    };
+    println!(
+        "{}",
+        text(
+            "recommended-settings",
+            &[
+                ("noise", &score),
+                ("population", &tuned.population_size),
+                ("initial-std", &tuned.initial_std),
+                ("decay-rate", &tuned.decay_rate),
+            ]
+        )
+    );
+    tuned
+}
@@ -0,0 +1,17 @@
+// Structs shared by the kernels, laid out like their Rust counterparts
+
+// genetics.rs
+struct Genome {
+    i: f32,
+    x: f32
+};
+
+// moments.rs, as per-chunk sums in moments.wgsl or means in fit.wgsl
+struct Moments {
+    rr: f32,
+    gg: f32,
+    bb: f32,
+    rg: f32,
+    rb: f32,
+    gb: f32
+};
@@ -0,0 +1,112 @@
+use crate::cli::SplitArgs;
+use crate::fits::read_mono_fits;
+use crate::messages::text;
+use ndarray::{Array2, Zip};
+use serde::Serialize;
+
+/// How closely an extraction follows a registered narrowband frame of the same line
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Score {
+    /// Pearson correlation, unaffected by the two frames' different scales
+    pub correlation: f32,
+    /// RMS of the extraction's difference from the reference fitted to it
+    pub rms: f32,
+    /// Scale and offset of the least-squares fit of the reference to the extraction
+    pub scale: f32,
+    pub offset: f32,
+}
+
+/// Scores of both lines against `--validate-against`
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Validation {
+    pub h_alpha: Score,
+    pub oiii: Score,
+}
+
+/// Scores the extractions against the reference frames `args` names, if any
+pub fn from_args(
+    args: &SplitArgs,
+    h_alpha: &Array2<f32>,
+    oiii: &Array2<f32>,
+) -> Result<Option<Validation>, String> {
+    let Some((h_alpha_path, oiii_path)) = &args.validate_against else {
+        return Ok(None);
+    };
+    let [h_alpha, oiii] = [(h_alpha, h_alpha_path), (oiii, oiii_path)].map(|(line, path)| {
+        let reference = read_mono_fits(path).map_err(|failure| failure.message)?;
+        if reference.dim() != line.dim() {
+            let (height, width) = reference.dim();
+            return Err(text(
+                "reference-size-differs",
+                &[
+                    ("path", &path.display()),
+                    ("width", &width),
+                    ("height", &height),
+                    ("image-width", &line.ncols()),
+                    ("image-height", &line.nrows()),
+                ],
+            ));
+        }
+        Ok(score(line, &reference))
+    });
+    Ok(Some(Validation {
+        h_alpha: h_alpha?,
+        oiii: oiii?,
+    }))
+}
+
+pub fn score(extraction: &Array2<f32>, reference: &Array2<f32>) -> Score {
+    let pixels = extraction.len().max(1) as f64;
+    let mean = |image: &Array2<f32>| image.iter().map(|&v| v as f64).sum::<f64>() / pixels;
+    let (mean_extraction, mean_reference) = (mean(extraction), mean(reference));
+    let (mut covariance, mut variance_extraction, mut variance_reference) = (0.0, 0.0, 0.0);
+    Zip::from(extraction)
+        .and(reference)
+        .for_each(|&extraction, &reference| {
+            let extraction = extraction as f64 - mean_extraction;
+            let reference = reference as f64 - mean_reference;
+            covariance += extraction * reference;
+            variance_extraction += extraction * extraction;
+            variance_reference += reference * reference;
+        });
+    let scale = if variance_reference > 0.0 {
+        covariance / variance_reference
+    } else {
+        0.0
+    };
+    let spread = (variance_extraction * variance_reference).sqrt();
+    // What the fit leaves of the extraction's variance
+    let residual = (variance_extraction - scale * covariance).max(0.0);
+    Score {
+        correlation: if spread > 0.0 {
+            (covariance / spread) as f32
+        } else {
+            0.0
+        },
+        rms: (residual / pixels).sqrt() as f32,
+        scale: scale as f32,
+        offset: (mean_extraction - scale * mean_reference) as f32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn scores_against_rescaled_references() {
+        let reference = array![[1.0, 2.0], [3.0, 6.0]];
+        let extraction = reference.mapv(|v| 0.5 * v + 1.0);
+        let exact = score(&extraction, &reference);
+        assert!((exact.correlation - 1.0).abs() < 1e-6);
+        assert!(exact.rms < 1e-6);
+        assert!((exact.scale - 0.5).abs() < 1e-6 && (exact.offset - 1.0).abs() < 1e-6);
+
+        let mut noisy = extraction.clone();
+        noisy[[0, 0]] += 0.5;
+        let noisy = score(&noisy, &reference);
+        assert!(noisy.correlation < exact.correlation);
+        assert!(noisy.rms > 0.1);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::background::Background;
+use crate::cli::SplitArgs;
+use crate::fits::{interleave, Channels};
+use crate::gpu::QEUniform;
+use crate::messages::text;
+use crate::status::ExitCode;
+
+/// Cells along each side of the grid the background is estimated on for `--wb auto`
+const GRID: usize = 8;
+
+/// Channel multipliers a camera or stacker applied to balance the colours, which `--wb`
+/// divides back out because they break the quantum efficiency ratios
+#[derive(Clone, Copy)]
+pub enum WhiteBalance {
+    Multipliers([f32; 3]),
+    /// Estimated from the sky background
+    Auto,
+}
+
+impl WhiteBalance {
+    /// The red, green and blue multipliers to undo
+    pub fn multipliers(
+        self,
+        channels: &Channels,
+        quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+    ) -> Result<[f32; 3], String> {
+        match self {
+            WhiteBalance::Multipliers(multipliers) => Ok(multipliers),
+            WhiteBalance::Auto => estimate(channels, quantum_efficiencies),
+        }
+    }
+}
+
+/// Undoes the white balance `args` asks to, returning the multipliers divided out
+pub fn undo_from_args(
+    args: &SplitArgs,
+    channels: &mut Channels,
+    quantum_efficiencies: (QEUniform, QEUniform, QEUniform),
+) -> Option<[f32; 3]> {
+    let multipliers = args
+        .white_balance?
+        .multipliers(channels, quantum_efficiencies)
+        .unwrap_or_else(|err| ExitCode::BadInput.exit(text("error", &[("message", &err)])));
+    println!(
+        "{}",
+        text(
+            "undoing-white-balance",
+            &[
+                ("r", &multipliers[0]),
+                ("g", &multipliers[1]),
+                ("b", &multipliers[2]),
+            ],
+        )
+    );
+    undo(channels, multipliers);
+    Some(multipliers)
+}
+
+/// Every unbalanced pixel is a mix of H-alpha and OIII, so lies in the plane the two
+/// columns of quantum efficiencies span. This finds the smallest rescaling of the channels
+/// that puts the sky background back into that plane, normalised to leave green alone.
+fn estimate(
+    channels: &Channels,
+    (red, green, blue): (QEUniform, QEUniform, QEUniform),
+) -> Result<[f32; 3], String> {
+    let pixels = interleave(channels);
+    let background = Background::estimate(&pixels, channels.0.ncols(), GRID);
+    let mut level = [0.0f64; 3];
+    for cell in &background.cells {
+        for (level, value) in level.iter_mut().zip(cell) {
+            *level += *value as f64 / background.cells.len() as f64;
+        }
+    }
+    if level.iter().any(|&value| value.is_nan() || value <= 0.0) {
+        return Err(text("background-too-faint", &[]));
+    }
+
+    let ha = [red.ha, green.ha, blue.ha].map(f64::from);
+    let oiii = [red.oiii, green.oiii, blue.oiii].map(f64::from);
+    let normal = [
+        ha[1] * oiii[2] - ha[2] * oiii[1],
+        ha[2] * oiii[0] - ha[0] * oiii[2],
+        ha[0] * oiii[1] - ha[1] * oiii[0],
+    ];
+    // Unbalanced background is `level / multipliers`; with u = 1 / multipliers the plane
+    // constraint is a · u = 0, and the u closest to no change is 1 - a (a · 1) / |a|²
+    let a = [0, 1, 2].map(|channel| normal[channel] * level[channel]);
+    let norm = a.iter().map(|value| value * value).sum::<f64>();
+    let offset = if norm > 0.0 {
+        a.iter().sum::<f64>() / norm
+    } else {
+        0.0
+    };
+    let inverse = a.map(|value| 1.0 - value * offset);
+    if inverse.iter().any(|&value| value.is_nan() || value <= 0.0) {
+        return Err(text("background-unbalanceable", &[]));
+    }
+    Ok(inverse.map(|value| (inverse[1] / value) as f32))
+}
+
+/// Divides each channel by its multiplier
+pub fn undo(channels: &mut Channels, [red, green, blue]: [f32; 3]) {
+    channels.0.mapv_inplace(|value| value / red);
+    channels.1.mapv_inplace(|value| value / green);
+    channels.2.mapv_inplace(|value| value / blue);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array2;
+
+    #[test]
+    fn auto_puts_the_background_back_between_the_lines() {
+        let qe = (
+            QEUniform {
+                ha: 0.8,
+                oiii: 0.05,
+            },
+            QEUniform { ha: 0.1, oiii: 0.6 },
+            QEUniform {
+                ha: 0.05,
+                oiii: 0.5,
+            },
+        );
+        // A background of H-alpha 2 and OIII 1, balanced with green left alone
+        let level = |qe: QEUniform| 2.0 * qe.ha + qe.oiii;
+        let multipliers = [1.6, 1.0, 1.9];
+        let channel = |qe, multiplier| Array2::from_elem((16, 16), level(qe) * multiplier);
+        let channels = (
+            channel(qe.0, multipliers[0]),
+            channel(qe.1, multipliers[1]),
+            channel(qe.2, multipliers[2]),
+        );
+        let estimated = WhiteBalance::Auto.multipliers(&channels, qe).unwrap();
+        // One background can't pin the multipliers down fully, but undoing the estimate must
+        // leave it a mix of the two lines again
+        let mut balanced = channels.clone();
+        undo(&mut balanced, estimated);
+        let level = [&balanced.0, &balanced.1, &balanced.2].map(|channel| channel[[0, 0]]);
+        let (ha, oiii) = solve_mix(level[0], level[1], qe);
+        assert!((ha * qe.2.ha + oiii * qe.2.oiii - level[2]).abs() < 1e-3);
+        assert_eq!(estimated[1], 1.0);
+
+        assert!(WhiteBalance::Auto
+            .multipliers(&(channels.0.clone() * 0.0, channels.1, channels.2), qe)
+            .is_err());
+    }
+
+    /// The H-alpha and OIII levels that give `red` and `green`
+    fn solve_mix(red: f32, green: f32, (r, g, _): (QEUniform, QEUniform, QEUniform)) -> (f32, f32) {
+        let det = r.ha * g.oiii - r.oiii * g.ha;
+        (
+            (red * g.oiii - r.oiii * green) / det,
+            (r.ha * green - red * g.ha) / det,
+        )
+    }
+}